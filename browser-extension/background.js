@@ -0,0 +1,46 @@
+// Reports the active tab to the TimiGS native-messaging host (`timigs native-host install`).
+const HOST_NAME = 'com.timigs.host';
+const api = globalThis.browser ?? globalThis.chrome;
+const BROWSER = navigator.userAgent.includes('Firefox') ? 'firefox' : 'chromium';
+
+let port = null;
+
+function connect() {
+  if (port) return port;
+  port = api.runtime.connectNative(HOST_NAME);
+  port.onMessage.addListener((reply) => {
+    if (!reply.ok) console.warn('TimiGS:', reply.error);
+  });
+  port.onDisconnect.addListener(() => {
+    port = null;
+  });
+  return port;
+}
+
+function report(tab) {
+  if (!tab || !tab.active || !tab.url) return;
+  try {
+    connect().postMessage({
+      type: 'tab',
+      url: tab.url,
+      title: tab.title || '',
+      incognito: tab.incognito,
+      browser: BROWSER,
+    });
+  } catch (e) {
+    port = null;
+  }
+}
+
+async function reportActive() {
+  const [tab] = await api.tabs.query({ active: true, lastFocusedWindow: true });
+  report(tab);
+}
+
+api.tabs.onActivated.addListener(reportActive);
+api.tabs.onUpdated.addListener((_id, change, tab) => {
+  if (change.url || change.title) report(tab);
+});
+api.windows.onFocusChanged.addListener((windowId) => {
+  if (windowId !== api.windows.WINDOW_ID_NONE) reportActive();
+});
@@ -0,0 +1,430 @@
+//! ActivityWatch import and export
+//!
+//! Reads the JSON written by ActivityWatch's "Export all buckets" (`GET /api/0/export`)
+//! or a single bucket export. Window events become activity sessions, minus the time
+//! the AFK watcher of the same host reported the user away. Web watcher events lend
+//! their URL to the window session they overlap most, when the window title contains
+//! the tab title. Other bucket types (editors, input, …) are ignored.
+//!
+//! The export goes the other way: a window bucket with every session, an AFK bucket
+//! that marks the gaps between them as away, and a web bucket per browser for the
+//! sessions with a URL, in the format `POST /api/0/import` accepts.
+
+use crate::db::{self, ActivitySession};
+use chrono::{DateTime, Duration, Local, Utc};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+
+const WINDOW_TYPE: &str = "currentwindow";
+const AFK_TYPE: &str = "afkstatus";
+const WEB_TYPE: &str = "web.tab.current";
+/// Sessions closer than this are one stretch of presence in the exported AFK bucket.
+const AFK_MERGE_SECS: i64 = 60;
+
+#[derive(Debug, Deserialize)]
+struct Bucket {
+    #[serde(default)]
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    hostname: Option<String>,
+    #[serde(default)]
+    events: Vec<Event>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Event {
+    timestamp: DateTime<Utc>,
+    #[serde(default)]
+    duration: f64,
+    #[serde(default)]
+    data: Map<String, Value>,
+}
+
+impl Event {
+    fn span(&self) -> Span {
+        let millis = (self.duration.max(0.0) * 1000.0).round() as i64;
+        (self.timestamp, self.timestamp + Duration::milliseconds(millis))
+    }
+
+    fn text(&self, key: &str) -> &str {
+        self.data.get(key).and_then(Value::as_str).unwrap_or("")
+    }
+}
+
+type Span = (DateTime<Utc>, DateTime<Utc>);
+
+/// An activity session rebuilt from ActivityWatch events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedSession {
+    pub app_name: String,
+    pub window_title: String,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub url: Option<String>,
+    pub domain: Option<String>,
+}
+
+/// Whether a parsed JSON document looks like an ActivityWatch export.
+pub fn is_export(value: &Value) -> bool {
+    value.get("buckets").is_some_and(|b| b.is_object() || b.is_array())
+        || (value.get("type").is_some() && value.get("events").is_some_and(Value::is_array))
+}
+
+pub fn is_export_file(path: &str) -> bool {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .is_some_and(|value| is_export(&value))
+}
+
+fn read_buckets(value: Value) -> Result<Vec<Bucket>, String> {
+    let buckets = match value {
+        Value::Object(mut map) if map.contains_key("buckets") => match map.remove("buckets") {
+            Some(Value::Object(buckets)) => buckets
+                .into_iter()
+                .map(|(id, mut bucket)| {
+                    // The id is the map key; older exports leave it out of the bucket
+                    if let Some(fields) = bucket.as_object_mut() {
+                        fields.entry("id").or_insert(Value::String(id));
+                    }
+                    bucket
+                })
+                .collect(),
+            Some(Value::Array(buckets)) => buckets,
+            _ => return Err("'buckets' must be an object or a list".to_string()),
+        },
+        bucket => vec![bucket],
+    };
+    buckets
+        .into_iter()
+        .map(|bucket| serde_json::from_value(bucket).map_err(|e| format!("Not an ActivityWatch bucket: {}", e)))
+        .collect()
+}
+
+/// `spans` sorted by start, with overlapping and touching ones joined.
+fn merge_spans(mut spans: Vec<Span>) -> Vec<Span> {
+    spans.sort();
+    let mut merged: Vec<Span> = Vec::with_capacity(spans.len());
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span.0 <= last.1 => last.1 = last.1.max(span.1),
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+/// `span` without the parts covered by `gaps`, which must be sorted and disjoint
+/// (see `merge_spans`), so the first one that matters is found by binary search.
+fn subtract(span: Span, gaps: &[Span]) -> Vec<Span> {
+    let mut pieces = Vec::new();
+    let mut start = span.0;
+    let first = gaps.partition_point(|gap| gap.1 <= span.0);
+    for &(gap_start, gap_end) in &gaps[first..] {
+        if gap_start >= span.1 {
+            break;
+        }
+        if gap_start > start {
+            pieces.push((start, gap_start));
+        }
+        start = start.max(gap_end);
+        if start >= span.1 {
+            return pieces;
+        }
+    }
+    pieces.push((start, span.1));
+    pieces
+}
+
+fn overlap(a: Span, b: Span) -> i64 {
+    (a.1.min(b.1) - a.0.max(b.0)).num_milliseconds().max(0)
+}
+
+fn sessions_from_buckets(buckets: &[Bucket]) -> Vec<ImportedSession> {
+    let mut away: HashMap<&str, Vec<Span>> = HashMap::new();
+    for bucket in buckets.iter().filter(|b| b.kind == AFK_TYPE) {
+        let host = bucket.hostname.as_deref().unwrap_or_default();
+        away.entry(host).or_default().extend(
+            bucket
+                .events
+                .iter()
+                .filter(|e| e.text("status") == "afk")
+                .map(Event::span),
+        );
+    }
+    let away: HashMap<&str, Vec<Span>> = away
+        .into_iter()
+        .map(|(host, spans)| (host, merge_spans(spans)))
+        .collect();
+
+    let mut tabs: Vec<(Span, &Event)> = buckets
+        .iter()
+        .filter(|b| b.kind == WEB_TYPE)
+        .flat_map(|b| b.events.iter())
+        .filter(|e| !e.text("title").is_empty() && e.data.get("incognito") != Some(&Value::Bool(true)))
+        .map(|e| (e.span(), e))
+        .collect();
+    tabs.sort_by_key(|(span, _)| span.0);
+
+    let mut sessions = Vec::new();
+    for bucket in buckets.iter().filter(|b| b.kind == WINDOW_TYPE) {
+        let gaps = away
+            .get(bucket.hostname.as_deref().unwrap_or_default())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        // Exports list events newest first; sweep both lists in start order so each
+        // event only looks at the tabs around it
+        let mut events: Vec<&Event> = bucket.events.iter().collect();
+        events.sort_by_key(|e| e.timestamp);
+        let mut next_tab = 0;
+        let mut nearby_tabs: Vec<&(Span, &Event)> = Vec::new();
+        for event in events {
+            let span = event.span();
+            nearby_tabs.retain(|(tab_span, _)| tab_span.1 > span.0);
+            while let Some(tab) = tabs.get(next_tab).filter(|(tab_span, _)| tab_span.0 < span.1) {
+                if tab.0 .1 > span.0 {
+                    nearby_tabs.push(tab);
+                }
+                next_tab += 1;
+            }
+
+            let app = event.text("app");
+            if app.is_empty() {
+                continue;
+            }
+            let title = event.text("title");
+            for piece in subtract(span, gaps) {
+                if (piece.1 - piece.0).num_seconds() < 1 {
+                    continue;
+                }
+                let tab = nearby_tabs
+                    .iter()
+                    .filter(|(_, tab)| title.contains(tab.text("title")))
+                    .map(|(span, tab)| (overlap(*span, piece), *tab))
+                    .filter(|(shared, _)| *shared > 0)
+                    .max_by_key(|(shared, _)| *shared)
+                    .and_then(|(_, tab)| db::normalize_url(tab.text("url")));
+                let (url, domain) = tab.unzip();
+                sessions.push(ImportedSession {
+                    app_name: app.to_string(),
+                    window_title: title.to_string(),
+                    start_time: piece.0,
+                    end_time: piece.1,
+                    url,
+                    domain,
+                });
+            }
+        }
+    }
+    sessions.sort_by_key(|s| s.start_time);
+    sessions
+}
+
+/// Sessions from an ActivityWatch export, oldest first.
+pub fn parse_export(content: &str) -> Result<Vec<ImportedSession>, String> {
+    let value: Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    if !is_export(&value) {
+        return Err("Not an ActivityWatch export".to_string());
+    }
+    let buckets = read_buckets(value)?;
+    if !buckets.iter().any(|b| b.kind == WINDOW_TYPE) {
+        return Err("The export has no window bucket (aw-watcher-window)".to_string());
+    }
+    Ok(sessions_from_buckets(&buckets))
+}
+
+pub fn hostname() -> String {
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .ok()
+        .or_else(|| {
+            std::fs::read_to_string("/etc/hostname")
+                .ok()
+                .map(|name| name.trim().to_string())
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn event(span: Span, data: Value) -> Value {
+    json!({
+        "timestamp": span.0.to_rfc3339_opts(chrono::SecondsFormat::Micros, false),
+        "duration": (span.1 - span.0).num_milliseconds() as f64 / 1000.0,
+        "data": data,
+    })
+}
+
+fn bucket(id: String, kind: &str, client: &str, host: &str, events: Vec<Value>) -> Value {
+    json!({
+        "id": id,
+        "created": Local::now().to_rfc3339(),
+        "name": Value::Null,
+        "type": kind,
+        "client": client,
+        "hostname": host,
+        "data": {},
+        "events": events,
+    })
+}
+
+/// ActivityWatch buckets for closed `sessions`, as `{"buckets": {id: bucket}}`.
+pub fn export_buckets(sessions: &[ActivitySession], host: &str) -> Value {
+    let mut spans: Vec<(Span, &ActivitySession)> = sessions
+        .iter()
+        .filter_map(|s| {
+            let end = s.end_time?.with_timezone(&Utc);
+            Some(((s.start_time.with_timezone(&Utc), end), s))
+        })
+        .collect();
+    spans.sort_by_key(|(span, _)| *span);
+
+    let windows = spans
+        .iter()
+        .map(|(span, s)| event(*span, json!({ "app": s.app_name, "title": s.window_title })))
+        .collect();
+
+    let mut present: Vec<Span> = Vec::new();
+    for (span, _) in &spans {
+        match present.last_mut() {
+            Some(last) if (span.0 - last.1).num_seconds() <= AFK_MERGE_SECS => last.1 = last.1.max(span.1),
+            _ => present.push(*span),
+        }
+    }
+    let mut afk = Vec::new();
+    for (i, span) in present.iter().enumerate() {
+        if i > 0 {
+            afk.push(event((present[i - 1].1, span.0), json!({ "status": "afk" })));
+        }
+        afk.push(event(*span, json!({ "status": "not-afk" })));
+    }
+
+    let mut web: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+    for (span, s) in &spans {
+        if let Some(url) = &s.url {
+            let browser: String = s
+                .app_name
+                .to_lowercase()
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+                .collect();
+            web.entry(browser).or_default().push(event(
+                *span,
+                json!({ "url": url, "title": s.window_title, "audible": false, "incognito": false }),
+            ));
+        }
+    }
+
+    let mut buckets = Map::new();
+    let mut add = |id: String, kind: &str, client: &str, events: Vec<Value>| {
+        buckets.insert(id.clone(), bucket(id, kind, client, host, events));
+    };
+    add(format!("aw-watcher-window_{}", host), WINDOW_TYPE, "aw-watcher-window", windows);
+    add(format!("aw-watcher-afk_{}", host), AFK_TYPE, "aw-watcher-afk", afk);
+    for (browser, events) in web {
+        add(format!("aw-watcher-web-{}_{}", browser, host), WEB_TYPE, "aw-client-web", events);
+    }
+    json!({ "buckets": buckets })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(&format!("2024-05-01T{}Z", time))
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_subtract() {
+        let span = (at("09:00:00"), at("10:00:00"));
+        let gaps = [
+            (at("08:00:00"), at("09:10:00")),
+            (at("09:30:00"), at("09:40:00")),
+            (at("09:50:00"), at("11:00:00")),
+        ];
+        assert_eq!(
+            subtract(span, &gaps),
+            [(at("09:10:00"), at("09:30:00")), (at("09:40:00"), at("09:50:00"))]
+        );
+        assert_eq!(subtract(span, &[]), [span]);
+        assert!(subtract(span, &[(at("08:00:00"), at("12:00:00"))]).is_empty());
+
+        let unsorted = vec![
+            (at("09:50:00"), at("11:00:00")),
+            (at("09:30:00"), at("09:35:00")),
+            (at("08:00:00"), at("09:10:00")),
+            (at("09:35:00"), at("09:40:00")),
+            (at("09:32:00"), at("09:34:00")),
+        ];
+        assert_eq!(merge_spans(unsorted), gaps);
+    }
+
+    #[test]
+    fn test_parse_export() {
+        let export = r#"{"buckets": {
+            "aw-watcher-window_laptop": {"type": "currentwindow", "hostname": "laptop", "events": [
+                {"timestamp": "2024-05-01T09:00:00+00:00", "duration": 3600, "data": {"app": "Firefox", "title": "Rust docs — Mozilla Firefox"}},
+                {"timestamp": "2024-05-01T10:00:00+00:00", "duration": 0.4, "data": {"app": "Terminal", "title": "bash"}}
+            ]},
+            "aw-watcher-afk_laptop": {"type": "afkstatus", "hostname": "laptop", "events": [
+                {"timestamp": "2024-05-01T09:00:00+00:00", "duration": 1200, "data": {"status": "not-afk"}},
+                {"timestamp": "2024-05-01T09:20:00+00:00", "duration": 600, "data": {"status": "afk"}}
+            ]},
+            "aw-watcher-web-firefox": {"type": "web.tab.current", "hostname": "unknown", "events": [
+                {"timestamp": "2024-05-01T09:00:00+00:00", "duration": 300, "data": {"url": "https://www.doc.rust-lang.org/std/?search=vec", "title": "Rust docs"}},
+                {"timestamp": "2024-05-01T09:05:00+00:00", "duration": 3300, "data": {"url": "https://example.com/", "title": "Elsewhere"}}
+            ]},
+            "aw-watcher-input_laptop": {"type": "os.hid.input", "events": []}
+        }}"#;
+
+        let sessions = parse_export(export).unwrap();
+        // Split by the AFK gap; the sub-second terminal event is dropped
+        assert_eq!(sessions.len(), 2);
+        assert_eq!((sessions[0].start_time, sessions[0].end_time), (at("09:00:00"), at("09:20:00")));
+        assert_eq!((sessions[1].start_time, sessions[1].end_time), (at("09:30:00"), at("10:00:00")));
+        assert_eq!(sessions[0].domain.as_deref(), Some("doc.rust-lang.org"));
+        assert_eq!(sessions[0].url.as_deref(), Some("https://www.doc.rust-lang.org/std/"));
+        // The only overlapping tab with that title ended before the second piece
+        assert_eq!(sessions[1].url, None);
+
+        assert!(parse_export(r#"{"activity_sessions": []}"#).is_err());
+    }
+
+    #[test]
+    fn test_export_buckets() {
+        let session = |start: &str, end: &str, app: &str, url: Option<&str>| ActivitySession {
+            id: None,
+            app_name: app.to_string(),
+            window_title: format!("{} window", app),
+            exe_path: format!("/usr/bin/{}", app),
+            start_time: at(start).with_timezone(&Local),
+            end_time: Some(at(end).with_timezone(&Local)),
+            duration_seconds: (at(end) - at(start)).num_seconds(),
+            url: url.map(str::to_string),
+            domain: None,
+        };
+        let sessions = [
+            session("09:00:00", "09:30:00", "firefox", Some("https://docs.rs/")),
+            session("09:30:30", "09:45:00", "code", None),
+            session("11:00:00", "11:10:00", "code", None),
+        ];
+
+        let export = export_buckets(&sessions, "laptop");
+        let afk = &export["buckets"]["aw-watcher-afk_laptop"]["events"];
+        assert_eq!(afk.as_array().unwrap().len(), 3);
+        assert_eq!(afk[1]["data"]["status"], "afk");
+        assert_eq!(afk[1]["duration"], 4500.0);
+        assert_eq!(export["buckets"]["aw-watcher-web-firefox_laptop"]["events"][0]["data"]["url"], "https://docs.rs/");
+
+        // Reads back as the same sessions
+        let again = parse_export(&export.to_string()).unwrap();
+        assert_eq!(again.len(), 3);
+        assert_eq!(again[0].domain.as_deref(), Some("docs.rs"));
+        assert_eq!(again[2].start_time, at("11:00:00"));
+    }
+}
@@ -0,0 +1,553 @@
+//! Opt-in local HTTP/JSON API
+//!
+//! JSON endpoints for status bars (waybar, polybar) and personal dashboards.
+//! Listens on 127.0.0.1 only and every request needs the token from Settings, sent
+//! as `Authorization: Bearer <token>` or `X-TimiGS-Token: <token>`.
+//!
+//! `GET /v1/status`, `/v1/current`, `/v1/summary[?date=]`, `/v1/sessions?from=&to=`,
+//! `/v1/coding[?from=&to=]`, `/v1/coding/repos?from=&to=`,
+//! `/v1/coding/branches?from=&to=[&repo=]`, `/v1/music[?from=&to=]`, `/v1/productivity[?date=]`,
+//! `/v1/focus`, `/v1/timeout`, `/v1/incognito`,
+//! `/v1/search?q=[&from=&to=&app=&limit=]`. Dates are `YYYY-MM-DD`.
+//!
+//! `POST /v1/tracking/pause`, `/v1/tracking/resume`, `/v1/focus/start`,
+//! `/v1/focus/stop`, `/v1/incognito/start[?minutes=]` and `/v1/incognito/stop` let the
+//! `timigs` CLI control a running instance.
+//!
+//! `POST /v1/shell/start` and `/v1/shell/end` take command timings from the shell
+//! hook (`timigs shell-hook`) as form fields or JSON; `GET /v1/shell/commands?from=&to=`
+//! lists them. Nothing is recorded while incognito.
+//!
+//! `/api/v1/users/current/...` takes heartbeats from WakaTime editor plugins, which
+//! send the token as HTTP Basic credentials (see `wakatime`).
+
+use crate::db;
+use chrono::NaiveDate;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::io::Read;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+pub const DEFAULT_API_PORT: u16 = 4646;
+const TOKEN_SETTING: &str = "api_token";
+/// Large enough for a wakatime-cli bulk upload of queued heartbeats.
+const MAX_BODY_BYTES: u64 = 4 * 1024 * 1024;
+
+struct RunningApi {
+    server: Arc<Server>,
+    port: u16,
+    handle: thread::JoinHandle<()>,
+}
+
+static API: Lazy<Mutex<Option<RunningApi>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiStatus {
+    pub enabled: bool,
+    pub running: bool,
+    pub port: u16,
+    pub url: String,
+    pub token: String,
+}
+
+/// The API token, created on first use.
+pub fn get_token() -> Result<String, String> {
+    match db::get_setting(TOKEN_SETTING) {
+        Some(token) if !token.is_empty() => Ok(token),
+        _ => regenerate_token(),
+    }
+}
+
+/// Replace the token; clients using the old one get 401 from now on.
+pub fn regenerate_token() -> Result<String, String> {
+    let mut bytes = [0u8; 24];
+    getrandom::getrandom(&mut bytes).map_err(|e| e.to_string())?;
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    db::save_setting(TOKEN_SETTING, &token).map_err(|e| e.to_string())?;
+    // Shells hooked up earlier read the header from this file on every command
+    if curl_config_path().exists() {
+        write_curl_config(&token)?;
+    }
+    Ok(token)
+}
+
+/// curl config with the token header for the shell hooks, next to the database.
+/// They pass it with `curl -K` so the token stays out of `ps` and /proc.
+pub fn curl_config_path() -> PathBuf {
+    db::get_db_path().with_file_name("shell-hook.curlrc")
+}
+
+/// Write the hooks' curl config (owner-only), replacing any older one.
+pub fn write_curl_config(token: &str) -> Result<PathBuf, String> {
+    let path = curl_config_path();
+    match std::fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(format!("{}: {}", path.display(), e))
+        }
+        _ => {}
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let config = format!("header = \"X-TimiGS-Token: {}\"\n", token);
+    std::io::Write::write_all(&mut file, config.as_bytes()).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+pub fn status() -> Result<ApiStatus, String> {
+    let settings = db::get_settings();
+    let running_port = API.lock().as_ref().map(|api| api.port);
+    let port = running_port.unwrap_or(settings.api_port);
+    Ok(ApiStatus {
+        enabled: settings.api_enabled,
+        running: running_port.is_some(),
+        port,
+        url: format!("http://127.0.0.1:{}/v1", port),
+        token: get_token()?,
+    })
+}
+
+/// Start, stop or restart the server to match the settings.
+pub fn apply_settings(settings: &db::Settings) -> Result<(), String> {
+    let running_port = API.lock().as_ref().map(|api| api.port);
+    match (settings.api_enabled, running_port) {
+        (false, Some(_)) => {
+            stop();
+            Ok(())
+        }
+        (true, Some(port)) if port != settings.api_port => {
+            stop();
+            start(settings.api_port)
+        }
+        (true, None) => start(settings.api_port),
+        _ => Ok(()),
+    }
+}
+
+pub fn start(port: u16) -> Result<(), String> {
+    let mut guard = API.lock();
+    if guard.is_some() {
+        return Ok(());
+    }
+
+    // Make sure a token exists before anyone can connect
+    get_token()?;
+
+    let server = Arc::new(
+        Server::http(("127.0.0.1", port)).map_err(|e| format!("Local API: {}", e))?,
+    );
+    let worker = server.clone();
+    let handle = thread::spawn(move || {
+        println!("Local API listening on 127.0.0.1:{}", port);
+        for request in worker.incoming_requests() {
+            handle_request(request);
+        }
+        println!("Local API stopped");
+    });
+
+    *guard = Some(RunningApi {
+        server,
+        port,
+        handle,
+    });
+    Ok(())
+}
+
+pub fn stop() {
+    if let Some(api) = API.lock().take() {
+        api.server.unblock();
+        let _ = api.handle.join();
+    }
+}
+
+fn json_header() -> Header {
+    Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap()
+}
+
+fn respond<T: Serialize>(request: Request, status: u16, body: &T) {
+    let body = serde_json::to_string(body).unwrap_or_else(|_| "null".to_string());
+    let _ = request.respond(
+        Response::from_string(body)
+            .with_status_code(StatusCode(status))
+            .with_header(json_header()),
+    );
+}
+
+fn respond_error(request: Request, status: u16, message: &str) {
+    respond(request, status, &serde_json::json!({ "error": message }));
+}
+
+/// Compare the SHA-256 digests of both strings byte by byte without stopping at the
+/// first difference, so the response time does not reveal how much of the token a
+/// guess got right.
+fn token_matches(candidate: &str, token: &str) -> bool {
+    let (a, b) = (Sha256::digest(candidate), Sha256::digest(token));
+    a.iter().zip(b.iter()).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn is_authorized(request: &Request, token: &str) -> bool {
+    use base64::{engine::general_purpose, Engine as _};
+
+    request.headers().iter().any(|h| {
+        let value = h.value.as_str().trim();
+        if h.field.equiv("Authorization") {
+            if let Some(basic) = value.strip_prefix("Basic ") {
+                // wakatime-cli sends base64(api_key), some plugins base64("api_key:")
+                return general_purpose::STANDARD
+                    .decode(basic.trim())
+                    .ok()
+                    .and_then(|d| String::from_utf8(d).ok())
+                    .is_some_and(|d| token_matches(d.trim_end_matches(':'), token));
+            }
+            value
+                .strip_prefix("Bearer ")
+                .is_some_and(|bearer| token_matches(bearer.trim(), token))
+        } else {
+            h.field.equiv("X-TimiGS-Token") && token_matches(value, token)
+        }
+    })
+}
+
+fn handle_request(mut request: Request) {
+    let token = match db::get_setting(TOKEN_SETTING) {
+        Some(token) if !token.is_empty() => token,
+        _ => return respond_error(request, 503, "API token not configured"),
+    };
+    if !is_authorized(&request, &token) {
+        return respond_error(request, 401, "Missing or invalid token");
+    }
+
+    let (path, query) = split_url(request.url());
+    let is_post = match request.method() {
+        Method::Get => false,
+        Method::Post => true,
+        _ => return respond_error(request, 405, "Only GET and POST are supported"),
+    };
+    let mut body = String::new();
+    if is_post && request.as_reader().take(MAX_BODY_BYTES).read_to_string(&mut body).is_err() {
+        return respond_error(request, 400, "Unreadable request body");
+    }
+
+    let result = if let Some(rest) = path.strip_prefix(crate::wakatime::PATH_PREFIX) {
+        let user_agent = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("User-Agent"))
+            .map(|h| h.value.as_str().to_string());
+        crate::wakatime::handle(rest, is_post, &body, user_agent.as_deref())
+    } else if is_post {
+        control(&path, &body).map(|value| (200, value))
+    } else {
+        route(&path, &query).map(|value| (200, value))
+    };
+    match result {
+        Ok((status, body)) => respond(request, status, &body),
+        Err((status, message)) => respond_error(request, status, &message),
+    }
+}
+
+fn split_url(url: &str) -> (String, HashMap<String, String>) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let query = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    (path.trim_end_matches('/').to_string(), query)
+}
+
+type RouteResult = Result<serde_json::Value, (u16, String)>;
+
+fn to_value<T: Serialize>(value: T) -> RouteResult {
+    serde_json::to_value(value).map_err(|e| (500, e.to_string()))
+}
+
+fn db_error(e: rusqlite::Error) -> (u16, String) {
+    (500, e.to_string())
+}
+
+fn date_param(query: &HashMap<String, String>, key: &str) -> Result<Option<NaiveDate>, (u16, String)> {
+    query
+        .get(key)
+        .map(|s| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .map_err(|_| (400, format!("'{}' must be a YYYY-MM-DD date", key)))
+        })
+        .transpose()
+}
+
+/// `from`/`to` query parameters, both defaulting to today.
+fn date_range(query: &HashMap<String, String>) -> Result<(NaiveDate, NaiveDate), (u16, String)> {
+    let today = chrono::Local::now().date_naive();
+    let from = date_param(query, "from")?.unwrap_or(today);
+    let to = date_param(query, "to")?.unwrap_or(today);
+    if to < from {
+        return Err((400, "'to' is before 'from'".to_string()));
+    }
+    Ok((from, to))
+}
+
+fn route(path: &str, query: &HashMap<String, String>) -> RouteResult {
+    match path {
+        "/v1/status" => to_value(serde_json::json!({
+            "app": "TimiGS",
+            "version": env!("CARGO_PKG_VERSION"),
+            "tracking": is_tracking(),
+            "incognito": crate::privacy::is_incognito(),
+        })),
+        "/v1/current" => current(),
+        "/v1/summary" => {
+            let date = date_param(query, "date")?.unwrap_or_else(|| chrono::Local::now().date_naive());
+            let apps = db::get_summary_by_date(&date.to_string()).map_err(db_error)?;
+            let total_seconds: i64 = apps.iter().map(|a| a.total_seconds).sum();
+            to_value(serde_json::json!({
+                "date": date.to_string(),
+                "total_seconds": total_seconds,
+                "apps": apps,
+            }))
+        }
+        "/v1/sessions" => {
+            let (from, to) = date_range(query)?;
+            to_value(db::get_sessions_range(from, to).map_err(db_error)?)
+        }
+        "/v1/coding" => {
+            if query.contains_key("from") || query.contains_key("to") {
+                let (from, to) = date_range(query)?;
+                let sessions = db::get_coding_sessions_range(&from.to_string(), &to.to_string())
+                    .map_err(db_error)?;
+                return to_value(sessions);
+            }
+            to_value(serde_json::json!({
+                "total_seconds": db::get_total_coding_time_today().map_err(db_error)?,
+                "ai_seconds": db::get_total_ai_coding_time_today().map_err(db_error)?,
+                "languages": db::get_coding_stats_today().map_err(db_error)?,
+                "projects": db::get_coding_project_stats_today().map_err(db_error)?,
+            }))
+        }
+        "/v1/coding/repos" => {
+            let (from, to) = date_range(query)?;
+            to_value(db::get_coding_repo_stats(&from.to_string(), &to.to_string()).map_err(db_error)?)
+        }
+        "/v1/coding/branches" => {
+            let (from, to) = date_range(query)?;
+            let repo = query.get("repo").map(String::as_str);
+            to_value(
+                db::get_coding_branch_stats(&from.to_string(), &to.to_string(), repo)
+                    .map_err(db_error)?,
+            )
+        }
+        "/v1/music" => {
+            if query.contains_key("from") || query.contains_key("to") {
+                let (from, to) = date_range(query)?;
+                return to_value(db::get_music_sessions_range(from, to).map_err(db_error)?);
+            }
+            to_value(serde_json::json!({
+                "total_seconds": db::get_total_music_time_today().map_err(db_error)?,
+                "apps": db::get_today_music_summary().map_err(db_error)?,
+            }))
+        }
+        "/v1/productivity" => {
+            let date = date_param(query, "date")?.unwrap_or_else(|| chrono::Local::now().date_naive());
+            to_value(db::get_productivity_score(&date.to_string()).map_err(db_error)?)
+        }
+        "/v1/focus" => to_value(crate::focus::get_focus_status()),
+        "/v1/timeout" => to_value(crate::timeout::get_timeout_status()),
+        "/v1/incognito" => to_value(crate::privacy::incognito_status()),
+        "/v1/search" => {
+            let text = query.get("q").ok_or((400, "'q' is required".to_string()))?;
+            let limit = query
+                .get("limit")
+                .map(|l| l.parse().map_err(|_| (400, "'limit' must be a number".to_string())))
+                .transpose()?;
+            let search = db::SearchQuery {
+                query: text.clone(),
+                from: date_param(query, "from")?.map(|d| d.to_string()),
+                to: date_param(query, "to")?.map(|d| d.to_string()),
+                app_name: query.get("app").cloned(),
+                limit,
+            };
+            to_value(db::search_history(&search, ("<mark>", "</mark>")).map_err(db_error)?)
+        }
+        "/v1/shell/commands" => {
+            let (from, to) = date_range(query)?;
+            to_value(db::get_shell_commands(&from.to_string(), &to.to_string()).map_err(db_error)?)
+        }
+        _ => Err((404, format!("Unknown endpoint {}", path))),
+    }
+}
+
+#[derive(Deserialize)]
+struct FocusStartRequest {
+    app_name: String,
+    exe_path: String,
+    duration_secs: u64,
+    #[serde(default)]
+    password: String,
+}
+
+#[derive(Deserialize)]
+struct FocusStopRequest {
+    #[serde(default)]
+    password: String,
+}
+
+fn parse_body<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, (u16, String)> {
+    serde_json::from_str(body).map_err(|e| (400, format!("Invalid request body: {}", e)))
+}
+
+/// Fields of a shell hook event. Shells post them form-encoded with
+/// `curl --data-urlencode`; JSON objects are accepted too.
+fn parse_fields(body: &str) -> Result<HashMap<String, String>, (u16, String)> {
+    if !body.trim_start().starts_with('{') {
+        return Ok(url::form_urlencoded::parse(body.as_bytes()).into_owned().collect());
+    }
+    let object: serde_json::Map<String, serde_json::Value> = parse_body(body)?;
+    Ok(object
+        .into_iter()
+        .filter_map(|(key, value)| match value {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(s) => Some((key, s)),
+            other => Some((key, other.to_string())),
+        })
+        .collect())
+}
+
+fn text_field<'a>(fields: &'a HashMap<String, String>, key: &str) -> Option<&'a str> {
+    fields.get(key).map(|v| v.trim()).filter(|v| !v.is_empty())
+}
+
+fn int_field(fields: &HashMap<String, String>, key: &str) -> Result<Option<i64>, (u16, String)> {
+    text_field(fields, key)
+        .map(|v| v.parse().map_err(|_| (400, format!("'{}' must be an integer", key))))
+        .transpose()
+}
+
+/// `time` as Unix seconds (fractions allowed), defaulting to now.
+fn event_time(fields: &HashMap<String, String>) -> Result<chrono::DateTime<chrono::Local>, (u16, String)> {
+    use chrono::TimeZone;
+
+    let Some(time) = text_field(fields, "time") else {
+        return Ok(chrono::Local::now());
+    };
+    // bash's $EPOCHREALTIME follows the locale's decimal separator
+    time.replace(',', ".")
+        .parse::<f64>()
+        .ok()
+        .filter(|t| t.is_finite() && *t >= 0.0)
+        .and_then(|t| chrono::Local.timestamp_millis_opt((t * 1000.0) as i64).single())
+        .ok_or_else(|| (400, "'time' must be a Unix timestamp".to_string()))
+}
+
+fn control(path: &str, body: &str) -> RouteResult {
+    match path {
+        "/v1/tracking/pause" => {
+            set_tracking(false)?;
+            to_value(serde_json::json!({ "tracking": is_tracking() }))
+        }
+        "/v1/tracking/resume" => {
+            set_tracking(true)?;
+            to_value(serde_json::json!({ "tracking": is_tracking() }))
+        }
+        "/v1/focus/start" => {
+            let req: FocusStartRequest = parse_body(body)?;
+            crate::focus::start_focus(&req.app_name, &req.exe_path, req.duration_secs, &req.password)
+                .map_err(|e| (409, e))?;
+            to_value(crate::focus::get_focus_status())
+        }
+        "/v1/focus/stop" => {
+            let req: FocusStopRequest = parse_body(body)?;
+            crate::focus::stop_focus(&req.password).map_err(|e| (409, e))?;
+            to_value(crate::focus::get_focus_status())
+        }
+        "/v1/incognito/start" => {
+            let fields = parse_fields(body)?;
+            let until = int_field(&fields, "minutes")?
+                .filter(|m| *m > 0)
+                .map(|m| chrono::Local::now() + chrono::Duration::minutes(m));
+            crate::privacy::start_incognito(until).map_err(|e| (500, e))?;
+            to_value(crate::privacy::incognito_status())
+        }
+        "/v1/incognito/stop" => {
+            crate::privacy::stop_incognito().map_err(|e| (500, e))?;
+            to_value(crate::privacy::incognito_status())
+        }
+        _ if path.starts_with("/v1/shell/") && crate::privacy::is_incognito() => {
+            to_value(serde_json::json!({ "id": null }))
+        }
+        "/v1/shell/start" => {
+            let fields = parse_fields(body)?;
+            let command = text_field(&fields, "command")
+                .ok_or_else(|| (400, "'command' is required".to_string()))?;
+            let id = db::start_shell_command(
+                text_field(&fields, "session"),
+                text_field(&fields, "shell"),
+                command,
+                text_field(&fields, "cwd"),
+                event_time(&fields)?,
+            )
+            .map_err(db_error)?;
+            to_value(serde_json::json!({ "id": id }))
+        }
+        "/v1/shell/end" => {
+            let fields = parse_fields(body)?;
+            let id = db::end_shell_command(
+                text_field(&fields, "session"),
+                event_time(&fields)?,
+                int_field(&fields, "exit_code")?,
+                int_field(&fields, "duration_ms")?,
+            )
+            .map_err(db_error)?;
+            to_value(serde_json::json!({ "id": id }))
+        }
+        _ => Err((404, format!("Unknown endpoint {}", path))),
+    }
+}
+
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+fn set_tracking(enabled: bool) -> Result<(), (u16, String)> {
+    if enabled {
+        crate::tracker::start_tracking();
+    } else {
+        crate::tracker::stop_tracking();
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn set_tracking(_enabled: bool) -> Result<(), (u16, String)> {
+    Err((501, "Tracking is not supported on this platform".to_string()))
+}
+
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+fn is_tracking() -> bool {
+    crate::tracker::is_tracking()
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn is_tracking() -> bool {
+    false
+}
+
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+fn current() -> RouteResult {
+    to_value(serde_json::json!({
+        "activity": crate::tracker::get_current_session(),
+        "coding": crate::tracker::get_current_coding_session(),
+        "music": db::get_today_music_sessions()
+            .map_err(db_error)?
+            .into_iter()
+            .next()
+            .filter(|s| s.end_time.is_none()),
+    }))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn current() -> RouteResult {
+    to_value(serde_json::json!({ "activity": null, "coding": null, "music": null }))
+}
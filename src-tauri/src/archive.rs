@@ -0,0 +1,600 @@
+//! Full-database archives for moving TimiGS data between machines
+//!
+//! An archive is a JSON document with a manifest (format version, schema version and a
+//! row count and SHA-256 per table) followed by every table as a column list plus rows
+//! of raw SQLite values, so exporting and importing into an empty database reproduces
+//! it exactly, ids included. Only `cloud_accounts` is left out: its tokens are sealed
+//! with a key that never leaves this machine (see `vault`).
+//!
+//! On import, rows are matched to existing ones by a natural key (e.g. start time and
+//! executable for sessions, name and creation time for boards) because ids are only
+//! meaningful on the machine that assigned them. What happens to a match depends on
+//! the `ConflictMode`; board references are rewritten to wherever the board ended up.
+
+use crate::{db, migrations};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Local;
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+
+const ARCHIVE_FORMAT: &str = "timigs-archive";
+const ARCHIVE_VERSION: u32 = 1;
+/// Key of the JSON object that stands for a BLOB value.
+const BLOB_KEY: &str = "base64";
+
+struct TableSpec {
+    name: &'static str,
+    /// Columns that identify the same row on another machine.
+    key: &'static [&'static str],
+    /// The key is also a UNIQUE or PRIMARY KEY constraint, so two copies cannot coexist.
+    unique: bool,
+    /// `(column, table)`: the column holds an id of a table imported earlier.
+    parent: Option<(&'static str, &'static str)>,
+}
+
+const fn table(name: &'static str, key: &'static [&'static str], unique: bool) -> TableSpec {
+    TableSpec { name, key, unique, parent: None }
+}
+
+/// Every archived table, parents before the tables that point at them.
+const TABLES: [TableSpec; 16] = [
+    table("settings", &["key"], true),
+    table("categories", &["name"], true),
+    table("classification_rules", &["name", "created_at"], false),
+    table("tasks", &["app_name", "created_at"], false),
+    table("clients", &["name"], true),
+    TableSpec {
+        parent: Some(("client_id", "clients")),
+        ..table("project_boards", &["name", "created_at"], false)
+    },
+    TableSpec {
+        parent: Some(("board_id", "project_boards")),
+        ..table("board_items", &["board_id", "app_name", "date", "created_at"], false)
+    },
+    TableSpec {
+        parent: Some(("board_id", "project_boards")),
+        ..table("project_tasks", &["board_id", "title", "created_at"], false)
+    },
+    table("activity_sessions", &["start_time", "exe_path"], false),
+    table("music_sessions", &["start_time", "exe_path"], false),
+    table("coding_sessions", &["start_time", "exe_path"], false),
+    table("session_edits", &["created_at", "action"], false),
+    table("shell_commands", &["started_at", "command"], false),
+    table("browser_tabs", &["browser"], true),
+    table("daily_usage", &["date", "app_name", "exe_path", "window_title"], true),
+    table("focus_blocks", &["start_time", "kind"], false),
+];
+
+/// What to do with an archived row that matches one already in the database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictMode {
+    /// Keep the local row.
+    #[default]
+    Skip,
+    /// Replace the local row's values with the archived ones.
+    Overwrite,
+    /// Add the archived row next to the local one. Tables keyed by a unique
+    /// constraint (settings, categories, …) fall back to `Skip`.
+    KeepBoth,
+}
+
+impl ConflictMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "skip" => Some(ConflictMode::Skip),
+            "overwrite" => Some(ConflictMode::Overwrite),
+            "keep-both" | "keep_both" => Some(ConflictMode::KeepBoth),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableManifest {
+    pub name: String,
+    pub rows: usize,
+    /// Hex SHA-256 of the table's serialized `{"columns", "rows"}` object.
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    /// `PRAGMA user_version` of the exporting database.
+    pub schema_version: i64,
+    pub app_version: String,
+    pub created_at: String,
+    pub tables: Vec<TableManifest>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TableData {
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Archive {
+    pub manifest: Manifest,
+    tables: BTreeMap<String, TableData>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TableReport {
+    pub table: String,
+    pub inserted: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ImportReport {
+    pub inserted: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub tables: Vec<TableReport>,
+}
+
+fn checksum(table: &TableData) -> Result<String, String> {
+    let bytes = serde_json::to_vec(table).map_err(|e| e.to_string())?;
+    Ok(Sha256::digest(&bytes).iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn to_json(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::from(i),
+        ValueRef::Real(f) => serde_json::Number::from_f64(f).map_or(Value::Null, Value::Number),
+        ValueRef::Text(text) => Value::String(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(bytes) => {
+            serde_json::json!({ BLOB_KEY: general_purpose::STANDARD.encode(bytes) })
+        }
+    }
+}
+
+fn to_sql(value: &Value) -> Result<SqlValue, String> {
+    Ok(match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        Value::Object(map) => match map.get(BLOB_KEY).and_then(Value::as_str) {
+            Some(data) => SqlValue::Blob(general_purpose::STANDARD.decode(data).map_err(|e| e.to_string())?),
+            None => return Err(format!("unexpected value {}", value)),
+        },
+        Value::Array(_) => return Err(format!("unexpected value {}", value)),
+    })
+}
+
+fn table_exists(conn: &Connection, name: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        [name],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|count| count > 0)
+}
+
+fn read_table(conn: &Connection, name: &str) -> rusqlite::Result<TableData> {
+    let mut stmt = conn.prepare(&format!("SELECT * FROM {} ORDER BY rowid", name))?;
+    let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let rows = stmt
+        .query_map([], |row| {
+            (0..columns.len())
+                .map(|i| row.get_ref(i).map(to_json))
+                .collect::<rusqlite::Result<Vec<_>>>()
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(TableData { columns, rows })
+}
+
+fn export_in(conn: &Connection) -> Result<Archive, String> {
+    let mut tables = BTreeMap::new();
+    let mut manifest_tables = Vec::new();
+    for spec in &TABLES {
+        if !table_exists(conn, spec.name).map_err(|e| e.to_string())? {
+            continue;
+        }
+        let data = read_table(conn, spec.name).map_err(|e| e.to_string())?;
+        manifest_tables.push(TableManifest {
+            name: spec.name.to_string(),
+            rows: data.rows.len(),
+            sha256: checksum(&data)?,
+        });
+        tables.insert(spec.name.to_string(), data);
+    }
+
+    Ok(Archive {
+        manifest: Manifest {
+            format: ARCHIVE_FORMAT.to_string(),
+            version: ARCHIVE_VERSION,
+            schema_version: migrations::current_version(conn).map_err(|e| e.to_string())?,
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: Local::now().to_rfc3339(),
+            tables: manifest_tables,
+        },
+        tables,
+    })
+}
+
+/// Check the manifest against this build and the data against the manifest.
+fn verify_archive(archive: &Archive) -> Result<(), String> {
+    let manifest = &archive.manifest;
+    if manifest.format != ARCHIVE_FORMAT {
+        return Err(format!("not a TimiGS archive (format '{}')", manifest.format));
+    }
+    if manifest.version > ARCHIVE_VERSION {
+        return Err(format!(
+            "archive version {} is newer than supported ({})",
+            manifest.version, ARCHIVE_VERSION
+        ));
+    }
+    if manifest.schema_version > migrations::latest_version() {
+        return Err(format!(
+            "the archive was made by a newer TimiGS (schema {}, this one has {})",
+            manifest.schema_version,
+            migrations::latest_version()
+        ));
+    }
+
+    for name in archive.tables.keys() {
+        if !TABLES.iter().any(|spec| spec.name == name) {
+            return Err(format!("unknown table '{}'", name));
+        }
+        if !manifest.tables.iter().any(|t| &t.name == name) {
+            return Err(format!("table '{}' is missing from the manifest", name));
+        }
+    }
+    for expected in &manifest.tables {
+        let data = archive
+            .tables
+            .get(&expected.name)
+            .ok_or_else(|| format!("table '{}' is missing", expected.name))?;
+        if data.rows.len() != expected.rows || checksum(data)? != expected.sha256 {
+            return Err(format!("table '{}' does not match its checksum", expected.name));
+        }
+        if let Some(row) = data.rows.iter().find(|row| row.len() != data.columns.len()) {
+            return Err(format!("table '{}' has a malformed row: {:?}", expected.name, row));
+        }
+    }
+    Ok(())
+}
+
+fn local_columns(conn: &Connection, name: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", name))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(columns)
+}
+
+fn insert_row(conn: &Connection, table: &str, columns: &[&String], values: &[&SqlValue]) -> rusqlite::Result<i64> {
+    let names = columns.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(", ");
+    let marks = vec!["?"; columns.len()].join(", ");
+    conn.execute(
+        &format!("INSERT INTO {} ({}) VALUES ({})", table, names, marks),
+        params_from_iter(values),
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn import_table(
+    conn: &Connection,
+    spec: &TableSpec,
+    data: &TableData,
+    mode: ConflictMode,
+    id_maps: &mut HashMap<&'static str, HashMap<i64, i64>>,
+) -> Result<TableReport, String> {
+    let sql_error = |e: rusqlite::Error| format!("{}: {}", spec.name, e);
+    let local = local_columns(conn, spec.name).map_err(sql_error)?;
+    if let Some(column) = data.columns.iter().find(|c| !local.contains(c)) {
+        return Err(format!("{}: unknown column '{}'", spec.name, column));
+    }
+    let position = |name: &str| data.columns.iter().position(|c| c == name);
+    let id_index = position("id");
+    let parent = spec
+        .parent
+        .and_then(|(column, table)| Some((position(column)?, table)));
+    let key: Option<Vec<usize>> = spec.key.iter().map(|k| position(k)).collect();
+
+    let find_sql = spec
+        .key
+        .iter()
+        .map(|k| format!("{} IS ?", k))
+        .collect::<Vec<_>>()
+        .join(" AND ");
+    let find_sql = format!("SELECT rowid FROM {} WHERE {} LIMIT 1", spec.name, find_sql);
+    let update_sql = format!(
+        "UPDATE {} SET {} WHERE rowid = ?",
+        spec.name,
+        data.columns
+            .iter()
+            .filter(|c| c.as_str() != "id")
+            .map(|c| format!("{} = ?", c))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let mut report = TableReport {
+        table: spec.name.to_string(),
+        ..TableReport::default()
+    };
+    let mut id_map = HashMap::new();
+    for row in &data.rows {
+        let mut values = row.iter().map(to_sql).collect::<Result<Vec<_>, _>>()?;
+        if let Some((index, table)) = parent {
+            if let SqlValue::Integer(old) = values[index] {
+                if let Some(new) = id_maps.get(table).and_then(|map| map.get(&old)) {
+                    values[index] = SqlValue::Integer(*new);
+                }
+            }
+        }
+        let archived_id = id_index.and_then(|i| match values[i] {
+            SqlValue::Integer(id) => Some(id),
+            _ => None,
+        });
+
+        let existing: Option<i64> = match &key {
+            Some(key) => conn
+                .query_row(&find_sql, params_from_iter(key.iter().map(|&i| &values[i])), |r| r.get(0))
+                .optional()
+                .map_err(sql_error)?,
+            None => None,
+        };
+
+        let local_id = match (existing, mode) {
+            (Some(local_id), ConflictMode::Overwrite) => {
+                let mut params: Vec<&SqlValue> = data
+                    .columns
+                    .iter()
+                    .zip(&values)
+                    .filter(|(c, _)| c.as_str() != "id")
+                    .map(|(_, v)| v)
+                    .collect();
+                let rowid = SqlValue::Integer(local_id);
+                params.push(&rowid);
+                conn.execute(&update_sql, params_from_iter(params)).map_err(sql_error)?;
+                report.updated += 1;
+                local_id
+            }
+            (Some(local_id), ConflictMode::Skip) => {
+                report.skipped += 1;
+                local_id
+            }
+            (Some(local_id), ConflictMode::KeepBoth) if spec.unique => {
+                report.skipped += 1;
+                local_id
+            }
+            (None, _) | (Some(_), ConflictMode::KeepBoth) => {
+                // Keep the archived id unless something here already has it
+                let id_taken = match archived_id {
+                    Some(id) => conn
+                        .query_row(&format!("SELECT 1 FROM {} WHERE id = ?1", spec.name), [id], |_| Ok(()))
+                        .optional()
+                        .map_err(sql_error)?
+                        .is_some(),
+                    None => false,
+                };
+                let (columns, values): (Vec<&String>, Vec<&SqlValue>) = data
+                    .columns
+                    .iter()
+                    .zip(&values)
+                    .filter(|(c, _)| !(id_taken && c.as_str() == "id"))
+                    .unzip();
+                report.inserted += 1;
+                insert_row(conn, spec.name, &columns, &values).map_err(sql_error)?
+            }
+        };
+        if let Some(id) = archived_id {
+            id_map.insert(id, local_id);
+        }
+    }
+
+    id_maps.insert(spec.name, id_map);
+    Ok(report)
+}
+
+fn import_in(conn: &Connection, archive: &Archive, mode: ConflictMode) -> Result<ImportReport, String> {
+    verify_archive(archive)?;
+
+    let mut report = ImportReport::default();
+    let mut id_maps = HashMap::new();
+    for spec in &TABLES {
+        let Some(data) = archive.tables.get(spec.name) else {
+            continue;
+        };
+        let table = import_table(conn, spec, data, mode, &mut id_maps)?;
+        report.inserted += table.inserted;
+        report.updated += table.updated;
+        report.skipped += table.skipped;
+        report.tables.push(table);
+    }
+    Ok(report)
+}
+
+/// Write the whole database to `path`.
+pub fn export_archive(path: &str) -> Result<Manifest, String> {
+    let archive = db::with_connection(|conn| Ok(export_in(conn))).map_err(|e| e.to_string())??;
+    let file = std::fs::File::create(path).map_err(|e| format!("Cannot write {}: {}", path, e))?;
+    serde_json::to_writer(std::io::BufWriter::new(file), &archive).map_err(|e| e.to_string())?;
+    Ok(archive.manifest)
+}
+
+/// Read and verify an archive. `Ok(None)` when the file is JSON but not an archive,
+/// such as a session export.
+pub fn read_archive(path: &str) -> Result<Option<Archive>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+    let value: Value = serde_json::from_str(&content).map_err(|e| format!("{}: {}", path, e))?;
+    if value.pointer("/manifest/format").and_then(Value::as_str) != Some(ARCHIVE_FORMAT) {
+        return Ok(None);
+    }
+    let archive: Archive = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    verify_archive(&archive)?;
+    Ok(Some(archive))
+}
+
+/// Import an archive in one transaction: either every row lands or none does.
+pub fn import(archive: &Archive, mode: ConflictMode) -> Result<ImportReport, String> {
+    let import = || {
+        db::with_connection_mut(|conn| {
+            let tx = conn.transaction()?;
+            match import_in(&tx, archive, mode) {
+                Ok(report) => tx.commit().map(|_| Ok(report)),
+                Err(e) => Ok(Err(e)),
+            }
+        })
+        .map_err(|e| e.to_string())?
+    };
+    // Overwriting may rewrite the rows of sessions the tracker still has open
+    let report = match mode {
+        ConflictMode::Overwrite => crate::backup::with_tracker_paused(import)?,
+        _ => import()?,
+    };
+
+    crate::rules::invalidate();
+    crate::privacy::invalidate();
+    Ok(report)
+}
+
+pub fn import_archive(path: &str, mode: ConflictMode) -> Result<ImportReport, String> {
+    let archive = read_archive(path)?.ok_or("not a TimiGS archive")?;
+    import(&archive, mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::run_migrations(&mut conn, None).unwrap();
+        conn
+    }
+
+    fn fill(conn: &Connection) {
+        conn.execute_batch(
+            "INSERT INTO settings (key, value) VALUES ('language', 'de'), ('retention_days', '90');
+             INSERT INTO categories (name, productivity_weight, color) VALUES ('Reading', 0.75, NULL);
+             INSERT INTO tasks (app_name, description, goal_seconds, created_at, title_filter)
+                VALUES ('code', 'Write docs', 3600, '2024-05-01T08:00:00+02:00', NULL);
+             INSERT INTO project_boards (id, name, board_type, created_at) VALUES (5, 'Website', 'activity', '2024-05-01T08:00:00+02:00');
+             INSERT INTO board_items (board_id, app_name, window_title, tracked_seconds, date, created_at)
+                VALUES (5, 'code', 'index.html', 1200, '2024-05-01', '2024-05-01T09:00:00+02:00');
+             INSERT INTO project_tasks (board_id, title, status, created_at) VALUES (5, 'Launch', 'todo', '2024-05-01T08:05:00+02:00');
+             INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, end_time, duration_seconds, url)
+                VALUES ('firefox', 'Docs', '/usr/bin/firefox', '2024-05-01T09:00:00+02:00', '2024-05-01T09:30:00+02:00', 1800, 'https://docs.rs/');
+             INSERT INTO music_sessions (app_name, window_title, exe_path, start_time, duration_seconds)
+                VALUES ('spotify', 'Song', '/usr/bin/spotify', '2024-05-01T10:00:00+02:00', 0);
+             INSERT INTO daily_usage (date, app_name, exe_path, window_title, total_seconds, session_count)
+                VALUES ('2024-01-02', 'code', '/usr/bin/code', 'main.rs', 5400, 3);",
+        )
+        .unwrap();
+    }
+
+    /// Through JSON text and back, as a file would.
+    fn reparse(archive: &Archive) -> Archive {
+        serde_json::from_str(&serde_json::to_string(archive).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = database();
+        fill(&source);
+        let archive = reparse(&export_in(&source).unwrap());
+        assert_eq!(archive.manifest.schema_version, migrations::latest_version());
+        assert!(!archive.tables.contains_key("cloud_accounts"));
+
+        let target = database();
+        let report = import_in(&target, &archive, ConflictMode::Skip).unwrap();
+        // The default categories exist on both sides
+        assert_eq!(report.skipped, 10);
+
+        let copy = export_in(&target).unwrap();
+        assert_eq!(copy.tables, archive.tables);
+        assert_eq!(copy.manifest.tables, archive.manifest.tables);
+
+        // Importing again matches every row
+        let again = import_in(&target, &archive, ConflictMode::Skip).unwrap();
+        assert_eq!(again.inserted, 0);
+        assert_eq!(again.skipped, archive.manifest.tables.iter().map(|t| t.rows).sum::<usize>());
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let source = database();
+        fill(&source);
+        let archive = export_in(&source).unwrap();
+
+        // Another machine: the board's id is taken and the session was renamed
+        let target = database();
+        target
+            .execute_batch(
+                "INSERT INTO project_boards (id, name, created_at) VALUES (5, 'Other', '2024-04-01T08:00:00+02:00');
+                 INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, duration_seconds)
+                    VALUES ('firefox', 'Renamed', '/usr/bin/firefox', '2024-05-01T09:00:00+02:00', 60);",
+            )
+            .unwrap();
+        let title = |conn: &Connection| -> Vec<String> {
+            let mut stmt = conn.prepare("SELECT window_title FROM activity_sessions ORDER BY id").unwrap();
+            stmt.query_map([], |r| r.get(0)).unwrap().map(Result::unwrap).collect()
+        };
+
+        let report = import_in(&target, &archive, ConflictMode::Skip).unwrap();
+        let sessions = report.tables.iter().find(|t| t.table == "activity_sessions").unwrap();
+        assert_eq!((sessions.inserted, sessions.skipped), (0, 1));
+        assert_eq!(title(&target), ["Renamed"]);
+
+        // The board got a new id and its items and tasks followed it
+        let board: i64 = target
+            .query_row("SELECT id FROM project_boards WHERE name = 'Website'", [], |r| r.get(0))
+            .unwrap();
+        assert_ne!(board, 5);
+        let children: i64 = target
+            .query_row(
+                "SELECT (SELECT count(*) FROM board_items WHERE board_id = ?1) + (SELECT count(*) FROM project_tasks WHERE board_id = ?1)",
+                [board],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(children, 2);
+
+        import_in(&target, &archive, ConflictMode::Overwrite).unwrap();
+        assert_eq!(title(&target), ["Docs"]);
+        let duration: i64 = target
+            .query_row("SELECT duration_seconds FROM activity_sessions", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(duration, 1800);
+
+        import_in(&target, &archive, ConflictMode::KeepBoth).unwrap();
+        assert_eq!(title(&target), ["Docs", "Docs"]);
+        let language: String = target
+            .query_row("SELECT value FROM settings WHERE key = 'language'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(language, "de");
+    }
+
+    #[test]
+    fn test_verify_archive() {
+        let source = database();
+        fill(&source);
+        let archive = export_in(&source).unwrap();
+        verify_archive(&archive).unwrap();
+
+        let mut tampered = archive.clone();
+        tampered.tables.get_mut("tasks").unwrap().rows[0][2] = Value::from("Write tests");
+        assert!(verify_archive(&tampered).unwrap_err().contains("tasks"));
+
+        let mut newer = archive;
+        newer.manifest.schema_version = migrations::latest_version() + 1;
+        assert!(verify_archive(&newer).is_err());
+    }
+}
@@ -0,0 +1,364 @@
+//! Local snapshots of `activity.db`
+//!
+//! Snapshots are taken with SQLite's online backup API, so they are consistent even
+//! while the tracker writes, and land in `backups/` next to the database as
+//! `activity-<YYYY-MM-DD_HH-MM-SS>-<reason>.db`. A snapshot only gets its final name
+//! once `PRAGMA integrity_check` passed on the copy. An encrypted database is copied
+//! with its own key, so snapshots are exactly as protected as the original.
+//!
+//! Scheduled (`auto`) snapshots are thinned out: the newest one of each of the last
+//! `KEEP_DAILY` days, `KEEP_WEEKLY` weeks and `KEEP_MONTHLY` months that have any are
+//! kept. Snapshots taken by hand or before a reset or restore are never rotated away.
+
+use crate::{db, migrations, vault};
+use chrono::{Local, NaiveDateTime, Timelike};
+use rusqlite::{backup::Backup, Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const DEFAULT_INTERVAL_HOURS: u32 = 24;
+const KEEP_DAILY: usize = 7;
+const KEEP_WEEKLY: usize = 4;
+const KEEP_MONTHLY: usize = 12;
+
+const BACKUP_DIR: &str = "backups";
+const NAME_PREFIX: &str = "activity-";
+const NAME_TIME_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+const PAGES_PER_STEP: i32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Reason {
+    /// Taken on the `backup_interval_hours` schedule; subject to rotation.
+    Auto,
+    Manual,
+    PreReset,
+    PreRestore,
+}
+
+impl Reason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Reason::Auto => "auto",
+            Reason::Manual => "manual",
+            Reason::PreReset => "pre-reset",
+            Reason::PreRestore => "pre-restore",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(Reason::Auto),
+            "manual" => Some(Reason::Manual),
+            "pre-reset" => Some(Reason::PreReset),
+            "pre-restore" => Some(Reason::PreRestore),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Snapshot {
+    /// File name inside the backup directory; also what `restore_snapshot` takes.
+    pub name: String,
+    pub path: String,
+    /// Local time the snapshot was taken.
+    pub created: NaiveDateTime,
+    pub reason: Reason,
+    pub size_bytes: u64,
+}
+
+fn snapshot_name(created: NaiveDateTime, reason: Reason) -> String {
+    format!(
+        "{}{}-{}.db",
+        NAME_PREFIX,
+        created.format(NAME_TIME_FORMAT),
+        reason.as_str()
+    )
+}
+
+fn parse_snapshot_name(name: &str) -> Option<(NaiveDateTime, Reason)> {
+    let rest = name.strip_prefix(NAME_PREFIX)?.strip_suffix(".db")?;
+    let (time, reason) = (rest.get(..19)?, rest.get(19..)?.strip_prefix('-')?);
+    let created = NaiveDateTime::parse_from_str(time, NAME_TIME_FORMAT).ok()?;
+    Some((created, Reason::parse(reason)?))
+}
+
+pub fn backup_dir() -> PathBuf {
+    db::get_db_path().with_file_name(BACKUP_DIR)
+}
+
+fn read_snapshot(path: &Path) -> Option<Snapshot> {
+    let name = path.file_name()?.to_str()?.to_string();
+    let (created, reason) = parse_snapshot_name(&name)?;
+    Some(Snapshot {
+        size_bytes: std::fs::metadata(path).ok()?.len(),
+        path: path.display().to_string(),
+        name,
+        created,
+        reason,
+    })
+}
+
+/// All snapshots, newest first.
+pub fn list_snapshots() -> Result<Vec<Snapshot>, String> {
+    let dir = backup_dir();
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Cannot read {}: {}", dir.display(), e)),
+    };
+    let mut snapshots: Vec<Snapshot> = entries
+        .flatten()
+        .filter_map(|entry| read_snapshot(&entry.path()))
+        .collect();
+    snapshots.sort_by_key(|s| std::cmp::Reverse(s.created));
+    Ok(snapshots)
+}
+
+/// Copy every page of `from` into `to`, replacing what `to` held.
+fn copy_into(from: &Connection, to: &mut Connection) -> rusqlite::Result<()> {
+    Backup::new(from, to)?.run_to_completion(PAGES_PER_STEP, Duration::ZERO, None)
+}
+
+fn verify(conn: &Connection) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare("PRAGMA integrity_check")
+        .map_err(|e| e.to_string())?;
+    let problems = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+        .map_err(|e| e.to_string())?;
+    if problems == ["ok"] {
+        Ok(())
+    } else {
+        Err(format!("Integrity check failed: {}", problems.join("; ")))
+    }
+}
+
+/// Snapshot the open database now.
+pub fn create_snapshot(reason: Reason) -> Result<Snapshot, String> {
+    let db_path = db::get_db_path();
+    let dir = db_path.with_file_name(BACKUP_DIR);
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+
+    let created = Local::now().naive_local().with_nanosecond(0).unwrap_or_default();
+    let path = dir.join(snapshot_name(created, reason));
+    let partial = path.with_extension("db.partial");
+    let _ = std::fs::remove_file(&partial);
+
+    let copied = (|| {
+        let mut target = Connection::open(&partial).map_err(|e| e.to_string())?;
+        vault::unlock(&target, &db_path).map_err(|e| e.to_string())?;
+        db::with_connection(|conn| copy_into(conn, &mut target)).map_err(|e| e.to_string())?;
+        verify(&target)
+    })();
+    if let Err(e) = copied {
+        let _ = std::fs::remove_file(&partial);
+        return Err(format!("Backup failed: {}", e));
+    }
+
+    std::fs::rename(&partial, &path).map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    read_snapshot(&path).ok_or_else(|| format!("Cannot read {}", path.display()))
+}
+
+/// Scheduled snapshots that fall outside the daily/weekly/monthly rotation.
+fn expired(snapshots: &[Snapshot]) -> Vec<&Snapshot> {
+    let mut auto: Vec<&Snapshot> = snapshots.iter().filter(|s| s.reason == Reason::Auto).collect();
+    auto.sort_by_key(|s| std::cmp::Reverse(s.created));
+
+    let mut keep = vec![false; auto.len()];
+    for (period, count) in [("%Y-%m-%d", KEEP_DAILY), ("%G-W%V", KEEP_WEEKLY), ("%Y-%m", KEEP_MONTHLY)] {
+        // Newest first, so the first snapshot of each period is the one to keep
+        let mut periods: Vec<String> = Vec::new();
+        for (i, snapshot) in auto.iter().enumerate() {
+            let key = snapshot.created.format(period).to_string();
+            if periods.last() == Some(&key) {
+                continue;
+            }
+            if periods.len() == count {
+                break;
+            }
+            periods.push(key);
+            keep[i] = true;
+        }
+    }
+
+    auto.into_iter()
+        .zip(keep)
+        .filter(|(_, keep)| !keep)
+        .map(|(snapshot, _)| snapshot)
+        .collect()
+}
+
+/// Delete the scheduled snapshots the rotation no longer needs.
+pub fn rotate() -> Result<Vec<Snapshot>, String> {
+    let snapshots = list_snapshots()?;
+    let mut removed = Vec::new();
+    for snapshot in expired(&snapshots) {
+        std::fs::remove_file(&snapshot.path)
+            .map_err(|e| format!("Cannot delete {}: {}", snapshot.path, e))?;
+        removed.push(snapshot.clone());
+    }
+    Ok(removed)
+}
+
+/// Background snapshot, driven by the `backup_enabled` and `backup_interval_hours` settings.
+pub fn snapshot_if_needed() -> Result<Option<Snapshot>, String> {
+    let settings = db::get_settings();
+    if !settings.backup_enabled {
+        return Ok(None);
+    }
+
+    let last_auto = list_snapshots()?
+        .into_iter()
+        .find(|s| s.reason == Reason::Auto)
+        .map(|s| s.created);
+    let due = match last_auto {
+        None => true,
+        Some(last) => {
+            let elapsed = Local::now().naive_local().signed_duration_since(last).num_hours();
+            elapsed >= settings.backup_interval_hours as i64
+        }
+    };
+    if !due {
+        return Ok(None);
+    }
+
+    let snapshot = create_snapshot(Reason::Auto)?;
+    rotate()?;
+    Ok(Some(snapshot))
+}
+
+/// Run `replace` with the tracker's open sessions ended and the tracker held until
+/// it returns, for anything that swaps out rows the tracker may still be writing to.
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+pub fn with_tracker_paused<T>(replace: impl FnOnce() -> T) -> T {
+    crate::tracker::with_sessions_closed(replace)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn with_tracker_paused<T>(replace: impl FnOnce() -> T) -> T {
+    replace()
+}
+
+/// Replace the contents of the open database with the snapshot called `name`.
+///
+/// The tracker's open sessions are ended and the current data is snapshotted first
+/// (`pre-restore`); that snapshot is returned. Snapshots from an older schema are migrated after the copy.
+pub fn restore_snapshot(name: &str) -> Result<Snapshot, String> {
+    let snapshot = list_snapshots()?
+        .into_iter()
+        .find(|s| s.name == name)
+        .ok_or_else(|| format!("No backup named '{}'", name))?;
+
+    let db_path = db::get_db_path();
+    let source = Connection::open_with_flags(&snapshot.path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| e.to_string())?;
+    vault::unlock(&source, &db_path).map_err(|e| {
+        format!("Cannot read {} with the current encryption settings: {}", name, e)
+    })?;
+    verify(&source).map_err(|e| format!("{} is damaged. {}", name, e))?;
+    let version = migrations::current_version(&source).map_err(|e| e.to_string())?;
+    if version > migrations::latest_version() {
+        return Err(format!("{} was made by a newer version of TimiGS", name));
+    }
+
+    let safety = with_tracker_paused(|| {
+        let safety = create_snapshot(Reason::PreRestore)?;
+        db::with_connection_mut(|conn| {
+            copy_into(&source, conn)?;
+            migrations::run_migrations(conn, None)?;
+            // Whatever the tracker had open when the snapshot was taken
+            db::recover_dangling_sessions(conn)?;
+            Ok(())
+        })
+        .map_err(|e| format!("Restore failed, the previous data is in {}: {}", safety.name, e))?;
+        Ok::<_, String>(safety)
+    })?;
+
+    crate::rules::invalidate();
+    crate::privacy::invalidate();
+    Ok(safety)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(created: &str, reason: Reason) -> Snapshot {
+        let created = NaiveDateTime::parse_from_str(created, "%Y-%m-%d %H:%M").unwrap();
+        Snapshot {
+            name: snapshot_name(created, reason),
+            path: String::new(),
+            created,
+            reason,
+            size_bytes: 0,
+        }
+    }
+
+    #[test]
+    fn test_snapshot_names() {
+        let s = snapshot("2026-03-09 14:05", Reason::PreReset);
+        assert_eq!(s.name, "activity-2026-03-09_14-05-00-pre-reset.db");
+        assert_eq!(parse_snapshot_name(&s.name), Some((s.created, Reason::PreReset)));
+        assert_eq!(parse_snapshot_name("activity-2026-03-09_14-05-00-auto.db.partial"), None);
+        assert_eq!(parse_snapshot_name("activity.db.v3.bak"), None);
+    }
+
+    #[test]
+    fn test_rotation() {
+        let mut snapshots = Vec::new();
+        // Two a day for 60 days, plus one kept for a reason
+        for day in 0..60 {
+            let date = NaiveDateTime::parse_from_str("2026-01-01 00:00", "%Y-%m-%d %H:%M").unwrap()
+                + chrono::Duration::days(day);
+            for hour in ["09:00", "21:00"] {
+                let created = format!("{} {}", date.format("%Y-%m-%d"), hour);
+                snapshots.push(snapshot(&created, Reason::Auto));
+            }
+        }
+        snapshots.push(snapshot("2026-01-01 10:00", Reason::PreReset));
+
+        let expired: Vec<NaiveDateTime> = expired(&snapshots).iter().map(|s| s.created).collect();
+        let kept: Vec<&Snapshot> = snapshots
+            .iter()
+            .filter(|s| !expired.contains(&s.created))
+            .collect();
+
+        // Evenings of Feb 23..Mar 1, the last Sunday evenings of three more weeks,
+        // the last day of January, and the manual one
+        let newest = NaiveDateTime::parse_from_str("2026-03-01 21:00", "%Y-%m-%d %H:%M").unwrap();
+        assert!(kept.iter().any(|s| s.created == newest));
+        assert_eq!(kept.iter().filter(|s| s.reason == Reason::Auto).count(), 7 + 3 + 1);
+        assert!(kept.iter().all(|s| s.created.format("%H:%M").to_string() == "21:00"
+            || s.reason == Reason::PreReset));
+        assert!(kept
+            .iter()
+            .any(|s| s.created.format("%Y-%m-%d").to_string() == "2026-01-31"));
+    }
+
+    #[test]
+    fn test_copy_and_verify() {
+        let source = Connection::open_in_memory().unwrap();
+        source
+            .execute_batch(
+                "CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (1), (2);
+                 PRAGMA user_version = 7;",
+            )
+            .unwrap();
+
+        let path = std::env::temp_dir().join(format!("timigs-backup-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut target = Connection::open(&path).unwrap();
+        copy_into(&source, &mut target).unwrap();
+        verify(&target).unwrap();
+
+        let count: i64 = target.query_row("SELECT count(*) FROM t", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(migrations::current_version(&target).unwrap(), 7);
+        drop(target);
+        let _ = std::fs::remove_file(&path);
+    }
+}
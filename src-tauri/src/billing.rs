@@ -0,0 +1,453 @@
+//! Timesheets and invoices from project boards
+//!
+//! Board items hold tracked seconds per app and day. For billing, each board's items
+//! are summed per day, the day is rounded by the board's rule (e.g. up to the next 6
+//! or 15 minutes) and priced at the board's hourly rate, or its client's. The result
+//! is written as a CSV timesheet or a printable HTML invoice.
+
+use crate::db::{self, BoardItem, Client, ProjectBoard};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoundingMode {
+    #[default]
+    Up,
+    Nearest,
+    Down,
+}
+
+impl RoundingMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RoundingMode::Up => "up",
+            RoundingMode::Nearest => "nearest",
+            RoundingMode::Down => "down",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "up" => Some(RoundingMode::Up),
+            "nearest" => Some(RoundingMode::Nearest),
+            "down" => Some(RoundingMode::Down),
+            _ => None,
+        }
+    }
+}
+
+/// `seconds` rounded to a multiple of `minutes`; 0 minutes leaves them as they are.
+pub fn round_seconds(seconds: i64, minutes: i64, mode: RoundingMode) -> i64 {
+    if minutes <= 0 || seconds <= 0 {
+        return seconds.max(0);
+    }
+    let step = minutes * 60;
+    let units = match mode {
+        RoundingMode::Up => (seconds + step - 1) / step,
+        RoundingMode::Nearest => (seconds + step / 2) / step,
+        RoundingMode::Down => seconds / step,
+    };
+    units * step
+}
+
+fn amount_cents(seconds: i64, hourly_rate: f64) -> i64 {
+    (seconds as f64 * hourly_rate * 100.0 / 3600.0).round() as i64
+}
+
+/// One board on one day.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimesheetLine {
+    pub date: String,
+    pub board_id: i64,
+    pub board: String,
+    pub client: Option<String>,
+    /// Apps tracked on the board that day, most time first.
+    pub apps: Vec<String>,
+    pub tracked_seconds: i64,
+    pub billed_seconds: i64,
+    /// `None` when neither the board nor its client has a rate.
+    pub hourly_rate: Option<f64>,
+    pub currency: String,
+    pub amount_cents: i64,
+}
+
+/// One board over the whole period, for the invoice.
+#[derive(Debug, Clone, Serialize)]
+pub struct InvoiceLine {
+    pub board: String,
+    pub billed_seconds: i64,
+    pub hourly_rate: Option<f64>,
+    pub currency: String,
+    pub amount_cents: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Total {
+    pub currency: String,
+    pub billed_seconds: i64,
+    pub amount_cents: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Timesheet {
+    pub from: String,
+    pub to: String,
+    /// Set when the timesheet is for one client.
+    pub client: Option<Client>,
+    pub lines: Vec<TimesheetLine>,
+    pub summary: Vec<InvoiceLine>,
+    /// One per currency.
+    pub totals: Vec<Total>,
+    pub tracked_seconds: i64,
+    pub billed_seconds: i64,
+}
+
+/// Seconds tracked on a board on one day, in total and per app.
+type Day<'a> = (i64, Vec<(&'a str, i64)>);
+
+/// Build a timesheet from board items already limited to the date range.
+pub fn build(
+    from: &str,
+    to: &str,
+    client_id: Option<i64>,
+    boards: &[ProjectBoard],
+    clients: &[Client],
+    items: &[BoardItem],
+    default_currency: &str,
+) -> Timesheet {
+    let client_of = |board: &ProjectBoard| board.client_id.and_then(|id| clients.iter().find(|c| c.id == id));
+
+    let mut days: BTreeMap<(&str, i64), Day> = BTreeMap::new();
+    for item in items {
+        let day = days.entry((item.date.as_str(), item.board_id)).or_default();
+        day.0 += item.tracked_seconds;
+        match day.1.iter_mut().find(|(app, _)| *app == item.app_name) {
+            Some((_, seconds)) => *seconds += item.tracked_seconds,
+            None => day.1.push((&item.app_name, item.tracked_seconds)),
+        }
+    }
+
+    let mut lines = Vec::new();
+    for ((date, board_id), (tracked, mut apps)) in days {
+        let Some(board) = boards.iter().find(|b| b.id == board_id) else {
+            continue;
+        };
+        let client = client_of(board);
+        if client_id.is_some() && client.map(|c| c.id) != client_id {
+            continue;
+        }
+        let mode = RoundingMode::parse(&board.rounding_mode).unwrap_or_default();
+        let billed = round_seconds(tracked, board.rounding_minutes, mode);
+        let hourly_rate = board.hourly_rate.or(client.and_then(|c| c.hourly_rate));
+        apps.sort_by_key(|(_, seconds)| std::cmp::Reverse(*seconds));
+        lines.push(TimesheetLine {
+            date: date.to_string(),
+            board_id,
+            board: board.name.clone(),
+            client: client.map(|c| c.name.clone()),
+            apps: apps.into_iter().map(|(app, _)| app.to_string()).collect(),
+            tracked_seconds: tracked,
+            billed_seconds: billed,
+            hourly_rate,
+            currency: client.map_or(default_currency, |c| c.currency.as_str()).to_string(),
+            amount_cents: hourly_rate.map_or(0, |rate| amount_cents(billed, rate)),
+        });
+    }
+
+    let mut summary: Vec<InvoiceLine> = Vec::new();
+    let mut totals: Vec<Total> = Vec::new();
+    for line in &lines {
+        match summary.iter_mut().find(|s| s.board == line.board && s.hourly_rate == line.hourly_rate) {
+            Some(entry) => {
+                entry.billed_seconds += line.billed_seconds;
+                entry.amount_cents += line.amount_cents;
+            }
+            None => summary.push(InvoiceLine {
+                board: line.board.clone(),
+                billed_seconds: line.billed_seconds,
+                hourly_rate: line.hourly_rate,
+                currency: line.currency.clone(),
+                amount_cents: line.amount_cents,
+            }),
+        }
+        match totals.iter_mut().find(|t| t.currency == line.currency) {
+            Some(total) => {
+                total.billed_seconds += line.billed_seconds;
+                total.amount_cents += line.amount_cents;
+            }
+            None => totals.push(Total {
+                currency: line.currency.clone(),
+                billed_seconds: line.billed_seconds,
+                amount_cents: line.amount_cents,
+            }),
+        }
+    }
+
+    Timesheet {
+        from: from.to_string(),
+        to: to.to_string(),
+        client: client_id.and_then(|id| clients.iter().find(|c| c.id == id).cloned()),
+        tracked_seconds: lines.iter().map(|l| l.tracked_seconds).sum(),
+        billed_seconds: lines.iter().map(|l| l.billed_seconds).sum(),
+        lines,
+        summary,
+        totals,
+    }
+}
+
+/// The timesheet for a date range, for one client or all boards.
+pub fn timesheet(from: &str, to: &str, client_id: Option<i64>) -> Result<Timesheet, String> {
+    let clients = db::get_clients().map_err(|e| e.to_string())?;
+    if let Some(id) = client_id {
+        if !clients.iter().any(|c| c.id == id) {
+            return Err(format!("No client with id {}", id));
+        }
+    }
+    let boards = db::get_boards().map_err(|e| e.to_string())?;
+    let items = db::get_board_items_range(from, to).map_err(|e| e.to_string())?;
+    let currency = db::get_settings().billing_currency;
+    Ok(build(from, to, client_id, &boards, &clients, &items, &currency))
+}
+
+fn hours(seconds: i64) -> String {
+    format!("{:.2}", seconds as f64 / 3600.0)
+}
+
+fn money(cents: i64, currency: &str) -> String {
+    format!("{}.{:02} {}", cents / 100, cents % 100, currency)
+}
+
+pub fn timesheet_csv(sheet: &Timesheet) -> Result<String, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let mut write = |record: &[String]| writer.write_record(record).map_err(|e| e.to_string());
+    write(
+        &[
+            "Date", "Client", "Board", "Apps", "Tracked (h)", "Billed (h)", "Rate", "Currency", "Amount",
+        ]
+        .map(String::from),
+    )?;
+    for line in &sheet.lines {
+        write(&[
+            line.date.clone(),
+            line.client.clone().unwrap_or_default(),
+            line.board.clone(),
+            line.apps.join(", "),
+            hours(line.tracked_seconds),
+            hours(line.billed_seconds),
+            line.hourly_rate.map(|r| format!("{:.2}", r)).unwrap_or_default(),
+            line.currency.clone(),
+            format!("{}.{:02}", line.amount_cents / 100, line.amount_cents % 100),
+        ])?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A printable invoice: a line per board and rate, the totals, and the daily timesheet.
+pub fn invoice_html(sheet: &Timesheet, number: &str, issuer: &str) -> String {
+    let multiline = |s: &str| escape(s).replace('\n', "<br>");
+    let mut html = String::from(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+"#,
+    );
+    html.push_str(&format!("<title>Invoice {}</title>\n", escape(number)));
+    html.push_str(
+        r#"<style>
+  body { font-family: -apple-system, "Segoe UI", Roboto, sans-serif; color: #1f2328; max-width: 820px; margin: 40px auto; padding: 0 24px; }
+  h1 { font-size: 28px; margin: 0 0 4px; }
+  .meta { color: #59636e; margin-bottom: 32px; }
+  .parties { display: flex; gap: 48px; margin-bottom: 32px; }
+  .parties h3 { font-size: 12px; text-transform: uppercase; color: #59636e; margin: 0 0 6px; }
+  table { width: 100%; border-collapse: collapse; margin-bottom: 28px; font-size: 14px; }
+  th, td { text-align: left; padding: 8px 6px; border-bottom: 1px solid #d1d9e0; }
+  th { font-size: 12px; text-transform: uppercase; color: #59636e; }
+  td.num, th.num { text-align: right; font-variant-numeric: tabular-nums; }
+  tr.total td { font-weight: 600; border-bottom: none; }
+  h2 { font-size: 16px; margin: 32px 0 8px; }
+  @media print { body { margin: 0; } h2 { break-before: page; } }
+</style>
+</head>
+<body>
+"#,
+    );
+    html.push_str(&format!(
+        "<h1>Invoice {}</h1>\n<div class=\"meta\">Period {} – {} · Issued {}</div>\n",
+        escape(number),
+        sheet.from,
+        sheet.to,
+        chrono::Local::now().format("%Y-%m-%d")
+    ));
+
+    html.push_str("<div class=\"parties\">\n");
+    if !issuer.trim().is_empty() {
+        html.push_str(&format!("<div><h3>From</h3>{}</div>\n", multiline(issuer.trim())));
+    }
+    if let Some(client) = &sheet.client {
+        let mut bill_to = escape(&client.name);
+        for extra in [&client.address, &client.email].into_iter().flatten() {
+            bill_to.push_str("<br>");
+            bill_to.push_str(&multiline(extra));
+        }
+        html.push_str(&format!("<div><h3>Bill to</h3>{}</div>\n", bill_to));
+    }
+    html.push_str("</div>\n");
+
+    html.push_str(
+        "<table>\n<tr><th>Project</th><th class=\"num\">Hours</th><th class=\"num\">Rate</th><th class=\"num\">Amount</th></tr>\n",
+    );
+    for line in &sheet.summary {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>\n",
+            escape(&line.board),
+            hours(line.billed_seconds),
+            line.hourly_rate
+                .map(|r| format!("{:.2} {}", r, escape(&line.currency)))
+                .unwrap_or_else(|| "—".to_string()),
+            money(line.amount_cents, &escape(&line.currency)),
+        ));
+    }
+    for total in &sheet.totals {
+        html.push_str(&format!(
+            "<tr class=\"total\"><td>Total</td><td class=\"num\">{}</td><td></td><td class=\"num\">{}</td></tr>\n",
+            hours(total.billed_seconds),
+            money(total.amount_cents, &escape(&total.currency)),
+        ));
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Timesheet</h2>\n<table>\n<tr><th>Date</th><th>Project</th><th>Apps</th><th class=\"num\">Tracked</th><th class=\"num\">Billed</th></tr>\n");
+    for line in &sheet.lines {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>\n",
+            line.date,
+            escape(&line.board),
+            escape(&line.apps.join(", ")),
+            hours(line.tracked_seconds),
+            hours(line.billed_seconds),
+        ));
+    }
+    html.push_str(&format!(
+        "<tr class=\"total\"><td>Total</td><td></td><td></td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>\n</table>\n",
+        hours(sheet.tracked_seconds),
+        hours(sheet.billed_seconds),
+    ));
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Write the timesheet as `csv` or an `html` invoice. `number` defaults to
+/// `INV-<end date>`.
+pub fn export(
+    path: &str,
+    format: &str,
+    from: &str,
+    to: &str,
+    client_id: Option<i64>,
+    number: Option<&str>,
+) -> Result<Timesheet, String> {
+    let sheet = timesheet(from, to, client_id)?;
+    let content = match format {
+        "csv" => timesheet_csv(&sheet)?,
+        "html" => {
+            let number = number
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("INV-{}", to.replace('-', "")));
+            invoice_html(&sheet, &number, &db::get_settings().invoice_issuer)
+        }
+        other => return Err(format!("Unknown timesheet format '{}' (use csv or html)", other)),
+    };
+    std::fs::write(path, content).map_err(|e| format!("Cannot write {}: {}", path, e))?;
+    Ok(sheet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_seconds() {
+        assert_eq!(round_seconds(61, 6, RoundingMode::Up), 360);
+        assert_eq!(round_seconds(360, 6, RoundingMode::Up), 360);
+        assert_eq!(round_seconds(7 * 60 + 29, 15, RoundingMode::Nearest), 0);
+        assert_eq!(round_seconds(7 * 60 + 30, 15, RoundingMode::Nearest), 900);
+        assert_eq!(round_seconds(899, 15, RoundingMode::Down), 0);
+        assert_eq!(round_seconds(1234, 0, RoundingMode::Up), 1234);
+    }
+
+    #[test]
+    fn test_build_timesheet() {
+        let board = |id: i64, name: &str, client_id: Option<i64>, rate: Option<f64>| ProjectBoard {
+            id,
+            name: name.to_string(),
+            board_type: "activity".to_string(),
+            github_project_id: None,
+            github_project_url: None,
+            synced_at: None,
+            created_at: "2024-05-01T09:00:00+00:00".to_string(),
+            client_id,
+            hourly_rate: rate,
+            rounding_minutes: 15,
+            rounding_mode: "up".to_string(),
+        };
+        let item = |board_id: i64, app: &str, seconds: i64, date: &str| BoardItem {
+            id: 0,
+            board_id,
+            app_name: app.to_string(),
+            window_title: None,
+            tracked_seconds: seconds,
+            status: "recorded".to_string(),
+            date: date.to_string(),
+            created_at: String::new(),
+        };
+        let acme = Client {
+            id: 1,
+            name: "Acme".to_string(),
+            email: None,
+            address: Some("1 Road\nTown".to_string()),
+            currency: "EUR".to_string(),
+            hourly_rate: Some(80.0),
+            created_at: String::new(),
+        };
+        let boards = [board(1, "Website", Some(1), None), board(2, "Internal", None, None)];
+        let items = [
+            item(1, "Code", 3000, "2024-05-01"),
+            item(1, "Firefox", 500, "2024-05-01"),
+            item(1, "Code", 600, "2024-05-02"),
+            item(2, "Slack", 1200, "2024-05-01"),
+        ];
+
+        let sheet = build("2024-05-01", "2024-05-02", None, &boards, std::slice::from_ref(&acme), &items, "USD");
+        assert_eq!(sheet.lines.len(), 3);
+        // 58m20s rounds up to 1h, 10m to 15m; the client's rate applies
+        assert_eq!(sheet.lines[0].billed_seconds, 3600);
+        assert_eq!(sheet.lines[0].apps, ["Code", "Firefox"]);
+        assert_eq!(sheet.lines[0].amount_cents, 8000);
+        assert_eq!(sheet.lines[1].currency, "USD");
+        assert_eq!(sheet.lines[1].hourly_rate, None);
+        assert_eq!(sheet.summary[0].billed_seconds, 4500);
+        assert_eq!(sheet.summary[0].amount_cents, 10000);
+        assert_eq!(sheet.totals.len(), 2);
+
+        let acme_only = build("2024-05-01", "2024-05-02", Some(1), &boards, &[acme], &items, "USD");
+        assert_eq!(acme_only.lines.len(), 2);
+        assert_eq!(acme_only.client.as_ref().map(|c| c.name.as_str()), Some("Acme"));
+
+        let csv = timesheet_csv(&acme_only).unwrap();
+        assert!(csv.contains("2024-05-01,Acme,Website,\"Code, Firefox\",0.97,1.00,80.00,EUR,80.00"));
+        let html = invoice_html(&acme_only, "INV-7", "Me <me@example.com>");
+        assert!(html.contains("Me &lt;me@example.com&gt;"));
+        assert!(html.contains("1 Road<br>Town"));
+        assert!(html.contains("100.00 EUR"));
+    }
+}
@@ -0,0 +1,301 @@
+//! Native-messaging host for browser extensions
+//!
+//! Window titles rarely contain the domain, so a browser extension can report the
+//! active tab instead. The browser starts `timigs` as a native-messaging host (see
+//! `install`) and talks to it over stdin/stdout: every message is a 32-bit length
+//! in native byte order followed by that many bytes of UTF-8 JSON.
+//!
+//! ```json
+//! { "type": "tab", "url": "https://github.com/BANSAFAn/timiGS-", "title": "BANSAFAn/timiGS-", "incognito": false }
+//! { "type": "ping" }
+//! ```
+//!
+//! Each message is answered with `{ "ok": true }` or `{ "ok": false, "error": "..." }`.
+//! Tabs are written to the database directly (`db::record_browser_tab`), so the local
+//! API does not need to be enabled. Only http(s) URLs are kept, without credentials,
+//! query string or fragment; incognito tabs, and every tab while TimiGS itself is in
+//! incognito mode, are ignored.
+
+use crate::db::{self, BrowserTab};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+pub const HOST_NAME: &str = "com.timigs.host";
+/// Gecko id of the extension in `browser-extension/`.
+pub const FIREFOX_EXTENSION_ID: &str = "tabs@timigs.app";
+/// Browsers cap messages to a host at 4 GB; nothing TimiGS expects comes close to this.
+const MAX_MESSAGE_BYTES: u32 = 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Message {
+    Tab {
+        url: String,
+        #[serde(default)]
+        title: String,
+        #[serde(default)]
+        incognito: bool,
+        #[serde(default)]
+        browser: Option<String>,
+    },
+    Ping,
+}
+
+/// Whether the browser started us as its native-messaging host. Chrome passes the
+/// caller's origin (`chrome-extension://<id>/`), Firefox the path to our manifest.
+pub fn is_host_invocation(args: &[String]) -> bool {
+    args.first().is_some_and(|first| {
+        first.starts_with("chrome-extension://")
+            || Path::new(first).file_name().and_then(|n| n.to_str())
+                == Some(&format!("{}.json", HOST_NAME))
+    })
+}
+
+fn read_message(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        // The browser closed the port
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_ne_bytes(len);
+    if len > MAX_MESSAGE_BYTES {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "message too large"));
+    }
+    let mut buf = vec![0u8; len as usize];
+    reader.read_exact(&mut buf)?;
+    Ok(Some(buf))
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let bytes = serde_json::to_vec(message)?;
+    writer.write_all(&(bytes.len() as u32).to_ne_bytes())?;
+    writer.write_all(&bytes)?;
+    writer.flush()
+}
+
+fn handle(raw: &[u8], origin: &str) -> Result<(), String> {
+    match serde_json::from_slice::<Message>(raw).map_err(|e| e.to_string())? {
+        Message::Ping => Ok(()),
+        Message::Tab {
+            url,
+            title,
+            incognito,
+            browser,
+        } => {
+            // The app may have switched incognito mode on since the last tab
+            crate::privacy::reload_incognito();
+            if incognito || crate::privacy::is_incognito() {
+                return Ok(());
+            }
+            let Some((url, domain)) = db::normalize_url(&url) else {
+                return Ok(());
+            };
+            db::record_browser_tab(&BrowserTab {
+                browser: browser.unwrap_or_else(|| origin.to_string()),
+                url,
+                domain,
+                title: title.trim().to_string(),
+            })
+            .map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Serve the browser until it closes the port. Only protocol frames may go to
+/// stdout; diagnostics go to stderr, which browsers log.
+pub fn run_host(args: &[String]) -> i32 {
+    if let Err(e) = db::init_database_client() {
+        eprintln!("timigs native host: cannot open the database: {}", e);
+        return 1;
+    }
+    let origin = args.first().cloned().unwrap_or_default();
+
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    loop {
+        let raw = match read_message(&mut stdin) {
+            Ok(Some(raw)) => raw,
+            Ok(None) => return 0,
+            Err(e) => {
+                eprintln!("timigs native host: {}", e);
+                return 1;
+            }
+        };
+        let reply = match handle(&raw, &origin) {
+            Ok(()) => json!({ "ok": true }),
+            Err(e) => json!({ "ok": false, "error": e }),
+        };
+        if write_message(&mut stdout, &reply).is_err() {
+            return 0;
+        }
+    }
+}
+
+// ── Host registration ──
+
+fn manifest(exe: &Path, allowed: (&str, Value)) -> Value {
+    let mut manifest = json!({
+        "name": HOST_NAME,
+        "description": "TimiGS activity tracker",
+        "path": exe,
+        "type": "stdio",
+    });
+    manifest[allowed.0] = allowed.1;
+    manifest
+}
+
+/// Where each browser family looks for host manifests: Chromium-based browsers and Firefox.
+#[cfg(target_os = "linux")]
+fn manifest_dirs() -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let config = dirs::config_dir().unwrap_or_default();
+    let home = dirs::home_dir().unwrap_or_default();
+    (
+        ["google-chrome", "chromium", "BraveSoftware/Brave-Browser", "microsoft-edge", "vivaldi"]
+            .iter()
+            .map(|b| config.join(b).join("NativeMessagingHosts"))
+            .collect(),
+        vec![home.join(".mozilla/native-messaging-hosts")],
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn manifest_dirs() -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let support = dirs::home_dir().unwrap_or_default().join("Library/Application Support");
+    (
+        ["Google/Chrome", "Chromium", "BraveSoftware/Brave-Browser", "Microsoft Edge", "Vivaldi"]
+            .iter()
+            .map(|b| support.join(b).join("NativeMessagingHosts"))
+            .collect(),
+        vec![support.join("Mozilla/NativeMessagingHosts")],
+    )
+}
+
+/// Windows finds manifests through the registry, so they live in the app data folder.
+#[cfg(target_os = "windows")]
+fn manifest_dirs() -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let base = dirs::data_dir().unwrap_or_default().join("TimiGS").join("native-messaging");
+    (vec![base.join("chromium")], vec![base.join("firefox")])
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn manifest_dirs() -> (Vec<PathBuf>, Vec<PathBuf>) {
+    (Vec::new(), Vec::new())
+}
+
+#[cfg(target_os = "windows")]
+const REGISTRY_KEYS: (&[&str], &[&str]) = (
+    &[
+        r"Software\Google\Chrome\NativeMessagingHosts",
+        r"Software\Chromium\NativeMessagingHosts",
+        r"Software\BraveSoftware\Brave-Browser\NativeMessagingHosts",
+        r"Software\Microsoft\Edge\NativeMessagingHosts",
+    ],
+    &[r"Software\Mozilla\NativeMessagingHosts"],
+);
+
+#[cfg(target_os = "windows")]
+fn register(keys: &[&str], manifest: Option<&Path>) -> Result<(), String> {
+    for key in keys {
+        let key = format!(r"HKCU\{}\{}", key, HOST_NAME);
+        let mut reg = std::process::Command::new("reg");
+        match manifest {
+            Some(path) => reg.args(["add", &key, "/ve", "/t", "REG_SZ", "/f", "/d"]).arg(path),
+            None => reg.args(["delete", &key, "/f"]),
+        };
+        let status = reg.output().map_err(|e| e.to_string())?.status;
+        if manifest.is_some() && !status.success() {
+            return Err(format!("Cannot write registry key {}", key));
+        }
+    }
+    Ok(())
+}
+
+/// Write the host manifest into every directory of one browser family. Writes
+/// nothing when no extension of that family is allowed.
+fn write_manifests(
+    exe: &Path,
+    dirs: Vec<PathBuf>,
+    allowed_key: &str,
+    allowed: Vec<String>,
+) -> Result<Vec<PathBuf>, String> {
+    if allowed.is_empty() {
+        return Ok(Vec::new());
+    }
+    let content = serde_json::to_string_pretty(&manifest(exe, (allowed_key, json!(allowed))))
+        .map_err(|e| e.to_string())?;
+    let mut written = Vec::new();
+    for dir in dirs {
+        std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let path = dir.join(format!("{}.json", HOST_NAME));
+        std::fs::write(&path, &content).map_err(|e| format!("{}: {}", path.display(), e))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Register TimiGS as the native-messaging host for the given extensions. Returns
+/// the manifests written.
+pub fn install(chrome_ids: &[String], firefox_ids: &[String]) -> Result<Vec<PathBuf>, String> {
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    let (chromium_dirs, firefox_dirs) = manifest_dirs();
+
+    let origins: Vec<String> = chrome_ids
+        .iter()
+        .map(|id| format!("chrome-extension://{}/", id.trim()))
+        .collect();
+    let chromium = write_manifests(&exe, chromium_dirs, "allowed_origins", origins)?;
+    let firefox = write_manifests(&exe, firefox_dirs, "allowed_extensions", firefox_ids.to_vec())?;
+    #[cfg(target_os = "windows")]
+    {
+        for path in &chromium {
+            register(REGISTRY_KEYS.0, Some(path))?;
+        }
+        for path in &firefox {
+            register(REGISTRY_KEYS.1, Some(path))?;
+        }
+    }
+    Ok(chromium.into_iter().chain(firefox).collect())
+}
+
+/// Remove every manifest `install` may have written.
+pub fn uninstall() -> Result<(), String> {
+    let (chromium_dirs, firefox_dirs) = manifest_dirs();
+    for dir in chromium_dirs.iter().chain(&firefox_dirs) {
+        let path = dir.join(format!("{}.json", HOST_NAME));
+        if path.exists() {
+            std::fs::remove_file(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+    }
+    #[cfg(target_os = "windows")]
+    {
+        register(REGISTRY_KEYS.0, None)?;
+        register(REGISTRY_KEYS.1, None)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_framing() {
+        let mut buf = Vec::new();
+        write_message(&mut buf, &json!({ "type": "ping" })).unwrap();
+        let mut reader = io::Cursor::new(buf);
+        let raw = read_message(&mut reader).unwrap().unwrap();
+        assert!(matches!(serde_json::from_slice(&raw).unwrap(), Message::Ping));
+        assert!(read_message(&mut reader).unwrap().is_none());
+
+        assert!(is_host_invocation(&["chrome-extension://abcdef/".to_string()]));
+        assert!(is_host_invocation(&[
+            "/home/me/.mozilla/native-messaging-hosts/com.timigs.host.json".to_string(),
+            FIREFOX_EXTENSION_ID.to_string(),
+        ]));
+        assert!(!is_host_invocation(&["--minimized".to_string()]));
+    }
+}
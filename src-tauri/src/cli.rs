@@ -0,0 +1,1215 @@
+//! `timigs` command-line client and headless daemon
+//!
+//! `timigs <command>` runs the command and exits without starting the UI; any other
+//! arguments (`--minimized`, deep links) fall through to the normal app. Commands that
+//! read data open the database directly. `pause`, `resume` and `focus` talk to the
+//! running instance through the local API (see `api`), so that has to be enabled.
+
+use crate::db;
+use chrono::{Local, NaiveDate};
+use serde_json::Value;
+use std::collections::HashMap;
+
+const USAGE: &str = "TimiGS activity tracker
+
+Usage: timigs [command] [options]
+
+Without a command the desktop app starts as usual.
+
+Commands:
+  daemon                      Track in the background without any UI (alias: --headless)
+  status [--json]             Current activity and today's totals
+  report [--from DATE] [--to DATE] [--format md|json] [--output PATH]
+                              Per-app and per-category summary (dates: YYYY-MM-DD, default today)
+  export --output PATH [--format csv|json|html|md|ics|aw] [--from DATE] [--to DATE]
+                              Export sessions (format defaults to the file extension);
+                              ics is a calendar of activity, coding, focus and breaks;
+                              aw writes ActivityWatch buckets [--hostname NAME]
+  pause                       Pause tracking in the running instance
+  resume                      Resume tracking in the running instance
+  focus start --exe PATH --minutes N [--app NAME] [--password PASS]
+  focus stop [--password PASS]
+  focus status
+  incognito on [--minutes N] | incognito off | incognito status
+                              Pause all recording in the running instance
+  privacy scrub [--level full|app_only|hash|redact] [--pattern REGEX] [--app NAME]
+                [--from DATE] [--to DATE] [--dry-run]
+                              Apply title privacy to recorded sessions (default: the
+                              current settings and rules)
+  search TEXT [--from DATE] [--to DATE] [--app NAME] [--limit N] [--json]
+                              Find sessions by window title, domain, file path or track
+  encryption status | encryption enable [--passphrase] | encryption disable
+                              Encrypt the database (needs a SQLCipher build; quit TimiGS
+                              first). --passphrase derives the key from
+                              TIMIGS_DB_PASSPHRASE instead of the OS secret store
+  retention set DAYS | retention run [--days N] [--dry-run] | retention status
+                              Roll sessions older than DAYS into daily totals
+                              (0 keeps everything)
+  backup list | backup now | backup restore NAME
+  backup on [--every HOURS] | backup off
+                              Snapshots of the database; restoring saves the current
+                              data as a snapshot first (quit TimiGS before restoring)
+  archive export PATH | archive import PATH [--on-conflict skip|overwrite|keep-both]
+                              Every table in one JSON file, to move to another computer
+                              (quit TimiGS before importing)
+  clients | clients add NAME [--rate R] [--currency CODE] [--email E] [--address TEXT]
+  clients remove ID           Clients to bill for project boards
+  boards | boards bill ID [--client ID|none] [--rate R|none] [--round MINUTES]
+                [--rounding up|nearest|down]
+                              Attach a client, an hourly rate (default: the client's) and
+                              rounding of each day's time to a project board
+  invoice --output PATH [--from DATE] [--to DATE] [--client ID] [--number N]
+                              Timesheet (.csv) or printable invoice (.html) from boards
+  api enable [--port N] | api disable | api token [--regenerate]
+                              Configure the local API (restart TimiGS to apply)
+  shell-hook bash|zsh|fish    Print a hook that reports command timings, for
+                              `eval \"$(timigs shell-hook zsh)\"` in ~/.zshrc
+  native-host install [--chrome-extension-id ID[,ID]] [--firefox-extension-id ID[,ID]]
+  native-host uninstall       Register the browser extension host that reports tab URLs
+  help                        Show this help";
+
+/// Handle a CLI command. Returns the exit code, or `None` when the arguments are
+/// not a command and the app should start normally.
+pub fn run(args: &[String]) -> Option<i32> {
+    // Started by a browser: stdout belongs to the messaging protocol
+    if crate::browser::is_host_invocation(args) {
+        return Some(crate::browser::run_host(args));
+    }
+
+    let command = args.first()?.as_str();
+    let rest = &args[1..];
+
+    let handler: fn(&[String]) -> Result<(), String> = match command {
+        "daemon" | "--headless" => daemon,
+        "status" => status,
+        "report" => report,
+        "export" => export,
+        "pause" => pause,
+        "resume" => resume,
+        "focus" => focus,
+        "incognito" => incognito,
+        "privacy" => privacy,
+        "retention" => retention,
+        "search" => search,
+        "encryption" => encryption,
+        "backup" => backup,
+        "archive" => archive,
+        "clients" => clients,
+        "boards" => boards,
+        "invoice" => invoice,
+        "api" => api,
+        "shell-hook" => shell_hook,
+        "native-host" => native_host,
+        "help" | "--help" | "-h" => |_| {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        _ => return None,
+    };
+
+    attach_console();
+    Some(match handler(rest) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("timigs: {}", e);
+            1
+        }
+    })
+}
+
+/// Release builds on Windows have no console of their own; borrow the terminal's.
+#[cfg(target_os = "windows")]
+fn attach_console() {
+    use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    unsafe {
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn attach_console() {}
+
+// ── Argument helpers ──
+
+/// Value of `--name value` or `--name=value`.
+fn option(args: &[String], name: &str) -> Option<String> {
+    let prefix = format!("{}=", name);
+    args.iter().enumerate().find_map(|(i, arg)| {
+        if arg == name {
+            args.get(i + 1).cloned()
+        } else {
+            arg.strip_prefix(&prefix).map(str::to_string)
+        }
+    })
+}
+
+fn flag(args: &[String], name: &str) -> bool {
+    args.iter().any(|arg| arg == name)
+}
+
+fn date_option(args: &[String], name: &str) -> Result<NaiveDate, String> {
+    match option(args, name) {
+        Some(s) => NaiveDate::parse_from_str(&s, "%Y-%m-%d")
+            .map_err(|_| format!("{} must be a YYYY-MM-DD date", name)),
+        None => Ok(Local::now().date_naive()),
+    }
+}
+
+fn date_range(args: &[String]) -> Result<(String, String), String> {
+    let from = date_option(args, "--from")?;
+    let to = date_option(args, "--to")?;
+    if to < from {
+        return Err("--to is before --from".to_string());
+    }
+    Ok((from.to_string(), to.to_string()))
+}
+
+fn open_db() -> Result<(), String> {
+    db::init_database_client().map_err(|e| format!("Cannot open the database: {}", e))
+}
+
+fn format_duration(secs: i64) -> String {
+    let (hrs, mins) = (secs / 3600, (secs % 3600) / 60);
+    if hrs > 0 {
+        format!("{}h {}m", hrs, mins)
+    } else if mins > 0 {
+        format!("{}m", mins)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+// ── Running instance ──
+
+struct Instance {
+    base: String,
+    token: String,
+    http: reqwest::blocking::Client,
+}
+
+impl Instance {
+    fn connect() -> Result<Self, String> {
+        let settings = db::get_settings();
+        if !settings.api_enabled {
+            return Err(
+                "the local API is disabled; run `timigs api enable` and restart TimiGS".to_string(),
+            );
+        }
+        Ok(Self {
+            base: format!("http://127.0.0.1:{}", settings.api_port),
+            token: crate::api::get_token()?,
+            http: reqwest::blocking::Client::builder()
+                .timeout(std::time::Duration::from_secs(5))
+                .build()
+                .map_err(|e| e.to_string())?,
+        })
+    }
+
+    fn send(&self, request: reqwest::blocking::RequestBuilder) -> Result<Value, String> {
+        let response = request
+            .bearer_auth(&self.token)
+            .send()
+            .map_err(|_| format!("TimiGS is not running (no answer on {})", self.base))?;
+        let ok = response.status().is_success();
+        let body: Value = response.json().map_err(|e| e.to_string())?;
+        if ok {
+            Ok(body)
+        } else {
+            Err(body["error"].as_str().unwrap_or("request failed").to_string())
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<Value, String> {
+        self.send(self.http.get(format!("{}{}", self.base, path)))
+    }
+
+    fn post(&self, path: &str, body: Value) -> Result<Value, String> {
+        self.send(self.http.post(format!("{}{}", self.base, path)).json(&body))
+    }
+}
+
+// ── Commands ──
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn daemon(_args: &[String]) -> Result<(), String> {
+    Err("Activity tracking is not supported on this platform".to_string())
+}
+
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+fn daemon(_args: &[String]) -> Result<(), String> {
+    // Before opening the database, which would close the other instance's sessions
+    db::acquire_instance_lock()?;
+    db::init_database().map_err(|e| format!("Cannot open the database: {}", e))?;
+    let settings = db::get_settings();
+    crate::tracker::start_tracking();
+
+    if settings.api_enabled {
+        if let Err(e) = crate::api::start(settings.api_port) {
+            eprintln!("Failed to start local API: {}", e);
+        }
+    }
+
+    std::thread::spawn(|| loop {
+        std::thread::sleep(std::time::Duration::from_secs(3600));
+        if let Err(e) = crate::backup::snapshot_if_needed() {
+            eprintln!("{}", e);
+        }
+        let _ = db::auto_export_if_needed();
+        if let Err(e) = db::retention_if_needed() {
+            eprintln!("Retention failed: {}", e);
+        }
+    });
+
+    println!("TimiGS is tracking in headless mode; press Ctrl+C to stop");
+    wait_for_shutdown_signal();
+
+    println!("Stopping...");
+    crate::tracker::stop_tracking();
+    crate::tracker::end_all_sessions_at(Local::now());
+    crate::api::stop();
+    db::close_database();
+    Ok(())
+}
+
+fn wait_for_shutdown_signal() {
+    let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Cannot listen for shutdown signals: {}", e);
+            loop {
+                std::thread::park();
+            }
+        }
+    };
+
+    runtime.block_on(async {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            match signal(SignalKind::terminate()) {
+                Ok(mut term) => {
+                    tokio::select! {
+                        _ = tokio::signal::ctrl_c() => {}
+                        _ = term.recv() => {}
+                    }
+                }
+                Err(_) => {
+                    let _ = tokio::signal::ctrl_c().await;
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    });
+}
+
+fn status(args: &[String]) -> Result<(), String> {
+    open_db()?;
+
+    let live = Instance::connect().and_then(|instance| {
+        Ok((instance.get("/v1/status")?, instance.get("/v1/current")?))
+    });
+    let apps = db::get_today_summary().map_err(|e| e.to_string())?;
+    let today = Local::now().format("%Y-%m-%d").to_string();
+    let productivity = db::get_productivity_score(&today).map_err(|e| e.to_string())?;
+    let total_seconds: i64 = apps.iter().map(|a| a.total_seconds).sum();
+
+    if flag(args, "--json") {
+        let (instance, current) = match &live {
+            Ok((status, current)) => (status.clone(), current.clone()),
+            Err(_) => (Value::Null, Value::Null),
+        };
+        let out = serde_json::json!({
+            "instance": instance,
+            "current": current,
+            "today_seconds": total_seconds,
+            "productivity_score": productivity.score,
+            "apps": apps,
+        });
+        println!("{}", serde_json::to_string_pretty(&out).map_err(|e| e.to_string())?);
+        return Ok(());
+    }
+
+    match &live {
+        Ok((status, current)) => {
+            let tracking = status["tracking"].as_bool().unwrap_or(false);
+            println!("TimiGS:   running, tracking {}", if tracking { "on" } else { "paused" });
+            match current["activity"].as_object() {
+                Some(activity) => println!(
+                    "Current:  {} — {}",
+                    activity.get("app_name").and_then(Value::as_str).unwrap_or("?"),
+                    activity.get("window_title").and_then(Value::as_str).unwrap_or("")
+                ),
+                None => println!("Current:  (idle)"),
+            }
+        }
+        Err(e) => println!("TimiGS:   {}", e),
+    }
+    println!(
+        "Today:    {} across {} apps, productivity score {:.1}",
+        format_duration(total_seconds),
+        apps.len(),
+        productivity.score
+    );
+    for app in apps.iter().take(5) {
+        println!("          {:>8}  {}", format_duration(app.total_seconds), app.app_name);
+    }
+    Ok(())
+}
+
+fn report(args: &[String]) -> Result<(), String> {
+    open_db()?;
+    let (from, to) = date_range(args)?;
+    let format = option(args, "--format").unwrap_or_else(|| "md".to_string());
+
+    let sessions = db::get_enriched_sessions(&from, &to).map_err(|e| e.to_string())?;
+    let weights = db::category_weights().map_err(|e| e.to_string())?;
+    let categories = db::category_breakdown(&sessions, &weights);
+    let score = db::productivity_score(&categories);
+    let total: i64 = sessions.iter().map(|s| s.duration_seconds).sum();
+
+    let mut apps: HashMap<&str, (i64, i64)> = HashMap::new();
+    for s in &sessions {
+        let entry = apps.entry(s.app_name.as_str()).or_insert((0, 0));
+        entry.0 += s.duration_seconds;
+        entry.1 += 1;
+    }
+    let mut apps: Vec<_> = apps.into_iter().collect();
+    apps.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(a.0.cmp(b.0)));
+
+    let content = match format.as_str() {
+        "json" => {
+            let apps: Vec<Value> = apps
+                .iter()
+                .map(|(name, (secs, count))| {
+                    serde_json::json!({ "app_name": name, "total_seconds": secs, "session_count": count })
+                })
+                .collect();
+            serde_json::to_string_pretty(&serde_json::json!({
+                "from": from,
+                "to": to,
+                "total_seconds": total,
+                "productivity_score": score,
+                "apps": apps,
+                "categories": categories,
+            }))
+            .map_err(|e| e.to_string())?
+        }
+        "md" | "markdown" => {
+            let share = |secs: i64| if total > 0 { secs as f64 * 100.0 / total as f64 } else { 0.0 };
+            let mut md = if from == to {
+                format!("# TimiGS report {}\n\n", from)
+            } else {
+                format!("# TimiGS report {} – {}\n\n", from, to)
+            };
+            md.push_str(&format!(
+                "**Total**: {} · **Productivity score**: {:.1}\n\n",
+                format_duration(total),
+                score
+            ));
+            md.push_str("## Apps\n\n| App | Time | Share | Sessions |\n|---|---|---|---|\n");
+            for (name, (secs, count)) in &apps {
+                md.push_str(&format!(
+                    "| {} | {} | {:.1}% | {} |\n",
+                    name.replace('|', "\\|"),
+                    format_duration(*secs),
+                    share(*secs),
+                    count
+                ));
+            }
+            md.push_str("\n## Categories\n\n| Category | Weight | Time | Share |\n|---|---|---|---|\n");
+            for c in &categories {
+                md.push_str(&format!(
+                    "| {} | {:+.2} | {} | {:.1}% |\n",
+                    c.category.replace('|', "\\|"),
+                    c.productivity_weight,
+                    format_duration(c.total_seconds),
+                    share(c.total_seconds)
+                ));
+            }
+            md
+        }
+        other => return Err(format!("Unknown report format '{}' (use md or json)", other)),
+    };
+
+    match option(args, "--output") {
+        Some(path) => std::fs::write(&path, content).map_err(|e| e.to_string()),
+        None => {
+            println!("{}", content);
+            Ok(())
+        }
+    }
+}
+
+fn export(args: &[String]) -> Result<(), String> {
+    let path = option(args, "--output").ok_or("export needs --output PATH")?;
+    let format = option(args, "--format").unwrap_or_else(|| {
+        std::path::Path::new(&path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+    });
+    let (from, to) = date_range(args)?;
+    open_db()?;
+
+    let result = match format.as_str() {
+        "csv" => db::export_sessions_csv(&path, &from, &to),
+        "json" => db::export_sessions_json(&path, &from, &to),
+        "html" => db::export_sessions_html(&path, &from, &to),
+        "md" | "markdown" => db::export_sessions_markdown(&path, &from, &to),
+        "ics" => db::export_sessions_ics(&path, &from, &to),
+        "aw" | "activitywatch" => {
+            db::export_sessions_activitywatch(&path, &from, &to, option(args, "--hostname").as_deref())
+        }
+        other => return Err(format!("Unknown export format '{}' (use csv, json, html, md, ics or aw)", other)),
+    };
+    result.map_err(|e| e.to_string())?;
+    println!("Exported {} – {} to {}", from, to, path);
+    Ok(())
+}
+
+fn pause(_args: &[String]) -> Result<(), String> {
+    open_db()?;
+    Instance::connect()?.post("/v1/tracking/pause", Value::Null)?;
+    println!("Tracking paused");
+    Ok(())
+}
+
+fn resume(_args: &[String]) -> Result<(), String> {
+    open_db()?;
+    Instance::connect()?.post("/v1/tracking/resume", Value::Null)?;
+    println!("Tracking resumed");
+    Ok(())
+}
+
+fn focus(args: &[String]) -> Result<(), String> {
+    open_db()?;
+    let instance = Instance::connect()?;
+    let password = option(args, "--password").unwrap_or_default();
+
+    match args.first().map(String::as_str) {
+        Some("start") => {
+            let exe_path = option(args, "--exe").ok_or("focus start needs --exe PATH")?;
+            let minutes: u64 = option(args, "--minutes")
+                .ok_or("focus start needs --minutes N")?
+                .parse()
+                .map_err(|_| "--minutes must be a whole number".to_string())?;
+            let app_name = option(args, "--app").unwrap_or_else(|| {
+                std::path::Path::new(&exe_path)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| exe_path.clone())
+            });
+            instance.post(
+                "/v1/focus/start",
+                serde_json::json!({
+                    "app_name": app_name,
+                    "exe_path": exe_path,
+                    "duration_secs": minutes * 60,
+                    "password": password,
+                }),
+            )?;
+            println!("Focus mode on {} for {} min", app_name, minutes);
+        }
+        Some("stop") => {
+            instance.post("/v1/focus/stop", serde_json::json!({ "password": password }))?;
+            println!("Focus mode stopped");
+        }
+        Some("status") | None => {
+            let status = instance.get("/v1/focus")?;
+            if status.is_null() || status["active"] != Value::Bool(true) {
+                println!("Focus mode is off");
+            } else {
+                println!(
+                    "Focus mode on {}, {} left",
+                    status["app_name"].as_str().unwrap_or("?"),
+                    format_duration(status["remaining_secs"].as_i64().unwrap_or(0))
+                );
+            }
+        }
+        Some(other) => return Err(format!("Unknown focus command '{}'", other)),
+    }
+    Ok(())
+}
+
+fn incognito(args: &[String]) -> Result<(), String> {
+    open_db()?;
+    let instance = Instance::connect()?;
+    let status = match args.first().map(String::as_str) {
+        Some("on") => {
+            let minutes = match option(args, "--minutes") {
+                Some(m) => Some(
+                    m.parse::<u64>()
+                        .map_err(|_| "--minutes must be a whole number".to_string())?,
+                ),
+                None => None,
+            };
+            instance.post("/v1/incognito/start", serde_json::json!({ "minutes": minutes }))?
+        }
+        Some("off") => instance.post("/v1/incognito/stop", Value::Null)?,
+        Some("status") | None => instance.get("/v1/incognito")?,
+        Some(other) => return Err(format!("Unknown incognito command '{}'", other)),
+    };
+    match (status["active"].as_bool(), status["until"].as_str()) {
+        (Some(true), Some(until)) => println!("Incognito until {}: nothing is recorded", until),
+        (Some(true), None) => println!("Incognito: nothing is recorded until `timigs incognito off`"),
+        _ => println!("Incognito off"),
+    }
+    Ok(())
+}
+
+fn privacy(args: &[String]) -> Result<(), String> {
+    if args.first().map(String::as_str) != Some("scrub") {
+        return Err("timigs privacy scrub [options]".to_string());
+    }
+    let level = match option(args, "--level") {
+        Some(level) => Some(crate::privacy::TitlePrivacy::parse(&level).ok_or_else(|| {
+            format!("Unknown level '{}' (full, app_only, hash or redact)", level)
+        })?),
+        None => None,
+    };
+    let date = |name: &str| -> Result<Option<String>, String> {
+        option(args, name)
+            .map(|_| date_option(args, name).map(|d| d.to_string()))
+            .transpose()
+    };
+    let request = crate::privacy::ScrubRequest {
+        level,
+        pattern: option(args, "--pattern"),
+        app_name: option(args, "--app"),
+        from: date("--from")?,
+        to: date("--to")?,
+        dry_run: flag(args, "--dry-run"),
+    };
+
+    open_db()?;
+    let report = crate::privacy::scrub(&request)?;
+    println!(
+        "{} {} activity, {} coding and {} music sessions, {} rolled-up titles and {} edit history entries",
+        if report.dry_run { "Would rewrite" } else { "Rewrote" },
+        report.activity_sessions,
+        report.coding_sessions,
+        report.music_sessions,
+        report.daily_usage,
+        report.session_edits
+    );
+    Ok(())
+}
+
+fn search(args: &[String]) -> Result<(), String> {
+    // Words up to the first option are the search text
+    let words: Vec<&str> = args
+        .iter()
+        .map(String::as_str)
+        .take_while(|arg| !arg.starts_with("--"))
+        .collect();
+    if words.is_empty() {
+        return Err("timigs search TEXT [options]".to_string());
+    }
+    let date = |name: &str| -> Result<Option<String>, String> {
+        option(args, name)
+            .map(|_| date_option(args, name).map(|d| d.to_string()))
+            .transpose()
+    };
+    let query = db::SearchQuery {
+        query: words.join(" "),
+        from: date("--from")?,
+        to: date("--to")?,
+        app_name: option(args, "--app"),
+        limit: option(args, "--limit")
+            .map(|l| l.parse().map_err(|_| "--limit must be a number".to_string()))
+            .transpose()?,
+    };
+
+    open_db()?;
+    let json = flag(args, "--json");
+    let marks = if json || !std::io::IsTerminal::is_terminal(&std::io::stdout()) {
+        ("[", "]")
+    } else {
+        ("\x1b[1m", "\x1b[0m")
+    };
+    let days = db::search_history(&query, marks).map_err(|e| e.to_string())?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&days).map_err(|e| e.to_string())?);
+        return Ok(());
+    }
+    if days.is_empty() {
+        println!("No matches");
+    }
+    for day in &days {
+        println!("{}  {}", day.date, format_duration(day.total_seconds));
+        for hit in &day.hits {
+            println!(
+                "  {}  {:>8}  {:<8} {} — {}",
+                hit.start_time.get(11..16).unwrap_or(""),
+                format_duration(hit.duration_seconds),
+                hit.kind,
+                hit.app_name,
+                hit.snippet
+            );
+        }
+    }
+    Ok(())
+}
+
+fn encryption(args: &[String]) -> Result<(), String> {
+    use crate::vault::{self, KeySource};
+
+    let status = match args.first().map(String::as_str) {
+        Some(action @ ("enable" | "disable")) => {
+            open_db()?;
+            // The running app or daemon would keep writing to the replaced file; the
+            // lock tells even when its API is off
+            if db::acquire_instance_lock().is_err() {
+                return Err("Quit TimiGS before changing the database encryption".to_string());
+            }
+            if action == "disable" {
+                vault::decrypt_database()?
+            } else if flag(args, "--passphrase") {
+                vault::encrypt_database(KeySource::Passphrase)?
+            } else {
+                vault::encrypt_database(KeySource::Keyring)?
+            }
+        }
+        Some("status") | None => vault::status()?,
+        Some(other) => return Err(format!("Unknown encryption command '{}'", other)),
+    };
+
+    match status.key_source {
+        Some(KeySource::Keyring) => println!("Database encrypted, key in the OS secret store"),
+        Some(KeySource::Passphrase) => println!(
+            "Database encrypted with a passphrase: set {} before starting TimiGS or the CLI",
+            vault::PASSPHRASE_ENV
+        ),
+        None if status.sqlcipher_available => println!("Database not encrypted"),
+        None => println!("Database not encrypted (this build has no SQLCipher support)"),
+    }
+    if status.database_encrypted && !status.plaintext_backups.is_empty() {
+        println!("These backups are still plaintext, delete them if you no longer need them:");
+        for backup in &status.plaintext_backups {
+            println!("  {}", backup);
+        }
+    }
+    Ok(())
+}
+
+fn print_retention(days: u32) {
+    if days == 0 {
+        println!("Keeping all raw sessions");
+    } else {
+        println!("Keeping raw sessions for {} days, then daily totals", days);
+    }
+}
+
+fn retention(args: &[String]) -> Result<(), String> {
+    open_db()?;
+    let mut settings = db::get_settings();
+    let parse_days = |value: &str| -> Result<u32, String> {
+        value.parse().map_err(|_| "DAYS must be a whole number".to_string())
+    };
+
+    match args.first().map(String::as_str) {
+        Some("set") => {
+            let days = args.get(1).ok_or("timigs retention set DAYS")?;
+            settings.retention_days = parse_days(days)?;
+            db::save_settings(&settings).map_err(|e| e.to_string())?;
+            print_retention(settings.retention_days);
+        }
+        Some("run") => {
+            let days = match option(args, "--days") {
+                Some(days) => parse_days(&days)?,
+                None => settings.retention_days,
+            };
+            if days == 0 {
+                return Err("Retention is off; pass --days N or run `timigs retention set N`".to_string());
+            }
+            let report = db::apply_retention(days, flag(args, "--dry-run")).map_err(|e| e.to_string())?;
+            println!(
+                "{} {} sessions ({}) before {} into {} daily rows{}",
+                if report.dry_run { "Would roll up" } else { "Rolled up" },
+                report.sessions_removed,
+                format_duration(report.seconds_rolled_up),
+                report.cutoff,
+                report.rollup_rows,
+                if report.vacuumed { ", database compacted" } else { "" }
+            );
+        }
+        Some("status") | None => print_retention(settings.retention_days),
+        Some(other) => return Err(format!("Unknown retention command '{}'", other)),
+    }
+    Ok(())
+}
+
+fn print_snapshot(snapshot: &crate::backup::Snapshot) {
+    println!(
+        "  {}  {:<11} {:>8.1} MB  {}",
+        snapshot.created.format("%Y-%m-%d %H:%M"),
+        snapshot.reason.as_str(),
+        snapshot.size_bytes as f64 / 1_048_576.0,
+        snapshot.name
+    );
+}
+
+fn backup(args: &[String]) -> Result<(), String> {
+    use crate::backup::{self, Reason};
+
+    open_db()?;
+    let mut settings = db::get_settings();
+
+    match args.first().map(String::as_str) {
+        Some("now") => {
+            let snapshot = backup::create_snapshot(Reason::Manual)?;
+            println!("Saved {}", snapshot.path);
+        }
+        Some("restore") => {
+            let name = args.get(1).ok_or("timigs backup restore NAME (see `timigs backup list`)")?;
+            // The running app or daemon keeps open sessions, rules and settings in
+            // memory; the lock tells even when its API is off
+            if db::acquire_instance_lock().is_err() {
+                return Err("Quit TimiGS before restoring, or restore from Settings".to_string());
+            }
+            let safety = backup::restore_snapshot(name)?;
+            println!("Restored {}; the replaced data is in {}", name, safety.name);
+        }
+        Some(action @ ("on" | "off")) => {
+            settings.backup_enabled = action == "on";
+            if let Some(hours) = option(args, "--every") {
+                settings.backup_interval_hours = hours
+                    .parse()
+                    .ok()
+                    .filter(|h| *h > 0)
+                    .ok_or("HOURS must be a whole number above 0")?;
+            }
+            db::save_settings(&settings).map_err(|e| e.to_string())?;
+            if settings.backup_enabled {
+                println!("Backing up every {} hours", settings.backup_interval_hours);
+            } else {
+                println!("Scheduled backups are off");
+            }
+        }
+        Some("list") | None => {
+            let snapshots = backup::list_snapshots()?;
+            let dir = backup::backup_dir();
+            if settings.backup_enabled {
+                println!(
+                    "Backing up every {} hours to {}",
+                    settings.backup_interval_hours,
+                    dir.display()
+                );
+            } else {
+                println!("Scheduled backups are off; snapshots are in {}", dir.display());
+            }
+            if snapshots.is_empty() {
+                println!("No snapshots yet");
+            }
+            for snapshot in &snapshots {
+                print_snapshot(snapshot);
+            }
+        }
+        Some(other) => return Err(format!("Unknown backup command '{}'", other)),
+    }
+    Ok(())
+}
+
+fn archive(args: &[String]) -> Result<(), String> {
+    use crate::archive::{self, ConflictMode};
+
+    open_db()?;
+    match args.first().map(String::as_str) {
+        Some("export") => {
+            let path = args.get(1).ok_or("timigs archive export PATH")?;
+            let manifest = archive::export_archive(path)?;
+            let rows: usize = manifest.tables.iter().map(|t| t.rows).sum();
+            println!("Archived {} rows from {} tables to {}", rows, manifest.tables.len(), path);
+        }
+        Some("import") => {
+            let path = args.get(1).ok_or("timigs archive import PATH")?;
+            let mode = match option(args, "--on-conflict") {
+                Some(mode) => ConflictMode::parse(&mode)
+                    .ok_or("--on-conflict must be skip, overwrite or keep-both")?,
+                None => ConflictMode::Skip,
+            };
+            // The running app or daemon keeps open sessions, rules and settings in
+            // memory; the lock tells even when its API is off
+            if db::acquire_instance_lock().is_err() {
+                return Err("Quit TimiGS before importing, or import from Settings".to_string());
+            }
+            let report = archive::import_archive(path, mode)?;
+            for table in report.tables.iter().filter(|t| t.inserted + t.updated + t.skipped > 0) {
+                println!(
+                    "  {:<22} {:>6} added {:>6} updated {:>6} skipped",
+                    table.table, table.inserted, table.updated, table.skipped
+                );
+            }
+            println!(
+                "Imported {} rows, updated {}, skipped {}",
+                report.inserted, report.updated, report.skipped
+            );
+        }
+        Some(other) => return Err(format!("Unknown archive command '{}'", other)),
+        None => return Err("timigs archive export PATH | archive import PATH".to_string()),
+    }
+    Ok(())
+}
+
+fn rate_option(args: &[String]) -> Result<Option<f64>, String> {
+    match option(args, "--rate") {
+        Some(rate) => rate
+            .parse::<f64>()
+            .ok()
+            .filter(|r| r.is_finite() && *r >= 0.0)
+            .map(Some)
+            .ok_or_else(|| "--rate must be a positive number".to_string()),
+        None => Ok(None),
+    }
+}
+
+fn clients(args: &[String]) -> Result<(), String> {
+    open_db()?;
+    match args.first().map(String::as_str) {
+        Some("add") => {
+            let name = args.get(1).ok_or("timigs clients add NAME")?;
+            let currency = option(args, "--currency").unwrap_or_else(|| db::get_settings().billing_currency);
+            let id = db::create_client(
+                name,
+                option(args, "--email").as_deref(),
+                option(args, "--address").map(|a| a.replace("\\n", "\n")).as_deref(),
+                &currency,
+                rate_option(args)?,
+            )
+            .map_err(|e| e.to_string())?;
+            println!("Added client {} ({})", id, name);
+        }
+        Some("remove") => {
+            let id: i64 = args
+                .get(1)
+                .and_then(|id| id.parse().ok())
+                .ok_or("timigs clients remove ID")?;
+            db::delete_client(id).map_err(|e| e.to_string())?;
+            println!("Removed client {}", id);
+        }
+        Some("list") | None => {
+            let clients = db::get_clients().map_err(|e| e.to_string())?;
+            if clients.is_empty() {
+                println!("No clients yet (timigs clients add NAME --rate 80)");
+            }
+            for client in clients {
+                let rate = client
+                    .hourly_rate
+                    .map(|r| format!("{:.2} {}/h", r, client.currency))
+                    .unwrap_or_else(|| format!("no rate, {}", client.currency));
+                println!("{:>4}  {:<24} {}", client.id, client.name, rate);
+            }
+        }
+        Some(other) => return Err(format!("Unknown clients command '{}'", other)),
+    }
+    Ok(())
+}
+
+fn boards(args: &[String]) -> Result<(), String> {
+    use crate::billing::RoundingMode;
+
+    open_db()?;
+    let boards = db::get_boards().map_err(|e| e.to_string())?;
+    match args.first().map(String::as_str) {
+        Some("bill") => {
+            let id: i64 = args
+                .get(1)
+                .and_then(|id| id.parse().ok())
+                .ok_or("timigs boards bill ID (see `timigs boards`)")?;
+            let board = boards
+                .iter()
+                .find(|b| b.id == id)
+                .ok_or_else(|| format!("No board with id {}", id))?;
+
+            let client_id = match option(args, "--client").as_deref() {
+                Some("none") => None,
+                Some(client) => {
+                    let client: i64 = client.parse().map_err(|_| "--client must be a client id or none")?;
+                    if !db::get_clients().map_err(|e| e.to_string())?.iter().any(|c| c.id == client) {
+                        return Err(format!("No client with id {}", client));
+                    }
+                    Some(client)
+                }
+                None => board.client_id,
+            };
+            let hourly_rate = match option(args, "--rate").as_deref() {
+                Some("none") => None,
+                Some(_) => rate_option(args)?,
+                None => board.hourly_rate,
+            };
+            let rounding_minutes = match option(args, "--round") {
+                Some(minutes) => minutes
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|m| *m >= 0)
+                    .ok_or("--round must be a number of minutes")?,
+                None => board.rounding_minutes,
+            };
+            let rounding_mode = match option(args, "--rounding") {
+                Some(mode) => RoundingMode::parse(&mode).ok_or("--rounding must be up, nearest or down")?,
+                None => RoundingMode::parse(&board.rounding_mode).unwrap_or_default(),
+            };
+            db::set_board_billing(id, client_id, hourly_rate, rounding_minutes, rounding_mode.as_str())
+                .map_err(|e| e.to_string())?;
+            println!("Updated billing for {}", board.name);
+        }
+        Some("list") | None => {
+            let clients = db::get_clients().map_err(|e| e.to_string())?;
+            if boards.is_empty() {
+                println!("No project boards yet");
+            }
+            for board in &boards {
+                let client = board
+                    .client_id
+                    .and_then(|id| clients.iter().find(|c| c.id == id))
+                    .map(|c| c.name.as_str())
+                    .unwrap_or("no client");
+                let rate = board
+                    .hourly_rate
+                    .map(|r| format!("{:.2}/h", r))
+                    .unwrap_or_else(|| "client rate".to_string());
+                let rounding = if board.rounding_minutes > 0 {
+                    format!("rounded {} to {}m", board.rounding_mode, board.rounding_minutes)
+                } else {
+                    "not rounded".to_string()
+                };
+                println!("{:>4}  {:<24} {}, {}, {}", board.id, board.name, client, rate, rounding);
+            }
+        }
+        Some(other) => return Err(format!("Unknown boards command '{}'", other)),
+    }
+    Ok(())
+}
+
+fn invoice(args: &[String]) -> Result<(), String> {
+    let path = option(args, "--output").ok_or("invoice needs --output PATH (.csv or .html)")?;
+    let format = option(args, "--format").unwrap_or_else(|| {
+        std::path::Path::new(&path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+    });
+    let client_id = match option(args, "--client") {
+        Some(id) => Some(id.parse::<i64>().map_err(|_| "--client must be a client id")?),
+        None => None,
+    };
+    let (from, to) = date_range(args)?;
+    open_db()?;
+
+    let sheet = crate::billing::export(&path, &format, &from, &to, client_id, option(args, "--number").as_deref())?;
+    for total in &sheet.totals {
+        println!(
+            "{:.2} h billed, {}.{:02} {}",
+            total.billed_seconds as f64 / 3600.0,
+            total.amount_cents / 100,
+            total.amount_cents % 100,
+            total.currency
+        );
+    }
+    println!("Wrote {} – {} to {}", from, to, path);
+    Ok(())
+}
+
+fn api(args: &[String]) -> Result<(), String> {
+    open_db()?;
+    let mut settings = db::get_settings();
+
+    match args.first().map(String::as_str) {
+        Some("enable") => {
+            if let Some(port) = option(args, "--port") {
+                settings.api_port = port.parse().map_err(|_| "--port must be a port number".to_string())?;
+            }
+            settings.api_enabled = true;
+            db::save_settings(&settings).map_err(|e| e.to_string())?;
+            crate::api::get_token()?;
+            println!(
+                "Local API enabled on 127.0.0.1:{} (restart TimiGS to apply)",
+                settings.api_port
+            );
+        }
+        Some("disable") => {
+            settings.api_enabled = false;
+            db::save_settings(&settings).map_err(|e| e.to_string())?;
+            println!("Local API disabled (restart TimiGS to apply)");
+        }
+        Some("token") => {
+            let token = if flag(args, "--regenerate") {
+                crate::api::regenerate_token()?
+            } else {
+                crate::api::get_token()?
+            };
+            println!("{}", token);
+        }
+        Some("status") | None => {
+            let status = crate::api::status()?;
+            println!(
+                "Local API {} on {}",
+                if status.enabled { "enabled" } else { "disabled" },
+                status.url
+            );
+        }
+        Some(other) => return Err(format!("Unknown api command '{}'", other)),
+    }
+    Ok(())
+}
+
+fn native_host(args: &[String]) -> Result<(), String> {
+    let ids = |name: &str| -> Vec<String> {
+        option(args, name)
+            .map(|list| {
+                list.split(',')
+                    .map(str::trim)
+                    .filter(|id| !id.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    match args.first().map(String::as_str) {
+        Some("install") => {
+            let mut firefox = ids("--firefox-extension-id");
+            if firefox.is_empty() {
+                firefox.push(crate::browser::FIREFOX_EXTENSION_ID.to_string());
+            }
+            let written = crate::browser::install(&ids("--chrome-extension-id"), &firefox)?;
+            for path in written {
+                println!("Wrote {}", path.display());
+            }
+        }
+        Some("uninstall") => {
+            crate::browser::uninstall()?;
+            println!("Native messaging host removed");
+        }
+        Some(other) => return Err(format!("Unknown native-host command '{}'", other)),
+        None => return Err("timigs native-host install|uninstall".to_string()),
+    }
+    Ok(())
+}
+
+// ── Shell hooks ──
+// Each reports a command's start and end to `/v1/shell/start` and `/v1/shell/end`
+// with a background curl, so a stopped TimiGS never slows the prompt down.
+
+const ZSH_HOOK: &str = r#"# TimiGS shell hook (zsh)
+if command -v curl >/dev/null 2>&1; then
+  zmodload zsh/datetime
+  _timigs_post() {
+    curl -s -m 1 -o /dev/null -K {config} "$@" &!
+  }
+  _timigs_preexec() {
+    _timigs_post --data-urlencode "command=$1" --data-urlencode "cwd=$PWD" \
+      -d "session=$$" -d shell=zsh -d "time=$EPOCHREALTIME" '{url}/v1/shell/start'
+  }
+  _timigs_precmd() {
+    _timigs_post -d "exit_code=$?" -d "session=$$" -d "time=$EPOCHREALTIME" '{url}/v1/shell/end'
+  }
+  autoload -Uz add-zsh-hook
+  add-zsh-hook preexec _timigs_preexec
+  add-zsh-hook precmd _timigs_precmd
+fi
+"#;
+
+const BASH_HOOK: &str = r#"# TimiGS shell hook (bash)
+if command -v curl >/dev/null 2>&1; then
+  _timigs_post() {
+    (curl -s -m 1 -o /dev/null -K {config} "$@" &)
+  }
+  _timigs_start() {
+    _timigs_post --data-urlencode "command=$1" --data-urlencode "cwd=$PWD" \
+      -d "session=$$" -d shell=bash -d "time=${EPOCHREALTIME:-$(date +%s)}" '{url}/v1/shell/start'
+  }
+  _timigs_end() {
+    _timigs_post -d "exit_code=$1" -d "session=$$" \
+      -d "time=${EPOCHREALTIME:-$(date +%s)}" '{url}/v1/shell/end'
+  }
+  if [ -n "${bash_preexec_imported:-}${__bp_imported:-}" ]; then
+    # bash-preexec owns the DEBUG trap and PROMPT_COMMAND; register with it
+    _timigs_bp_precmd() { _timigs_end "$?"; }
+    if [ -z "${_timigs_hooked:-}" ]; then
+      _timigs_hooked=1
+      preexec_functions+=(_timigs_start)
+      precmd_functions+=(_timigs_bp_precmd)
+    fi
+  else
+    _timigs_preexec() {
+      # Only the first command after a prompt; after an empty command line that
+      # is PROMPT_COMMAND itself
+      [ -n "$_timigs_at_prompt" ] || return 0
+      [ -n "$COMP_LINE" ] && return 0
+      unset _timigs_at_prompt
+      case "$BASH_COMMAND" in _timigs_status=*) return 0 ;; esac
+      local cmd
+      cmd=$(HISTTIMEFORMAT= history 1 | sed 's/^ *[0-9]* *//')
+      _timigs_start "$cmd"
+    }
+    _timigs_precmd() {
+      _timigs_end "$_timigs_status"
+      _timigs_at_prompt=1
+    }
+    # Keep any DEBUG trap that is already set: run it after ours, so its status
+    # still decides whether the command runs under extdebug
+    if [ -z "${_timigs_hooked:-}" ]; then
+      _timigs_hooked=1
+      _timigs_trap_command() { printf '%s' "$3"; }
+      _timigs_prev_debug=$(eval "_timigs_trap_command $(trap -p DEBUG)")
+      trap '_timigs_preexec; eval "$_timigs_prev_debug"' DEBUG
+      PROMPT_COMMAND="_timigs_status=\$?;${PROMPT_COMMAND:+$PROMPT_COMMAND;}_timigs_precmd"
+    fi
+  fi
+fi
+"#;
+
+const FISH_HOOK: &str = r#"# TimiGS shell hook (fish)
+if command -q curl
+  function _timigs_preexec --on-event fish_preexec
+    curl -s -m 1 -o /dev/null -K {config} \
+      --data-urlencode "command=$argv" --data-urlencode "cwd=$PWD" \
+      -d "session=$fish_pid" -d shell=fish '{url}/v1/shell/start' &
+    disown 2>/dev/null
+  end
+  function _timigs_postexec --on-event fish_postexec
+    set -l code $status
+    curl -s -m 1 -o /dev/null -K {config} \
+      -d "exit_code=$code" -d "duration_ms=$CMD_DURATION" -d "session=$fish_pid" \
+      '{url}/v1/shell/end' &
+    disown 2>/dev/null
+  end
+end
+"#;
+
+/// Single-quote `text` for the shell; fish escapes quotes inside single quotes
+/// with a backslash, POSIX shells have to close and reopen the quote.
+fn shell_quote(text: &str, fish: bool) -> String {
+    if fish {
+        format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+    } else {
+        format!("'{}'", text.replace('\'', "'\\''"))
+    }
+}
+
+fn shell_hook(args: &[String]) -> Result<(), String> {
+    let template = match args.first().map(String::as_str) {
+        Some("zsh") => ZSH_HOOK,
+        Some("bash") => BASH_HOOK,
+        Some("fish") => FISH_HOOK,
+        Some(other) => return Err(format!("Unsupported shell '{}' (bash, zsh or fish)", other)),
+        None => return Err("Which shell? timigs shell-hook bash|zsh|fish".to_string()),
+    };
+
+    open_db()?;
+    let settings = db::get_settings();
+    if !settings.api_enabled {
+        return Err("the local API is disabled; run `timigs api enable` and restart TimiGS".to_string());
+    }
+    let url = format!("http://127.0.0.1:{}", settings.api_port);
+    // The token goes in a private curl config rather than on curl's command line
+    let config = crate::api::write_curl_config(&crate::api::get_token()?)?;
+    let config = shell_quote(&config.to_string_lossy(), template == FISH_HOOK);
+    print!("{}", template.replace("{url}", &url).replace("{config}", &config));
+    Ok(())
+}
@@ -186,6 +186,36 @@ pub fn get_summary_by_date_cmd(date: String) -> Vec<db::AppUsageSummary> {
     db::get_summary_by_date(&date).unwrap_or_default()
 }
 
+#[command]
+pub fn get_category_stats_cmd(start_date: String, end_date: String) -> Result<Vec<db::CategoryDailyStats>, String> {
+    db::get_category_stats(&start_date, &end_date).map_err(|e| e.to_string())
+}
+
+/// Productivity score for `date` (YYYY-MM-DD), today when omitted.
+#[command]
+pub fn get_productivity_score_cmd(date: Option<String>) -> Result<db::ProductivityScore, String> {
+    let date = date.unwrap_or_else(|| chrono::Local::now().format("%Y-%m-%d").to_string());
+    db::get_productivity_score(&date).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_categories_cmd() -> Result<Vec<db::Category>, String> {
+    db::get_categories().map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn save_category_cmd(category: db::Category) -> Result<i64, String> {
+    if category.name.trim().is_empty() {
+        return Err("Category name is empty".to_string());
+    }
+    db::save_category(&category).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_category_cmd(id: i64) -> Result<(), String> {
+    db::delete_category(id).map_err(|e| e.to_string())
+}
+
 #[command]
 pub fn get_computer_name() -> String {
     std::env::var("COMPUTERNAME")
@@ -285,7 +315,25 @@ pub fn save_settings(app: tauri::AppHandle, settings: db::Settings) -> Result<()
     #[cfg(mobile)]
     let _ = &app;
 
-    db::save_settings(&settings).map_err(|e| e.to_string())
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    {
+        crate::tracker::set_idle_threshold(settings.idle_threshold_secs);
+        crate::tracker::set_terminal_tracking(settings.terminal_tracking);
+    }
+
+    db::save_settings(&settings).map_err(|e| e.to_string())?;
+    crate::privacy::invalidate();
+    crate::api::apply_settings(&settings)
+}
+
+#[command]
+pub fn get_api_status_cmd() -> Result<crate::api::ApiStatus, String> {
+    crate::api::status()
+}
+
+#[command]
+pub fn regenerate_api_token_cmd() -> Result<String, String> {
+    crate::api::regenerate_token()
 }
 
 #[command]
@@ -643,6 +691,72 @@ pub fn populate_board_cmd(board_id: i64) -> Result<usize, String> {
     crate::db::populate_board_from_activity(board_id).map_err(|e| e.to_string())
 }
 
+#[command]
+pub fn set_board_billing_cmd(
+    board_id: i64,
+    client_id: Option<i64>,
+    hourly_rate: Option<f64>,
+    rounding_minutes: i64,
+    rounding_mode: crate::billing::RoundingMode,
+) -> Result<(), String> {
+    if hourly_rate.is_some_and(|rate| !rate.is_finite() || rate < 0.0) {
+        return Err("The hourly rate must be a positive number".to_string());
+    }
+    crate::db::set_board_billing(board_id, client_id, hourly_rate, rounding_minutes, rounding_mode.as_str())
+        .map_err(|e| e.to_string())
+}
+
+// ── Clients & Invoices ──
+
+#[command]
+pub fn get_clients_cmd() -> Result<Vec<crate::db::Client>, String> {
+    crate::db::get_clients().map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn create_client_cmd(
+    name: String,
+    email: Option<String>,
+    address: Option<String>,
+    currency: String,
+    hourly_rate: Option<f64>,
+) -> Result<i64, String> {
+    crate::db::create_client(&name, email.as_deref(), address.as_deref(), &currency, hourly_rate)
+        .map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn update_client_cmd(client: crate::db::Client) -> Result<(), String> {
+    crate::db::update_client(&client).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_client_cmd(id: i64) -> Result<(), String> {
+    crate::db::delete_client(id).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_timesheet_cmd(
+    start_date: String,
+    end_date: String,
+    client_id: Option<i64>,
+) -> Result<crate::billing::Timesheet, String> {
+    crate::billing::timesheet(&start_date, &end_date, client_id)
+}
+
+/// `format` is `csv` (timesheet) or `html` (printable invoice).
+#[command]
+pub fn export_timesheet_cmd(
+    path: String,
+    format: String,
+    start_date: String,
+    end_date: String,
+    client_id: Option<i64>,
+    invoice_number: Option<String>,
+) -> Result<crate::billing::Timesheet, String> {
+    crate::billing::export(&path, &format, &start_date, &end_date, client_id, invoice_number.as_deref())
+}
+
 // ── Project Tasks ──
 
 #[command]
@@ -767,9 +881,27 @@ pub fn set_music_settings_cmd(
 
 #[command]
 pub fn reset_all_data_cmd() -> Result<(), String> {
+    crate::backup::create_snapshot(crate::backup::Reason::PreReset)
+        .map_err(|e| format!("Nothing was deleted. {}", e))?;
     crate::db::reset_all_data().map_err(|e| e.to_string())
 }
 
+#[command]
+pub fn list_backups_cmd() -> Result<Vec<crate::backup::Snapshot>, String> {
+    crate::backup::list_snapshots()
+}
+
+#[command]
+pub fn create_backup_cmd() -> Result<crate::backup::Snapshot, String> {
+    crate::backup::create_snapshot(crate::backup::Reason::Manual)
+}
+
+/// Returns the snapshot of the data that was replaced.
+#[command]
+pub fn restore_backup_cmd(name: String) -> Result<crate::backup::Snapshot, String> {
+    crate::backup::restore_snapshot(&name)
+}
+
 #[command]
 pub fn export_data_csv_cmd(path: String, start_date: String, end_date: String) -> Result<(), String> {
     crate::db::export_sessions_csv(&path, &start_date, &end_date).map_err(|e| e.to_string())
@@ -785,6 +917,16 @@ pub fn export_data_json_cmd(path: String, start_date: String, end_date: String)
     crate::db::export_sessions_json(&path, &start_date, &end_date).map_err(|e| e.to_string())
 }
 
+#[command]
+pub fn export_data_activitywatch_cmd(path: String, start_date: String, end_date: String) -> Result<(), String> {
+    crate::db::export_sessions_activitywatch(&path, &start_date, &end_date, None).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn export_data_ics_cmd(path: String, start_date: String, end_date: String) -> Result<(), String> {
+    crate::db::export_sessions_ics(&path, &start_date, &end_date).map_err(|e| e.to_string())
+}
+
 #[command]
 pub fn import_data_cmd(path: String) -> Result<usize, String> {
     let lower_path = path.to_lowercase();
@@ -794,16 +936,138 @@ pub fn import_data_cmd(path: String) -> Result<usize, String> {
         crate::db::import_sessions_html(&path).map_err(|e| e.to_string())
     } else if lower_path.ends_with(".md") {
         crate::db::import_sessions_markdown(&path).map_err(|e| e.to_string())
+    } else if let Some(archive) = crate::archive::read_archive(&path)? {
+        crate::archive::import(&archive, crate::archive::ConflictMode::Skip).map(|r| r.inserted)
+    } else if crate::activitywatch::is_export_file(&path) {
+        crate::db::import_sessions_activitywatch(&path).map_err(|e| e.to_string())
     } else {
         crate::db::import_sessions_json(&path).map_err(|e| e.to_string())
     }
 }
 
+#[command]
+pub fn export_archive_cmd(path: String) -> Result<crate::archive::Manifest, String> {
+    crate::archive::export_archive(&path)
+}
+
+#[command]
+pub fn import_archive_cmd(
+    path: String,
+    on_conflict: crate::archive::ConflictMode,
+) -> Result<crate::archive::ImportReport, String> {
+    crate::archive::import_archive(&path, on_conflict)
+}
+
 #[command]
 pub fn export_data_markdown_cmd(path: String, start_date: String, end_date: String) -> Result<(), String> {
     crate::db::export_sessions_markdown(&path, &start_date, &end_date).map_err(|e| e.to_string())
 }
 
+// Session editing
+
+/// Accepts RFC 3339 or a local `YYYY-MM-DDTHH:MM[:SS]` (as sent by datetime inputs).
+fn parse_local_datetime(s: &str) -> Result<chrono::DateTime<chrono::Local>, String> {
+    use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Ok(dt.with_timezone(&Local));
+    }
+    let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M"))
+        .map_err(|_| format!("Invalid date/time: {}", s))?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| format!("Invalid local time: {}", s))
+}
+
+#[command]
+pub fn create_manual_entry_cmd(
+    app_name: String,
+    window_title: String,
+    start_time: String,
+    end_time: String,
+) -> Result<i64, String> {
+    let start = parse_local_datetime(&start_time)?;
+    let end = parse_local_datetime(&end_time)?;
+    db::create_manual_entry(&app_name, &window_title, start, end).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn update_session_cmd(
+    id: i64,
+    app_name: Option<String>,
+    window_title: Option<String>,
+    start_time: Option<String>,
+    end_time: Option<String>,
+) -> Result<(), String> {
+    let update = db::SessionUpdate {
+        app_name,
+        window_title,
+        start_time: start_time.as_deref().map(parse_local_datetime).transpose()?,
+        end_time: end_time.as_deref().map(parse_local_datetime).transpose()?,
+    };
+    db::update_session(id, &update).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn split_session_cmd(id: i64, at: String) -> Result<(i64, i64), String> {
+    let at = parse_local_datetime(&at)?;
+    db::split_session(id, at).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn merge_sessions_cmd(ids: Vec<i64>) -> Result<i64, String> {
+    db::merge_sessions(&ids).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn delete_session_cmd(id: i64) -> Result<(), String> {
+    db::delete_session(id).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn get_session_edits_cmd(limit: Option<i64>) -> Result<Vec<db::SessionEdit>, String> {
+    db::get_session_edits(limit.unwrap_or(100)).map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn revert_session_edit_cmd(id: i64) -> Result<(), String> {
+    db::revert_session_edit(id).map_err(|e| e.to_string())
+}
+
+// Classification rules
+
+#[command]
+pub fn get_classification_rules_cmd() -> Result<Vec<crate::db::ClassificationRule>, String> {
+    crate::db::get_classification_rules().map_err(|e| e.to_string())
+}
+
+#[command]
+pub fn save_classification_rule_cmd(rule: crate::db::ClassificationRule) -> Result<i64, String> {
+    crate::rules::validate(&rule)?;
+    let id = crate::db::save_classification_rule(&rule).map_err(|e| e.to_string())?;
+    crate::rules::invalidate();
+    Ok(id)
+}
+
+#[command]
+pub fn delete_classification_rule_cmd(id: i64) -> Result<(), String> {
+    crate::db::delete_classification_rule(id).map_err(|e| e.to_string())?;
+    crate::rules::invalidate();
+    Ok(())
+}
+
+#[command]
+pub fn export_rules_cmd(path: String) -> Result<usize, String> {
+    crate::rules::export_rules(&path)
+}
+
+#[command]
+pub fn import_rules_cmd(path: String, replace: bool) -> Result<usize, String> {
+    crate::rules::import_rules(&path, replace)
+}
+
 #[command]
 pub fn save_auto_export_settings_cmd(
     enabled: bool,
@@ -817,6 +1081,13 @@ pub fn save_auto_export_settings_cmd(
     crate::db::save_settings(&settings).map_err(|e| e.to_string())
 }
 
+/// Roll up sessions older than `days` (default: the `retention_days` setting).
+#[command]
+pub fn run_retention_cmd(days: Option<u32>, dry_run: bool) -> Result<crate::db::RetentionReport, String> {
+    let days = days.unwrap_or_else(|| crate::db::get_settings().retention_days);
+    crate::db::apply_retention(days, dry_run).map_err(|e| e.to_string())
+}
+
 #[command]
 pub fn get_auto_export_settings_cmd() -> Result<serde_json::Value, String> {
     let settings = crate::db::get_settings();
@@ -850,6 +1121,74 @@ pub fn get_coding_project_stats_today() -> Vec<crate::db::CodingProjectStats> {
     crate::db::get_coding_project_stats_today().unwrap_or_default()
 }
 
+#[command]
+pub fn get_coding_repo_stats_cmd(from: String, to: String) -> Vec<crate::db::CodingRepoStats> {
+    crate::db::get_coding_repo_stats(&from, &to).unwrap_or_default()
+}
+
+#[command]
+pub fn get_coding_branch_stats_cmd(
+    from: String,
+    to: String,
+    repo: Option<String>,
+) -> Vec<crate::db::CodingBranchStats> {
+    crate::db::get_coding_branch_stats(&from, &to, repo.as_deref()).unwrap_or_default()
+}
+
+#[command]
+pub fn get_shell_commands_cmd(from: String, to: String) -> Vec<crate::db::ShellCommand> {
+    crate::db::get_shell_commands(&from, &to).unwrap_or_default()
+}
+
+#[command]
+pub fn get_incognito_status_cmd() -> crate::privacy::IncognitoStatus {
+    crate::privacy::incognito_status()
+}
+
+/// Pause all recording for `minutes`, or until stopped when `None`.
+#[command]
+pub fn start_incognito_cmd(minutes: Option<i64>) -> Result<crate::privacy::IncognitoStatus, String> {
+    let until = minutes
+        .filter(|m| *m > 0)
+        .map(|m| chrono::Local::now() + chrono::Duration::minutes(m));
+    crate::privacy::start_incognito(until)?;
+    Ok(crate::privacy::incognito_status())
+}
+
+#[command]
+pub fn stop_incognito_cmd() -> Result<crate::privacy::IncognitoStatus, String> {
+    crate::privacy::stop_incognito()?;
+    Ok(crate::privacy::incognito_status())
+}
+
+#[command]
+pub fn scrub_titles_cmd(request: crate::privacy::ScrubRequest) -> Result<crate::db::ScrubReport, String> {
+    crate::privacy::scrub(&request)
+}
+
+#[command]
+pub fn get_encryption_status_cmd() -> Result<crate::vault::EncryptionStatus, String> {
+    crate::vault::status()
+}
+
+/// Encrypt the database with a key from the OS secret store. Passphrase keys are
+/// only offered by the CLI, since the app would need the passphrase on every start.
+#[command]
+pub fn encrypt_database_cmd() -> Result<crate::vault::EncryptionStatus, String> {
+    crate::vault::encrypt_database(crate::vault::KeySource::Keyring)
+}
+
+#[command]
+pub fn decrypt_database_cmd() -> Result<crate::vault::EncryptionStatus, String> {
+    crate::vault::decrypt_database()
+}
+
+/// Full-text search; matched terms are wrapped in `<mark>` in the snippets.
+#[command]
+pub fn search_history_cmd(query: crate::db::SearchQuery) -> Result<Vec<crate::db::SearchDay>, String> {
+    crate::db::search_history(&query, ("<mark>", "</mark>")).map_err(|e| e.to_string())
+}
+
 #[command]
 pub fn get_total_coding_time_today() -> i64 {
     crate::db::get_total_coding_time_today().unwrap_or(0)
@@ -1,10 +1,12 @@
 //! Невелика база
 
+use crate::privacy::TitlePrivacy;
 use chrono::{DateTime, Local, NaiveDate};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
-use rusqlite::{params, Connection, Result};
+use rusqlite::{params, Connection, OptionalExtension, Result, Transaction};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 static DB: Lazy<Mutex<Option<Connection>>> = Lazy::new(|| Mutex::new(None));
@@ -18,6 +20,11 @@ pub struct ActivitySession {
     pub start_time: DateTime<Local>,
     pub end_time: Option<DateTime<Local>>,
     pub duration_seconds: i64,
+    /// Active tab reported by the browser extension (see `browser`).
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub domain: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +33,9 @@ pub struct AppUsageSummary {
     pub exe_path: String,
     pub total_seconds: i64,
     pub session_count: i64,
+    /// From app/exe rules only, since the summary is not per window title.
+    #[serde(default)]
+    pub category: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -68,6 +78,21 @@ pub struct CodingSession {
     pub start_time: String,
     pub end_time: Option<String>,
     pub duration_seconds: i64,
+    /// `title` (parsed from the window title) or `heartbeat` (editor plugin).
+    #[serde(default = "default_coding_source")]
+    pub source: String,
+    #[serde(default)]
+    pub branch: Option<String>,
+    #[serde(default)]
+    pub repo_root: Option<String>,
+    #[serde(default)]
+    pub repo_name: Option<String>,
+    #[serde(default)]
+    pub repo_remote: Option<String>,
+}
+
+fn default_coding_source() -> String {
+    CODING_SOURCE_TITLE.to_string()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -87,6 +112,26 @@ pub struct CodingProjectStats {
     pub session_count: i64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodingRepoStats {
+    pub repo_name: String,
+    pub repo_remote: Option<String>,
+    pub total_seconds: i64,
+    pub ai_seconds: i64,
+    pub session_count: i64,
+    pub branch_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodingBranchStats {
+    pub repo_name: String,
+    pub branch: String,
+    pub total_seconds: i64,
+    pub session_count: i64,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
     pub language: String,
@@ -98,6 +143,55 @@ pub struct Settings {
     pub auto_export_folder: String,
     pub last_export_time: Option<String>,
     pub excluded_processes: Vec<String>,
+    #[serde(default = "default_idle_threshold_secs")]
+    pub idle_threshold_secs: u64,
+    #[serde(default)]
+    pub api_enabled: bool,
+    #[serde(default = "default_api_port")]
+    pub api_port: u16,
+    /// Record the program running inside terminal windows (see `terminal`).
+    #[serde(default = "default_true")]
+    pub terminal_tracking: bool,
+    /// How window titles are stored when no rule says otherwise (see `privacy`).
+    #[serde(default)]
+    pub title_privacy: TitlePrivacy,
+    /// Store only the app name for private-browsing windows.
+    #[serde(default = "default_true")]
+    pub redact_private_windows: bool,
+    /// Days of raw sessions to keep before they are rolled up into `daily_usage`; 0 keeps everything.
+    #[serde(default)]
+    pub retention_days: u32,
+    /// Take scheduled snapshots of the database (see `backup`).
+    #[serde(default = "default_true")]
+    pub backup_enabled: bool,
+    #[serde(default = "default_backup_interval_hours")]
+    pub backup_interval_hours: u32,
+    /// Name and address printed under "From" on invoices.
+    #[serde(default)]
+    pub invoice_issuer: String,
+    /// Currency of boards without a client.
+    #[serde(default = "default_billing_currency")]
+    pub billing_currency: String,
+}
+
+/// Inactivity (seconds) after which the tracker treats the user as away.
+pub const DEFAULT_IDLE_THRESHOLD_SECS: u64 = 300;
+pub const MIN_IDLE_THRESHOLD_SECS: u64 = 30;
+
+fn default_idle_threshold_secs() -> u64 {
+    DEFAULT_IDLE_THRESHOLD_SECS
+}
+
+fn default_api_port() -> u16 {
+    crate::api::DEFAULT_API_PORT
+}
+
+fn default_backup_interval_hours() -> u32 {
+    crate::backup::DEFAULT_INTERVAL_HOURS
+}
+
+fn default_billing_currency() -> String {
+    "USD".to_string()
 }
 
 impl Default for Settings {
@@ -112,6 +206,17 @@ impl Default for Settings {
             auto_export_folder: String::new(),
             last_export_time: None,
             excluded_processes: Vec::new(),
+            idle_threshold_secs: DEFAULT_IDLE_THRESHOLD_SECS,
+            api_enabled: false,
+            api_port: crate::api::DEFAULT_API_PORT,
+            terminal_tracking: true,
+            title_privacy: TitlePrivacy::Full,
+            redact_private_windows: true,
+            retention_days: 0,
+            backup_enabled: true,
+            backup_interval_hours: default_backup_interval_hours(),
+            invoice_issuer: String::new(),
+            billing_currency: default_billing_currency(),
         }
     }
 }
@@ -143,172 +248,71 @@ pub fn get_db_path() -> PathBuf {
     db_path
 }
 
-pub fn init_database() -> Result<()> {
-    let db_path = get_db_path();
-    let conn = Connection::open(&db_path)?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS activity_sessions (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            app_name TEXT NOT NULL,
-            window_title TEXT NOT NULL,
-            exe_path TEXT NOT NULL,
-            start_time TEXT NOT NULL,
-            end_time TEXT,
-            duration_seconds INTEGER DEFAULT 0
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS settings (
-            key TEXT PRIMARY KEY,
-            value TEXT NOT NULL
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_start_time ON activity_sessions(start_time)",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS tasks (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            app_name TEXT NOT NULL,
-            description TEXT,
-            goal_seconds INTEGER NOT NULL,
-            created_at TEXT NOT NULL,
-            status TEXT DEFAULT 'active',
-            title_filter TEXT
-        )",
-        [],
-    )?;
-
-    // Migration for existing table
-    let _ = conn.execute("ALTER TABLE tasks ADD COLUMN title_filter TEXT", []);
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS project_boards (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL,
-            board_type TEXT NOT NULL DEFAULT 'activity',
-            github_project_id TEXT,
-            github_project_url TEXT,
-            synced_at TEXT,
-            created_at TEXT NOT NULL
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS board_items (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            board_id INTEGER NOT NULL,
-            app_name TEXT NOT NULL,
-            window_title TEXT,
-            tracked_seconds INTEGER DEFAULT 0,
-            status TEXT DEFAULT 'recorded',
-            date TEXT NOT NULL,
-            created_at TEXT NOT NULL,
-            FOREIGN KEY (board_id) REFERENCES project_boards(id) ON DELETE CASCADE
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_board_items_board_id ON board_items(board_id)",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS project_tasks (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            board_id INTEGER NOT NULL,
-            title TEXT NOT NULL,
-            description TEXT,
-            status TEXT NOT NULL DEFAULT 'todo',
-            priority TEXT NOT NULL DEFAULT 'medium',
-            due_date TEXT,
-            created_at TEXT NOT NULL,
-            FOREIGN KEY (board_id) REFERENCES project_boards(id) ON DELETE CASCADE
-        )",
-        [],
-    )?;
+/// Lock file held by the process that tracks activity, the app or `timigs daemon`.
+static INSTANCE_LOCK: once_cell::sync::OnceCell<std::fs::File> = once_cell::sync::OnceCell::new();
+
+/// Become the tracking instance. Fails while another process holds the lock next to
+/// the database; the lock goes away with the process, even after a crash.
+pub fn acquire_instance_lock() -> std::result::Result<(), String> {
+    INSTANCE_LOCK
+        .get_or_try_init(|| {
+            let path = get_db_path().with_file_name("timigs.lock");
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&path)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            match file.try_lock() {
+                Ok(()) => Ok(file),
+                Err(std::fs::TryLockError::WouldBlock) => {
+                    Err("TimiGS is already running (the app or `timigs daemon`)".to_string())
+                }
+                Err(std::fs::TryLockError::Error(e)) => Err(format!("{}: {}", path.display(), e)),
+            }
+        })
+        .map(|_| ())
+}
 
-    // Migration: add board_type column to existing project_boards
-    let _ = conn.execute(
-        "ALTER TABLE project_boards ADD COLUMN board_type TEXT NOT NULL DEFAULT 'activity'",
-        [],
-    );
+pub fn holds_instance_lock() -> bool {
+    INSTANCE_LOCK.get().is_some()
+}
 
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS cloud_accounts (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            email TEXT NOT NULL,
-            provider TEXT NOT NULL,
-            access_token TEXT NOT NULL,
-            refresh_token TEXT NOT NULL DEFAULT '',
-            created_at TEXT NOT NULL
-        )",
-        [],
-    )?;
-//music kurwa (Це ідея Славіка....ОСЬ СУКА Я ХОТІЛ КИНУТИ ПУЛ, НО НІ ТРЕБА ЙОМУ ЗАХУЯРИТИ ТАКУ ІДЕЮ)
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS music_sessions (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            app_name TEXT NOT NULL,
-            window_title TEXT,
-            exe_path TEXT NOT NULL,
-            start_time TEXT NOT NULL,
-            end_time TEXT,
-            duration_seconds INTEGER DEFAULT 0
-        )",
-        [],
-    )?;
-// НІ ну реально їбашити базу для музики)
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_music_start_time ON music_sessions(start_time)",
-        [],
-    )?;
+pub fn init_database() -> Result<()> {
+    let db_path = get_db_path();
+    let mut conn = Connection::open(&db_path)?;
+    crate::vault::unlock(&conn, &db_path)?;
+
+    crate::migrations::run_migrations(&mut conn, Some(&db_path))?;
+
+    // Open sessions belong to whichever process tracks; only that one may close them
+    match acquire_instance_lock() {
+        Ok(()) => match recover_dangling_sessions(&conn) {
+            Ok(0) => {}
+            Ok(n) => println!("Closed {} session(s) left open by an unclean shutdown", n),
+            Err(e) => eprintln!("Failed to recover open sessions: {}", e),
+        },
+        Err(e) => eprintln!("{}; leaving its open sessions alone", e),
+    }
+    match seal_cloud_tokens_in(&conn, &crate::vault::seal) {
+        Ok(0) => {}
+        Ok(n) => println!("Encrypted the stored tokens of {} cloud account(s)", n),
+        Err(e) => eprintln!("Failed to encrypt cloud tokens: {}", e),
+    }
 
-    // Coding sessions - track developer activity
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS coding_sessions (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            app_name TEXT NOT NULL,
-            editor_name TEXT NOT NULL,
-            file_path TEXT,
-            language TEXT,
-            project_dir TEXT,
-            is_ai_assisted INTEGER NOT NULL DEFAULT 0,
-            window_title TEXT NOT NULL,
-            exe_path TEXT NOT NULL,
-            start_time TEXT NOT NULL,
-            end_time TEXT,
-            duration_seconds INTEGER DEFAULT 0
-        )",
-        [],
-    )?;
+    *DB.lock() = Some(conn);
+    Ok(())
+}
 
-    // Safe migrations for coding_sessions (ignored if column already exists)
-    let _ = conn.execute("ALTER TABLE coding_sessions ADD COLUMN app_name TEXT NOT NULL DEFAULT ''", []);
-    let _ = conn.execute("ALTER TABLE coding_sessions ADD COLUMN editor_name TEXT NOT NULL DEFAULT ''", []);
-    let _ = conn.execute("ALTER TABLE coding_sessions ADD COLUMN file_path TEXT", []);
-    let _ = conn.execute("ALTER TABLE coding_sessions ADD COLUMN language TEXT", []);
-    let _ = conn.execute("ALTER TABLE coding_sessions ADD COLUMN project_dir TEXT", []);
-    let _ = conn.execute("ALTER TABLE coding_sessions ADD COLUMN is_ai_assisted INTEGER NOT NULL DEFAULT 0", []);
-    let _ = conn.execute("ALTER TABLE coding_sessions ADD COLUMN window_title TEXT NOT NULL DEFAULT ''", []);
-    let _ = conn.execute("ALTER TABLE coding_sessions ADD COLUMN exe_path TEXT NOT NULL DEFAULT ''", []);
-    let _ = conn.execute("ALTER TABLE coding_sessions ADD COLUMN start_time TEXT NOT NULL DEFAULT ''", []);
-    let _ = conn.execute("ALTER TABLE coding_sessions ADD COLUMN end_time TEXT", []);
-    let _ = conn.execute("ALTER TABLE coding_sessions ADD COLUMN duration_seconds INTEGER DEFAULT 0", []);
+/// Open the database for a short-lived client such as the `timigs` CLI, which may run
+/// next to the tracker: unlike `init_database`, open sessions are left alone.
+pub fn init_database_client() -> Result<()> {
+    let db_path = get_db_path();
+    let mut conn = Connection::open(&db_path)?;
+    conn.busy_timeout(std::time::Duration::from_secs(5))?;
+    crate::vault::unlock(&conn, &db_path)?;
 
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_coding_start_time ON coding_sessions(start_time)",
-        [],
-    )?;
+    crate::migrations::run_migrations(&mut conn, Some(&db_path))?;
 
     *DB.lock() = Some(conn);
     Ok(())
@@ -318,65 +322,125 @@ pub fn close_database() {
     *DB.lock() = None;
 }
 
-pub fn start_session(app_name: &str, window_title: &str, exe_path: &str) -> Result<i64> {
+/// Run `f` on the open connection, outside any transaction.
+pub fn with_connection<T>(f: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    f(conn)
+}
 
-    let now = Local::now();
+/// `with_connection` for callers that write into the connection itself, such as a
+/// backup restore.
+pub fn with_connection_mut<T>(f: impl FnOnce(&mut Connection) -> Result<T>) -> Result<T> {
+    let mut guard = DB.lock();
+    let conn = guard.as_mut().ok_or(rusqlite::Error::InvalidQuery)?;
+    f(conn)
+}
+
+pub fn start_session_in(
+    conn: &Connection,
+    app_name: &str,
+    window_title: &str,
+    exe_path: &str,
+    start_time: DateTime<Local>,
+) -> Result<i64> {
     conn.execute(
         "INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time) VALUES (?1, ?2, ?3, ?4)",
-        params![app_name, window_title, exe_path, now.to_rfc3339()],
+        params![app_name, window_title, exe_path, start_time.to_rfc3339()],
     )?;
 
     Ok(conn.last_insert_rowid())
 }
 
-pub fn end_session(id: i64) -> Result<()> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
-
-    let now = Local::now();
-
-    // Get start time to calculate duration
+/// Close session `id` of `table` at `end_time`.
+fn end_session_row(conn: &Connection, table: &str, id: i64, end_time: DateTime<Local>) -> Result<()> {
     let start_time: String = conn.query_row(
-        "SELECT start_time FROM activity_sessions WHERE id = ?1",
+        &format!("SELECT start_time FROM {} WHERE id = ?1", table),
         [id],
         |row| row.get(0),
     )?;
 
     if let Ok(start) = DateTime::parse_from_rfc3339(&start_time) {
-        let duration = (now - start.with_timezone(&Local)).num_seconds();
+        let duration = (end_time - start.with_timezone(&Local)).num_seconds();
+        let duration = std::cmp::max(0, duration);
         conn.execute(
-            "UPDATE activity_sessions SET end_time = ?1, duration_seconds = ?2 WHERE id = ?3",
-            params![now.to_rfc3339(), duration, id],
+            &format!("UPDATE {} SET end_time = ?1, duration_seconds = ?2 WHERE id = ?3", table),
+            params![end_time.to_rfc3339(), duration, id],
         )?;
     }
 
     Ok(())
 }
 
-pub fn end_session_retroactive(id: i64, end_time: DateTime<Local>) -> Result<()> {
+pub fn end_session_in(conn: &Connection, id: i64, end_time: DateTime<Local>) -> Result<()> {
+    end_session_row(conn, "activity_sessions", id, end_time)
+}
+
+const SESSION_TABLES: [&str; 3] = ["activity_sessions", "music_sessions", "coding_sessions"];
+
+/// Mark the tracker's open sessions as still alive. Called periodically by the
+/// tracking loop with the ids it holds; other open rows (imported ones, or ones whose
+/// end failed to save) keep their last heartbeat, so recovery ends them there.
+pub fn heartbeat_sessions(activity: Option<i64>, music: Option<i64>, coding: Option<i64>) -> Result<()> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    heartbeat_sessions_in(conn, [activity, music, coding], Local::now())
+}
 
-    let start_time: String = conn.query_row(
-        "SELECT start_time FROM activity_sessions WHERE id = ?1",
-        [id],
-        |row| row.get(0),
-    )?;
-
-    if let Ok(start) = DateTime::parse_from_rfc3339(&start_time) {
-        let duration = (end_time - start.with_timezone(&Local)).num_seconds();
-        let duration = std::cmp::max(0, duration);
+/// `ids` in `SESSION_TABLES` order.
+fn heartbeat_sessions_in(conn: &Connection, ids: [Option<i64>; 3], now: DateTime<Local>) -> Result<()> {
+    let now = now.to_rfc3339();
+    for (table, id) in SESSION_TABLES.into_iter().zip(ids) {
+        let Some(id) = id else {
+            continue;
+        };
         conn.execute(
-            "UPDATE activity_sessions SET end_time = ?1, duration_seconds = ?2 WHERE id = ?3",
-            params![end_time.to_rfc3339(), duration, id],
+            &format!("UPDATE {} SET last_heartbeat = ?1 WHERE id = ?2 AND end_time IS NULL", table),
+            params![now, id],
         )?;
     }
-
     Ok(())
 }
 
+/// Close sessions left open by a crash or forced shutdown at their last heartbeat
+/// (or their start, if the tracker died before the first one). Must run before the
+/// tracker starts, while no session is legitimately open.
+pub fn recover_dangling_sessions(conn: &Connection) -> Result<usize> {
+    let mut recovered = 0;
+
+    for table in SESSION_TABLES {
+        let dangling: Vec<(i64, String, Option<String>)> = {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT id, start_time, last_heartbeat FROM {} WHERE end_time IS NULL",
+                table
+            ))?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.collect::<Result<_>>()?
+        };
+
+        for (id, start_time, last_heartbeat) in dangling {
+            let end_time = last_heartbeat.unwrap_or_else(|| start_time.clone());
+            let duration = match (
+                DateTime::parse_from_rfc3339(&start_time),
+                DateTime::parse_from_rfc3339(&end_time),
+            ) {
+                (Ok(start), Ok(end)) => std::cmp::max(0, (end - start).num_seconds()),
+                _ => 0,
+            };
+            conn.execute(
+                &format!(
+                    "UPDATE {} SET end_time = ?1, duration_seconds = ?2 WHERE id = ?3",
+                    table
+                ),
+                params![end_time, duration, id],
+            )?;
+            recovered += 1;
+        }
+    }
+
+    Ok(recovered)
+}
+
 pub fn get_today_sessions() -> Result<Vec<ActivitySession>> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
@@ -385,7 +449,7 @@ pub fn get_today_sessions() -> Result<Vec<ActivitySession>> {
     let start_of_day = today.and_hms_opt(0, 0, 0).unwrap();
 
     let mut stmt = conn.prepare(
-        "SELECT id, app_name, window_title, exe_path, start_time, end_time, duration_seconds
+        "SELECT id, app_name, window_title, exe_path, start_time, end_time, duration_seconds, url, domain
          FROM activity_sessions
          WHERE date(start_time) = date(?1)
          ORDER BY start_time DESC",
@@ -406,6 +470,8 @@ pub fn get_today_sessions() -> Result<Vec<ActivitySession>> {
                     .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
                     .map(|dt| dt.with_timezone(&Local)),
                 duration_seconds: row.get(6)?,
+                url: row.get(7)?,
+                domain: row.get(8)?,
             })
         })?
         .collect::<Result<Vec<_>>>()?;
@@ -418,7 +484,7 @@ pub fn get_sessions_range(from: NaiveDate, to: NaiveDate) -> Result<Vec<Activity
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
     let mut stmt = conn.prepare(
-        "SELECT id, app_name, window_title, exe_path, start_time, end_time, duration_seconds
+        "SELECT id, app_name, window_title, exe_path, start_time, end_time, duration_seconds, url, domain
          FROM activity_sessions
          WHERE date(start_time) >= date(?1) AND date(start_time) <= date(?2)
          ORDER BY start_time DESC",
@@ -439,6 +505,8 @@ pub fn get_sessions_range(from: NaiveDate, to: NaiveDate) -> Result<Vec<Activity
                     .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
                     .map(|dt| dt.with_timezone(&Local)),
                 duration_seconds: row.get(6)?,
+                url: row.get(7)?,
+                domain: row.get(8)?,
             })
         })?
         .collect::<Result<Vec<_>>>()?;
@@ -451,7 +519,7 @@ pub fn get_all_sessions() -> Result<Vec<ActivitySession>> {
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
     let mut stmt = conn.prepare(
-        "SELECT id, app_name, window_title, exe_path, start_time, end_time, duration_seconds
+        "SELECT id, app_name, window_title, exe_path, start_time, end_time, duration_seconds, url, domain
          FROM activity_sessions
          ORDER BY start_time DESC",
     )?;
@@ -471,6 +539,8 @@ pub fn get_all_sessions() -> Result<Vec<ActivitySession>> {
                     .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
                     .map(|dt| dt.with_timezone(&Local)),
                 duration_seconds: row.get(6)?,
+                url: row.get(7)?,
+                domain: row.get(8)?,
             })
         })?
         .collect::<Result<Vec<_>>>()?;
@@ -478,27 +548,40 @@ pub fn get_all_sessions() -> Result<Vec<ActivitySession>> {
     Ok(sessions)
 }
 
+/// Per-app totals for one day, from raw sessions and from rolled-up `daily_usage` rows.
+const DAY_SUMMARY_SQL: &str =
+    "SELECT app_name, exe_path, SUM(seconds) as total, SUM(sessions) as count
+     FROM (
+         SELECT app_name, exe_path, duration_seconds AS seconds, 1 AS sessions
+         FROM activity_sessions WHERE date(start_time) = date(?1)
+         UNION ALL
+         SELECT app_name, exe_path, total_seconds, session_count
+         FROM daily_usage WHERE date = date(?1)
+     )
+     GROUP BY app_name
+     ORDER BY total DESC";
+
 pub fn get_today_summary() -> Result<Vec<AppUsageSummary>> {
+    let rules = crate::rules::ruleset();
+
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
     let today = Local::now().date_naive();
 
-    let mut stmt = conn.prepare(
-        "SELECT app_name, exe_path, SUM(duration_seconds) as total, COUNT(*) as count
-         FROM activity_sessions
-         WHERE date(start_time) = date(?1)
-         GROUP BY app_name
-         ORDER BY total DESC",
-    )?;
+    let mut stmt = conn.prepare(DAY_SUMMARY_SQL)?;
 
     let summaries = stmt
         .query_map([today.to_string()], |row| {
+            let app_name: String = row.get(0)?;
+            let exe_path: String = row.get(1)?;
+            let category = rules.classify(&app_name, &exe_path, "").category;
             Ok(AppUsageSummary {
-                app_name: row.get(0)?,
-                exe_path: row.get(1)?,
+                app_name,
+                exe_path,
                 total_seconds: row.get(2)?,
                 session_count: row.get(3)?,
+                category,
             })
         })?
         .collect::<Result<Vec<_>>>()?;
@@ -507,24 +590,24 @@ pub fn get_today_summary() -> Result<Vec<AppUsageSummary>> {
 }
 
 pub fn get_summary_by_date(date_str: &str) -> Result<Vec<AppUsageSummary>> {
+    let rules = crate::rules::ruleset();
+
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    let mut stmt = conn.prepare(
-        "SELECT app_name, exe_path, SUM(duration_seconds) as total, COUNT(*) as count
-         FROM activity_sessions
-         WHERE date(start_time) = date(?1)
-         GROUP BY app_name
-         ORDER BY total DESC",
-    )?;
+    let mut stmt = conn.prepare(DAY_SUMMARY_SQL)?;
 
     let summaries = stmt
         .query_map([date_str.to_string()], |row| {
+            let app_name: String = row.get(0)?;
+            let exe_path: String = row.get(1)?;
+            let category = rules.classify(&app_name, &exe_path, "").category;
             Ok(AppUsageSummary {
-                app_name: row.get(0)?,
-                exe_path: row.get(1)?,
+                app_name,
+                exe_path,
                 total_seconds: row.get(2)?,
                 session_count: row.get(3)?,
+                category,
             })
         })?
         .collect::<Result<Vec<_>>>()?;
@@ -563,6 +646,19 @@ pub fn get_weekly_stats() -> Result<Vec<DailyStats>> {
         })?
         .collect::<std::result::Result<Vec<_>, rusqlite::Error>>()?;
 
+    // Days past the retention window only survive as roll-ups
+    let mut rollup_stmt = conn.prepare(
+        "SELECT date, app_name, SUM(total_seconds)
+         FROM daily_usage
+         WHERE date >= date('now', '-10 days')
+         GROUP BY date, app_name",
+    )?;
+    let rolled_up = rollup_stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
     use chrono::TimeZone;
     use std::collections::HashSet;
 
@@ -640,7 +736,7 @@ pub fn get_weekly_stats() -> Result<Vec<DailyStats>> {
             }
         }
 
-        let total_seconds = if intervals.is_empty() {
+        let mut total_seconds = if intervals.is_empty() {
             0
         } else {
             intervals.sort_by_key(|k| k.0);
@@ -664,6 +760,11 @@ pub fn get_weekly_stats() -> Result<Vec<DailyStats>> {
                 .sum::<i64>()
         };
 
+        for (_, app_name, seconds) in rolled_up.iter().filter(|r| r.0 == day_str) {
+            total_seconds += seconds;
+            apps_for_day.insert(app_name.clone());
+        }
+
         stats.push(DailyStats {
             date: day_str,
             total_seconds,
@@ -753,6 +854,102 @@ pub fn get_settings() -> Settings {
             settings.excluded_processes = serde_json::from_str(&excluded).unwrap_or_default();
         }
 
+        if let Ok(threshold) = conn.query_row(
+            "SELECT value FROM settings WHERE key = 'idle_threshold_secs'",
+            [],
+            |row| row.get::<_, String>(0),
+        ) {
+            if let Ok(secs) = threshold.parse::<u64>() {
+                settings.idle_threshold_secs = secs.max(MIN_IDLE_THRESHOLD_SECS);
+            }
+        }
+
+        if let Ok(api_enabled) = conn.query_row(
+            "SELECT value FROM settings WHERE key = 'api_enabled'",
+            [],
+            |row| row.get::<_, String>(0),
+        ) {
+            settings.api_enabled = api_enabled == "true";
+        }
+
+        if let Ok(port) = conn.query_row(
+            "SELECT value FROM settings WHERE key = 'api_port'",
+            [],
+            |row| row.get::<_, String>(0),
+        ) {
+            if let Ok(port) = port.parse::<u16>() {
+                settings.api_port = port;
+            }
+        }
+
+        if let Ok(terminal_tracking) = conn.query_row(
+            "SELECT value FROM settings WHERE key = 'terminal_tracking'",
+            [],
+            |row| row.get::<_, String>(0),
+        ) {
+            settings.terminal_tracking = terminal_tracking == "true";
+        }
+
+        if let Ok(title_privacy) = conn.query_row(
+            "SELECT value FROM settings WHERE key = 'title_privacy'",
+            [],
+            |row| row.get::<_, String>(0),
+        ) {
+            settings.title_privacy = TitlePrivacy::parse(&title_privacy).unwrap_or_default();
+        }
+
+        if let Ok(redact_private_windows) = conn.query_row(
+            "SELECT value FROM settings WHERE key = 'redact_private_windows'",
+            [],
+            |row| row.get::<_, String>(0),
+        ) {
+            settings.redact_private_windows = redact_private_windows == "true";
+        }
+
+        if let Ok(days) = conn.query_row(
+            "SELECT value FROM settings WHERE key = 'retention_days'",
+            [],
+            |row| row.get::<_, String>(0),
+        ) {
+            settings.retention_days = days.parse().unwrap_or(0);
+        }
+
+        if let Ok(backup_enabled) = conn.query_row(
+            "SELECT value FROM settings WHERE key = 'backup_enabled'",
+            [],
+            |row| row.get::<_, String>(0),
+        ) {
+            settings.backup_enabled = backup_enabled == "true";
+        }
+
+        if let Ok(hours) = conn.query_row(
+            "SELECT value FROM settings WHERE key = 'backup_interval_hours'",
+            [],
+            |row| row.get::<_, String>(0),
+        ) {
+            if let Ok(hours) = hours.parse::<u32>() {
+                settings.backup_interval_hours = hours.max(1);
+            }
+        }
+
+        if let Ok(issuer) = conn.query_row(
+            "SELECT value FROM settings WHERE key = 'invoice_issuer'",
+            [],
+            |row| row.get::<_, String>(0),
+        ) {
+            settings.invoice_issuer = issuer;
+        }
+
+        if let Ok(currency) = conn.query_row(
+            "SELECT value FROM settings WHERE key = 'billing_currency'",
+            [],
+            |row| row.get::<_, String>(0),
+        ) {
+            if !currency.trim().is_empty() {
+                settings.billing_currency = currency;
+            }
+        }
+
         settings
     } else {
         Settings::default()
@@ -814,6 +1011,51 @@ pub fn save_settings(settings: &Settings) -> Result<()> {
         [&excluded_json],
     )?;
 
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES ('idle_threshold_secs', ?1)",
+        [&settings.idle_threshold_secs.max(MIN_IDLE_THRESHOLD_SECS).to_string()],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES ('api_enabled', ?1)",
+        [if settings.api_enabled { "true" } else { "false" }],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES ('api_port', ?1)",
+        [&settings.api_port.to_string()],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES ('terminal_tracking', ?1)",
+        [if settings.terminal_tracking { "true" } else { "false" }],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES ('title_privacy', ?1)",
+        [settings.title_privacy.as_str()],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES ('redact_private_windows', ?1)",
+        [if settings.redact_private_windows { "true" } else { "false" }],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES ('retention_days', ?1)",
+        [settings.retention_days.to_string()],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES ('backup_enabled', ?1)",
+        [if settings.backup_enabled { "true" } else { "false" }],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES ('backup_interval_hours', ?1)",
+        [settings.backup_interval_hours.max(1).to_string()],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES ('invoice_issuer', ?1)",
+        [&settings.invoice_issuer],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES ('billing_currency', ?1)",
+        [settings.billing_currency.trim().to_uppercase()],
+    )?;
+
     Ok(())
 }
 
@@ -1020,534 +1262,1607 @@ pub fn get_recent_apps() -> Result<Vec<String>> {
     Ok(apps)
 }
 
-// Project Boards
+// Session editing
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ProjectBoard {
-    pub id: i64,
-    pub name: String,
-    pub board_type: String,
-    pub github_project_id: Option<String>,
-    pub github_project_url: Option<String>,
-    pub synced_at: Option<String>,
-    pub created_at: String,
-}
+/// `exe_path` of sessions entered by hand rather than tracked.
+pub const MANUAL_EXE_PATH: &str = "manual";
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BoardItem {
+/// An activity session row as stored; snapshots of these make up the edit audit trail.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionRow {
     pub id: i64,
-    pub board_id: i64,
     pub app_name: String,
-    pub window_title: Option<String>,
-    pub tracked_seconds: i64,
-    pub status: String,
-    pub date: String,
+    pub window_title: String,
+    pub exe_path: String,
+    pub start_time: String,
+    pub end_time: Option<String>,
+    pub duration_seconds: i64,
+    pub is_manual: bool,
+    // Snapshots recorded before these were kept don't have them
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub domain: Option<String>,
+    #[serde(default)]
+    pub process_name: Option<String>,
+    #[serde(default)]
+    pub process_cwd: Option<String>,
+}
+
+/// One entry of the audit trail: the rows an edit touched, before and after.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionEdit {
+    pub id: i64,
+    pub action: String,
+    pub before: Vec<SessionRow>,
+    pub after: Vec<SessionRow>,
     pub created_at: String,
+    pub reverted_at: Option<String>,
 }
 
-pub fn create_board(name: &str, board_type: &str) -> Result<i64> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
-    let now = Local::now().to_rfc3339();
+/// Fields to change on a session; `None` keeps the current value.
+#[derive(Debug, Clone, Default)]
+pub struct SessionUpdate {
+    pub app_name: Option<String>,
+    pub window_title: Option<String>,
+    pub start_time: Option<DateTime<Local>>,
+    pub end_time: Option<DateTime<Local>>,
+}
 
-    conn.execute(
-        "INSERT INTO project_boards (name, board_type, created_at) VALUES (?1, ?2, ?3)",
-        params![name, board_type, now],
-    )?;
-    Ok(conn.last_insert_rowid())
+const SESSION_ROW_COLUMNS: &str = "id, app_name, window_title, exe_path, start_time, end_time,
+    duration_seconds, is_manual, url, domain, process_name, process_cwd";
+
+fn edit_error(msg: String) -> rusqlite::Error {
+    rusqlite::Error::ToSqlConversionFailure(msg.into())
 }
 
-pub fn get_boards() -> Result<Vec<ProjectBoard>> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+fn parse_session_time(s: &str) -> Result<DateTime<Local>> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|dt| dt.with_timezone(&Local))
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
 
-    let mut stmt = conn.prepare(
-        "SELECT id, name, board_type, github_project_id, github_project_url, synced_at, created_at
-         FROM project_boards ORDER BY created_at DESC",
-    )?;
+fn check_time_range(start: DateTime<Local>, end: DateTime<Local>) -> Result<()> {
+    if end <= start {
+        return Err(edit_error("End time must be after start time".to_string()));
+    }
+    Ok(())
+}
 
-    let boards = stmt
-        .query_map([], |row| {
-            Ok(ProjectBoard {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                board_type: row.get(2)?,
-                github_project_id: row.get(3)?,
-                github_project_url: row.get(4)?,
-                synced_at: row.get(5)?,
-                created_at: row.get(6)?,
-            })
-        })?
-        .filter_map(|r| r.ok())
-        .collect();
+fn read_session_row(row: &rusqlite::Row) -> Result<SessionRow> {
+    Ok(SessionRow {
+        id: row.get(0)?,
+        app_name: row.get(1)?,
+        window_title: row.get(2)?,
+        exe_path: row.get(3)?,
+        start_time: row.get(4)?,
+        end_time: row.get(5)?,
+        duration_seconds: row.get(6)?,
+        is_manual: row.get(7)?,
+        url: row.get(8)?,
+        domain: row.get(9)?,
+        process_name: row.get(10)?,
+        process_cwd: row.get(11)?,
+    })
+}
 
-    Ok(boards)
+fn find_session_row(conn: &Connection, id: i64) -> Result<Option<SessionRow>> {
+    conn.query_row(
+        &format!("SELECT {} FROM activity_sessions WHERE id = ?1", SESSION_ROW_COLUMNS),
+        [id],
+        read_session_row,
+    )
+    .optional()
 }
 
-pub fn delete_board(id: i64) -> Result<()> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+/// A finished session; the one the tracker is still writing to can't be edited.
+fn closed_session_row(conn: &Connection, id: i64) -> Result<SessionRow> {
+    let row = find_session_row(conn, id)?
+        .ok_or_else(|| edit_error(format!("Session {} not found", id)))?;
+    if row.end_time.is_none() {
+        return Err(edit_error(format!("Session {} is still being tracked", id)));
+    }
+    Ok(row)
+}
 
-    conn.execute("DELETE FROM board_items WHERE board_id = ?1", params![id])?;
-    conn.execute("DELETE FROM project_boards WHERE id = ?1", params![id])?;
-    Ok(())
+/// Insert `row`, keeping its id when it is non-zero (used to restore deleted rows).
+fn insert_session_row(conn: &Connection, row: &SessionRow) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO activity_sessions
+            (id, app_name, window_title, exe_path, start_time, end_time, duration_seconds, is_manual,
+             url, domain, process_name, process_cwd)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            if row.id == 0 { None } else { Some(row.id) },
+            row.app_name,
+            row.window_title,
+            row.exe_path,
+            row.start_time,
+            row.end_time,
+            row.duration_seconds,
+            row.is_manual,
+            row.url,
+            row.domain,
+            row.process_name,
+            row.process_cwd
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
 }
 
-pub fn get_board_items(board_id: i64) -> Result<Vec<BoardItem>> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+fn write_session_row(conn: &Connection, row: &SessionRow) -> Result<()> {
+    conn.execute(
+        "UPDATE activity_sessions SET app_name = ?1, window_title = ?2, start_time = ?3, end_time = ?4,
+            duration_seconds = ?5
+         WHERE id = ?6",
+        params![
+            row.app_name,
+            row.window_title,
+            row.start_time,
+            row.end_time,
+            row.duration_seconds,
+            row.id
+        ],
+    )?;
+    Ok(())
+}
 
-    let mut stmt = conn.prepare(
-        "SELECT id, board_id, app_name, window_title, tracked_seconds, status, date, created_at
-         FROM board_items WHERE board_id = ?1 ORDER BY tracked_seconds DESC",
+fn record_session_edit(
+    conn: &Connection,
+    action: &str,
+    before: &[SessionRow],
+    after: &[SessionRow],
+) -> Result<i64> {
+    let to_json = |rows: &[SessionRow]| {
+        serde_json::to_string(rows).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+    };
+    conn.execute(
+        "INSERT INTO session_edits (action, before_json, after_json, created_at) VALUES (?1, ?2, ?3, ?4)",
+        params![action, to_json(before)?, to_json(after)?, Local::now().to_rfc3339()],
     )?;
+    Ok(conn.last_insert_rowid())
+}
 
-    let items = stmt
-        .query_map(params![board_id], |row| {
-            Ok(BoardItem {
-                id: row.get(0)?,
-                board_id: row.get(1)?,
-                app_name: row.get(2)?,
-                window_title: row.get(3)?,
-                tracked_seconds: row.get(4)?,
-                status: row.get(5)?,
-                date: row.get(6)?,
-                created_at: row.get(7)?,
-            })
-        })?
-        .filter_map(|r| r.ok())
-        .collect();
+fn with_transaction<T>(f: impl FnOnce(&Transaction) -> Result<T>) -> Result<T> {
+    let mut guard = DB.lock();
+    let conn = guard.as_mut().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    Ok(items)
+    let tx = conn.transaction()?;
+    let result = f(&tx)?;
+    tx.commit()?;
+    Ok(result)
 }
 
-pub fn add_board_item(
-    board_id: i64,
+fn create_manual_entry_in(
+    conn: &Connection,
     app_name: &str,
-    window_title: Option<&str>,
-    tracked_seconds: i64,
-    date: &str,
+    window_title: &str,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
 ) -> Result<i64> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
-    let now = Local::now().to_rfc3339();
+    check_time_range(start, end)?;
+    let mut row = SessionRow {
+        id: 0,
+        app_name: app_name.to_string(),
+        window_title: window_title.to_string(),
+        exe_path: MANUAL_EXE_PATH.to_string(),
+        start_time: start.to_rfc3339(),
+        end_time: Some(end.to_rfc3339()),
+        duration_seconds: (end - start).num_seconds(),
+        is_manual: true,
+        url: None,
+        domain: None,
+        process_name: None,
+        process_cwd: None,
+    };
+    row.id = insert_session_row(conn, &row)?;
+    record_session_edit(conn, "create", &[], &[row.clone()])?;
+    Ok(row.id)
+}
 
-    conn.execute(
-        "INSERT INTO board_items (board_id, app_name, window_title, tracked_seconds, date, created_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![board_id, app_name, window_title, tracked_seconds, date, now],
-    )?;
-    Ok(conn.last_insert_rowid())
+fn update_session_in(conn: &Connection, id: i64, update: &SessionUpdate) -> Result<()> {
+    let before = closed_session_row(conn, id)?;
+    let start = match update.start_time {
+        Some(t) => t,
+        None => parse_session_time(&before.start_time)?,
+    };
+    let end = match update.end_time {
+        Some(t) => t,
+        None => parse_session_time(before.end_time.as_deref().unwrap_or_default())?,
+    };
+    check_time_range(start, end)?;
+
+    let after = SessionRow {
+        app_name: update.app_name.clone().unwrap_or_else(|| before.app_name.clone()),
+        window_title: update
+            .window_title
+            .clone()
+            .unwrap_or_else(|| before.window_title.clone()),
+        start_time: start.to_rfc3339(),
+        end_time: Some(end.to_rfc3339()),
+        duration_seconds: (end - start).num_seconds(),
+        ..before.clone()
+    };
+    write_session_row(conn, &after)?;
+    record_session_edit(conn, "update", &[before], &[after])?;
+    Ok(())
 }
 
-pub fn populate_board_from_activity(board_id: i64) -> Result<usize> {
-    let today = Local::now().format("%Y-%m-%d").to_string();
-    let sessions = get_today_sessions()?;
+fn split_session_in(conn: &Connection, id: i64, at: DateTime<Local>) -> Result<(i64, i64)> {
+    let before = closed_session_row(conn, id)?;
+    let start = parse_session_time(&before.start_time)?;
+    let end = parse_session_time(before.end_time.as_deref().unwrap_or_default())?;
+    if at <= start || at >= end {
+        return Err(edit_error("Split time must fall inside the session".to_string()));
+    }
 
-    // Group sessions by app_name
-    let mut app_map: std::collections::HashMap<String, (i64, String)> =
-        std::collections::HashMap::new();
+    let first = SessionRow {
+        end_time: Some(at.to_rfc3339()),
+        duration_seconds: (at - start).num_seconds(),
+        ..before.clone()
+    };
+    let mut second = SessionRow {
+        id: 0,
+        start_time: at.to_rfc3339(),
+        duration_seconds: (end - at).num_seconds(),
+        ..before.clone()
+    };
+    write_session_row(conn, &first)?;
+    second.id = insert_session_row(conn, &second)?;
+    record_session_edit(conn, "split", &[before], &[first, second.clone()])?;
+    Ok((id, second.id))
+}
 
-    for session in &sessions {
-        let entry = app_map
-            .entry(session.app_name.clone())
-            .or_insert((0, session.window_title.clone()));
-        entry.0 += session.duration_seconds;
+/// Merge sessions into the earliest one, which then spans from the first start to
+/// the last end. No other session may start in between.
+fn merge_sessions_in(conn: &Connection, ids: &[i64]) -> Result<i64> {
+    if ids.len() < 2 {
+        return Err(edit_error("Select at least two sessions to merge".to_string()));
     }
+    let mut before = ids
+        .iter()
+        .map(|&id| closed_session_row(conn, id))
+        .collect::<Result<Vec<_>>>()?;
+    before.sort_by(|a, b| a.start_time.cmp(&b.start_time));
+    before.dedup_by_key(|r| r.id);
 
-    // Clear old items for today to avoid duplicates
-    {
-        let guard = DB.lock();
-        let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
-        conn.execute(
-            "DELETE FROM board_items WHERE board_id = ?1 AND date = ?2",
-            params![board_id, today],
-        )?;
+    let start = parse_session_time(&before[0].start_time)?;
+    let mut end = start;
+    for row in &before {
+        end = end.max(parse_session_time(row.end_time.as_deref().unwrap_or_default())?);
     }
 
-    let mut count = 0;
-    for (app_name, (seconds, window)) in &app_map {
-        if *seconds > 0 {
-            add_board_item(board_id, app_name, Some(window), *seconds, &today)?;
-            count += 1;
-        }
+    let between: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM activity_sessions WHERE start_time >= ?1 AND start_time < ?2",
+        params![start.to_rfc3339(), end.to_rfc3339()],
+        |row| row.get(0),
+    )?;
+    if between as usize != before.len() {
+        return Err(edit_error("Only adjacent sessions can be merged".to_string()));
     }
 
-    Ok(count)
+    let merged = SessionRow {
+        end_time: Some(end.to_rfc3339()),
+        duration_seconds: (end - start).num_seconds(),
+        ..before[0].clone()
+    };
+    write_session_row(conn, &merged)?;
+    for row in &before[1..] {
+        conn.execute("DELETE FROM activity_sessions WHERE id = ?1", [row.id])?;
+    }
+    record_session_edit(conn, "merge", &before, std::slice::from_ref(&merged))?;
+    Ok(merged.id)
 }
 
-pub fn update_board_github(
-    board_id: i64,
-    github_project_id: &str,
-    github_project_url: &str,
-) -> Result<()> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
-    let now = Local::now().to_rfc3339();
+fn delete_session_in(conn: &Connection, id: i64) -> Result<()> {
+    let before = closed_session_row(conn, id)?;
+    conn.execute("DELETE FROM activity_sessions WHERE id = ?1", [id])?;
+    record_session_edit(conn, "delete", &[before], &[])?;
+    Ok(())
+}
+
+/// Whether `current` is still the row an edit left behind. Older snapshots lack the
+/// URL and process columns; finished sessions never gain those, so missing matches.
+fn unchanged_since(snapshot: &SessionRow, current: &SessionRow) -> bool {
+    let snapshot = SessionRow {
+        url: snapshot.url.clone().or_else(|| current.url.clone()),
+        domain: snapshot.domain.clone().or_else(|| current.domain.clone()),
+        process_name: snapshot.process_name.clone().or_else(|| current.process_name.clone()),
+        process_cwd: snapshot.process_cwd.clone().or_else(|| current.process_cwd.clone()),
+        ..snapshot.clone()
+    };
+    snapshot == *current
+}
+
+/// Undo an edit by putting its `before` rows back. Refused when any row it produced
+/// has changed since, so later edits have to be reverted first. The revert is itself
+/// recorded, which makes it revertible too.
+fn revert_session_edit_in(conn: &Connection, edit_id: i64) -> Result<()> {
+    let (before_json, after_json, reverted_at): (String, String, Option<String>) = conn
+        .query_row(
+            "SELECT before_json, after_json, reverted_at FROM session_edits WHERE id = ?1",
+            [edit_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?
+        .ok_or_else(|| edit_error(format!("Edit {} not found", edit_id)))?;
+    if reverted_at.is_some() {
+        return Err(edit_error(format!("Edit {} was already reverted", edit_id)));
+    }
+
+    let from_json = |json: &str| -> Result<Vec<SessionRow>> {
+        serde_json::from_str(json).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+    };
+    let before = from_json(&before_json)?;
+    let after = from_json(&after_json)?;
+
+    for row in &after {
+        let current = find_session_row(conn, row.id)?;
+        if !current.is_some_and(|current| unchanged_since(row, &current)) {
+            return Err(edit_error(format!(
+                "Session {} changed after edit {}; revert the later edits first",
+                row.id, edit_id
+            )));
+        }
+    }
+
+    for row in &after {
+        conn.execute("DELETE FROM activity_sessions WHERE id = ?1", [row.id])?;
+    }
+    for row in &before {
+        insert_session_row(conn, row)?;
+    }
 
     conn.execute(
-        "UPDATE project_boards SET github_project_id = ?1, github_project_url = ?2, synced_at = ?3 WHERE id = ?4",
-        params![github_project_id, github_project_url, now, board_id],
+        "UPDATE session_edits SET reverted_at = ?1 WHERE id = ?2",
+        params![Local::now().to_rfc3339(), edit_id],
     )?;
+    record_session_edit(conn, "revert", &after, &before)?;
     Ok(())
 }
 
-pub fn update_board_synced_at(board_id: i64) -> Result<()> {
+/// Add a session for time spent away from the computer (meetings, calls, ...).
+pub fn create_manual_entry(
+    app_name: &str,
+    window_title: &str,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+) -> Result<i64> {
+    with_transaction(|tx| create_manual_entry_in(tx, app_name, window_title, start, end))
+}
+
+pub fn update_session(id: i64, update: &SessionUpdate) -> Result<()> {
+    with_transaction(|tx| update_session_in(tx, id, update))
+}
+
+/// Split a session in two at `at`. Returns the ids of both halves.
+pub fn split_session(id: i64, at: DateTime<Local>) -> Result<(i64, i64)> {
+    with_transaction(|tx| split_session_in(tx, id, at))
+}
+
+pub fn merge_sessions(ids: &[i64]) -> Result<i64> {
+    with_transaction(|tx| merge_sessions_in(tx, ids))
+}
+
+pub fn delete_session(id: i64) -> Result<()> {
+    with_transaction(|tx| delete_session_in(tx, id))
+}
+
+pub fn revert_session_edit(edit_id: i64) -> Result<()> {
+    with_transaction(|tx| revert_session_edit_in(tx, edit_id))
+}
+
+/// The most recent edits, newest first.
+pub fn get_session_edits(limit: i64) -> Result<Vec<SessionEdit>> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
-    let now = Local::now().to_rfc3339();
 
-    conn.execute(
-        "UPDATE project_boards SET synced_at = ?1 WHERE id = ?2",
-        params![now, board_id],
+    let mut stmt = conn.prepare(
+        "SELECT id, action, before_json, after_json, created_at, reverted_at
+         FROM session_edits ORDER BY id DESC LIMIT ?1",
     )?;
-    Ok(())
+
+    let edits = stmt
+        .query_map([limit], |row| {
+            let before: String = row.get(2)?;
+            let after: String = row.get(3)?;
+            Ok(SessionEdit {
+                id: row.get(0)?,
+                action: row.get(1)?,
+                before: serde_json::from_str(&before).unwrap_or_default(),
+                after: serde_json::from_str(&after).unwrap_or_default(),
+                created_at: row.get(4)?,
+                reverted_at: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(edits)
 }
 
-// Project Tasks (for table & roadmap boards)
+// Classification rules
+
+fn default_true() -> bool {
+    true
+}
 
+/// A user rule. Patterns are case-insensitive regexes; a missing pattern matches
+/// anything, but at least one must be set. See `rules` for how rules combine.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ProjectTask {
+pub struct ClassificationRule {
+    #[serde(default)]
     pub id: i64,
-    pub board_id: i64,
-    pub title: String,
-    pub description: Option<String>,
-    pub status: String,
-    pub priority: String,
-    pub due_date: Option<String>,
-    pub created_at: String,
+    pub name: String,
+    #[serde(default)]
+    pub priority: i64,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub app_pattern: Option<String>,
+    #[serde(default)]
+    pub exe_pattern: Option<String>,
+    #[serde(default)]
+    pub title_pattern: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub project: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub ignore: bool,
+    #[serde(default)]
+    pub music: bool,
+    #[serde(default)]
+    pub coding: bool,
+    /// How titles of matching windows are stored (see `privacy`).
+    #[serde(default)]
+    pub privacy: Option<TitlePrivacy>,
+    /// What `privacy: redact` removes; the built-in pattern when empty.
+    #[serde(default)]
+    pub redact_pattern: Option<String>,
 }
 
-pub fn add_project_task(
-    board_id: i64,
-    title: &str,
-    description: Option<&str>,
-    priority: &str,
-    due_date: Option<&str>,
-) -> Result<i64> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
-    let now = Local::now().to_rfc3339();
-
+fn insert_classification_rule(conn: &Connection, rule: &ClassificationRule) -> Result<i64> {
     conn.execute(
-        "INSERT INTO project_tasks (board_id, title, description, priority, due_date, created_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![board_id, title, description, priority, due_date, now],
+        "INSERT INTO classification_rules
+            (name, priority, enabled, app_pattern, exe_pattern, title_pattern, category, project, tag, ignore, music, coding,
+             privacy, redact_pattern, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+        params![
+            rule.name,
+            rule.priority,
+            rule.enabled,
+            rule.app_pattern,
+            rule.exe_pattern,
+            rule.title_pattern,
+            rule.category,
+            rule.project,
+            rule.tag,
+            rule.ignore,
+            rule.music,
+            rule.coding,
+            rule.privacy.map(TitlePrivacy::as_str),
+            rule.redact_pattern,
+            Local::now().to_rfc3339()
+        ],
     )?;
     Ok(conn.last_insert_rowid())
 }
 
-pub fn get_project_tasks(board_id: i64) -> Result<Vec<ProjectTask>> {
+pub fn get_classification_rules() -> Result<Vec<ClassificationRule>> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
     let mut stmt = conn.prepare(
-        "SELECT id, board_id, title, description, status, priority, due_date, created_at
-         FROM project_tasks WHERE board_id = ?1 ORDER BY created_at ASC",
+        "SELECT id, name, priority, enabled, app_pattern, exe_pattern, title_pattern, category, project, tag, ignore, music, coding,
+                privacy, redact_pattern
+         FROM classification_rules ORDER BY priority DESC, id ASC",
     )?;
 
-    let tasks = stmt
-        .query_map(params![board_id], |row| {
-            Ok(ProjectTask {
+    let rules = stmt
+        .query_map([], |row| {
+            Ok(ClassificationRule {
                 id: row.get(0)?,
-                board_id: row.get(1)?,
-                title: row.get(2)?,
-                description: row.get(3)?,
-                status: row.get(4)?,
-                priority: row.get(5)?,
-                due_date: row.get(6)?,
-                created_at: row.get(7)?,
+                name: row.get(1)?,
+                priority: row.get(2)?,
+                enabled: row.get(3)?,
+                app_pattern: row.get(4)?,
+                exe_pattern: row.get(5)?,
+                title_pattern: row.get(6)?,
+                category: row.get(7)?,
+                project: row.get(8)?,
+                tag: row.get(9)?,
+                ignore: row.get(10)?,
+                music: row.get(11)?,
+                coding: row.get(12)?,
+                privacy: row
+                    .get::<_, Option<String>>(13)?
+                    .and_then(|p| TitlePrivacy::parse(&p)),
+                redact_pattern: row.get(14)?,
             })
         })?
-        .filter_map(|r| r.ok())
-        .collect();
+        .collect::<Result<Vec<_>>>()?;
 
-    Ok(tasks)
+    Ok(rules)
 }
 
-pub fn update_project_task_status(id: i64, status: &str) -> Result<()> {
+/// Insert the rule when `id` is 0, otherwise update it. Returns the rule id.
+pub fn save_classification_rule(rule: &ClassificationRule) -> Result<i64> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
+    if rule.id == 0 {
+        return insert_classification_rule(conn, rule);
+    }
+
     conn.execute(
-        "UPDATE project_tasks SET status = ?1 WHERE id = ?2",
-        params![status, id],
+        "UPDATE classification_rules SET
+            name = ?1, priority = ?2, enabled = ?3, app_pattern = ?4, exe_pattern = ?5, title_pattern = ?6,
+            category = ?7, project = ?8, tag = ?9, ignore = ?10, music = ?11, coding = ?12,
+            privacy = ?13, redact_pattern = ?14
+         WHERE id = ?15",
+        params![
+            rule.name,
+            rule.priority,
+            rule.enabled,
+            rule.app_pattern,
+            rule.exe_pattern,
+            rule.title_pattern,
+            rule.category,
+            rule.project,
+            rule.tag,
+            rule.ignore,
+            rule.music,
+            rule.coding,
+            rule.privacy.map(TitlePrivacy::as_str),
+            rule.redact_pattern,
+            rule.id
+        ],
     )?;
-    Ok(())
+    Ok(rule.id)
 }
 
-pub fn delete_project_task(id: i64) -> Result<()> {
+pub fn delete_classification_rule(id: i64) -> Result<()> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    conn.execute("DELETE FROM project_tasks WHERE id = ?1", params![id])?;
+    conn.execute("DELETE FROM classification_rules WHERE id = ?1", [id])?;
     Ok(())
 }
 
-// Cloud Accounts
+/// Add `rules` as new rows, optionally dropping every existing rule first.
+pub fn import_classification_rules(rules: &[ClassificationRule], replace: bool) -> Result<usize> {
+    let mut guard = DB.lock();
+    let conn = guard.as_mut().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let tx = conn.transaction()?;
+    if replace {
+        tx.execute("DELETE FROM classification_rules", [])?;
+    }
+    for rule in rules {
+        insert_classification_rule(&tx, rule)?;
+    }
+    tx.commit()?;
+    Ok(rules.len())
+}
+
+// Categories & productivity
 
+/// Sessions no rule or built-in heuristic put in a category.
+pub const UNCATEGORIZED: &str = "Uncategorized";
+
+/// A category and how productive time in it counts, from -1.0 (distracting) to 1.0.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CloudAccount {
+pub struct Category {
+    #[serde(default)]
     pub id: i64,
-    pub email: String,
-    pub provider: String,
-    pub created_at: String,
+    pub name: String,
+    #[serde(default)]
+    pub productivity_weight: f64,
+    #[serde(default)]
+    pub color: Option<String>,
 }
 
-pub fn add_cloud_account(
-    email: &str,
-    provider: &str,
-    access_token: &str,
-    refresh_token: &str,
-) -> Result<i64> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
-    let now = Local::now().to_rfc3339();
-
-    // Remove existing account for this provider to avoid duplicates
-    conn.execute(
-        "DELETE FROM cloud_accounts WHERE provider = ?1",
-        params![provider],
-    )?;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoryUsage {
+    pub category: String,
+    pub total_seconds: i64,
+    pub productivity_weight: f64,
+}
 
-    conn.execute(
-        "INSERT INTO cloud_accounts (email, provider, access_token, refresh_token, created_at)
-         VALUES (?1, ?2, ?3, ?4, ?5)",
-        params![email, provider, access_token, refresh_token, now],
-    )?;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoryDailyStats {
+    pub date: String,
+    pub category: String,
+    pub total_seconds: i64,
+    pub productivity_weight: f64,
+}
 
-    Ok(conn.last_insert_rowid())
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProductivityScore {
+    pub date: String,
+    /// 0 (all distracting) to 100 (all productive); 50 is neutral.
+    pub score: f64,
+    pub productive_seconds: i64,
+    pub neutral_seconds: i64,
+    pub distracting_seconds: i64,
+    pub total_seconds: i64,
+    pub categories: Vec<CategoryUsage>,
 }
 
-pub fn get_cloud_accounts() -> Result<Vec<CloudAccount>> {
+pub fn get_categories() -> Result<Vec<Category>> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
     let mut stmt = conn.prepare(
-        "SELECT id, email, provider, created_at FROM cloud_accounts ORDER BY created_at DESC",
+        "SELECT id, name, productivity_weight, color FROM categories ORDER BY name COLLATE NOCASE",
     )?;
 
-    let accounts = stmt
+    let categories = stmt
         .query_map([], |row| {
-            Ok(CloudAccount {
+            Ok(Category {
                 id: row.get(0)?,
-                email: row.get(1)?,
-                provider: row.get(2)?,
-                created_at: row.get(3)?,
+                name: row.get(1)?,
+                productivity_weight: row.get(2)?,
+                color: row.get(3)?,
             })
         })?
-        .filter_map(|r| r.ok())
-        .collect();
+        .collect::<Result<Vec<_>>>()?;
 
-    Ok(accounts)
+    Ok(categories)
 }
 
-pub fn get_cloud_token(account_id: i64) -> Result<(String, String)> {
+/// Insert the category when `id` is 0, otherwise update it. Returns the category id.
+pub fn save_category(category: &Category) -> Result<i64> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    conn.query_row(
-        "SELECT access_token, refresh_token FROM cloud_accounts WHERE id = ?1",
-        params![account_id],
-        |row| Ok((row.get(0)?, row.get(1)?)),
-    )
-}
-
-pub fn remove_cloud_account(account_id: i64) -> Result<()> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    let weight = category.productivity_weight.clamp(-1.0, 1.0);
+    if category.id == 0 {
+        conn.execute(
+            "INSERT INTO categories (name, productivity_weight, color) VALUES (?1, ?2, ?3)
+             ON CONFLICT(name) DO UPDATE SET productivity_weight = excluded.productivity_weight, color = excluded.color",
+            params![category.name.trim(), weight, category.color],
+        )?;
+        return conn.query_row(
+            "SELECT id FROM categories WHERE name = ?1",
+            [category.name.trim()],
+            |row| row.get(0),
+        );
+    }
 
     conn.execute(
-        "DELETE FROM cloud_accounts WHERE id = ?1",
-        params![account_id],
+        "UPDATE categories SET name = ?1, productivity_weight = ?2, color = ?3 WHERE id = ?4",
+        params![category.name.trim(), weight, category.color, category.id],
     )?;
-    Ok(())
+    Ok(category.id)
 }
 
-// Data Management
-
-pub fn reset_all_data() -> Result<()> {
+pub fn delete_category(id: i64) -> Result<()> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    conn.execute("DELETE FROM music_sessions", [])?;
-    conn.execute("DELETE FROM activity_sessions", [])?;
-    conn.execute("DELETE FROM tasks", [])?;
-    conn.execute("DELETE FROM board_items", [])?;
-    conn.execute("DELETE FROM project_boards", [])?;
-    conn.execute("DELETE FROM project_tasks", [])?;
-    conn.execute("DELETE FROM cloud_accounts", [])?;
-    conn.execute("DELETE FROM settings", [])?;
-    conn.execute("DELETE FROM coding_sessions", [])?;
-
+    conn.execute("DELETE FROM categories WHERE id = ?1", [id])?;
     Ok(())
 }
 
-pub struct EnrichedActivitySession {
-    pub app_name: String,
-    pub display_name: String,
-    pub window_title: String,
-    pub exe_path: String,
-    pub start_time: String,
-    pub end_time: Option<String>,
-    pub duration_seconds: i64,
-    pub website: Option<String>,
-    pub music_track: Option<String>,
-    pub editor_name: Option<String>,
-    pub file_path: Option<String>,
-    pub language: Option<String>,
-    pub project_dir: Option<String>,
-    pub is_ai_assisted: Option<bool>,
+/// Productivity weight per category name.
+pub fn category_weights() -> Result<HashMap<String, f64>> {
+    Ok(get_categories()?
+        .into_iter()
+        .map(|c| (c.name, c.productivity_weight))
+        .collect())
 }
 
-fn extract_website(app_name: &str, window_title: &str) -> Option<String> {
-    let app_lower = app_name.to_lowercase();
-    let is_browser = app_lower.contains("chrome")
-        || app_lower.contains("edge")
-        || app_lower.contains("firefox")
-        || app_lower.contains("opera")
-        || app_lower.contains("brave")
-        || app_lower.contains("vivaldi")
-        || app_lower.contains("safari")
-        || app_lower.contains("browser")
-        || app_lower.contains("explorer")
-        || app_lower.contains("webview");
+/// Categories the user picked per app on the dashboard (`app_categories` setting).
+fn app_categories() -> HashMap<String, String> {
+    get_setting("app_categories")
+        .and_then(|json| serde_json::from_str::<HashMap<String, String>>(&json).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(_, category)| !category.is_empty() && category != UNCATEGORIZED)
+        .collect()
+}
 
-    if !is_browser {
-        return None;
+/// The dashboard's built-in guess from the app name (`detectCategory` in the
+/// activity store), so reports and the dashboard agree.
+pub fn detect_category(app_name: &str, exe_path: &str) -> Option<&'static str> {
+    const GAMES: &[&str] = &[
+        "steam", "epicgames", "riot", "league of", "valorant", "overwatch", "minecraft", "roblox",
+        "csgo", "dota", "gta", "witcher", "cyberpunk", "ea app", "battle.net",
+    ];
+    const WORK: &[&str] = &[
+        "word", "excel", "powerpoint", "code", "studio", "figma", "notion", "obsidian", "webstorm",
+        "pycharm", "intellij", "slack", "zoom", "teams", "illustrator", "photoshop",
+        "after effects", "premiere", "cursor", "windsurf", "zed", "neovim", "nvim", "vim", "helix",
+        "lapce", "sublime", "atom", "emacs", "fleet", "kiro", "qoder", "antigravity", "rustrover",
+        "goland", "rider", "clion", "phpstorm", "datagrip", "chatgpt", "claude", "deepseek",
+        "gemini", "copilot", "v0.dev", "z.ai", "qwen", "omniroute", "openrouter", "perplexity",
+        "phind", "bolt.new", "lovable", "devin", "replit",
+    ];
+    const REST: &[&str] = &[
+        "spotify", "youtube music", "discord", "netflix", "telegram", "whatsapp", "viber", "tiktok",
+        "instagram", "facebook", "twitter", "yandex music", "apple music", "tidal", "deezer",
+        "soundcloud", "foobar2000", "musicbee", "aimp", "winamp", "itunes", "audible", "vlc", "mpv",
+        "potplayer",
+    ];
+    const PROGRAMS: &[&str] = &[
+        "chrome", "edge", "firefox", "brave", "opera", "vivaldi", "safari", "explorer", "settings",
+        "taskmgr",
+    ];
+
+    let name = app_name.to_lowercase();
+    let path = exe_path.to_lowercase();
+    let any = |keywords: &[&str]| keywords.iter().any(|kw| name.contains(kw));
+
+    if any(GAMES)
+        || ["\\steam\\", "\\epic games\\", "\\riot games\\", "battlenet"]
+            .iter()
+            .any(|p| path.contains(p))
+    {
+        Some("Games")
+    } else if any(WORK) {
+        Some("Work")
+    } else if any(REST) {
+        Some("Rest")
+    } else if any(PROGRAMS) {
+        Some("Programs")
+    } else {
+        None
     }
+}
 
-    let clean_title = window_title
-        .replace(" - Google Chrome", "")
-        .replace(" - Microsoft Edge", "")
-        .replace(" - Mozilla Firefox", "")
-        .replace(" - Opera", "")
-        .replace(" - Brave", "")
-        .replace(" - Vivaldi", "")
-        .replace(" - Internet Explorer", "")
-        .replace(" - Tor Browser", "");
-
-    let parts: Vec<&str> = clean_title.split(" - ").collect();
-    let site = if parts.len() > 1 {
-        parts[parts.len() - 1].trim()
-    } else {
-        let parts2: Vec<&str> = clean_title.split(" | ").collect();
-        if parts2.len() > 1 {
-            parts2[parts2.len() - 1].trim()
-        } else {
-            clean_title.trim()
-        }
-    };
+/// The category of an enriched session: the rules' verdict or the user's choice for
+/// the app, else the dashboard's guess, else coding time counts as Development.
+fn session_category(session: &EnrichedActivitySession) -> String {
+    if let Some(category) = &session.category {
+        return category.clone();
+    }
+    match detect_category(&session.app_name, &session.exe_path) {
+        Some(category) => category.to_string(),
+        None if session.editor_name.is_some() => "Development".to_string(),
+        None => UNCATEGORIZED.to_string(),
+    }
+}
 
-    if site.is_empty() || site == "New Tab" || site == "Нова вкладка" || site == "Новая вкладка" {
-        None
-    } else {
-        Some(site.to_string())
+/// Time per category, largest first. Unknown categories weigh 0.
+pub fn category_breakdown(
+    sessions: &[EnrichedActivitySession],
+    weights: &HashMap<String, f64>,
+) -> Vec<CategoryUsage> {
+    let mut totals: HashMap<String, i64> = HashMap::new();
+    for s in sessions {
+        *totals.entry(session_category(s)).or_insert(0) += s.duration_seconds;
     }
+
+    let mut usage: Vec<CategoryUsage> = totals
+        .into_iter()
+        .map(|(category, total_seconds)| CategoryUsage {
+            productivity_weight: weights.get(&category).copied().unwrap_or(0.0),
+            category,
+            total_seconds,
+        })
+        .collect();
+    usage.sort_by(|a, b| b.total_seconds.cmp(&a.total_seconds).then(a.category.cmp(&b.category)));
+    usage
 }
 
-fn detect_browser_ai(app_name: &str, window_title: &str) -> Option<String> {
-    let app_lower = app_name.to_lowercase();
-    let title_lower = window_title.to_lowercase();
+/// Weighted average of the category weights, mapped from -1..1 onto 0..100.
+pub fn productivity_score(usage: &[CategoryUsage]) -> f64 {
+    let total: i64 = usage.iter().map(|u| u.total_seconds).sum();
+    if total <= 0 {
+        return 0.0;
+    }
+    let weighted: f64 = usage
+        .iter()
+        .map(|u| u.productivity_weight * u.total_seconds as f64)
+        .sum();
+    let score = 50.0 + 50.0 * weighted / total as f64;
+    (score * 10.0).round() / 10.0
+}
 
-    let is_browser = app_lower.contains("chrome") ||
-                     app_lower.contains("firefox") ||
-                     app_lower.contains("msedge") ||
-                     app_lower.contains("brave") ||
-                     app_lower.contains("safari") ||
-                     app_lower.contains("opera") ||
-                     app_lower.contains("vivaldi") ||
-                     app_lower.contains("browser");
+/// Time per category per day between the two dates (inclusive).
+pub fn get_category_stats(start_date: &str, end_date: &str) -> Result<Vec<CategoryDailyStats>> {
+    let enriched = get_enriched_sessions(start_date, end_date)?;
+    let weights = category_weights()?;
 
-    if is_browser {
-        const AI_SERVICES: &[(&str, &str)] = &[
-            ("chatgpt", "ChatGPT"),
-            ("claude", "Claude AI"),
-            ("deepseek", "DeepSeek"),
-            ("gemini", "Gemini"),
-            ("v0.dev", "v0.dev"),
-            ("v0", "v0.dev"),
-            ("copilot", "Copilot"),
-            ("poe.com", "Poe"),
-            ("poe", "Poe"),
-            ("ollama", "Ollama"),
-            ("grok", "Grok AI"),
-            ("perplexity", "Perplexity"),
-            ("phind", "Phind"),
-            ("mistral", "Mistral AI"),
-            ("qwen", "Qwen"),
-            ("huggingface", "Hugging Face"),
-            ("blackbox", "Blackbox AI"),
-            ("lm-studio", "LM Studio"),
-            ("lmstudio", "LM Studio"),
-            ("jan.ai", "Jan AI"),
-            ("bolt.new", "Bolt.new"),
-            ("bolt", "Bolt.new"),
-            ("devin", "Devin AI"),
-            ("lovable", "Lovable.dev"),
-            ("sider", "Sider AI"),
-            ("monica", "Monica AI"),
-            ("open-webui", "Open WebUI"),
-            ("openwebui", "Open WebUI"),
-            ("librechat", "LibreChat"),
-            ("codeium", "Codeium"),
-            ("tabnine", "Tabnine"),
-            ("replit", "Replit Agent"),
-        ];
+    let mut by_day: HashMap<String, Vec<EnrichedActivitySession>> = HashMap::new();
+    for s in enriched {
+        let date = s.start_time.get(..10).unwrap_or(&s.start_time).to_string();
+        by_day.entry(date).or_default().push(s);
+    }
 
-        for &(key, label) in AI_SERVICES {
-            if title_lower.contains(key) {
-                return Some(label.to_string());
-            }
+    let mut stats = Vec::new();
+    for (date, sessions) in by_day {
+        for usage in category_breakdown(&sessions, &weights) {
+            stats.push(CategoryDailyStats {
+                date: date.clone(),
+                category: usage.category,
+                total_seconds: usage.total_seconds,
+                productivity_weight: usage.productivity_weight,
+            });
         }
     }
-    None
+    stats.sort_by(|a, b| a.date.cmp(&b.date).then(b.total_seconds.cmp(&a.total_seconds)));
+    Ok(stats)
 }
 
-pub fn get_enriched_sessions(start_date: &str, end_date: &str) -> Result<Vec<EnrichedActivitySession>> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+pub fn get_productivity_score(date: &str) -> Result<ProductivityScore> {
+    let enriched = get_enriched_sessions(date, date)?;
+    let categories = category_breakdown(&enriched, &category_weights()?);
 
-    // 1. Fetch activity sessions
-    let mut stmt = conn.prepare(
-        "SELECT app_name, window_title, exe_path, start_time, end_time, duration_seconds
-         FROM activity_sessions
-         WHERE date(start_time) >= date(?) AND date(start_time) <= date(?)
-         ORDER BY start_time DESC",
-    )?;
+    let seconds_where = |f: fn(f64) -> bool| -> i64 {
+        categories
+            .iter()
+            .filter(|c| f(c.productivity_weight))
+            .map(|c| c.total_seconds)
+            .sum()
+    };
 
-    let activity_rows = stmt.query_map([start_date, end_date], |row| {
-        Ok((
-            row.get::<_, String>(0)?,
-            row.get::<_, String>(1)?,
-            row.get::<_, String>(2)?,
-            row.get::<_, String>(3)?,
-            row.get::<_, Option<String>>(4)?,
-            row.get::<_, i64>(5)?,
-        ))
-    })?.collect::<Result<Vec<_>>>()?;
+    Ok(ProductivityScore {
+        date: date.to_string(),
+        score: productivity_score(&categories),
+        productive_seconds: seconds_where(|w| w > 0.0),
+        neutral_seconds: seconds_where(|w| w == 0.0),
+        distracting_seconds: seconds_where(|w| w < 0.0),
+        total_seconds: categories.iter().map(|c| c.total_seconds).sum(),
+        categories,
+    })
+}
 
-    // 2. Fetch music sessions
-    let mut music_stmt = conn.prepare(
-        "SELECT app_name, window_title, start_time, end_time
-         FROM music_sessions
-         WHERE date(start_time) >= date(?) AND date(start_time) <= date(?)",
-    )?;
-    let music_rows = music_stmt.query_map([start_date, end_date], |row| {
-        Ok((
-            row.get::<_, String>(0)?,
-            row.get::<_, Option<String>>(1)?,
-            row.get::<_, String>(2)?,
-            row.get::<_, Option<String>>(3)?,
-        ))
-    })?.collect::<Result<Vec<_>>>()?;
+// Project Boards
 
-    // 3. Fetch coding sessions
-    let mut coding_stmt = conn.prepare(
-        "SELECT editor_name, file_path, language, project_dir, is_ai_assisted, start_time, end_time
-         FROM coding_sessions
-         WHERE date(start_time) >= date(?) AND date(start_time) <= date(?)",
-    )?;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectBoard {
+    pub id: i64,
+    pub name: String,
+    pub board_type: String,
+    pub github_project_id: Option<String>,
+    pub github_project_url: Option<String>,
+    pub synced_at: Option<String>,
+    pub created_at: String,
+    /// Billing (see `billing`): the client, a rate overriding the client's, and how
+    /// each day's time is rounded.
+    #[serde(default)]
+    pub client_id: Option<i64>,
+    #[serde(default)]
+    pub hourly_rate: Option<f64>,
+    #[serde(default)]
+    pub rounding_minutes: i64,
+    #[serde(default = "default_rounding_mode")]
+    pub rounding_mode: String,
+}
+
+fn default_rounding_mode() -> String {
+    "up".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BoardItem {
+    pub id: i64,
+    pub board_id: i64,
+    pub app_name: String,
+    pub window_title: Option<String>,
+    pub tracked_seconds: i64,
+    pub status: String,
+    pub date: String,
+    pub created_at: String,
+}
+
+pub fn create_board(name: &str, board_type: &str) -> Result<i64> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    let now = Local::now().to_rfc3339();
+
+    conn.execute(
+        "INSERT INTO project_boards (name, board_type, created_at) VALUES (?1, ?2, ?3)",
+        params![name, board_type, now],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn get_boards() -> Result<Vec<ProjectBoard>> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, name, board_type, github_project_id, github_project_url, synced_at, created_at,
+                client_id, hourly_rate, rounding_minutes, rounding_mode
+         FROM project_boards ORDER BY created_at DESC",
+    )?;
+
+    let boards = stmt
+        .query_map([], |row| {
+            Ok(ProjectBoard {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                board_type: row.get(2)?,
+                github_project_id: row.get(3)?,
+                github_project_url: row.get(4)?,
+                synced_at: row.get(5)?,
+                created_at: row.get(6)?,
+                client_id: row.get(7)?,
+                hourly_rate: row.get(8)?,
+                rounding_minutes: row.get(9)?,
+                rounding_mode: row.get(10)?,
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(boards)
+}
+
+pub fn delete_board(id: i64) -> Result<()> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    conn.execute("DELETE FROM board_items WHERE board_id = ?1", params![id])?;
+    conn.execute("DELETE FROM project_boards WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+pub fn get_board_items(board_id: i64) -> Result<Vec<BoardItem>> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, board_id, app_name, window_title, tracked_seconds, status, date, created_at
+         FROM board_items WHERE board_id = ?1 ORDER BY tracked_seconds DESC",
+    )?;
+
+    let items = stmt
+        .query_map(params![board_id], |row| {
+            Ok(BoardItem {
+                id: row.get(0)?,
+                board_id: row.get(1)?,
+                app_name: row.get(2)?,
+                window_title: row.get(3)?,
+                tracked_seconds: row.get(4)?,
+                status: row.get(5)?,
+                date: row.get(6)?,
+                created_at: row.get(7)?,
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(items)
+}
+
+pub fn add_board_item(
+    board_id: i64,
+    app_name: &str,
+    window_title: Option<&str>,
+    tracked_seconds: i64,
+    date: &str,
+) -> Result<i64> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    let now = Local::now().to_rfc3339();
+
+    conn.execute(
+        "INSERT INTO board_items (board_id, app_name, window_title, tracked_seconds, date, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![board_id, app_name, window_title, tracked_seconds, date, now],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn populate_board_from_activity(board_id: i64) -> Result<usize> {
+    let today = Local::now().format("%Y-%m-%d").to_string();
+    let sessions = get_today_sessions()?;
+
+    // Group sessions by app_name
+    let mut app_map: std::collections::HashMap<String, (i64, String)> =
+        std::collections::HashMap::new();
+
+    for session in &sessions {
+        let entry = app_map
+            .entry(session.app_name.clone())
+            .or_insert((0, session.window_title.clone()));
+        entry.0 += session.duration_seconds;
+    }
+
+    // Clear old items for today to avoid duplicates
+    {
+        let guard = DB.lock();
+        let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+        conn.execute(
+            "DELETE FROM board_items WHERE board_id = ?1 AND date = ?2",
+            params![board_id, today],
+        )?;
+    }
+
+    let mut count = 0;
+    for (app_name, (seconds, window)) in &app_map {
+        if *seconds > 0 {
+            add_board_item(board_id, app_name, Some(window), *seconds, &today)?;
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+pub fn update_board_github(
+    board_id: i64,
+    github_project_id: &str,
+    github_project_url: &str,
+) -> Result<()> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    let now = Local::now().to_rfc3339();
+
+    conn.execute(
+        "UPDATE project_boards SET github_project_id = ?1, github_project_url = ?2, synced_at = ?3 WHERE id = ?4",
+        params![github_project_id, github_project_url, now, board_id],
+    )?;
+    Ok(())
+}
+
+pub fn update_board_synced_at(board_id: i64) -> Result<()> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    let now = Local::now().to_rfc3339();
+
+    conn.execute(
+        "UPDATE project_boards SET synced_at = ?1 WHERE id = ?2",
+        params![now, board_id],
+    )?;
+    Ok(())
+}
+
+/// Board items recorded between two dates (inclusive), oldest first.
+pub fn get_board_items_range(from: &str, to: &str) -> Result<Vec<BoardItem>> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, board_id, app_name, window_title, tracked_seconds, status, date, created_at
+         FROM board_items WHERE date >= ?1 AND date <= ?2 ORDER BY date, board_id, tracked_seconds DESC",
+    )?;
+
+    let items = stmt
+        .query_map(params![from, to], |row| {
+            Ok(BoardItem {
+                id: row.get(0)?,
+                board_id: row.get(1)?,
+                app_name: row.get(2)?,
+                window_title: row.get(3)?,
+                tracked_seconds: row.get(4)?,
+                status: row.get(5)?,
+                date: row.get(6)?,
+                created_at: row.get(7)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(items)
+}
+
+pub fn set_board_billing(
+    board_id: i64,
+    client_id: Option<i64>,
+    hourly_rate: Option<f64>,
+    rounding_minutes: i64,
+    rounding_mode: &str,
+) -> Result<()> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let updated = conn.execute(
+        "UPDATE project_boards SET client_id = ?1, hourly_rate = ?2, rounding_minutes = ?3, rounding_mode = ?4
+         WHERE id = ?5",
+        params![client_id, hourly_rate, rounding_minutes.max(0), rounding_mode, board_id],
+    )?;
+    if updated == 0 {
+        return Err(rusqlite::Error::QueryReturnedNoRows);
+    }
+    Ok(())
+}
+
+// Clients (billing)
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Client {
+    pub id: i64,
+    pub name: String,
+    pub email: Option<String>,
+    /// Printed under "Bill to" on invoices.
+    pub address: Option<String>,
+    pub currency: String,
+    /// Used for boards of this client that have no rate of their own.
+    pub hourly_rate: Option<f64>,
+    pub created_at: String,
+}
+
+pub fn create_client(
+    name: &str,
+    email: Option<&str>,
+    address: Option<&str>,
+    currency: &str,
+    hourly_rate: Option<f64>,
+) -> Result<i64> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    let now = Local::now().to_rfc3339();
+
+    conn.execute(
+        "INSERT INTO clients (name, email, address, currency, hourly_rate, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![name, email, address, currency.trim().to_uppercase(), hourly_rate, now],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn update_client(client: &Client) -> Result<()> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let updated = conn.execute(
+        "UPDATE clients SET name = ?1, email = ?2, address = ?3, currency = ?4, hourly_rate = ?5 WHERE id = ?6",
+        params![
+            client.name,
+            client.email,
+            client.address,
+            client.currency.trim().to_uppercase(),
+            client.hourly_rate,
+            client.id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(rusqlite::Error::QueryReturnedNoRows);
+    }
+    Ok(())
+}
+
+pub fn get_clients() -> Result<Vec<Client>> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, name, email, address, currency, hourly_rate, created_at FROM clients ORDER BY name",
+    )?;
+
+    let clients = stmt
+        .query_map([], |row| {
+            Ok(Client {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                email: row.get(2)?,
+                address: row.get(3)?,
+                currency: row.get(4)?,
+                hourly_rate: row.get(5)?,
+                created_at: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(clients)
+}
+
+/// Delete a client; its boards stay, without a client.
+pub fn delete_client(id: i64) -> Result<()> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    conn.execute("UPDATE project_boards SET client_id = NULL WHERE client_id = ?1", params![id])?;
+    conn.execute("DELETE FROM clients WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+// Project Tasks (for table & roadmap boards)
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectTask {
+    pub id: i64,
+    pub board_id: i64,
+    pub title: String,
+    pub description: Option<String>,
+    pub status: String,
+    pub priority: String,
+    pub due_date: Option<String>,
+    pub created_at: String,
+}
+
+pub fn add_project_task(
+    board_id: i64,
+    title: &str,
+    description: Option<&str>,
+    priority: &str,
+    due_date: Option<&str>,
+) -> Result<i64> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    let now = Local::now().to_rfc3339();
+
+    conn.execute(
+        "INSERT INTO project_tasks (board_id, title, description, priority, due_date, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![board_id, title, description, priority, due_date, now],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn get_project_tasks(board_id: i64) -> Result<Vec<ProjectTask>> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, board_id, title, description, status, priority, due_date, created_at
+         FROM project_tasks WHERE board_id = ?1 ORDER BY created_at ASC",
+    )?;
+
+    let tasks = stmt
+        .query_map(params![board_id], |row| {
+            Ok(ProjectTask {
+                id: row.get(0)?,
+                board_id: row.get(1)?,
+                title: row.get(2)?,
+                description: row.get(3)?,
+                status: row.get(4)?,
+                priority: row.get(5)?,
+                due_date: row.get(6)?,
+                created_at: row.get(7)?,
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(tasks)
+}
+
+pub fn update_project_task_status(id: i64, status: &str) -> Result<()> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    conn.execute(
+        "UPDATE project_tasks SET status = ?1 WHERE id = ?2",
+        params![status, id],
+    )?;
+    Ok(())
+}
+
+pub fn delete_project_task(id: i64) -> Result<()> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    conn.execute("DELETE FROM project_tasks WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+// Cloud Accounts
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloudAccount {
+    pub id: i64,
+    pub email: String,
+    pub provider: String,
+    pub created_at: String,
+}
+
+pub fn add_cloud_account(
+    email: &str,
+    provider: &str,
+    access_token: &str,
+    refresh_token: &str,
+) -> Result<i64> {
+    // Tokens are sealed before they reach the database (see `vault`)
+    let access_token = crate::vault::seal(access_token).map_err(vault_error)?;
+    let refresh_token = crate::vault::seal(refresh_token).map_err(vault_error)?;
+
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    let now = Local::now().to_rfc3339();
+
+    // Remove existing account for this provider to avoid duplicates
+    conn.execute(
+        "DELETE FROM cloud_accounts WHERE provider = ?1",
+        params![provider],
+    )?;
+
+    conn.execute(
+        "INSERT INTO cloud_accounts (email, provider, access_token, refresh_token, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![email, provider, access_token, refresh_token, now],
+    )?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn get_cloud_accounts() -> Result<Vec<CloudAccount>> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, email, provider, created_at FROM cloud_accounts ORDER BY created_at DESC",
+    )?;
+
+    let accounts = stmt
+        .query_map([], |row| {
+            Ok(CloudAccount {
+                id: row.get(0)?,
+                email: row.get(1)?,
+                provider: row.get(2)?,
+                created_at: row.get(3)?,
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(accounts)
+}
+
+pub fn get_cloud_token(account_id: i64) -> Result<(String, String)> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let (access_token, refresh_token): (String, String) = conn.query_row(
+        "SELECT access_token, refresh_token FROM cloud_accounts WHERE id = ?1",
+        params![account_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    Ok((
+        crate::vault::open(&access_token).map_err(vault_error)?,
+        crate::vault::open(&refresh_token).map_err(vault_error)?,
+    ))
+}
+
+fn vault_error(msg: String) -> rusqlite::Error {
+    rusqlite::Error::ToSqlConversionFailure(msg.into())
+}
+
+fn seal_cloud_tokens_in(conn: &Connection, seal: &dyn Fn(&str) -> std::result::Result<String, String>) -> Result<usize> {
+    let mut stmt = conn.prepare("SELECT id, access_token, refresh_token FROM cloud_accounts")?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)))?
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut sealed = 0;
+    for (id, access_token, refresh_token) in rows {
+        let access = seal(&access_token).map_err(vault_error)?;
+        let refresh = seal(&refresh_token).map_err(vault_error)?;
+        if access == access_token && refresh == refresh_token {
+            continue;
+        }
+        conn.execute(
+            "UPDATE cloud_accounts SET access_token = ?1, refresh_token = ?2 WHERE id = ?3",
+            params![access, refresh, id],
+        )?;
+        sealed += 1;
+    }
+    Ok(sealed)
+}
+
+/// Seal tokens stored by releases before `vault` existed. Returns the accounts changed.
+pub fn seal_cloud_tokens() -> Result<usize> {
+    with_transaction(|tx| seal_cloud_tokens_in(tx, &crate::vault::seal))
+}
+
+pub fn remove_cloud_account(account_id: i64) -> Result<()> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    conn.execute(
+        "DELETE FROM cloud_accounts WHERE id = ?1",
+        params![account_id],
+    )?;
+    Ok(())
+}
+
+// Data Management
+
+pub fn reset_all_data() -> Result<()> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    conn.execute("DELETE FROM music_sessions", [])?;
+    conn.execute("DELETE FROM activity_sessions", [])?;
+    conn.execute("DELETE FROM tasks", [])?;
+    conn.execute("DELETE FROM board_items", [])?;
+    conn.execute("DELETE FROM project_boards", [])?;
+    conn.execute("DELETE FROM project_tasks", [])?;
+    conn.execute("DELETE FROM cloud_accounts", [])?;
+    conn.execute("DELETE FROM settings", [])?;
+    conn.execute("DELETE FROM coding_sessions", [])?;
+    conn.execute("DELETE FROM session_edits", [])?;
+    conn.execute("DELETE FROM shell_commands", [])?;
+    conn.execute("DELETE FROM browser_tabs", [])?;
+    conn.execute("DELETE FROM daily_usage", [])?;
+    conn.execute("DELETE FROM focus_blocks", [])?;
+    conn.execute("DELETE FROM clients", [])?;
+
+    Ok(())
+}
+
+pub struct EnrichedActivitySession {
+    pub app_name: String,
+    pub display_name: String,
+    pub window_title: String,
+    pub exe_path: String,
+    pub start_time: String,
+    pub end_time: Option<String>,
+    pub duration_seconds: i64,
+    pub website: Option<String>,
+    pub music_track: Option<String>,
+    pub editor_name: Option<String>,
+    pub file_path: Option<String>,
+    pub language: Option<String>,
+    pub project_dir: Option<String>,
+    pub is_ai_assisted: Option<bool>,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    /// Program running in the terminal, for terminal windows.
+    pub process_name: Option<String>,
+    pub process_cwd: Option<String>,
+    /// Tab URL reported by the browser extension.
+    pub url: Option<String>,
+}
+
+pub fn is_browser(app_name: &str) -> bool {
+    let app_lower = app_name.to_lowercase();
+    app_lower.contains("chrome")
+        || app_lower.contains("edge")
+        || app_lower.contains("firefox")
+        || app_lower.contains("opera")
+        || app_lower.contains("brave")
+        || app_lower.contains("vivaldi")
+        || app_lower.contains("safari")
+        || app_lower.contains("browser")
+        || app_lower.contains("explorer")
+        || app_lower.contains("webview")
+}
+
+fn extract_website(app_name: &str, window_title: &str) -> Option<String> {
+    if !is_browser(app_name) {
+        return None;
+    }
+
+    let clean_title = window_title
+        .replace(" - Google Chrome", "")
+        .replace(" - Microsoft Edge", "")
+        .replace(" - Mozilla Firefox", "")
+        .replace(" - Opera", "")
+        .replace(" - Brave", "")
+        .replace(" - Vivaldi", "")
+        .replace(" - Internet Explorer", "")
+        .replace(" - Tor Browser", "");
+
+    let parts: Vec<&str> = clean_title.split(" - ").collect();
+    let site = if parts.len() > 1 {
+        parts[parts.len() - 1].trim()
+    } else {
+        let parts2: Vec<&str> = clean_title.split(" | ").collect();
+        if parts2.len() > 1 {
+            parts2[parts2.len() - 1].trim()
+        } else {
+            clean_title.trim()
+        }
+    };
+
+    if site.is_empty() || site == "New Tab" || site == "Нова вкладка" || site == "Новая вкладка" {
+        None
+    } else {
+        Some(site.to_string())
+    }
+}
+
+fn detect_browser_ai(app_name: &str, window_title: &str) -> Option<String> {
+    let app_lower = app_name.to_lowercase();
+    let title_lower = window_title.to_lowercase();
+
+    let is_browser = app_lower.contains("chrome") ||
+                     app_lower.contains("firefox") ||
+                     app_lower.contains("msedge") ||
+                     app_lower.contains("brave") ||
+                     app_lower.contains("safari") ||
+                     app_lower.contains("opera") ||
+                     app_lower.contains("vivaldi") ||
+                     app_lower.contains("browser");
+
+    if is_browser {
+        const AI_SERVICES: &[(&str, &str)] = &[
+            ("chatgpt", "ChatGPT"),
+            ("claude", "Claude AI"),
+            ("deepseek", "DeepSeek"),
+            ("gemini", "Gemini"),
+            ("v0.dev", "v0.dev"),
+            ("v0", "v0.dev"),
+            ("copilot", "Copilot"),
+            ("poe.com", "Poe"),
+            ("poe", "Poe"),
+            ("ollama", "Ollama"),
+            ("grok", "Grok AI"),
+            ("perplexity", "Perplexity"),
+            ("phind", "Phind"),
+            ("mistral", "Mistral AI"),
+            ("qwen", "Qwen"),
+            ("huggingface", "Hugging Face"),
+            ("blackbox", "Blackbox AI"),
+            ("lm-studio", "LM Studio"),
+            ("lmstudio", "LM Studio"),
+            ("jan.ai", "Jan AI"),
+            ("bolt.new", "Bolt.new"),
+            ("bolt", "Bolt.new"),
+            ("devin", "Devin AI"),
+            ("lovable", "Lovable.dev"),
+            ("sider", "Sider AI"),
+            ("monica", "Monica AI"),
+            ("open-webui", "Open WebUI"),
+            ("openwebui", "Open WebUI"),
+            ("librechat", "LibreChat"),
+            ("codeium", "Codeium"),
+            ("tabnine", "Tabnine"),
+            ("replit", "Replit Agent"),
+        ];
+
+        for &(key, label) in AI_SERVICES {
+            if title_lower.contains(key) {
+                return Some(label.to_string());
+            }
+        }
+    }
+    None
+}
+
+pub fn get_enriched_sessions(start_date: &str, end_date: &str) -> Result<Vec<EnrichedActivitySession>> {
+    // Loads from the database on a cold cache, so it has to happen before we take the lock
+    let rules = crate::rules::ruleset();
+    let app_categories = app_categories();
+
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    // 1. Fetch activity sessions
+    let mut stmt = conn.prepare(
+        "SELECT app_name, window_title, exe_path, start_time, end_time, duration_seconds, process_name, process_cwd, url, domain
+         FROM activity_sessions
+         WHERE date(start_time) >= date(?) AND date(start_time) <= date(?)
+         ORDER BY start_time DESC",
+    )?;
+
+    let activity_rows = stmt.query_map([start_date, end_date], |row| {
+        Ok((
+            (
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, i64>(5)?,
+            ),
+            (
+                row.get::<_, Option<String>>(6)?,
+                row.get::<_, Option<String>>(7)?,
+                row.get::<_, Option<String>>(8)?,
+                row.get::<_, Option<String>>(9)?,
+            ),
+        ))
+    })?.collect::<Result<Vec<_>>>()?;
+
+    // 2. Fetch music sessions
+    let mut music_stmt = conn.prepare(
+        "SELECT app_name, window_title, start_time, end_time
+         FROM music_sessions
+         WHERE date(start_time) >= date(?) AND date(start_time) <= date(?)",
+    )?;
+    let music_rows = music_stmt.query_map([start_date, end_date], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, Option<String>>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, Option<String>>(3)?,
+        ))
+    })?.collect::<Result<Vec<_>>>()?;
+
+    // 3. Fetch coding sessions
+    let mut coding_stmt = conn.prepare(
+        "SELECT editor_name, file_path, language, project_dir, is_ai_assisted, start_time, end_time
+         FROM coding_sessions
+         WHERE date(start_time) >= date(?) AND date(start_time) <= date(?)",
+    )?;
     let coding_rows = coding_stmt.query_map([start_date, end_date], |row| {
         Ok((
             row.get::<_, String>(0)?,
@@ -1560,897 +2875,1823 @@ pub fn get_enriched_sessions(start_date: &str, end_date: &str) -> Result<Vec<Enr
         ))
     })?.collect::<Result<Vec<_>>>()?;
 
-    // Helper to parse dates
-    let parse_dt = |s: &str| -> DateTime<Local> {
-        DateTime::parse_from_rfc3339(s)
-            .map(|dt| dt.with_timezone(&Local))
-            .unwrap_or_else(|_| Local::now())
+    // Helper to parse dates
+    let parse_dt = |s: &str| -> DateTime<Local> {
+        DateTime::parse_from_rfc3339(s)
+            .map(|dt| dt.with_timezone(&Local))
+            .unwrap_or_else(|_| Local::now())
+    };
+
+    let mut enriched = Vec::new();
+
+    for (
+        (app_name, window_title, exe_path, start_time, end_time, duration_seconds),
+        (process_name, process_cwd, url, domain),
+    ) in activity_rows
+    {
+        let act_start = parse_dt(&start_time);
+        let act_end = end_time.as_ref().map(|s| parse_dt(s)).unwrap_or_else(Local::now);
+
+        let class = rules.classify(&app_name, &exe_path, &window_title);
+        if class.ignore {
+            continue;
+        }
+
+        // A. Extract website, preferring the domain the browser extension reported
+        let mut website = domain.or_else(|| extract_website(&app_name, &window_title));
+        let mut display_name = app_name.clone();
+
+        if let Some(ai_label) = detect_browser_ai(&app_name, &window_title) {
+            display_name = format!("{} (AI)", app_name);
+            website = Some(ai_label);
+        }
+
+        // B. Find overlapping music tracks
+        let mut overlapping_tracks = Vec::new();
+        for (m_app, m_title, m_start_str, m_end_str) in &music_rows {
+            let m_start = parse_dt(m_start_str);
+            let m_end = m_end_str.as_ref().map(|s| parse_dt(s)).unwrap_or_else(Local::now);
+
+            // Overlap check
+            if act_start < m_end && m_start < act_end {
+                if let Some(t) = m_title {
+                    if !t.is_empty() {
+                        overlapping_tracks.push(format!("{} ({})", t, m_app));
+                    }
+                }
+            }
+        }
+        let music_track = if overlapping_tracks.is_empty() {
+            None
+        } else {
+            Some(overlapping_tracks.join("; "))
+        };
+
+        // C. Find overlapping coding sessions
+        let mut editor_name = None;
+        let mut file_path = None;
+        let mut language = None;
+        let mut project_dir = None;
+        let mut is_ai_assisted = None;
+
+        for (c_editor, c_file, c_lang, c_proj, c_ai, c_start_str, c_end_str) in &coding_rows {
+            let c_start = parse_dt(c_start_str);
+            let c_end = c_end_str.as_ref().map(|s| parse_dt(s)).unwrap_or_else(Local::now);
+
+            // Overlap check
+            if act_start < c_end && c_start < act_end {
+                editor_name = Some(c_editor.clone());
+                is_ai_assisted = Some(*c_ai);
+
+                // Only write file path, language, and project if AI was NOT used!
+                if !*c_ai {
+                    file_path = c_file.clone();
+                    language = c_lang.clone();
+                    project_dir = c_proj.clone();
+                }
+                break; // Just match the first one for simplicity
+            }
+        }
+
+        if let Some(_) = editor_name {
+            if !display_name.ends_with(" (AI)") {
+                if let Some(ai) = is_ai_assisted {
+                    if ai {
+                        display_name = format!("{} (AI)", display_name);
+                    } else {
+                        display_name = format!("{} (No AI)", display_name);
+                    }
+                }
+            }
+        }
+
+        if class.project.is_some() {
+            project_dir = class.project;
+        }
+
+        let category = class.category.or_else(|| app_categories.get(&app_name).cloned());
+        enriched.push(EnrichedActivitySession {
+            app_name,
+            display_name,
+            window_title,
+            exe_path,
+            start_time,
+            end_time,
+            duration_seconds,
+            website,
+            music_track,
+            editor_name,
+            file_path,
+            language,
+            project_dir,
+            is_ai_assisted,
+            category,
+            tags: class.tags,
+            process_name,
+            process_cwd,
+            url,
+        });
+    }
+
+    // 4. Days past the retention window: one entry per rolled-up title, dated by day
+    let mut rollup_stmt = conn.prepare(
+        "SELECT date, app_name, exe_path, window_title, domain, total_seconds, session_count
+         FROM daily_usage
+         WHERE date >= date(?) AND date <= date(?)
+         ORDER BY date DESC, total_seconds DESC",
+    )?;
+    let rollup_rows = rollup_stmt
+        .query_map([start_date, end_date], read_daily_usage_row)?
+        .collect::<Result<Vec<_>>>()?;
+
+    for DailyUsageRow {
+        date,
+        app_name,
+        exe_path,
+        window_title,
+        domain,
+        total_seconds: duration_seconds,
+        ..
+    } in rollup_rows
+    {
+        let class = rules.classify(&app_name, &exe_path, &window_title);
+        if class.ignore {
+            continue;
+        }
+        let mut website = domain.or_else(|| extract_website(&app_name, &window_title));
+        let mut display_name = app_name.clone();
+        if let Some(ai_label) = detect_browser_ai(&app_name, &window_title) {
+            display_name = format!("{} (AI)", app_name);
+            website = Some(ai_label);
+        }
+
+        let category = class.category.or_else(|| app_categories.get(&app_name).cloned());
+        enriched.push(EnrichedActivitySession {
+            app_name,
+            display_name,
+            window_title,
+            exe_path,
+            start_time: date.clone(),
+            end_time: Some(date),
+            duration_seconds,
+            website,
+            music_track: None,
+            editor_name: None,
+            file_path: None,
+            language: None,
+            project_dir: class.project,
+            is_ai_assisted: None,
+            category,
+            tags: class.tags,
+            process_name: None,
+            process_cwd: None,
+            url: None,
+        });
+    }
+
+    Ok(enriched)
+}
+
+fn get_music_sessions_range_raw(start_date: &str, end_date: &str) -> Result<Vec<serde_json::Value>> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT app_name, window_title, exe_path, start_time, end_time, duration_seconds
+         FROM music_sessions
+         WHERE date(start_time) >= date(?1) AND date(start_time) <= date(?2)
+         ORDER BY start_time DESC",
+    )?;
+
+    let rows = stmt.query_map([start_date, end_date], |row| {
+        Ok(serde_json::json!({
+            "app_name": row.get::<_, String>(0)?,
+            "window_title": row.get::<_, Option<String>>(1)?,
+            "exe_path": row.get::<_, String>(2)?,
+            "start_time": row.get::<_, String>(3)?,
+            "end_time": row.get::<_, Option<String>>(4)?,
+            "duration_seconds": row.get::<_, i64>(5)?,
+        }))
+    })?.collect::<Result<Vec<_>>>()?;
+
+    Ok(rows)
+}
+
+fn get_coding_sessions_range_raw(start_date: &str, end_date: &str) -> Result<Vec<serde_json::Value>> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT app_name, editor_name, file_path, language, project_dir, is_ai_assisted, window_title, exe_path, start_time, end_time, duration_seconds, source, branch,
+                repo_name, repo_remote
+         FROM coding_sessions
+         WHERE date(start_time) >= date(?1) AND date(start_time) <= date(?2)
+         ORDER BY start_time DESC",
+    )?;
+
+    let rows = stmt.query_map([start_date, end_date], |row| {
+        Ok(serde_json::json!({
+            "app_name": row.get::<_, String>(0)?,
+            "editor_name": row.get::<_, String>(1)?,
+            "file_path": row.get::<_, Option<String>>(2)?,
+            "language": row.get::<_, Option<String>>(3)?,
+            "project_dir": row.get::<_, Option<String>>(4)?,
+            "is_ai_assisted": row.get::<_, i64>(5)? != 0,
+            "window_title": row.get::<_, String>(6)?,
+            "exe_path": row.get::<_, String>(7)?,
+            "start_time": row.get::<_, String>(8)?,
+            "end_time": row.get::<_, Option<String>>(9)?,
+            "duration_seconds": row.get::<_, i64>(10)?,
+            "source": row.get::<_, String>(11)?,
+            "branch": row.get::<_, Option<String>>(12)?,
+            "repo_name": row.get::<_, Option<String>>(13)?,
+            "repo_remote": row.get::<_, Option<String>>(14)?,
+        }))
+    })?.collect::<Result<Vec<_>>>()?;
+
+    Ok(rows)
+}
+
+pub fn export_sessions_csv(path: &str, start_date: &str, end_date: &str) -> Result<()> {
+    let enriched = get_enriched_sessions(start_date, end_date)?;
+
+    let mut csv_content =
+        String::from("App Name,Window Title,Website,Music Track,Editor,File Path,Language,Project,AI Assisted,Exe Path,Start Time,End Time,Duration (seconds)\n");
+
+    for s in enriched {
+        let escape = |val: &str| -> String {
+            if val.contains(',') || val.contains('"') || val.contains('\n') {
+                format!("\"{}\"", val.replace('"', "\"\""))
+            } else {
+                val.to_string()
+            }
+        };
+        csv_content.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+            escape(&s.display_name),
+            escape(&s.window_title),
+            escape(s.website.as_deref().unwrap_or("")),
+            escape(s.music_track.as_deref().unwrap_or("")),
+            escape(s.editor_name.as_deref().unwrap_or("")),
+            escape(s.file_path.as_deref().unwrap_or("")),
+            escape(s.language.as_deref().unwrap_or("")),
+            escape(s.project_dir.as_deref().unwrap_or("")),
+            s.is_ai_assisted.map(|b| if b { "Yes" } else { "No" }).unwrap_or(""),
+            escape(&s.exe_path),
+            escape(&s.start_time),
+            escape(s.end_time.as_deref().unwrap_or("")),
+            s.duration_seconds
+        ));
+    }
+
+    std::fs::write(path, csv_content)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    Ok(())
+}
+
+pub fn export_sessions_html(path: &str, start_date: &str, end_date: &str) -> Result<()> {
+    let enriched = get_enriched_sessions(start_date, end_date)?;
+
+    let mut html_content = String::from(r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>TimiGS Activity Report</title>
+    <style>
+        body { font-family: 'Segoe UI', Arial, sans-serif; background: #0f172a; color: #e2e8f0; padding: 20px; }
+        h2 { color: #8b5cf6; margin-top: 40px; margin-bottom: 20px; border-bottom: 2px solid #334155; padding-bottom: 10px; }
+        h2:first-of-type { margin-top: 20px; }
+        table { border-collapse: collapse; width: 100%; background: #1e293b; border-radius: 8px; overflow: hidden; margin-bottom: 30px; }
+        th { background: linear-gradient(135deg, #8b5cf6, #6d28d9); color: white; padding: 14px 12px; text-align: left; font-weight: 600; }
+        td { padding: 12px; border-bottom: 1px solid #334155; }
+        tr:hover td { background: #334155; }
+        .duration { color: #60a5fa; font-weight: 600; }
+        .timestamp { color: #94a3b8; font-size: 0.9em; }
+        .badge { background: rgba(139, 92, 246, 0.2); color: #c084fc; padding: 2px 6px; border-radius: 4px; font-size: 0.8em; font-weight: 600; }
+    </style>
+</head>
+<body>
+    <h2>📊 General Activity & Applications</h2>
+    <table>
+        <thead>
+            <tr>
+                <th style="width: 25%;">Application</th>
+                <th style="width: 45%;">Details</th>
+                <th style="width: 12%;">Start Time</th>
+                <th style="width: 12%;">End Time</th>
+                <th style="width: 6%;">Duration</th>
+            </tr>
+        </thead>
+        <tbody>
+"#);
+
+    let escape = |s: &str| -> String {
+        s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+    };
+
+    let format_duration_helper = |dur: i64| -> String {
+        let hrs = dur / 3600;
+        let mins = (dur % 3600) / 60;
+        let secs = dur % 60;
+        if hrs > 0 {
+            format!("{}h {}m {}s", hrs, mins, secs)
+        } else if mins > 0 {
+            format!("{}m {}s", mins, secs)
+        } else {
+            format!("{}s", secs)
+        }
+    };
+
+    // Populate General Activity
+    for s in &enriched {
+        let mut details = escape(&s.window_title);
+        if let Some(ref web) = s.website {
+            details.push_str(&format!(r#"<div style="margin-top: 4px; color: #10b981; font-size: 0.9em;">🌐 <strong>Website</strong>: {}</div>"#, escape(web)));
+        }
+        if let Some(ref track) = s.music_track {
+            details.push_str(&format!(r#"<div style="margin-top: 4px; color: #f43f5e; font-size: 0.9em;">🎵 <strong>Music</strong>: {}</div>"#, escape(track)));
+        }
+        if let Some(ref editor) = s.editor_name {
+            if !s.is_ai_assisted.unwrap_or(false) {
+                let file_str = s.file_path.as_deref().unwrap_or("Workspace / General");
+                let lang_str = s.language.as_deref().unwrap_or("Unknown");
+                let proj_str = s.project_dir.as_deref().unwrap_or("Unknown");
+                details.push_str(&format!(
+                    r#"<div style="margin-top: 4px; color: #60a5fa; font-size: 0.9em;">💻 <strong>Coding</strong>: {} | <strong>File</strong>: {} | <strong>Lang</strong>: {} | <strong>Project</strong>: {}</div>"#,
+                    escape(editor), escape(file_str), escape(lang_str), escape(proj_str)
+                ));
+            }
+        }
+
+        html_content.push_str(&format!(
+            r#"<tr>
+                <td><strong>{}</strong></td>
+                <td>{}</td>
+                <td class="timestamp">{}</td>
+                <td class="timestamp">{}</td>
+                <td class="duration">{}</td>
+            </tr>"#,
+            escape(&s.display_name),
+            details,
+            escape(&s.start_time),
+            escape(s.end_time.as_deref().unwrap_or("Now")),
+            format_duration_helper(s.duration_seconds)
+        ));
+    }
+
+    html_content.push_str(r#"
+        </tbody>
+    </table>
+"#);
+
+    // Category breakdown
+    let categories = category_breakdown(&enriched, &category_weights()?);
+    let total: i64 = categories.iter().map(|c| c.total_seconds).sum();
+    html_content.push_str(&format!(
+        r#"
+    <h2>🗂️ Categories <span class="badge">Productivity score: {:.1}</span></h2>
+    <table>
+        <thead>
+            <tr>
+                <th style="width: 40%;">Category</th>
+                <th style="width: 20%;">Weight</th>
+                <th style="width: 20%;">Share</th>
+                <th style="width: 20%;">Duration</th>
+            </tr>
+        </thead>
+        <tbody>
+"#,
+        productivity_score(&categories)
+    ));
+    for c in &categories {
+        let share = if total > 0 { c.total_seconds as f64 * 100.0 / total as f64 } else { 0.0 };
+        html_content.push_str(&format!(
+            r#"<tr>
+                <td><strong>{}</strong></td>
+                <td>{:+.2}</td>
+                <td>{:.1}%</td>
+                <td class="duration">{}</td>
+            </tr>"#,
+            escape(&c.category),
+            c.productivity_weight,
+            share,
+            format_duration_helper(c.total_seconds)
+        ));
+    }
+
+    html_content.push_str(r#"
+        </tbody>
+    </table>
+</body>
+</html>"#);
+
+    std::fs::write(path, html_content)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    Ok(())
+}
+
+pub fn export_sessions_json(path: &str, start_date: &str, end_date: &str) -> Result<()> {
+    let enriched = get_enriched_sessions(start_date, end_date)?;
+    let raw_music = get_music_sessions_range_raw(start_date, end_date)?;
+    let raw_coding = get_coding_sessions_range_raw(start_date, end_date)?;
+
+    let categories = category_breakdown(&enriched, &category_weights()?);
+
+    let mut activities = Vec::new();
+    for s in enriched {
+        activities.push(serde_json::json!({
+            "app_name": s.app_name,
+            "display_name": s.display_name,
+            "window_title": s.window_title,
+            "exe_path": s.exe_path,
+            "start_time": s.start_time,
+            "end_time": s.end_time,
+            "duration_seconds": s.duration_seconds,
+            "website": s.website,
+            "music_track": s.music_track,
+            "editor_name": s.editor_name,
+            "file_path": s.file_path,
+            "language": s.language,
+            "project_dir": s.project_dir,
+            "is_ai_assisted": s.is_ai_assisted,
+            "category": s.category,
+            "tags": s.tags,
+            "process_name": s.process_name,
+            "process_cwd": s.process_cwd,
+            "url": s.url,
+        }));
+    }
+
+    let json_content = serde_json::to_string_pretty(&serde_json::json!({
+        "activity_sessions": activities,
+        "music_sessions": raw_music,
+        "coding_sessions": raw_coding,
+        "category_breakdown": categories,
+        "productivity_score": productivity_score(&categories),
+    }))
+    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    std::fs::write(path, json_content)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    Ok(())
+}
+
+pub fn import_sessions_json(path: &str) -> Result<usize> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    let parsed: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    let sessions = if let Some(arr) = parsed.as_array() {
+        arr.clone()
+    } else if let Some(obj) = parsed.as_object() {
+        if let Some(arr) = obj.get("activity_sessions").and_then(|v| v.as_array()) {
+            arr.clone()
+        } else {
+            Vec::new()
+        }
+    } else {
+        Vec::new()
     };
 
-    let mut enriched = Vec::new();
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    for (app_name, window_title, exe_path, start_time, end_time, duration_seconds) in activity_rows {
-        let act_start = parse_dt(&start_time);
-        let act_end = end_time.as_ref().map(|s| parse_dt(s)).unwrap_or_else(Local::now);
+    let mut count = 0;
+    for session in sessions {
+        let app_name = session["app_name"].as_str().unwrap_or("Unknown");
+        let window_title = session["window_title"].as_str().unwrap_or("");
+        let exe_path = session["exe_path"].as_str().unwrap_or("");
+        let start_time = session["start_time"].as_str().unwrap_or("");
+        let end_time = session["end_time"].as_str(); // Option<&str>
+        let duration = session["duration_seconds"].as_i64().unwrap_or(0);
 
-        // A. Extract website
-        let mut website = extract_website(&app_name, &window_title);
-        let mut display_name = app_name.clone();
+        let exists: i64 = conn.query_row(
+            "SELECT count(*) FROM activity_sessions WHERE start_time = ?1 AND exe_path = ?2",
+            params![start_time, exe_path],
+            |row| row.get(0),
+        ).unwrap_or(0);
 
-        if let Some(ai_label) = detect_browser_ai(&app_name, &window_title) {
-            display_name = format!("{} (AI)", app_name);
-            website = Some(ai_label);
+        if exists == 0 {
+            conn.execute(
+                "INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, end_time, duration_seconds)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    app_name,
+                    window_title,
+                    exe_path,
+                    start_time,
+                    end_time,
+                    duration,
+                ],
+            )?;
+            count += 1;
         }
+    }
 
-        // B. Find overlapping music tracks
-        let mut overlapping_tracks = Vec::new();
-        for (m_app, m_title, m_start_str, m_end_str) in &music_rows {
-            let m_start = parse_dt(m_start_str);
-            let m_end = m_end_str.as_ref().map(|s| parse_dt(s)).unwrap_or_else(Local::now);
+    Ok(count)
+}
 
-            // Overlap check
-            if act_start < m_end && m_start < act_end {
-                if let Some(t) = m_title {
-                    if !t.is_empty() {
-                        overlapping_tracks.push(format!("{} ({})", t, m_app));
-                    }
-                }
+pub fn import_sessions_csv(path: &str) -> Result<usize> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .from_path(path)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let mut count = 0;
+    for result in reader.records() {
+        if let Ok(record) = result {
+            if record.len() < 6 {
+                continue;
+            }
+
+            let app_name = &record[0];
+            let window_title = &record[1];
+            let exe_path = &record[2];
+            let start_time = &record[3];
+            let end_time_str = &record[4];
+            let duration: i64 = record[5].parse().unwrap_or(0);
+
+            let end_time = if end_time_str.trim().is_empty() {
+                None
+            } else {
+                Some(end_time_str)
+            };
+
+            let exists: i64 = conn.query_row(
+                "SELECT count(*) FROM activity_sessions WHERE start_time = ?1 AND exe_path = ?2",
+                params![start_time, exe_path],
+                |row| row.get(0),
+            ).unwrap_or(0);
+
+            if exists == 0 {
+                conn.execute(
+                    "INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, end_time, duration_seconds)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        app_name,
+                        window_title,
+                        exe_path,
+                        start_time,
+                        end_time,
+                        duration,
+                    ],
+                )?;
+                count += 1;
             }
         }
-        let music_track = if overlapping_tracks.is_empty() {
-            None
-        } else {
-            Some(overlapping_tracks.join("; "))
-        };
+    }
 
-        // C. Find overlapping coding sessions
-        let mut editor_name = None;
-        let mut file_path = None;
-        let mut language = None;
-        let mut project_dir = None;
-        let mut is_ai_assisted = None;
+    Ok(count)
+}
 
-        for (c_editor, c_file, c_lang, c_proj, c_ai, c_start_str, c_end_str) in &coding_rows {
-            let c_start = parse_dt(c_start_str);
-            let c_end = c_end_str.as_ref().map(|s| parse_dt(s)).unwrap_or_else(Local::now);
+pub fn import_sessions_markdown(path: &str) -> Result<usize> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
 
-            // Overlap check
-            if act_start < c_end && c_start < act_end {
-                editor_name = Some(c_editor.clone());
-                is_ai_assisted = Some(*c_ai);
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-                // Only write file path, language, and project if AI was NOT used!
-                if !*c_ai {
-                    file_path = c_file.clone();
-                    language = c_lang.clone();
-                    project_dir = c_proj.clone();
+    let mut count = 0;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('|') && !line.contains("---|") && !line.contains("App Name | Window Title") {
+            let parts: Vec<&str> = line.split('|').map(|s| s.trim()).collect();
+            if parts.len() >= 6 {
+                let app_name = parts[1].replace("\\|", "|");
+                let window_title = parts[2].replace("\\|", "|");
+                let start_time = parts[3];
+                let end_time_str = parts[4];
+                let duration: i64 = parts[5].parse().unwrap_or(0);
+
+                let end_time = if end_time_str.is_empty() { None } else { Some(end_time_str) };
+                let exe_path = "imported_from_markdown";
+
+                let exists: i64 = conn.query_row(
+                    "SELECT count(*) FROM activity_sessions WHERE start_time = ?1 AND exe_path = ?2",
+                    params![start_time, exe_path],
+                    |row| row.get(0),
+                ).unwrap_or(0);
+
+                if exists == 0 {
+                    conn.execute(
+                        "INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, end_time, duration_seconds)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                        params![app_name, window_title, exe_path, start_time, end_time, duration],
+                    )?;
+                    count += 1;
                 }
-                break; // Just match the first one for simplicity
             }
         }
+    }
 
-        if let Some(_) = editor_name {
-            if !display_name.ends_with(" (AI)") {
-                if let Some(ai) = is_ai_assisted {
-                    if ai {
-                        display_name = format!("{} (AI)", display_name);
-                    } else {
-                        display_name = format!("{} (No AI)", display_name);
-                    }
-                }
+    Ok(count)
+}
+
+pub fn import_sessions_html(path: &str) -> Result<usize> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let mut count = 0;
+
+    for tr_block in content.split("<tr>").skip(2) {
+        let td_parts: Vec<&str> = tr_block.split("</td>").collect();
+        if td_parts.len() >= 5 {
+            let app_name = td_parts[0].split("<strong>").nth(1).unwrap_or("").split("</strong>").next().unwrap_or("").trim()
+                .replace("&amp;", "&").replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"");
+
+            let window_title = td_parts[1].split("<td>").nth(1).unwrap_or("").trim()
+                .replace("&amp;", "&").replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"");
+
+            let start_time = td_parts[2].split("\">").nth(1).unwrap_or("").trim()
+                .replace("&amp;", "&").replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"");
+
+            let end_time_str = td_parts[3].split("\">").nth(1).unwrap_or("").trim()
+                .replace("&amp;", "&").replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"");
+
+            let end_time = if end_time_str == "Now" || end_time_str.is_empty() { None } else { Some(end_time_str.as_str()) };
+
+            let duration_str = td_parts[4].split("\">").nth(1).unwrap_or("").trim();
+            let mut duration: i64 = 0;
+            for part in duration_str.split_whitespace() {
+                 let num = part.trim_end_matches(&['h', 'm', 's'][..]).parse::<i64>().unwrap_or(0);
+                 if part.ends_with('h') { duration += num * 3600; }
+                 else if part.ends_with('m') { duration += num * 60; }
+                 else if part.ends_with('s') { duration += num; }
+            }
+
+            let exe_path = "imported_from_html";
+
+            let exists: i64 = conn.query_row(
+                "SELECT count(*) FROM activity_sessions WHERE start_time = ?1 AND exe_path = ?2",
+                params![start_time, exe_path],
+                |row| row.get(0),
+            ).unwrap_or(0);
+
+            if exists == 0 && !app_name.is_empty() {
+                conn.execute(
+                    "INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, end_time, duration_seconds)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![app_name, window_title, exe_path, start_time, end_time, duration],
+                )?;
+                count += 1;
             }
         }
-
-        enriched.push(EnrichedActivitySession {
-            app_name,
-            display_name,
-            window_title,
-            exe_path,
-            start_time,
-            end_time,
-            duration_seconds,
-            website,
-            music_track,
-            editor_name,
-            file_path,
-            language,
-            project_dir,
-            is_ai_assisted,
-        });
     }
 
-    Ok(enriched)
+    Ok(count)
 }
 
-fn get_music_sessions_range_raw(start_date: &str, end_date: &str) -> Result<Vec<serde_json::Value>> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
-
-    let mut stmt = conn.prepare(
-        "SELECT app_name, window_title, exe_path, start_time, end_time, duration_seconds
-         FROM music_sessions
-         WHERE date(start_time) >= date(?1) AND date(start_time) <= date(?2)
-         ORDER BY start_time DESC",
-    )?;
+/// Imports window activity from an ActivityWatch export (see `activitywatch`), with
+/// away time left out. Titles and URLs go through the same privacy rules as tracking.
+pub fn import_sessions_activitywatch(path: &str) -> Result<usize> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    let sessions = crate::activitywatch::parse_export(&content)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.into()))?;
+
+    // Privacy rules read the database, so resolve them before taking the lock
+    let sessions: Vec<_> = sessions
+        .into_iter()
+        .map(|s| {
+            let privacy = crate::privacy::for_window(&s.app_name, &s.app_name, &s.window_title);
+            let title = privacy.title(&s.window_title);
+            let (url, domain) = if privacy.keeps_url() { (s.url, s.domain) } else { (None, None) };
+            (s.app_name, title, s.start_time, s.end_time, url, domain)
+        })
+        .collect();
 
-    let rows = stmt.query_map([start_date, end_date], |row| {
-        Ok(serde_json::json!({
-            "app_name": row.get::<_, String>(0)?,
-            "window_title": row.get::<_, Option<String>>(1)?,
-            "exe_path": row.get::<_, String>(2)?,
-            "start_time": row.get::<_, String>(3)?,
-            "end_time": row.get::<_, Option<String>>(4)?,
-            "duration_seconds": row.get::<_, i64>(5)?,
-        }))
-    })?.collect::<Result<Vec<_>>>()?;
+    // One transaction: fast for years of events, and nothing half-imported on failure
+    with_transaction(|tx| {
+        let mut exists =
+            tx.prepare("SELECT 1 FROM activity_sessions WHERE start_time = ?1 AND exe_path = ?2")?;
+        let mut insert = tx.prepare(
+            "INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, end_time, duration_seconds, url, domain)
+             VALUES (?1, ?2, ?1, ?3, ?4, ?5, ?6, ?7)",
+        )?;
 
-    Ok(rows)
+        let mut count = 0;
+        for (app_name, window_title, start, end, url, domain) in sessions {
+            let start_time = start.with_timezone(&Local).to_rfc3339();
+            if exists.exists(params![start_time, app_name])? {
+                continue;
+            }
+            insert.execute(params![
+                app_name,
+                window_title,
+                start_time,
+                end.with_timezone(&Local).to_rfc3339(),
+                (end - start).num_seconds(),
+                url,
+                domain,
+            ])?;
+            count += 1;
+        }
+        Ok(count)
+    })
 }
 
-fn get_coding_sessions_range_raw(start_date: &str, end_date: &str) -> Result<Vec<serde_json::Value>> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+/// Writes closed sessions as ActivityWatch buckets, importable with `aw-client import`
+/// or the web UI. `hostname` defaults to this machine's.
+pub fn export_sessions_activitywatch(
+    path: &str,
+    start_date: &str,
+    end_date: &str,
+    hostname: Option<&str>,
+) -> Result<()> {
+    let parse = |date: &str| {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+    };
+    let sessions = get_sessions_range(parse(start_date)?, parse(end_date)?)?;
+    let host = hostname.map(str::to_string).unwrap_or_else(crate::activitywatch::hostname);
 
-    let mut stmt = conn.prepare(
-        "SELECT app_name, editor_name, file_path, language, project_dir, is_ai_assisted, window_title, exe_path, start_time, end_time, duration_seconds
-         FROM coding_sessions
-         WHERE date(start_time) >= date(?1) AND date(start_time) <= date(?2)
-         ORDER BY start_time DESC",
-    )?;
+    let json_content = serde_json::to_string_pretty(&crate::activitywatch::export_buckets(&sessions, &host))
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
 
-    let rows = stmt.query_map([start_date, end_date], |row| {
-        Ok(serde_json::json!({
-            "app_name": row.get::<_, String>(0)?,
-            "editor_name": row.get::<_, String>(1)?,
-            "file_path": row.get::<_, Option<String>>(2)?,
-            "language": row.get::<_, Option<String>>(3)?,
-            "project_dir": row.get::<_, Option<String>>(4)?,
-            "is_ai_assisted": row.get::<_, i64>(5)? != 0,
-            "window_title": row.get::<_, String>(6)?,
-            "exe_path": row.get::<_, String>(7)?,
-            "start_time": row.get::<_, String>(8)?,
-            "end_time": row.get::<_, Option<String>>(9)?,
-            "duration_seconds": row.get::<_, i64>(10)?,
-        }))
-    })?.collect::<Result<Vec<_>>>()?;
+    std::fs::write(path, json_content)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
 
-    Ok(rows)
+    Ok(())
 }
 
-pub fn export_sessions_csv(path: &str, start_date: &str, end_date: &str) -> Result<()> {
+pub fn export_sessions_markdown(path: &str, start_date: &str, end_date: &str) -> Result<()> {
     let enriched = get_enriched_sessions(start_date, end_date)?;
+    let raw_music = get_music_sessions_range_raw(start_date, end_date)?;
 
-    let mut csv_content =
-        String::from("App Name,Window Title,Website,Music Track,Editor,File Path,Language,Project,AI Assisted,Exe Path,Start Time,End Time,Duration (seconds)\n");
+    let mut md_content = String::from("# TimiGS Activity Report\n\n");
 
-    for s in enriched {
-        let escape = |val: &str| -> String {
-            if val.contains(',') || val.contains('"') || val.contains('\n') {
-                format!("\"{}\"", val.replace('"', "\"\""))
-            } else {
-                val.to_string()
+    // 1. General Activity
+    md_content.push_str("## 📊 General Activity & Applications\n\n");
+    md_content.push_str("| App Name | Activity Details | Start Time | End Time | Duration |\n");
+    md_content.push_str("|---|---|---|---|---|\n");
+
+    let format_duration_helper = |dur: i64| -> String {
+        let hrs = dur / 3600;
+        let mins = (dur % 3600) / 60;
+        let secs = dur % 60;
+        if hrs > 0 {
+            format!("{}h {}m {}s", hrs, mins, secs)
+        } else if mins > 0 {
+            format!("{}m {}s", mins, secs)
+        } else {
+            format!("{}s", secs)
+        }
+    };
+
+    let escape = |s: &str| -> String {
+        s.replace('|', "\\|")
+    };
+
+    for s in &enriched {
+        let mut details = escape(&s.window_title);
+        if let Some(ref web) = s.website {
+            details.push_str(&format!(" <br>🌐 **Website**: {}", escape(web)));
+        }
+        if let Some(ref track) = s.music_track {
+            details.push_str(&format!(" <br>🎵 **Music**: {}", escape(track)));
+        }
+        if let Some(ref editor) = s.editor_name {
+            if !s.is_ai_assisted.unwrap_or(false) {
+                let file_str = s.file_path.as_deref().unwrap_or("Workspace / General");
+                let lang_str = s.language.as_deref().unwrap_or("Unknown");
+                let proj_str = s.project_dir.as_deref().unwrap_or("Unknown");
+                details.push_str(&format!(
+                    " <br>💻 **Coding**: {} | File: {} | Lang: {} | Project: {}",
+                    escape(editor), escape(file_str), escape(lang_str), escape(proj_str)
+                ));
             }
-        };
-        csv_content.push_str(&format!(
-            "{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+        }
+
+        md_content.push_str(&format!(
+            "| **{}** | {} | {} | {} | {} |\n",
             escape(&s.display_name),
-            escape(&s.window_title),
-            escape(s.website.as_deref().unwrap_or("")),
-            escape(s.music_track.as_deref().unwrap_or("")),
-            escape(s.editor_name.as_deref().unwrap_or("")),
-            escape(s.file_path.as_deref().unwrap_or("")),
-            escape(s.language.as_deref().unwrap_or("")),
-            escape(s.project_dir.as_deref().unwrap_or("")),
-            s.is_ai_assisted.map(|b| if b { "Yes" } else { "No" }).unwrap_or(""),
-            escape(&s.exe_path),
+            details,
             escape(&s.start_time),
-            escape(s.end_time.as_deref().unwrap_or("")),
-            s.duration_seconds
+            escape(s.end_time.as_deref().unwrap_or("Now")),
+            format_duration_helper(s.duration_seconds)
         ));
     }
 
-    std::fs::write(path, csv_content)
+    // 2. Categories
+    let categories = category_breakdown(&enriched, &category_weights()?);
+    let total: i64 = categories.iter().map(|c| c.total_seconds).sum();
+    md_content.push_str("\n## 🗂️ Categories\n\n");
+    md_content.push_str(&format!(
+        "**Productivity score**: {:.1} / 100\n\n",
+        productivity_score(&categories)
+    ));
+    md_content.push_str("| Category | Weight | Share | Duration |\n");
+    md_content.push_str("|---|---|---|---|\n");
+    for c in &categories {
+        let share = if total > 0 { c.total_seconds as f64 * 100.0 / total as f64 } else { 0.0 };
+        md_content.push_str(&format!(
+            "| **{}** | {:+.2} | {:.1}% | {} |\n",
+            escape(&c.category),
+            c.productivity_weight,
+            share,
+            format_duration_helper(c.total_seconds)
+        ));
+    }
+
+    // 3. Music History
+    md_content.push_str("\n## 🎵 Music Listening History\n\n");
+    md_content.push_str("| Song / Track Title | Player / Application | Start Time | End Time | Duration |\n");
+    md_content.push_str("|---|---|---|---|---|\n");
+
+    if raw_music.is_empty() {
+        md_content.push_str("| *No music history tracked* | | | | |\n");
+    } else {
+        for m in &raw_music {
+            let title = m["window_title"].as_str().unwrap_or("Unknown");
+            let app = m["app_name"].as_str().unwrap_or("Unknown");
+            let start = m["start_time"].as_str().unwrap_or("");
+            let end = m["end_time"].as_str().unwrap_or("Now");
+            let dur = m["duration_seconds"].as_i64().unwrap_or(0);
+            md_content.push_str(&format!(
+                "| **{}** | {} | {} | {} | {} |\n",
+                escape(title),
+                escape(app),
+                escape(start),
+                escape(end),
+                format_duration_helper(dur)
+            ));
+        }
+    }
+
+    std::fs::write(path, md_content)
         .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
 
     Ok(())
 }
 
-pub fn export_sessions_html(path: &str, start_date: &str, end_date: &str) -> Result<()> {
-    let enriched = get_enriched_sessions(start_date, end_date)?;
+// Focus sessions and Time OUT breaks
 
-    let mut html_content = String::from(r#"<!DOCTYPE html>
-<html>
-<head>
-    <meta charset="utf-8">
-    <title>TimiGS Activity Report</title>
-    <style>
-        body { font-family: 'Segoe UI', Arial, sans-serif; background: #0f172a; color: #e2e8f0; padding: 20px; }
-        h2 { color: #8b5cf6; margin-top: 40px; margin-bottom: 20px; border-bottom: 2px solid #334155; padding-bottom: 10px; }
-        h2:first-of-type { margin-top: 20px; }
-        table { border-collapse: collapse; width: 100%; background: #1e293b; border-radius: 8px; overflow: hidden; margin-bottom: 30px; }
-        th { background: linear-gradient(135deg, #8b5cf6, #6d28d9); color: white; padding: 14px 12px; text-align: left; font-weight: 600; }
-        td { padding: 12px; border-bottom: 1px solid #334155; }
-        tr:hover td { background: #334155; }
-        .duration { color: #60a5fa; font-weight: 600; }
-        .timestamp { color: #94a3b8; font-size: 0.9em; }
-        .badge { background: rgba(139, 92, 246, 0.2); color: #c084fc; padding: 2px 6px; border-radius: 4px; font-size: 0.8em; font-weight: 600; }
-    </style>
-</head>
-<body>
-    <h2>📊 General Activity & Applications</h2>
-    <table>
-        <thead>
-            <tr>
-                <th style="width: 25%;">Application</th>
-                <th style="width: 45%;">Details</th>
-                <th style="width: 12%;">Start Time</th>
-                <th style="width: 12%;">End Time</th>
-                <th style="width: 6%;">Duration</th>
-            </tr>
-        </thead>
-        <tbody>
-"#);
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FocusBlock {
+    pub id: i64,
+    /// `focus` or `break`.
+    pub kind: String,
+    /// The focused app, empty for breaks.
+    pub label: String,
+    pub start_time: String,
+    pub end_time: String,
+    pub duration_seconds: i64,
+}
 
-    let escape = |s: &str| -> String {
-        s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
-    };
+/// Record a finished focus session or break. Blocks shorter than a second are dropped.
+pub fn record_focus_block(kind: &str, label: &str, start: DateTime<Local>, end: DateTime<Local>) -> Result<()> {
+    let duration = (end - start).num_seconds();
+    if duration < 1 {
+        return Ok(());
+    }
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    conn.execute(
+        "INSERT INTO focus_blocks (kind, label, start_time, end_time, duration_seconds)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![kind, label, start.to_rfc3339(), end.to_rfc3339(), duration],
+    )?;
+    Ok(())
+}
+
+pub fn get_focus_blocks_range(from: &str, to: &str) -> Result<Vec<FocusBlock>> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, kind, label, start_time, end_time, duration_seconds
+         FROM focus_blocks
+         WHERE date(start_time) >= date(?1) AND date(start_time) <= date(?2)
+         ORDER BY start_time",
+    )?;
+
+    let blocks = stmt
+        .query_map(params![from, to], |row| {
+            Ok(FocusBlock {
+                id: row.get(0)?,
+                kind: row.get(1)?,
+                label: row.get(2)?,
+                start_time: row.get(3)?,
+                end_time: row.get(4)?,
+                duration_seconds: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(blocks)
+}
+
+/// Calendar events for a date range: activity merged into blocks per category (see
+/// `ical::merge_blocks`), coding sessions, focus sessions and Time OUT breaks.
+/// Days already rolled up by retention have no times and are left out.
+fn calendar_events(start_date: &str, end_date: &str) -> Result<Vec<crate::ical::CalendarEvent>> {
+    use crate::ical::CalendarEvent;
 
-    let format_duration_helper = |dur: i64| -> String {
+    let parse = |s: &str| {
+        DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|dt| dt.with_timezone(&Local))
+    };
+    let format_duration = |dur: i64| -> String {
         let hrs = dur / 3600;
         let mins = (dur % 3600) / 60;
-        let secs = dur % 60;
         if hrs > 0 {
-            format!("{}h {}m {}s", hrs, mins, secs)
-        } else if mins > 0 {
-            format!("{}m {}s", mins, secs)
+            format!("{}h {}m", hrs, mins)
         } else {
-            format!("{}s", secs)
+            format!("{}m", mins.max(1))
         }
     };
 
-    // Populate General Activity
-    for s in &enriched {
-        let mut details = escape(&s.window_title);
-        if let Some(ref web) = s.website {
-            details.push_str(&format!(r#"<div style="margin-top: 4px; color: #10b981; font-size: 0.9em;">🌐 <strong>Website</strong>: {}</div>"#, escape(web)));
-        }
-        if let Some(ref track) = s.music_track {
-            details.push_str(&format!(r#"<div style="margin-top: 4px; color: #f43f5e; font-size: 0.9em;">🎵 <strong>Music</strong>: {}</div>"#, escape(track)));
-        }
-        if let Some(ref editor) = s.editor_name {
-            if !s.is_ai_assisted.unwrap_or(false) {
-                let file_str = s.file_path.as_deref().unwrap_or("Workspace / General");
-                let lang_str = s.language.as_deref().unwrap_or("Unknown");
-                let proj_str = s.project_dir.as_deref().unwrap_or("Unknown");
-                details.push_str(&format!(
-                    r#"<div style="margin-top: 4px; color: #60a5fa; font-size: 0.9em;">💻 <strong>Coding</strong>: {} | <strong>File</strong>: {} | <strong>Lang</strong>: {} | <strong>Project</strong>: {}</div>"#,
-                    escape(editor), escape(file_str), escape(lang_str), escape(proj_str)
-                ));
-            }
-        }
+    let mut spans: Vec<_> = get_enriched_sessions(start_date, end_date)?
+        .into_iter()
+        .filter_map(|s| {
+            let start = parse(&s.start_time)?;
+            let end = s.end_time.as_deref().and_then(parse)?;
+            let category = Some(session_category(&s)).filter(|c| c != UNCATEGORIZED);
+            Some((start, end, s.display_name, category))
+        })
+        .collect();
+    spans.sort_by_key(|span| span.0);
+
+    let mut events = Vec::new();
+    for block in crate::ical::merge_blocks(spans) {
+        let apps: Vec<&str> = block.apps.iter().take(3).map(|(app, _)| app.as_str()).collect();
+        let summary = match &block.category {
+            Some(category) => format!("{}: {}", category, apps.join(", ")),
+            None => apps.join(", "),
+        };
+        let description = block
+            .apps
+            .iter()
+            .map(|(app, secs)| format!("{} — {}", app, format_duration(*secs)))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut categories = vec!["Activity".to_string()];
+        categories.extend(block.category.clone());
+        events.push(CalendarEvent {
+            uid: format!("activity-{}@timigs", block.start.timestamp()),
+            start: block.start,
+            end: block.end,
+            summary,
+            description,
+            categories,
+        });
+    }
 
-        html_content.push_str(&format!(
-            r#"<tr>
-                <td><strong>{}</strong></td>
-                <td>{}</td>
-                <td class="timestamp">{}</td>
-                <td class="timestamp">{}</td>
-                <td class="duration">{}</td>
-            </tr>"#,
-            escape(&s.display_name),
-            details,
-            escape(&s.start_time),
-            escape(s.end_time.as_deref().unwrap_or("Now")),
-            format_duration_helper(s.duration_seconds)
-        ));
+    for session in get_coding_sessions_range(start_date, end_date)? {
+        let (Some(start), Some(end)) = (parse(&session.start_time), session.end_time.as_deref().and_then(parse)) else {
+            continue;
+        };
+        let subject = session
+            .repo_name
+            .clone()
+            .or_else(|| session.project_dir.clone())
+            .or_else(|| session.file_path.clone())
+            .unwrap_or_else(|| session.editor_name.clone());
+        let mut description = vec![format!("Editor: {}", session.editor_name)];
+        description.extend(session.file_path.as_ref().map(|f| format!("File: {}", f)));
+        description.extend(session.branch.as_ref().map(|b| format!("Branch: {}", b)));
+        let mut categories = vec!["Coding".to_string()];
+        categories.extend(session.language.clone());
+        events.push(CalendarEvent {
+            uid: format!("coding-{}@timigs", session.id),
+            start,
+            end,
+            summary: format!("Coding: {}", subject),
+            description: description.join("\n"),
+            categories,
+        });
     }
 
-    html_content.push_str(r#"
-        </tbody>
-    </table>
-</body>
-</html>"#);
+    for block in get_focus_blocks_range(start_date, end_date)? {
+        let (Some(start), Some(end)) = (parse(&block.start_time), parse(&block.end_time)) else {
+            continue;
+        };
+        let (summary, category) = if block.kind == "break" {
+            ("Time OUT break".to_string(), "Break")
+        } else {
+            (format!("Focus: {}", block.label), "Focus")
+        };
+        events.push(CalendarEvent {
+            uid: format!("{}-{}@timigs", block.kind, block.id),
+            start,
+            end,
+            summary,
+            description: String::new(),
+            categories: vec![category.to_string()],
+        });
+    }
 
-    std::fs::write(path, html_content)
-        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    events.sort_by_key(|e| e.start);
+    Ok(events)
+}
 
+pub fn export_sessions_ics(path: &str, start_date: &str, end_date: &str) -> Result<()> {
+    let events = calendar_events(start_date, end_date)?;
+    std::fs::write(path, crate::ical::calendar("TimiGS", &events))
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
     Ok(())
 }
 
-pub fn export_sessions_json(path: &str, start_date: &str, end_date: &str) -> Result<()> {
-    let enriched = get_enriched_sessions(start_date, end_date)?;
-    let raw_music = get_music_sessions_range_raw(start_date, end_date)?;
-    let raw_coding = get_coding_sessions_range_raw(start_date, end_date)?;
-
-    let mut activities = Vec::new();
-    for s in enriched {
-        activities.push(serde_json::json!({
-            "app_name": s.app_name,
-            "display_name": s.display_name,
-            "window_title": s.window_title,
-            "exe_path": s.exe_path,
-            "start_time": s.start_time,
-            "end_time": s.end_time,
-            "duration_seconds": s.duration_seconds,
-            "website": s.website,
-            "music_track": s.music_track,
-            "editor_name": s.editor_name,
-            "file_path": s.file_path,
-            "language": s.language,
-            "project_dir": s.project_dir,
-            "is_ai_assisted": s.is_ai_assisted,
-        }));
-    }
-
-    let json_content = serde_json::to_string_pretty(&serde_json::json!({
-        "activity_sessions": activities,
-        "music_sessions": raw_music,
-        "coding_sessions": raw_coding,
-    }))
-    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
-
-    std::fs::write(path, json_content)
-        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+/// Days of history in the calendar feed kept in the auto-export folder.
+const CALENDAR_FEED_DAYS: i64 = 90;
+const CALENDAR_FEED_FILE: &str = "TimiGS.ics";
 
-    Ok(())
+/// Rewrite the subscribable calendar in the auto-export folder. It is replaced in one
+/// step so a calendar app never reads half a file.
+fn refresh_calendar_feed(folder: &str) -> Result<()> {
+    let today = Local::now().date_naive();
+    let from = (today - chrono::Duration::days(CALENDAR_FEED_DAYS)).to_string();
+    let events = calendar_events(&from, &today.to_string())?;
+
+    std::fs::create_dir_all(folder).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    let path = PathBuf::from(folder).join(CALENDAR_FEED_FILE);
+    let partial = path.with_extension("ics.partial");
+    std::fs::write(&partial, crate::ical::calendar("TimiGS", &events))
+        .and_then(|_| std::fs::rename(&partial, &path))
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
 }
 
-pub fn import_sessions_json(path: &str) -> Result<usize> {
-    let content = std::fs::read_to_string(path)
-        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+pub fn auto_export_if_needed() -> Result<()> {
+    let settings = get_settings();
 
-    let parsed: serde_json::Value = serde_json::from_str(&content)
-        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    if !settings.auto_export_enabled || settings.auto_export_folder.is_empty() {
+        return Ok(());
+    }
 
-    let sessions = if let Some(arr) = parsed.as_array() {
-        arr.clone()
-    } else if let Some(obj) = parsed.as_object() {
-        if let Some(arr) = obj.get("activity_sessions").and_then(|v| v.as_array()) {
-            arr.clone()
-        } else {
-            Vec::new()
+    let now = Local::now();
+    let last_export = settings.last_export_time
+        .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+        .map(|dt| dt.with_timezone(&Local));
+
+    let should_export = match last_export {
+        None => true,
+        Some(last) => {
+            let elapsed = now.signed_duration_since(last).num_hours();
+            elapsed >= settings.auto_export_interval_hours
         }
-    } else {
-        Vec::new()
     };
 
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    if should_export {
+        let timestamp = now.format("%Y-%m-%d_%H-%M-%S").to_string();
+        let filename = format!("TimiGS_Activity_{}.html", timestamp);
+        let export_path = PathBuf::from(&settings.auto_export_folder).join(filename);
 
-    let mut count = 0;
-    for session in sessions {
-        let app_name = session["app_name"].as_str().unwrap_or("Unknown");
-        let window_title = session["window_title"].as_str().unwrap_or("");
-        let exe_path = session["exe_path"].as_str().unwrap_or("");
-        let start_time = session["start_time"].as_str().unwrap_or("");
-        let end_time = session["end_time"].as_str(); // Option<&str>
-        let duration = session["duration_seconds"].as_i64().unwrap_or(0);
+        std::fs::create_dir_all(&settings.auto_export_folder)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
 
-        let exists: i64 = conn.query_row(
-            "SELECT count(*) FROM activity_sessions WHERE start_time = ?1 AND exe_path = ?2",
-            params![start_time, exe_path],
-            |row| row.get(0),
-        ).unwrap_or(0);
+        export_sessions_html(export_path.to_str().unwrap(), "1970-01-01", "2099-12-31")?;
 
-        if exists == 0 {
-            conn.execute(
-                "INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, end_time, duration_seconds)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-                params![
-                    app_name,
-                    window_title,
-                    exe_path,
-                    start_time,
-                    end_time,
-                    duration,
-                ],
-            )?;
-            count += 1;
-        }
+        save_last_export_time(&now.to_rfc3339())?;
     }
 
-    Ok(count)
+    // The calendar feed follows along every hour, whatever the export interval
+    refresh_calendar_feed(&settings.auto_export_folder)?;
+
+    Ok(())
 }
 
-pub fn import_sessions_csv(path: &str) -> Result<usize> {
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .from_path(path)
-        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+fn save_last_export_time(time: &str) -> Result<()> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES ('last_export_time', ?1)",
+        [time],
+    )?;
+    Ok(())
+}
 
+/// Get all activity sessions (for export/sync)
+pub fn get_all_activity() -> Result<Vec<ActivitySession>> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    let mut count = 0;
-    for result in reader.records() {
-        if let Ok(record) = result {
-            if record.len() < 6 {
-                continue;
-            }
+    let mut stmt = conn.prepare(
+        "SELECT id, app_name, window_title, exe_path, start_time, end_time, duration_seconds, url, domain
+         FROM activity_sessions
+         ORDER BY start_time DESC",
+    )?;
 
-            let app_name = &record[0];
-            let window_title = &record[1];
-            let exe_path = &record[2];
-            let start_time = &record[3];
-            let end_time_str = &record[4];
-            let duration: i64 = record[5].parse().unwrap_or(0);
+    let sessions = stmt
+        .query_map([], |row| {
+            Ok(ActivitySession {
+                id: Some(row.get(0)?),
+                app_name: row.get(1)?,
+                window_title: row.get(2)?,
+                exe_path: row.get(3)?,
+                start_time: DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
+                    .map(|dt| dt.with_timezone(&Local))
+                    .unwrap_or_else(|_| Local::now()),
+                end_time: row
+                    .get::<_, Option<String>>(5)?
+                    .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+                    .map(|dt| dt.with_timezone(&Local)),
+                duration_seconds: row.get(6)?,
+                url: row.get(7)?,
+                domain: row.get(8)?,
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
 
-            let end_time = if end_time_str.trim().is_empty() {
-                None
-            } else {
-                Some(end_time_str)
-            };
+    Ok(sessions)
+}
 
-            let exists: i64 = conn.query_row(
-                "SELECT count(*) FROM activity_sessions WHERE start_time = ?1 AND exe_path = ?2",
-                params![start_time, exe_path],
-                |row| row.get(0),
-            ).unwrap_or(0);
+// Music Sessions Functions
 
-            if exists == 0 {
-                conn.execute(
-                    "INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, end_time, duration_seconds)
-                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-                    params![
-                        app_name,
-                        window_title,
-                        exe_path,
-                        start_time,
-                        end_time,
-                        duration,
-                    ],
-                )?;
-                count += 1;
-            }
-        }
-    }
+pub fn start_music_session_in(
+    conn: &Connection,
+    app_name: &str,
+    window_title: Option<&str>,
+    exe_path: &str,
+    start_time: DateTime<Local>,
+) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO music_sessions (app_name, window_title, exe_path, start_time) VALUES (?1, ?2, ?3, ?4)",
+        params![app_name, window_title, exe_path, start_time.to_rfc3339()],
+    )?;
 
-    Ok(count)
+    Ok(conn.last_insert_rowid())
 }
 
-pub fn import_sessions_markdown(path: &str) -> Result<usize> {
-    let content = std::fs::read_to_string(path)
-        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+pub fn end_music_session_in(conn: &Connection, id: i64, end_time: DateTime<Local>) -> Result<()> {
+    end_session_row(conn, "music_sessions", id, end_time)
+}
 
+pub fn get_today_music_summary() -> Result<Vec<MusicAppUsage>> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    let mut count = 0;
-
-    for line in content.lines() {
-        let line = line.trim();
-        if line.starts_with('|') && !line.contains("---|") && !line.contains("App Name | Window Title") {
-            let parts: Vec<&str> = line.split('|').map(|s| s.trim()).collect();
-            if parts.len() >= 6 {
-                let app_name = parts[1].replace("\\|", "|");
-                let window_title = parts[2].replace("\\|", "|");
-                let start_time = parts[3];
-                let end_time_str = parts[4];
-                let duration: i64 = parts[5].parse().unwrap_or(0);
-
-                let end_time = if end_time_str.is_empty() { None } else { Some(end_time_str) };
-                let exe_path = "imported_from_markdown";
+    let today = Local::now().date_naive();
 
-                let exists: i64 = conn.query_row(
-                    "SELECT count(*) FROM activity_sessions WHERE start_time = ?1 AND exe_path = ?2",
-                    params![start_time, exe_path],
-                    |row| row.get(0),
-                ).unwrap_or(0);
+    let mut stmt = conn.prepare(
+        "SELECT app_name, exe_path, SUM(duration_seconds) as total, COUNT(*) as count
+         FROM music_sessions
+         WHERE date(start_time) = date(?1)
+         GROUP BY app_name
+         ORDER BY total DESC",
+    )?;
 
-                if exists == 0 {
-                    conn.execute(
-                        "INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, end_time, duration_seconds)
-                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-                        params![app_name, window_title, exe_path, start_time, end_time, duration],
-                    )?;
-                    count += 1;
-                }
-            }
-        }
-    }
+    let summaries = stmt
+        .query_map([today.to_string()], |row| {
+            Ok(MusicAppUsage {
+                app_name: row.get(0)?,
+                exe_path: row.get(1)?,
+                total_seconds: row.get(2)?,
+                session_count: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
 
-    Ok(count)
+    Ok(summaries)
 }
 
-pub fn import_sessions_html(path: &str) -> Result<usize> {
-    let content = std::fs::read_to_string(path)
-        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
-
+pub fn get_today_music_sessions() -> Result<Vec<MusicSession>> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    let mut count = 0;
-
-    for tr_block in content.split("<tr>").skip(2) {
-        let td_parts: Vec<&str> = tr_block.split("</td>").collect();
-        if td_parts.len() >= 5 {
-            let app_name = td_parts[0].split("<strong>").nth(1).unwrap_or("").split("</strong>").next().unwrap_or("").trim()
-                .replace("&amp;", "&").replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"");
-
-            let window_title = td_parts[1].split("<td>").nth(1).unwrap_or("").trim()
-                .replace("&amp;", "&").replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"");
+    let today = Local::now().date_naive();
+    let start_of_day = today.and_hms_opt(0, 0, 0).unwrap();
 
-            let start_time = td_parts[2].split("\">").nth(1).unwrap_or("").trim()
-                .replace("&amp;", "&").replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"");
+    let mut stmt = conn.prepare(
+        "SELECT id, app_name, window_title, exe_path, start_time, end_time, duration_seconds
+         FROM music_sessions
+         WHERE date(start_time) = date(?1)
+         ORDER BY start_time DESC",
+    )?;
 
-            let end_time_str = td_parts[3].split("\">").nth(1).unwrap_or("").trim()
-                .replace("&amp;", "&").replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"");
+    let sessions = stmt
+        .query_map([start_of_day.to_string()], |row| {
+            Ok(MusicSession {
+                id: Some(row.get(0)?),
+                app_name: row.get(1)?,
+                window_title: row.get(2)?,
+                exe_path: row.get(3)?,
+                start_time: DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
+                    .map(|dt| dt.with_timezone(&Local))
+                    .unwrap_or_else(|_| Local::now()),
+                end_time: row
+                    .get::<_, Option<String>>(5)?
+                    .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+                    .map(|dt| dt.with_timezone(&Local)),
+                duration_seconds: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
 
-            let end_time = if end_time_str == "Now" || end_time_str.is_empty() { None } else { Some(end_time_str.as_str()) };
+    Ok(sessions)
+}
 
-            let duration_str = td_parts[4].split("\">").nth(1).unwrap_or("").trim();
-            let mut duration: i64 = 0;
-            for part in duration_str.split_whitespace() {
-                 let num = part.trim_end_matches(&['h', 'm', 's'][..]).parse::<i64>().unwrap_or(0);
-                 if part.ends_with('h') { duration += num * 3600; }
-                 else if part.ends_with('m') { duration += num * 60; }
-                 else if part.ends_with('s') { duration += num; }
-            }
+pub fn get_music_sessions_range(from: NaiveDate, to: NaiveDate) -> Result<Vec<MusicSession>> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-            let exe_path = "imported_from_html";
+    // Use date() function for proper date comparison
+    let from_date_str = from.format("%Y-%m-%d").to_string();
+    let to_date_str = to.format("%Y-%m-%d").to_string();
 
-            let exists: i64 = conn.query_row(
-                "SELECT count(*) FROM activity_sessions WHERE start_time = ?1 AND exe_path = ?2",
-                params![start_time, exe_path],
-                |row| row.get(0),
-            ).unwrap_or(0);
+    let mut stmt = conn.prepare(
+        "SELECT id, app_name, window_title, exe_path, start_time, end_time, duration_seconds
+         FROM music_sessions
+         WHERE date(start_time) >= date(?1) AND date(start_time) <= date(?2)
+         ORDER BY start_time DESC",
+    )?;
 
-            if exists == 0 && !app_name.is_empty() {
-                conn.execute(
-                    "INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, end_time, duration_seconds)
-                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-                    params![app_name, window_title, exe_path, start_time, end_time, duration],
-                )?;
-                count += 1;
-            }
-        }
-    }
+    let sessions = stmt
+        .query_map(
+            [from_date_str, to_date_str],
+            |row| {
+                Ok(MusicSession {
+                    id: Some(row.get(0)?),
+                    app_name: row.get(1)?,
+                    window_title: row.get(2)?,
+                    exe_path: row.get(3)?,
+                    start_time: DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
+                        .map(|dt| dt.with_timezone(&Local))
+                        .unwrap_or_else(|_| Local::now()),
+                    end_time: row
+                        .get::<_, Option<String>>(5)?
+                        .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+                        .map(|dt| dt.with_timezone(&Local)),
+                    duration_seconds: row.get(6)?,
+                })
+            },
+        )?
+        .collect::<Result<Vec<_>>>()?;
 
-    Ok(count)
+    Ok(sessions)
 }
 
-pub fn export_sessions_markdown(path: &str, start_date: &str, end_date: &str) -> Result<()> {
-    let enriched = get_enriched_sessions(start_date, end_date)?;
-    let raw_music = get_music_sessions_range_raw(start_date, end_date)?;
-
-    let mut md_content = String::from("# TimiGS Activity Report\n\n");
-
-    // 1. General Activity
-    md_content.push_str("## 📊 General Activity & Applications\n\n");
-    md_content.push_str("| App Name | Activity Details | Start Time | End Time | Duration |\n");
-    md_content.push_str("|---|---|---|---|---|\n");
+pub fn get_total_music_time_today() -> Result<i64> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    let format_duration_helper = |dur: i64| -> String {
-        let hrs = dur / 3600;
-        let mins = (dur % 3600) / 60;
-        let secs = dur % 60;
-        if hrs > 0 {
-            format!("{}h {}m {}s", hrs, mins, secs)
-        } else if mins > 0 {
-            format!("{}m {}s", mins, secs)
-        } else {
-            format!("{}s", secs)
-        }
-    };
+    let today = Local::now().date_naive();
 
-    let escape = |s: &str| -> String {
-        s.replace('|', "\\|")
-    };
+    let total: Option<i64> = conn.query_row(
+        "SELECT SUM(duration_seconds) FROM music_sessions WHERE date(start_time) = date(?1)",
+        [today.to_string()],
+        |row| row.get(0),
+    ).ok();
 
-    for s in &enriched {
-        let mut details = escape(&s.window_title);
-        if let Some(ref web) = s.website {
-            details.push_str(&format!(" <br>🌐 **Website**: {}", escape(web)));
-        }
-        if let Some(ref track) = s.music_track {
-            details.push_str(&format!(" <br>🎵 **Music**: {}", escape(track)));
-        }
-        if let Some(ref editor) = s.editor_name {
-            if !s.is_ai_assisted.unwrap_or(false) {
-                let file_str = s.file_path.as_deref().unwrap_or("Workspace / General");
-                let lang_str = s.language.as_deref().unwrap_or("Unknown");
-                let proj_str = s.project_dir.as_deref().unwrap_or("Unknown");
-                details.push_str(&format!(
-                    " <br>💻 **Coding**: {} | File: {} | Lang: {} | Project: {}",
-                    escape(editor), escape(file_str), escape(lang_str), escape(proj_str)
-                ));
-            }
-        }
+    Ok(total.unwrap_or(0))
+}
 
-        md_content.push_str(&format!(
-            "| **{}** | {} | {} | {} | {} |\n",
-            escape(&s.display_name),
-            details,
-            escape(&s.start_time),
-            escape(s.end_time.as_deref().unwrap_or("Now")),
-            format_duration_helper(s.duration_seconds)
-        ));
-    }
+// ── Coding Session Functions ──
 
-    // 2. Music History
-    md_content.push_str("\n## 🎵 Music Listening History\n\n");
-    md_content.push_str("| Song / Track Title | Player / Application | Start Time | End Time | Duration |\n");
-    md_content.push_str("|---|---|---|---|---|\n");
+/// A coding session as the tracker opens it from the focused window.
+pub struct NewCodingSession<'a> {
+    pub app_name: &'a str,
+    pub editor_name: &'a str,
+    pub file_path: Option<&'a str>,
+    pub language: Option<&'a str>,
+    pub project_dir: Option<&'a str>,
+    pub is_ai_assisted: bool,
+    pub window_title: &'a str,
+    pub exe_path: &'a str,
+}
 
-    if raw_music.is_empty() {
-        md_content.push_str("| *No music history tracked* | | | | |\n");
-    } else {
-        for m in &raw_music {
-            let title = m["window_title"].as_str().unwrap_or("Unknown");
-            let app = m["app_name"].as_str().unwrap_or("Unknown");
-            let start = m["start_time"].as_str().unwrap_or("");
-            let end = m["end_time"].as_str().unwrap_or("Now");
-            let dur = m["duration_seconds"].as_i64().unwrap_or(0);
-            md_content.push_str(&format!(
-                "| **{}** | {} | {} | {} | {} |\n",
-                escape(title),
-                escape(app),
-                escape(start),
-                escape(end),
-                format_duration_helper(dur)
-            ));
-        }
-    }
+pub fn start_coding_session_in(
+    conn: &Connection,
+    session: &NewCodingSession,
+    start_time: DateTime<Local>,
+) -> Result<i64> {
+    let ai_flag: i64 = if session.is_ai_assisted { 1 } else { 0 };
+    conn.execute(
+        "INSERT INTO coding_sessions \
+         (app_name, editor_name, file_path, language, project_dir, is_ai_assisted, window_title, exe_path, start_time) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            session.app_name,
+            session.editor_name,
+            session.file_path,
+            session.language,
+            session.project_dir,
+            ai_flag,
+            session.window_title,
+            session.exe_path,
+            start_time.to_rfc3339()
+        ],
+    )?;
 
-    std::fs::write(path, md_content)
-        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    Ok(conn.last_insert_rowid())
+}
 
-    Ok(())
+pub fn end_coding_session_in(conn: &Connection, id: i64, end_time: DateTime<Local>) -> Result<()> {
+    end_session_row(conn, "coding_sessions", id, end_time)
 }
 
-pub fn auto_export_if_needed() -> Result<()> {
-    let settings = get_settings();
+// ── Editor heartbeats ──
 
-    if !settings.auto_export_enabled || settings.auto_export_folder.is_empty() {
-        return Ok(());
-    }
+pub const CODING_SOURCE_TITLE: &str = "title";
+pub const CODING_SOURCE_HEARTBEAT: &str = "heartbeat";
 
-    let now = Local::now();
-    let last_export = settings.last_export_time
-        .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
-        .map(|dt| dt.with_timezone(&Local));
+/// Heartbeats further apart than this are separate coding sessions
+/// (WakaTime's default keystroke timeout).
+pub const HEARTBEAT_TIMEOUT_SECS: i64 = 15 * 60;
 
-    let should_export = match last_export {
-        None => true,
-        Some(last) => {
-            let elapsed = now.signed_duration_since(last).num_hours();
-            elapsed >= settings.auto_export_interval_hours
-        }
-    };
+/// One editor plugin heartbeat, already mapped from the wire format.
+#[derive(Debug, Clone)]
+pub struct CodingHeartbeat {
+    pub time: DateTime<Local>,
+    pub editor_name: String,
+    /// File path for file heartbeats, app or domain name otherwise.
+    pub entity: String,
+    pub is_file: bool,
+    pub language: Option<String>,
+    pub project: Option<String>,
+    /// As reported by the plugin, else the repository's current branch.
+    pub branch: Option<String>,
+    pub is_ai_assisted: bool,
+    pub repo: Option<crate::git::RepoInfo>,
+}
 
-    if should_export {
-        let timestamp = now.format("%Y-%m-%d_%H-%M-%S").to_string();
-        let filename = format!("TimiGS_Activity_{}.html", timestamp);
-        let export_path = PathBuf::from(&settings.auto_export_folder).join(filename);
+struct HeartbeatSession {
+    id: i64,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+    window_title: String,
+    language: Option<String>,
+    project: Option<String>,
+    branch: Option<String>,
+}
 
-        std::fs::create_dir_all(&settings.auto_export_folder)
-            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+/// Store heartbeats as coding sessions. A heartbeat within the timeout of the
+/// editor's previous one extends that session when it is about the same file,
+/// project and branch, and starts a new session otherwise. Title-parsed sessions
+/// covering the same time are trimmed, since the plugin knows better.
+pub fn record_coding_heartbeats(heartbeats: &[CodingHeartbeat]) -> Result<usize> {
+    let mut sorted: Vec<&CodingHeartbeat> = heartbeats.iter().collect();
+    sorted.sort_by_key(|hb| hb.time);
+
+    with_transaction(|tx| {
+        for hb in &sorted {
+            record_coding_heartbeat_in(tx, hb)?;
+        }
+        Ok(sorted.len())
+    })
+}
 
-        export_sessions_html(export_path.to_str().unwrap(), "1970-01-01", "2099-12-31")?;
+fn record_coding_heartbeat_in(conn: &Connection, hb: &CodingHeartbeat) -> Result<()> {
+    let time = hb.time.to_rfc3339();
+    let previous = conn
+        .query_row(
+            "SELECT id, start_time, end_time, window_title, language, project_dir, branch \
+             FROM coding_sessions \
+             WHERE source = ?1 AND editor_name = ?2 \
+               AND julianday(start_time) <= julianday(?3) \
+               AND julianday(end_time) >= julianday(?3) - ?4 \
+             ORDER BY julianday(end_time) DESC LIMIT 1",
+            params![
+                CODING_SOURCE_HEARTBEAT,
+                hb.editor_name,
+                time,
+                HEARTBEAT_TIMEOUT_SECS as f64 / 86400.0
+            ],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                ))
+            },
+        )
+        .optional()?;
+    let previous = match previous {
+        Some((id, start, end, window_title, language, project, branch)) => Some(HeartbeatSession {
+            id,
+            start: parse_session_time(&start)?,
+            end: parse_session_time(&end)?,
+            window_title,
+            language,
+            project,
+            branch,
+        }),
+        None => None,
+    };
 
-        save_last_export_time(&now.to_rfc3339())?;
+    match previous {
+        Some(prev)
+            if prev.window_title == hb.entity
+                && prev.project == hb.project
+                && prev.branch == hb.branch
+                && (hb.language.is_none() || prev.language == hb.language) =>
+        {
+            if hb.time > prev.end {
+                set_heartbeat_session_end(conn, prev.id, prev.start, hb.time)?;
+                yield_title_sessions(conn, prev.start, hb.time)?;
+            }
+        }
+        // Time up to an earlier heartbeat is already counted
+        Some(prev) if hb.time <= prev.end => {}
+        Some(prev) => {
+            set_heartbeat_session_end(conn, prev.id, prev.start, hb.time)?;
+            yield_title_sessions(conn, prev.start, hb.time)?;
+            insert_heartbeat_session(conn, hb)?;
+        }
+        None => insert_heartbeat_session(conn, hb)?,
     }
+    Ok(())
+}
 
+fn insert_heartbeat_session(conn: &Connection, hb: &CodingHeartbeat) -> Result<()> {
+    let time = hb.time.to_rfc3339();
+    conn.execute(
+        "INSERT INTO coding_sessions \
+         (app_name, editor_name, file_path, language, project_dir, is_ai_assisted, window_title, \
+          exe_path, start_time, end_time, duration_seconds, source, branch) \
+         VALUES (?1, ?1, ?2, ?3, ?4, ?5, ?6, '', ?7, ?7, 0, ?8, ?9)",
+        params![
+            hb.editor_name,
+            hb.is_file.then_some(hb.entity.as_str()),
+            hb.language,
+            hb.project,
+            hb.is_ai_assisted as i64,
+            hb.entity,
+            time,
+            CODING_SOURCE_HEARTBEAT,
+            hb.branch
+        ],
+    )?;
+    if let Some(repo) = &hb.repo {
+        set_coding_session_repo_in(conn, conn.last_insert_rowid(), repo, false)?;
+    }
     Ok(())
 }
 
-fn save_last_export_time(time: &str) -> Result<()> {
+/// Attach the git repository to a coding session; the branch too unless `with_branch`
+/// is false (heartbeats set their own).
+pub fn set_coding_session_repo_in(
+    conn: &Connection,
+    id: i64,
+    repo: &crate::git::RepoInfo,
+    with_branch: bool,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE coding_sessions \
+         SET repo_root = ?1, repo_name = ?2, repo_remote = ?3, \
+             branch = CASE WHEN ?4 THEN ?5 ELSE branch END \
+         WHERE id = ?6",
+        params![
+            repo.root.to_string_lossy(),
+            repo.name,
+            repo.remote_url,
+            with_branch,
+            repo.branch,
+            id
+        ],
+    )?;
+    Ok(())
+}
+
+/// Working tree roots of every repository seen in coding sessions, most recent first.
+pub fn get_known_repo_roots() -> Result<Vec<String>> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT repo_root FROM coding_sessions WHERE repo_root IS NOT NULL \
+         GROUP BY repo_root ORDER BY MAX(start_time) DESC",
+    )?;
+    let roots = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>>>()?;
+    Ok(roots)
+}
+
+fn set_heartbeat_session_end(
+    conn: &Connection,
+    id: i64,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+) -> Result<()> {
     conn.execute(
-        "INSERT OR REPLACE INTO settings (key, value) VALUES ('last_export_time', ?1)",
-        [time],
+        "UPDATE coding_sessions SET end_time = ?1, duration_seconds = ?2 WHERE id = ?3",
+        params![end.to_rfc3339(), (end - start).num_seconds(), id],
     )?;
     Ok(())
 }
 
-/// Get all activity sessions (for export/sync)
-pub fn get_all_activity() -> Result<Vec<ActivitySession>> {
+/// Cut `start..end` out of the title-parsed coding sessions overlapping it.
+/// Open sessions (still tracked) only ever get their start moved forward.
+fn yield_title_sessions(conn: &Connection, start: DateTime<Local>, end: DateTime<Local>) -> Result<()> {
+    let overlapping = {
+        let mut stmt = conn.prepare(
+            "SELECT id, start_time, end_time FROM coding_sessions \
+             WHERE source = ?1 AND julianday(start_time) < julianday(?3) \
+               AND (end_time IS NULL OR julianday(end_time) > julianday(?2))",
+        )?;
+        let rows = stmt
+            .query_map(
+                params![CODING_SOURCE_TITLE, start.to_rfc3339(), end.to_rfc3339()],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?)),
+            )?
+            .collect::<Result<Vec<_>>>()?;
+        rows
+    };
+
+    for (id, row_start, row_end) in overlapping {
+        let row_start = parse_session_time(&row_start)?;
+        let row_end = row_end.as_deref().map(parse_session_time).transpose()?;
+        let starts_before = row_start < start;
+        let ends_after = row_end.is_none_or(|e| e > end);
+
+        if starts_before && !ends_after {
+            conn.execute(
+                "UPDATE coding_sessions SET end_time = ?1, duration_seconds = ?2 WHERE id = ?3",
+                params![start.to_rfc3339(), (start - row_start).num_seconds(), id],
+            )?;
+            continue;
+        }
+        if !ends_after {
+            conn.execute("DELETE FROM coding_sessions WHERE id = ?1", [id])?;
+            continue;
+        }
+
+        if starts_before {
+            // The session spans the heartbeats: keep the part before them as its own row
+            conn.execute(
+                "INSERT INTO coding_sessions \
+                 (app_name, editor_name, file_path, language, project_dir, is_ai_assisted, window_title, \
+                  exe_path, start_time, end_time, duration_seconds, source, branch, repo_root, repo_name, repo_remote) \
+                 SELECT app_name, editor_name, file_path, language, project_dir, is_ai_assisted, window_title, \
+                        exe_path, start_time, ?1, ?2, source, branch, repo_root, repo_name, repo_remote \
+                 FROM coding_sessions WHERE id = ?3",
+                params![start.to_rfc3339(), (start - row_start).num_seconds(), id],
+            )?;
+        }
+        let duration = row_end.map_or(0, |e| (e - end).num_seconds());
+        conn.execute(
+            "UPDATE coding_sessions SET start_time = ?1, duration_seconds = ?2 WHERE id = ?3",
+            params![end.to_rfc3339(), duration, id],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn get_today_coding_sessions() -> Result<Vec<CodingSession>> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
+    let today = Local::now().date_naive();
+    let start_of_day = today.and_hms_opt(0, 0, 0).unwrap();
+
     let mut stmt = conn.prepare(
-        "SELECT id, app_name, window_title, exe_path, start_time, end_time, duration_seconds
-         FROM activity_sessions
+        "SELECT id, app_name, editor_name, file_path, language, project_dir, \
+                is_ai_assisted, window_title, exe_path, start_time, end_time, duration_seconds, \
+                source, branch, repo_root, repo_name, repo_remote \
+         FROM coding_sessions \
+         WHERE date(start_time) = date(?1) \
          ORDER BY start_time DESC",
     )?;
 
     let sessions = stmt
-        .query_map([], |row| {
-            Ok(ActivitySession {
-                id: Some(row.get(0)?),
+        .query_map([start_of_day.to_string()], |row| {
+            Ok(CodingSession {
+                id: row.get(0)?,
                 app_name: row.get(1)?,
-                window_title: row.get(2)?,
-                exe_path: row.get(3)?,
-                start_time: DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
-                    .map(|dt| dt.with_timezone(&Local))
-                    .unwrap_or_else(|_| Local::now()),
-                end_time: row
-                    .get::<_, Option<String>>(5)?
-                    .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
-                    .map(|dt| dt.with_timezone(&Local)),
-                duration_seconds: row.get(6)?,
+                editor_name: row.get(2)?,
+                file_path: row.get(3)?,
+                language: row.get(4)?,
+                project_dir: row.get(5)?,
+                is_ai_assisted: row.get::<_, i64>(6)? != 0,
+                window_title: row.get(7)?,
+                exe_path: row.get(8)?,
+                start_time: row.get(9)?,
+                end_time: row.get(10)?,
+                duration_seconds: row.get(11)?,
+                source: row.get(12)?,
+                branch: row.get(13)?,
+                repo_root: row.get(14)?,
+                repo_name: row.get(15)?,
+                repo_remote: row.get(16)?,
             })
         })?
-        .filter_map(|r| r.ok())
-        .collect();
+        .collect::<Result<Vec<_>>>()?;
 
     Ok(sessions)
 }
 
-// Music Sessions Functions
-
-pub fn start_music_session(app_name: &str, window_title: Option<&str>, exe_path: &str) -> Result<i64> {
+pub fn get_coding_sessions_range(from: &str, to: &str) -> Result<Vec<CodingSession>> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    let now = Local::now();
-    conn.execute(
-        "INSERT INTO music_sessions (app_name, window_title, exe_path, start_time) VALUES (?1, ?2, ?3, ?4)",
-        params![app_name, window_title, exe_path, now.to_rfc3339()],
+    let mut stmt = conn.prepare(
+        "SELECT id, app_name, editor_name, file_path, language, project_dir, \
+                is_ai_assisted, window_title, exe_path, start_time, end_time, duration_seconds, \
+                source, branch, repo_root, repo_name, repo_remote \
+         FROM coding_sessions \
+         WHERE date(start_time) >= date(?1) AND date(start_time) <= date(?2) \
+         ORDER BY start_time DESC",
     )?;
 
-    Ok(conn.last_insert_rowid())
+    let sessions = stmt
+        .query_map(params![from, to], |row| {
+            Ok(CodingSession {
+                id: row.get(0)?,
+                app_name: row.get(1)?,
+                editor_name: row.get(2)?,
+                file_path: row.get(3)?,
+                language: row.get(4)?,
+                project_dir: row.get(5)?,
+                is_ai_assisted: row.get::<_, i64>(6)? != 0,
+                window_title: row.get(7)?,
+                exe_path: row.get(8)?,
+                start_time: row.get(9)?,
+                end_time: row.get(10)?,
+                duration_seconds: row.get(11)?,
+                source: row.get(12)?,
+                branch: row.get(13)?,
+                repo_root: row.get(14)?,
+                repo_name: row.get(15)?,
+                repo_remote: row.get(16)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(sessions)
 }
 
-pub fn end_music_session(id: i64) -> Result<()> {
+pub fn get_coding_stats_today() -> Result<Vec<CodingStats>> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    let now = Local::now();
+    let today = Local::now().date_naive();
 
-    // Get start time to calculate duration
-    let start_time: String = conn.query_row(
-        "SELECT start_time FROM music_sessions WHERE id = ?1",
-        [id],
-        |row| row.get(0),
+    let mut stmt = conn.prepare(
+        "SELECT \
+             COALESCE(language, 'Unknown') as lang, \
+             SUM(duration_seconds) as total, \
+             SUM(CASE WHEN is_ai_assisted = 1 THEN duration_seconds ELSE 0 END) as ai_secs, \
+             SUM(CASE WHEN is_ai_assisted = 0 THEN duration_seconds ELSE 0 END) as manual_secs, \
+             COUNT(*) as cnt \
+         FROM coding_sessions \
+         WHERE date(start_time) = date(?1) \
+         GROUP BY lang \
+         ORDER BY total DESC",
     )?;
 
-    if let Ok(start) = DateTime::parse_from_rfc3339(&start_time) {
-        let duration = (now - start.with_timezone(&Local)).num_seconds();
-        conn.execute(
-            "UPDATE music_sessions SET end_time = ?1, duration_seconds = ?2 WHERE id = ?3",
-            params![now.to_rfc3339(), duration, id],
-        )?;
-    }
+    let stats = stmt
+        .query_map([today.to_string()], |row| {
+            Ok(CodingStats {
+                language: row.get(0)?,
+                total_seconds: row.get(1)?,
+                ai_seconds: row.get(2)?,
+                manual_seconds: row.get(3)?,
+                session_count: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
 
-    Ok(())
+    Ok(stats)
 }
 
-pub fn end_music_session_retroactive(id: i64, end_time: DateTime<Local>) -> Result<()> {
+pub fn get_coding_project_stats_today() -> Result<Vec<CodingProjectStats>> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    let start_time: String = conn.query_row(
-        "SELECT start_time FROM music_sessions WHERE id = ?1",
-        [id],
-        |row| row.get(0),
+    let today = Local::now().date_naive();
+
+    let mut stmt = conn.prepare(
+        "SELECT \
+             COALESCE(repo_name, project_dir, 'Unknown') as proj, \
+             SUM(duration_seconds) as total, \
+             SUM(CASE WHEN is_ai_assisted = 1 THEN duration_seconds ELSE 0 END) as ai_secs, \
+             COUNT(*) as cnt \
+         FROM coding_sessions \
+         WHERE date(start_time) = date(?1) \
+         GROUP BY proj \
+         ORDER BY total DESC",
     )?;
 
-    if let Ok(start) = DateTime::parse_from_rfc3339(&start_time) {
-        let duration = (end_time - start.with_timezone(&Local)).num_seconds();
-        let duration = std::cmp::max(0, duration);
-        conn.execute(
-            "UPDATE music_sessions SET end_time = ?1, duration_seconds = ?2 WHERE id = ?3",
-            params![end_time.to_rfc3339(), duration, id],
-        )?;
-    }
+    let stats = stmt
+        .query_map([today.to_string()], |row| {
+            Ok(CodingProjectStats {
+                project_dir: row.get(0)?,
+                total_seconds: row.get(1)?,
+                ai_seconds: row.get(2)?,
+                session_count: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
 
-    Ok(())
+    Ok(stats)
 }
 
-pub fn get_today_music_summary() -> Result<Vec<MusicAppUsage>> {
+/// Coding time per git repository between two dates (inclusive).
+pub fn get_coding_repo_stats(from: &str, to: &str) -> Result<Vec<CodingRepoStats>> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    let today = Local::now().date_naive();
-
     let mut stmt = conn.prepare(
-        "SELECT app_name, exe_path, SUM(duration_seconds) as total, COUNT(*) as count
-         FROM music_sessions
-         WHERE date(start_time) = date(?1)
-         GROUP BY app_name
+        "SELECT \
+             repo_name, \
+             MAX(repo_remote), \
+             SUM(duration_seconds) as total, \
+             SUM(CASE WHEN is_ai_assisted = 1 THEN duration_seconds ELSE 0 END), \
+             COUNT(*), \
+             COUNT(DISTINCT branch) \
+         FROM coding_sessions \
+         WHERE repo_name IS NOT NULL \
+           AND date(start_time) >= date(?1) AND date(start_time) <= date(?2) \
+         GROUP BY repo_name \
          ORDER BY total DESC",
     )?;
 
-    let summaries = stmt
-        .query_map([today.to_string()], |row| {
-            Ok(MusicAppUsage {
-                app_name: row.get(0)?,
-                exe_path: row.get(1)?,
+    let stats = stmt
+        .query_map(params![from, to], |row| {
+            Ok(CodingRepoStats {
+                repo_name: row.get(0)?,
+                repo_remote: row.get(1)?,
                 total_seconds: row.get(2)?,
-                session_count: row.get(3)?,
+                ai_seconds: row.get(3)?,
+                session_count: row.get(4)?,
+                branch_count: row.get(5)?,
             })
         })?
         .collect::<Result<Vec<_>>>()?;
 
-    Ok(summaries)
+    Ok(stats)
 }
 
-pub fn get_today_music_sessions() -> Result<Vec<MusicSession>> {
+/// Coding time per branch between two dates (inclusive), optionally for one repository.
+/// Sessions without a repository fall back to their project name.
+pub fn get_coding_branch_stats(from: &str, to: &str, repo: Option<&str>) -> Result<Vec<CodingBranchStats>> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    let today = Local::now().date_naive();
-    let start_of_day = today.and_hms_opt(0, 0, 0).unwrap();
-
     let mut stmt = conn.prepare(
-        "SELECT id, app_name, window_title, exe_path, start_time, end_time, duration_seconds
-         FROM music_sessions
-         WHERE date(start_time) = date(?1)
-         ORDER BY start_time DESC",
+        "SELECT \
+             COALESCE(repo_name, project_dir, 'Unknown') as repo, \
+             branch, \
+             SUM(duration_seconds) as total, \
+             COUNT(*), \
+             MIN(start_time), \
+             MAX(COALESCE(end_time, start_time)) \
+         FROM coding_sessions \
+         WHERE branch IS NOT NULL \
+           AND date(start_time) >= date(?1) AND date(start_time) <= date(?2) \
+           AND (?3 IS NULL OR COALESCE(repo_name, project_dir, 'Unknown') = ?3) \
+         GROUP BY repo, branch \
+         ORDER BY total DESC",
     )?;
 
-    let sessions = stmt
-        .query_map([start_of_day.to_string()], |row| {
-            Ok(MusicSession {
-                id: Some(row.get(0)?),
-                app_name: row.get(1)?,
-                window_title: row.get(2)?,
-                exe_path: row.get(3)?,
-                start_time: DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
-                    .map(|dt| dt.with_timezone(&Local))
-                    .unwrap_or_else(|_| Local::now()),
-                end_time: row
-                    .get::<_, Option<String>>(5)?
-                    .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
-                    .map(|dt| dt.with_timezone(&Local)),
-                duration_seconds: row.get(6)?,
+    let stats = stmt
+        .query_map(params![from, to, repo], |row| {
+            Ok(CodingBranchStats {
+                repo_name: row.get(0)?,
+                branch: row.get(1)?,
+                total_seconds: row.get(2)?,
+                session_count: row.get(3)?,
+                first_seen: row.get(4)?,
+                last_seen: row.get(5)?,
             })
         })?
         .collect::<Result<Vec<_>>>()?;
 
-    Ok(sessions)
+    Ok(stats)
 }
 
-pub fn get_music_sessions_range(from: NaiveDate, to: NaiveDate) -> Result<Vec<MusicSession>> {
+pub fn get_total_coding_time_today() -> Result<i64> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    // Use date() function for proper date comparison
-    let from_date_str = from.format("%Y-%m-%d").to_string();
-    let to_date_str = to.format("%Y-%m-%d").to_string();
-
-    let mut stmt = conn.prepare(
-        "SELECT id, app_name, window_title, exe_path, start_time, end_time, duration_seconds
-         FROM music_sessions
-         WHERE date(start_time) >= date(?1) AND date(start_time) <= date(?2)
-         ORDER BY start_time DESC",
-    )?;
+    let today = Local::now().date_naive();
 
-    let sessions = stmt
-        .query_map(
-            [from_date_str, to_date_str],
-            |row| {
-                Ok(MusicSession {
-                    id: Some(row.get(0)?),
-                    app_name: row.get(1)?,
-                    window_title: row.get(2)?,
-                    exe_path: row.get(3)?,
-                    start_time: DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
-                        .map(|dt| dt.with_timezone(&Local))
-                        .unwrap_or_else(|_| Local::now()),
-                    end_time: row
-                        .get::<_, Option<String>>(5)?
-                        .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
-                        .map(|dt| dt.with_timezone(&Local)),
-                    duration_seconds: row.get(6)?,
-                })
-            },
-        )?
-        .collect::<Result<Vec<_>>>()?;
+    let total: Option<i64> = conn.query_row(
+        "SELECT SUM(duration_seconds) FROM coding_sessions WHERE date(start_time) = date(?1)",
+        [today.to_string()],
+        |row| row.get(0),
+    ).ok();
 
-    Ok(sessions)
+    Ok(total.unwrap_or(0))
 }
 
-pub fn get_total_music_time_today() -> Result<i64> {
+pub fn get_total_ai_coding_time_today() -> Result<i64> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
     let today = Local::now().date_naive();
 
     let total: Option<i64> = conn.query_row(
-        "SELECT SUM(duration_seconds) FROM music_sessions WHERE date(start_time) = date(?1)",
+        "SELECT SUM(duration_seconds) FROM coding_sessions \
+         WHERE date(start_time) = date(?1) AND is_ai_assisted = 1",
         [today.to_string()],
         |row| row.get(0),
     ).ok();
@@ -2458,254 +4699,699 @@ pub fn get_total_music_time_today() -> Result<i64> {
     Ok(total.unwrap_or(0))
 }
 
-// ── Coding Session Functions ──
+// ── Terminal Functions ──
 
-pub fn start_coding_session(
-    app_name: &str,
-    editor_name: &str,
-    file_path: Option<&str>,
-    language: Option<&str>,
-    project_dir: Option<&str>,
-    is_ai_assisted: bool,
-    window_title: &str,
-    exe_path: &str,
+/// Record the program running in a terminal window for an activity session.
+pub fn set_session_process_in(conn: &Connection, id: i64, name: &str, cwd: Option<&str>) -> Result<()> {
+    conn.execute(
+        "UPDATE activity_sessions SET process_name = ?1, process_cwd = ?2 WHERE id = ?3",
+        params![name, cwd, id],
+    )?;
+    Ok(())
+}
+
+/// A command reported by the shell hook. `session` identifies the shell instance
+/// (`$$`), so a command's end is matched to its start.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellCommand {
+    pub id: i64,
+    pub session: Option<String>,
+    pub shell: Option<String>,
+    pub command: String,
+    pub cwd: Option<String>,
+    pub started_at: String,
+    pub ended_at: Option<String>,
+    pub duration_ms: Option<i64>,
+    pub exit_code: Option<i64>,
+}
+
+fn start_shell_command_in(
+    conn: &Connection,
+    session: Option<&str>,
+    shell: Option<&str>,
+    command: &str,
+    cwd: Option<&str>,
+    at: DateTime<Local>,
 ) -> Result<i64> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    conn.execute(
+        "INSERT INTO shell_commands (session, shell, command, cwd, started_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![session, shell, command, cwd, at.to_rfc3339()],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
 
-    let now = Local::now();
-    let ai_flag: i64 = if is_ai_assisted { 1 } else { 0 };
+/// Close the latest open command of `session`. The duration comes from the shell
+/// when it measured one, otherwise from the two timestamps. Returns the closed id.
+fn end_shell_command_in(
+    conn: &Connection,
+    session: Option<&str>,
+    at: DateTime<Local>,
+    exit_code: Option<i64>,
+    duration_ms: Option<i64>,
+) -> Result<Option<i64>> {
+    let open: Option<(i64, String)> = conn
+        .query_row(
+            "SELECT id, started_at FROM shell_commands \
+             WHERE session IS ?1 AND ended_at IS NULL ORDER BY started_at DESC, id DESC LIMIT 1",
+            params![session],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let Some((id, started_at)) = open else {
+        return Ok(None);
+    };
+
+    let duration_ms = duration_ms.unwrap_or_else(|| {
+        parse_session_time(&started_at)
+            .map(|start| (at - start).num_milliseconds().max(0))
+            .unwrap_or(0)
+    });
     conn.execute(
-        "INSERT INTO coding_sessions \
-         (app_name, editor_name, file_path, language, project_dir, is_ai_assisted, window_title, exe_path, start_time) \
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-        params![
-            app_name,
-            editor_name,
-            file_path,
-            language,
-            project_dir,
-            ai_flag,
-            window_title,
-            exe_path,
-            now.to_rfc3339()
-        ],
+        "UPDATE shell_commands SET ended_at = ?1, duration_ms = ?2, exit_code = ?3 WHERE id = ?4",
+        params![at.to_rfc3339(), duration_ms, exit_code, id],
     )?;
+    Ok(Some(id))
+}
 
-    Ok(conn.last_insert_rowid())
+pub fn start_shell_command(
+    session: Option<&str>,
+    shell: Option<&str>,
+    command: &str,
+    cwd: Option<&str>,
+    at: DateTime<Local>,
+) -> Result<i64> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    start_shell_command_in(conn, session, shell, command, cwd, at)
 }
 
-pub fn end_coding_session(id: i64) -> Result<()> {
+pub fn end_shell_command(
+    session: Option<&str>,
+    at: DateTime<Local>,
+    exit_code: Option<i64>,
+    duration_ms: Option<i64>,
+) -> Result<Option<i64>> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    end_shell_command_in(conn, session, at, exit_code, duration_ms)
+}
 
-    let now = Local::now();
+pub fn get_shell_commands(from: &str, to: &str) -> Result<Vec<ShellCommand>> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
 
-    let start_time: String = conn.query_row(
-        "SELECT start_time FROM coding_sessions WHERE id = ?1",
-        [id],
-        |row| row.get(0),
+    let mut stmt = conn.prepare(
+        "SELECT id, session, shell, command, cwd, started_at, ended_at, duration_ms, exit_code \
+         FROM shell_commands \
+         WHERE date(started_at) >= date(?1) AND date(started_at) <= date(?2) \
+         ORDER BY started_at DESC",
     )?;
+    let commands = stmt
+        .query_map([from, to], |row| {
+            Ok(ShellCommand {
+                id: row.get(0)?,
+                session: row.get(1)?,
+                shell: row.get(2)?,
+                command: row.get(3)?,
+                cwd: row.get(4)?,
+                started_at: row.get(5)?,
+                ended_at: row.get(6)?,
+                duration_ms: row.get(7)?,
+                exit_code: row.get(8)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(commands)
+}
 
-    if let Ok(start) = DateTime::parse_from_rfc3339(&start_time) {
-        let duration = (now - start.with_timezone(&Local)).num_seconds();
-        conn.execute(
-            "UPDATE coding_sessions SET end_time = ?1, duration_seconds = ?2 WHERE id = ?3",
-            params![now.to_rfc3339(), duration, id],
-        )?;
+// ── Browser Tabs ──
+
+/// The active tab of a browser, as reported by the native-messaging host.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BrowserTab {
+    pub browser: String,
+    pub url: String,
+    pub domain: String,
+    pub title: String,
+}
+
+/// `(url, domain)` for an http(s) URL, reduced to scheme, host and path: credentials,
+/// query string and fragment may hold secrets.
+pub fn normalize_url(raw: &str) -> Option<(String, String)> {
+    let mut url = url::Url::parse(raw.trim()).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
     }
+    let domain = url.host_str()?.to_lowercase();
+    let domain = domain.strip_prefix("www.").unwrap_or(&domain).to_string();
+
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url.set_query(None);
+    url.set_fragment(None);
+    Some((url.to_string(), domain))
+}
 
-    Ok(())
+/// A window switch makes the extension report the tab again, so older reports may
+/// describe a tab that is no longer in front.
+const BROWSER_TAB_MAX_AGE_SECS: i64 = 10;
+
+/// Engine behind a browser app name or a tab's reporter (`firefox`, `chromium`, or
+/// the host's origin), so one browser's tab never labels another's window.
+fn browser_family(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    if ["firefox", "mozilla", "librewolf", "waterfox"].iter().any(|n| name.contains(n)) {
+        Some("firefox")
+    } else if ["chrom", "edge", "brave", "vivaldi", "opera"].iter().any(|n| name.contains(n)) {
+        Some("chromium")
+    } else {
+        None
+    }
 }
 
-pub fn end_coding_session_retroactive(id: i64, end_time: DateTime<Local>) -> Result<()> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+/// Whether a session of `app_name` can show a tab reported by `browser`.
+fn shows_tab_of(app_name: &str, browser: &str) -> bool {
+    is_browser(app_name)
+        && match (browser_family(app_name), browser_family(browser)) {
+            (Some(app), Some(tab)) => app == tab,
+            _ => true,
+        }
+}
 
-    let start_time: String = conn.query_row(
-        "SELECT start_time FROM coding_sessions WHERE id = ?1",
-        [id],
-        |row| row.get(0),
+/// Remember the tab and label the open session of that browser whose window shows it.
+fn record_browser_tab_in(conn: &Connection, tab: &BrowserTab) -> Result<()> {
+    conn.execute(
+        "INSERT INTO browser_tabs (browser, url, domain, title, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(browser) DO UPDATE SET
+            url = excluded.url, domain = excluded.domain, title = excluded.title, updated_at = excluded.updated_at",
+        params![tab.browser, tab.url, tab.domain, tab.title, Local::now().to_rfc3339()],
     )?;
-
-    if let Ok(start) = DateTime::parse_from_rfc3339(&start_time) {
-        let duration = (end_time - start.with_timezone(&Local)).num_seconds();
-        let duration = std::cmp::max(0, duration);
+    if tab.title.is_empty() {
+        return Ok(());
+    }
+    let mut stmt = conn.prepare(
+        "SELECT id, app_name FROM activity_sessions WHERE end_time IS NULL AND instr(window_title, ?1) > 0",
+    )?;
+    let open = stmt
+        .query_map([&tab.title], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>>>()?;
+    for (id, _) in open.iter().filter(|(_, app_name)| shows_tab_of(app_name, &tab.browser)) {
         conn.execute(
-            "UPDATE coding_sessions SET end_time = ?1, duration_seconds = ?2 WHERE id = ?3",
-            params![end_time.to_rfc3339(), duration, id],
+            "UPDATE activity_sessions SET url = ?1, domain = ?2 WHERE id = ?3",
+            params![tab.url, tab.domain, id],
         )?;
     }
+    Ok(())
+}
 
+/// The tab may be reported before the tracker opens the session for its window,
+/// so new browser sessions look for a tab of the same browser, reported just
+/// before `now`, whose title they show.
+pub fn attach_browser_tab_in(
+    conn: &Connection,
+    id: i64,
+    app_name: &str,
+    window_title: &str,
+    now: DateTime<Local>,
+) -> Result<()> {
+    let since = now - chrono::Duration::seconds(BROWSER_TAB_MAX_AGE_SECS);
+    let mut stmt = conn.prepare(
+        "SELECT browser, url, domain FROM browser_tabs
+         WHERE title != '' AND instr(?1, title) > 0 AND julianday(updated_at) >= julianday(?2)
+         ORDER BY julianday(updated_at) DESC",
+    )?;
+    let tabs = stmt
+        .query_map(params![window_title, since.to_rfc3339()], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    let tab = tabs.into_iter().find(|(browser, _, _)| shows_tab_of(app_name, browser));
+    if let Some((_, url, domain)) = tab {
+        conn.execute(
+            "UPDATE activity_sessions SET url = ?1, domain = ?2 WHERE id = ?3",
+            params![url, domain, id],
+        )?;
+    }
     Ok(())
 }
 
-pub fn get_today_coding_sessions() -> Result<Vec<CodingSession>> {
+pub fn record_browser_tab(tab: &BrowserTab) -> Result<()> {
     let guard = DB.lock();
     let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    record_browser_tab_in(conn, tab)
+}
 
-    let today = Local::now().date_naive();
-    let start_of_day = today.and_hms_opt(0, 0, 0).unwrap();
+// ── Title scrubbing ──
 
-    let mut stmt = conn.prepare(
-        "SELECT id, app_name, editor_name, file_path, language, project_dir, \
-                is_ai_assisted, window_title, exe_path, start_time, end_time, duration_seconds \
-         FROM coding_sessions \
-         WHERE date(start_time) = date(?1) \
-         ORDER BY start_time DESC",
-    )?;
+/// Which rows `scrub_titles` looks at; `None` matches everything.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TitleFilter<'a> {
+    pub app_name: Option<&'a str>,
+    /// `YYYY-MM-DD`, inclusive.
+    pub from: Option<&'a str>,
+    pub to: Option<&'a str>,
+}
 
-    let sessions = stmt
-        .query_map([start_of_day.to_string()], |row| {
-            Ok(CodingSession {
-                id: row.get(0)?,
-                app_name: row.get(1)?,
-                editor_name: row.get(2)?,
-                file_path: row.get(3)?,
-                language: row.get(4)?,
-                project_dir: row.get(5)?,
-                is_ai_assisted: row.get::<_, i64>(6)? != 0,
-                window_title: row.get(7)?,
-                exe_path: row.get(8)?,
-                start_time: row.get(9)?,
-                end_time: row.get(10)?,
-                duration_seconds: row.get(11)?,
-            })
-        })?
-        .collect::<Result<Vec<_>>>()?;
+const TITLE_FILTER_SQL: &str = "(?1 IS NULL OR lower(app_name) = lower(?1))
+     AND (?2 IS NULL OR date(start_time) >= date(?2))
+     AND (?3 IS NULL OR date(start_time) <= date(?3))";
 
-    Ok(sessions)
+impl TitleFilter<'_> {
+    fn matches(&self, row: &SessionRow) -> bool {
+        let date = || {
+            DateTime::parse_from_rfc3339(&row.start_time)
+                .map(|t| t.with_timezone(&Local).format("%Y-%m-%d").to_string())
+                .unwrap_or_default()
+        };
+        self.app_name.is_none_or(|app| app.eq_ignore_ascii_case(&row.app_name))
+            && self.from.is_none_or(|from| date().as_str() >= from)
+            && self.to.is_none_or(|to| date().as_str() <= to)
+    }
 }
 
-pub fn get_coding_sessions_range(from: &str, to: &str) -> Result<Vec<CodingSession>> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+/// Rows a scrub changed, or would change on a dry run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ScrubReport {
+    pub activity_sessions: usize,
+    pub coding_sessions: usize,
+    pub music_sessions: usize,
+    pub session_edits: usize,
+    pub daily_usage: usize,
+    pub dry_run: bool,
+}
 
-    let mut stmt = conn.prepare(
-        "SELECT id, app_name, editor_name, file_path, language, project_dir, \
-                is_ai_assisted, window_title, exe_path, start_time, end_time, duration_seconds \
-         FROM coding_sessions \
-         WHERE date(start_time) >= date(?1) AND date(start_time) <= date(?2) \
-         ORDER BY start_time DESC",
-    )?;
+/// `(app_name, exe_path, title) -> (stored title, keep browser URL)`
+pub type TitleScrubber<'a> = dyn Fn(&str, &str, &str) -> (String, bool) + 'a;
+
+fn scrub_titles_in(
+    conn: &Connection,
+    filter: &TitleFilter,
+    dry_run: bool,
+    scrub: &TitleScrubber,
+) -> Result<ScrubReport> {
+    let mut report = ScrubReport {
+        dry_run,
+        ..ScrubReport::default()
+    };
+    let filter_params = params![filter.app_name, filter.from, filter.to];
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, app_name, exe_path, window_title, url IS NOT NULL FROM activity_sessions WHERE {}",
+        TITLE_FILTER_SQL
+    ))?;
+    let rows = stmt
+        .query_map(filter_params, |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, bool>(4)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    for (id, app_name, exe_path, title, has_url) in rows {
+        let (stored, keep_url) = scrub(&app_name, &exe_path, &title);
+        if stored == title && (keep_url || !has_url) {
+            continue;
+        }
+        report.activity_sessions += 1;
+        if !dry_run {
+            conn.execute(
+                "UPDATE activity_sessions SET window_title = ?1,
+                    url = CASE WHEN ?2 THEN url END, domain = CASE WHEN ?2 THEN domain END
+                 WHERE id = ?3",
+                params![stored, keep_url, id],
+            )?;
+        }
+    }
 
-    let sessions = stmt
-        .query_map(params![from, to], |row| {
-            Ok(CodingSession {
-                id: row.get(0)?,
-                app_name: row.get(1)?,
-                editor_name: row.get(2)?,
-                file_path: row.get(3)?,
-                language: row.get(4)?,
-                project_dir: row.get(5)?,
-                is_ai_assisted: row.get::<_, i64>(6)? != 0,
-                window_title: row.get(7)?,
-                exe_path: row.get(8)?,
-                start_time: row.get(9)?,
-                end_time: row.get(10)?,
-                duration_seconds: row.get(11)?,
-            })
+    for table in ["coding_sessions", "music_sessions"] {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, app_name, exe_path, window_title FROM {} WHERE window_title IS NOT NULL AND {}",
+            table, TITLE_FILTER_SQL
+        ))?;
+        let rows = stmt
+            .query_map(filter_params, |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        let mut changed = 0;
+        for (id, app_name, exe_path, title) in rows {
+            let (stored, _) = scrub(&app_name, &exe_path, &title);
+            if stored == title {
+                continue;
+            }
+            changed += 1;
+            if !dry_run {
+                conn.execute(
+                    &format!("UPDATE {} SET window_title = ?1 WHERE id = ?2", table),
+                    params![stored, id],
+                )?;
+            }
+        }
+        if table == "coding_sessions" {
+            report.coding_sessions = changed;
+        } else {
+            report.music_sessions = changed;
+        }
+    }
+
+    // Rolled-up days are keyed by title, so renamed titles may merge
+    let mut stmt = conn.prepare(
+        "SELECT date, app_name, exe_path, window_title, domain, total_seconds, session_count
+         FROM daily_usage
+         WHERE (?1 IS NULL OR lower(app_name) = lower(?1))
+           AND (?2 IS NULL OR date >= date(?2))
+           AND (?3 IS NULL OR date <= date(?3))",
+    )?;
+    let rows = stmt
+        .query_map(filter_params, read_daily_usage_row)?
+        .collect::<Result<Vec<_>>>()?;
+    for mut row in rows {
+        let (stored, keep_url) = scrub(&row.app_name, &row.exe_path, &row.window_title);
+        if stored == row.window_title && (keep_url || row.domain.is_none()) {
+            continue;
+        }
+        report.daily_usage += 1;
+        if !dry_run {
+            conn.execute(
+                "DELETE FROM daily_usage WHERE date = ?1 AND app_name = ?2 AND exe_path = ?3 AND window_title = ?4",
+                params![row.date, row.app_name, row.exe_path, row.window_title],
+            )?;
+            row.window_title = stored;
+            if !keep_url {
+                row.domain = None;
+            }
+            add_daily_usage(conn, &row)?;
+        }
+    }
+
+    // The edit history keeps snapshots of the rows it touched
+    let mut stmt = conn.prepare("SELECT id, before_json, after_json FROM session_edits")?;
+    let edits = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
         })?
         .collect::<Result<Vec<_>>>()?;
+    for (id, before_json, after_json) in edits {
+        let mut changed = false;
+        let mut scrub_rows = |json: &str| -> String {
+            let Ok(mut rows) = serde_json::from_str::<Vec<SessionRow>>(json) else {
+                return json.to_string();
+            };
+            for row in rows.iter_mut().filter(|r| filter.matches(r)) {
+                let (stored, keep_url) = scrub(&row.app_name, &row.exe_path, &row.window_title);
+                if stored != row.window_title {
+                    row.window_title = stored;
+                    changed = true;
+                }
+                if !keep_url && (row.url.is_some() || row.domain.is_some()) {
+                    row.url = None;
+                    row.domain = None;
+                    changed = true;
+                }
+            }
+            serde_json::to_string(&rows).unwrap_or_else(|_| json.to_string())
+        };
+        let before = scrub_rows(&before_json);
+        let after = scrub_rows(&after_json);
+        if !changed {
+            continue;
+        }
+        report.session_edits += 1;
+        if !dry_run {
+            conn.execute(
+                "UPDATE session_edits SET before_json = ?1, after_json = ?2 WHERE id = ?3",
+                params![before, after, id],
+            )?;
+        }
+    }
 
-    Ok(sessions)
+    Ok(report)
 }
 
-pub fn get_coding_stats_today() -> Result<Vec<CodingStats>> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
-
-    let today = Local::now().date_naive();
+/// Rewrite stored window titles with `scrub`, in one transaction.
+pub fn scrub_titles(filter: &TitleFilter, dry_run: bool, scrub: &TitleScrubber) -> Result<ScrubReport> {
+    with_transaction(|tx| scrub_titles_in(tx, filter, dry_run, scrub))
+}
 
-    let mut stmt = conn.prepare(
-        "SELECT \
-             COALESCE(language, 'Unknown') as lang, \
-             SUM(duration_seconds) as total, \
-             SUM(CASE WHEN is_ai_assisted = 1 THEN duration_seconds ELSE 0 END) as ai_secs, \
-             SUM(CASE WHEN is_ai_assisted = 0 THEN duration_seconds ELSE 0 END) as manual_secs, \
-             COUNT(*) as cnt \
-         FROM coding_sessions \
-         WHERE date(start_time) = date(?1) \
-         GROUP BY lang \
-         ORDER BY total DESC",
-    )?;
+// ── Retention ──
 
-    let stats = stmt
-        .query_map([today.to_string()], |row| {
-            Ok(CodingStats {
-                language: row.get(0)?,
-                total_seconds: row.get(1)?,
-                ai_seconds: row.get(2)?,
-                manual_seconds: row.get(3)?,
-                session_count: row.get(4)?,
-            })
-        })?
-        .collect::<Result<Vec<_>>>()?;
+/// One `daily_usage` row: everything spent on a title during one day.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DailyUsageRow {
+    pub date: String,
+    pub app_name: String,
+    pub exe_path: String,
+    pub window_title: String,
+    pub domain: Option<String>,
+    pub total_seconds: i64,
+    pub session_count: i64,
+}
 
-    Ok(stats)
+fn read_daily_usage_row(row: &rusqlite::Row) -> Result<DailyUsageRow> {
+    Ok(DailyUsageRow {
+        date: row.get(0)?,
+        app_name: row.get(1)?,
+        exe_path: row.get(2)?,
+        window_title: row.get(3)?,
+        domain: row.get(4)?,
+        total_seconds: row.get(5)?,
+        session_count: row.get(6)?,
+    })
 }
 
-pub fn get_coding_project_stats_today() -> Result<Vec<CodingProjectStats>> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+fn add_daily_usage(conn: &Connection, row: &DailyUsageRow) -> Result<()> {
+    conn.execute(
+        "INSERT INTO daily_usage (date, app_name, exe_path, window_title, domain, total_seconds, session_count)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT(date, app_name, exe_path, window_title) DO UPDATE SET
+            domain = COALESCE(daily_usage.domain, excluded.domain),
+            total_seconds = daily_usage.total_seconds + excluded.total_seconds,
+            session_count = daily_usage.session_count + excluded.session_count",
+        params![
+            row.date,
+            row.app_name,
+            row.exe_path,
+            row.window_title,
+            row.domain,
+            row.total_seconds,
+            row.session_count
+        ],
+    )?;
+    Ok(())
+}
 
-    let today = Local::now().date_naive();
+/// What a retention pass rolled up, or would roll up on a dry run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RetentionReport {
+    /// Closed sessions that started before this day (`YYYY-MM-DD`) are rolled up.
+    pub cutoff: String,
+    pub sessions_removed: usize,
+    /// `daily_usage` rows the removed sessions were summed into.
+    pub rollup_rows: usize,
+    pub seconds_rolled_up: i64,
+    pub oldest_session: Option<String>,
+    pub dry_run: bool,
+    pub vacuumed: bool,
+}
 
+fn rollup_sessions_in(conn: &Connection, cutoff: &str, dry_run: bool) -> Result<RetentionReport> {
     let mut stmt = conn.prepare(
-        "SELECT \
-             COALESCE(project_dir, 'Unknown') as proj, \
-             SUM(duration_seconds) as total, \
-             SUM(CASE WHEN is_ai_assisted = 1 THEN duration_seconds ELSE 0 END) as ai_secs, \
-             COUNT(*) as cnt \
-         FROM coding_sessions \
-         WHERE date(start_time) = date(?1) \
-         GROUP BY proj \
-         ORDER BY total DESC",
+        "SELECT date(start_time), app_name, exe_path, window_title, MAX(domain),
+                SUM(duration_seconds), COUNT(*), MIN(start_time)
+         FROM activity_sessions
+         WHERE end_time IS NOT NULL AND date(start_time) < date(?1)
+         GROUP BY date(start_time), app_name, exe_path, window_title",
     )?;
-
-    let stats = stmt
-        .query_map([today.to_string()], |row| {
-            Ok(CodingProjectStats {
-                project_dir: row.get(0)?,
-                total_seconds: row.get(1)?,
-                ai_seconds: row.get(2)?,
-                session_count: row.get(3)?,
-            })
-        })?
+    let groups = stmt
+        .query_map([cutoff], |row| Ok((read_daily_usage_row(row)?, row.get::<_, String>(7)?)))?
         .collect::<Result<Vec<_>>>()?;
 
-    Ok(stats)
+    let mut report = RetentionReport {
+        cutoff: cutoff.to_string(),
+        rollup_rows: groups.len(),
+        dry_run,
+        ..RetentionReport::default()
+    };
+    for (row, first_start) in &groups {
+        report.sessions_removed += row.session_count as usize;
+        report.seconds_rolled_up += row.total_seconds;
+        if report.oldest_session.as_ref().is_none_or(|oldest| first_start < oldest) {
+            report.oldest_session = Some(first_start.clone());
+        }
+        if !dry_run {
+            add_daily_usage(conn, row)?;
+        }
+    }
+
+    if !dry_run {
+        conn.execute(
+            "DELETE FROM activity_sessions WHERE end_time IS NOT NULL AND date(start_time) < date(?1)",
+            [cutoff],
+        )?;
+    }
+    Ok(report)
 }
 
-pub fn get_total_coding_time_today() -> Result<i64> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+/// Roll closed sessions older than `days` into `daily_usage`, then `VACUUM` if
+/// anything was removed. `days` must be at least 1 so today stays untouched.
+pub fn apply_retention(days: u32, dry_run: bool) -> Result<RetentionReport> {
+    if days == 0 {
+        return Err(edit_error("Retention needs to keep at least one day".to_string()));
+    }
+    let cutoff = (Local::now().date_naive() - chrono::Duration::days(days as i64))
+        .format("%Y-%m-%d")
+        .to_string();
+    let mut report = with_transaction(|tx| rollup_sessions_in(tx, &cutoff, dry_run))?;
 
-    let today = Local::now().date_naive();
+    if !dry_run && report.sessions_removed > 0 {
+        let guard = DB.lock();
+        let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+        // Cannot run inside a transaction
+        conn.execute_batch("VACUUM")?;
+        report.vacuumed = true;
+    }
+    Ok(report)
+}
 
-    let total: Option<i64> = conn.query_row(
-        "SELECT SUM(duration_seconds) FROM coding_sessions WHERE date(start_time) = date(?1)",
-        [today.to_string()],
-        |row| row.get(0),
-    ).ok();
+/// Background counterpart of `apply_retention`, driven by the `retention_days` setting.
+pub fn retention_if_needed() -> Result<Option<RetentionReport>> {
+    let days = get_settings().retention_days;
+    if days == 0 {
+        return Ok(None);
+    }
+    apply_retention(days, false).map(Some)
+}
 
-    Ok(total.unwrap_or(0))
+// ── Search ──
+
+/// Filters for `search_history`; dates are `YYYY-MM-DD`, inclusive.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SearchQuery {
+    pub query: String,
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub to: Option<String>,
+    #[serde(default)]
+    pub app_name: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
 }
 
-pub fn get_total_ai_coding_time_today() -> Result<i64> {
-    let guard = DB.lock();
-    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchHit {
+    /// `activity`, `coding` or `music`.
+    pub kind: String,
+    pub session_id: i64,
+    pub app_name: String,
+    pub start_time: String,
+    pub end_time: Option<String>,
+    pub duration_seconds: i64,
+    /// Matching text with the matched terms between the caller's markers.
+    pub snippet: String,
+}
 
-    let today = Local::now().date_naive();
+/// Hits of one day, newest first.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchDay {
+    pub date: String,
+    pub total_seconds: i64,
+    pub hits: Vec<SearchHit>,
+}
 
-    let total: Option<i64> = conn.query_row(
-        "SELECT SUM(duration_seconds) FROM coding_sessions \
-         WHERE date(start_time) = date(?1) AND is_ai_assisted = 1",
-        [today.to_string()],
-        |row| row.get(0),
-    ).ok();
+const DEFAULT_SEARCH_LIMIT: usize = 200;
 
-    Ok(total.unwrap_or(0))
+/// Turn free text into an FTS5 query: every word must match, the last one as a
+/// prefix so results show up while typing. Quoting keeps `-`, `:` or `"` in the
+/// input from being read as query syntax.
+fn fts_query(input: &str) -> Option<String> {
+    let words: Vec<String> = input
+        .split_whitespace()
+        .map(|w| format!("\"{}\"", w.replace('"', "\"\"")))
+        .collect();
+    if words.is_empty() {
+        return None;
+    }
+    Some(format!("{}*", words.join(" ")))
+}
+
+fn search_history_in(conn: &Connection, query: &SearchQuery, marks: (&str, &str)) -> Result<Vec<SearchDay>> {
+    let Some(fts) = fts_query(&query.query) else {
+        return Ok(Vec::new());
+    };
+    let arm = |kind: &str, fts_table: &str, table: &str| {
+        format!(
+            "SELECT '{kind}', s.id, s.app_name, s.start_time, s.end_time, COALESCE(s.duration_seconds, 0),
+                    snippet({fts_table}, -1, ?2, ?3, '…', 12)
+             FROM {fts_table} JOIN {table} s ON s.id = {fts_table}.rowid
+             WHERE {fts_table} MATCH ?1
+               AND (?4 IS NULL OR date(s.start_time) >= date(?4))
+               AND (?5 IS NULL OR date(s.start_time) <= date(?5))
+               AND (?6 IS NULL OR lower(s.app_name) = lower(?6))",
+            kind = kind,
+            fts_table = fts_table,
+            table = table
+        )
+    };
+    let sql = format!(
+        "{} UNION ALL {} UNION ALL {} ORDER BY 4 DESC LIMIT ?7",
+        arm("activity", "activity_fts", "activity_sessions"),
+        arm("coding", "coding_fts", "coding_sessions"),
+        arm("music", "music_fts", "music_sessions")
+    );
+    let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT) as i64;
+
+    let mut stmt = conn.prepare(&sql)?;
+    let hits = stmt
+        .query_map(
+            params![fts, marks.0, marks.1, query.from, query.to, query.app_name, limit],
+            |row| {
+                Ok(SearchHit {
+                    kind: row.get(0)?,
+                    session_id: row.get(1)?,
+                    app_name: row.get(2)?,
+                    start_time: row.get(3)?,
+                    end_time: row.get(4)?,
+                    duration_seconds: row.get(5)?,
+                    snippet: row.get(6)?,
+                })
+            },
+        )?
+        .collect::<Result<Vec<_>>>()?;
+
+    // Start times carry the local offset, so their first ten characters are the local day
+    let mut days: Vec<SearchDay> = Vec::new();
+    for hit in hits {
+        let date = hit.start_time.get(..10).unwrap_or(&hit.start_time).to_string();
+        match days.last_mut() {
+            Some(day) if day.date == date => {
+                day.total_seconds += hit.duration_seconds;
+                day.hits.push(hit);
+            }
+            _ => days.push(SearchDay {
+                date,
+                total_seconds: hit.duration_seconds,
+                hits: vec![hit],
+            }),
+        }
+    }
+    Ok(days)
+}
+
+/// Search window titles, browser domains, edited file paths and music tracks.
+/// `marks` surround the matched terms in each snippet.
+pub fn search_history(query: &SearchQuery, marks: (&str, &str)) -> Result<Vec<SearchDay>> {
+    let guard = DB.lock();
+    let conn = guard.as_ref().ok_or(rusqlite::Error::InvalidQuery)?;
+    search_history_in(conn, query, marks)
 }
 
 #[test]
@@ -2754,3 +5440,556 @@ fn test_inspect_db() {
         }
     }
 }
+
+#[test]
+fn test_recover_dangling_sessions() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    crate::migrations::run_migrations(&mut conn, None).unwrap();
+
+    conn.execute_batch(
+        "INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, last_heartbeat)
+             VALUES ('Code', 'main.rs', '/usr/bin/code', '2024-05-01T10:00:00+02:00', '2024-05-01T10:45:30+02:00');
+         INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, end_time, duration_seconds)
+             VALUES ('Slack', 'general', '/usr/bin/slack', '2024-05-01T09:00:00+02:00', '2024-05-01T09:10:00+02:00', 600);
+         INSERT INTO music_sessions (app_name, exe_path, start_time)
+             VALUES ('Spotify', '/usr/bin/spotify', '2024-05-01T11:00:00+02:00');",
+    )
+    .unwrap();
+
+    // The tracker only holds the music session; the stray activity row isn't kept alive
+    let at = |s: &str| parse_session_time(s).unwrap();
+    heartbeat_sessions_in(&conn, [None, Some(1), None], at("2024-05-01T11:02:00+02:00")).unwrap();
+
+    assert_eq!(recover_dangling_sessions(&conn).unwrap(), 2);
+    assert_eq!(recover_dangling_sessions(&conn).unwrap(), 0);
+
+    let (end, duration): (String, i64) = conn
+        .query_row(
+            "SELECT end_time, duration_seconds FROM activity_sessions WHERE app_name = 'Code'",
+            [],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!(end, "2024-05-01T10:45:30+02:00");
+    assert_eq!(duration, 45 * 60 + 30);
+
+    let slack_duration: i64 = conn
+        .query_row("SELECT duration_seconds FROM activity_sessions WHERE app_name = 'Slack'", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(slack_duration, 600);
+
+    let (end, duration): (String, i64) = conn
+        .query_row("SELECT end_time, duration_seconds FROM music_sessions", [], |r| Ok((r.get(0)?, r.get(1)?)))
+        .unwrap();
+    assert_eq!(parse_session_time(&end).unwrap(), at("2024-05-01T11:02:00+02:00"));
+    assert_eq!(duration, 120);
+
+    // No heartbeat yet: closed at its start with zero duration
+    conn.execute(
+        "INSERT INTO music_sessions (app_name, exe_path, start_time) VALUES ('Spotify', '/usr/bin/spotify', '2024-05-01T12:00:00+02:00')",
+        [],
+    )
+    .unwrap();
+    assert_eq!(recover_dangling_sessions(&conn).unwrap(), 1);
+    let (end, duration): (String, i64) = conn
+        .query_row("SELECT end_time, duration_seconds FROM music_sessions WHERE id = 2", [], |r| {
+            Ok((r.get(0)?, r.get(1)?))
+        })
+        .unwrap();
+    assert_eq!(end, "2024-05-01T12:00:00+02:00");
+    assert_eq!(duration, 0);
+}
+
+#[test]
+fn test_detect_category_matches_dashboard() {
+    assert_eq!(detect_category("Steam", "/usr/bin/steam"), Some("Games"));
+    assert_eq!(detect_category("Game", "C:\\Program Files\\Steam\\game.exe"), Some("Games"));
+    assert_eq!(detect_category("Visual Studio Code", "/usr/bin/code"), Some("Work"));
+    assert_eq!(detect_category("Discord", "/usr/bin/discord"), Some("Rest"));
+    assert_eq!(detect_category("Firefox", "/usr/bin/firefox"), Some("Programs"));
+    assert_eq!(detect_category("Kitty", "/usr/bin/kitty"), None);
+}
+
+#[test]
+fn test_productivity_score() {
+    let usage = |category: &str, total_seconds: i64, productivity_weight: f64| CategoryUsage {
+        category: category.to_string(),
+        total_seconds,
+        productivity_weight,
+    };
+
+    assert_eq!(productivity_score(&[]), 0.0);
+    assert_eq!(productivity_score(&[usage("Development", 3600, 1.0)]), 100.0);
+    assert_eq!(
+        productivity_score(&[usage("Development", 1800, 1.0), usage("Entertainment", 1800, -1.0)]),
+        50.0
+    );
+    assert_eq!(
+        productivity_score(&[usage("Work", 3000, 1.0), usage(UNCATEGORIZED, 1000, 0.0)]),
+        87.5
+    );
+}
+
+#[test]
+fn test_session_edits_and_revert() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    crate::migrations::run_migrations(&mut conn, None).unwrap();
+
+    let at = |s: &str| parse_session_time(s).unwrap();
+    let id = create_manual_entry_in(
+        &conn,
+        "Meeting",
+        "Standup",
+        at("2024-05-01T09:00:00+00:00"),
+        at("2024-05-01T10:00:00+00:00"),
+    )
+    .unwrap();
+
+    let (first, second) = split_session_in(&conn, id, at("2024-05-01T09:15:00+00:00")).unwrap();
+    assert_eq!(first, id);
+    assert_eq!(find_session_row(&conn, first).unwrap().unwrap().duration_seconds, 15 * 60);
+    assert_eq!(find_session_row(&conn, second).unwrap().unwrap().duration_seconds, 45 * 60);
+    assert!(split_session_in(&conn, id, at("2024-05-01T11:00:00+00:00")).is_err());
+
+    let merged = merge_sessions_in(&conn, &[second, first]).unwrap();
+    assert_eq!(merged, first);
+    assert!(find_session_row(&conn, second).unwrap().is_none());
+    assert_eq!(find_session_row(&conn, first).unwrap().unwrap().duration_seconds, 3600);
+
+    // The split can't be undone while the merge that followed it still stands
+    let edits: Vec<(i64, String)> = conn
+        .prepare("SELECT id, action FROM session_edits ORDER BY id")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+        .unwrap()
+        .collect::<Result<_>>()
+        .unwrap();
+    let actions: Vec<&str> = edits.iter().map(|(_, a)| a.as_str()).collect();
+    assert_eq!(actions, ["create", "split", "merge"]);
+    assert!(revert_session_edit_in(&conn, edits[1].0).is_err());
+
+    revert_session_edit_in(&conn, edits[2].0).unwrap();
+    assert!(find_session_row(&conn, second).unwrap().is_some());
+    revert_session_edit_in(&conn, edits[1].0).unwrap();
+    let row = find_session_row(&conn, first).unwrap().unwrap();
+    assert_eq!(row.duration_seconds, 3600);
+    assert!(row.is_manual);
+    assert!(find_session_row(&conn, second).unwrap().is_none());
+
+    delete_session_in(&conn, first).unwrap();
+    assert!(find_session_row(&conn, first).unwrap().is_none());
+}
+
+#[test]
+fn test_session_edits_keep_url_and_process() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    crate::migrations::run_migrations(&mut conn, None).unwrap();
+
+    conn.execute_batch(
+        "INSERT INTO activity_sessions
+            (app_name, window_title, exe_path, start_time, end_time, duration_seconds,
+             url, domain, process_name, process_cwd)
+         VALUES ('firefox', 'Issues — Mozilla Firefox', '/usr/bin/firefox',
+                 '2024-05-01T09:00:00+00:00', '2024-05-01T10:00:00+00:00', 3600,
+                 'https://github.com/timigs/issues', 'github.com', NULL, NULL);
+         INSERT INTO activity_sessions
+            (app_name, window_title, exe_path, start_time, end_time, duration_seconds,
+             url, domain, process_name, process_cwd)
+         VALUES ('kitty', 'cargo test', '/usr/bin/kitty',
+                 '2024-05-01T10:00:00+00:00', '2024-05-01T10:30:00+00:00', 1800,
+                 NULL, NULL, 'cargo', '/home/me/timigs');",
+    )
+    .unwrap();
+    let original = |id| find_session_row(&conn, id).unwrap().unwrap();
+    let (browser, terminal) = (original(1), original(2));
+    assert_eq!(browser.domain.as_deref(), Some("github.com"));
+    assert_eq!(terminal.process_cwd.as_deref(), Some("/home/me/timigs"));
+
+    let at = |s: &str| parse_session_time(s).unwrap();
+    let (_, second) = split_session_in(&conn, 1, at("2024-05-01T09:30:00+00:00")).unwrap();
+    assert_eq!(find_session_row(&conn, second).unwrap().unwrap().url, browser.url);
+    delete_session_in(&conn, 2).unwrap();
+
+    let edits: Vec<i64> = conn
+        .prepare("SELECT id FROM session_edits ORDER BY id DESC")
+        .unwrap()
+        .query_map([], |r| r.get(0))
+        .unwrap()
+        .collect::<Result<_>>()
+        .unwrap();
+    for edit in edits {
+        revert_session_edit_in(&conn, edit).unwrap();
+    }
+    assert_eq!(original(1), browser);
+    assert_eq!(original(2), terminal);
+
+    // Snapshots from before the columns were kept still revert
+    conn.execute(
+        "INSERT INTO session_edits (action, before_json, after_json, created_at)
+         VALUES ('update', ?1, ?1, '2024-05-02T00:00:00+00:00')",
+        [r#"[{"id":2,"app_name":"kitty","window_title":"cargo test","exe_path":"/usr/bin/kitty",
+              "start_time":"2024-05-01T10:00:00+00:00","end_time":"2024-05-01T10:30:00+00:00",
+              "duration_seconds":1800,"is_manual":false}]"#],
+    )
+    .unwrap();
+    revert_session_edit_in(&conn, conn.last_insert_rowid()).unwrap();
+}
+
+#[test]
+fn test_coding_heartbeats_override_title_sessions() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    crate::migrations::run_migrations(&mut conn, None).unwrap();
+
+    let at = |s: &str| parse_session_time(s).unwrap();
+    conn.execute(
+        "INSERT INTO coding_sessions (app_name, editor_name, window_title, exe_path, start_time, end_time, duration_seconds, \
+                                      repo_root, repo_name) \
+         VALUES ('Code', 'VS Code', 'main.rs - timigs', '/usr/bin/code', ?1, ?2, 3600, '/src', 'timigs')",
+        params![at("2024-05-01T09:00:00+00:00").to_rfc3339(), at("2024-05-01T10:00:00+00:00").to_rfc3339()],
+    )
+    .unwrap();
+
+    let hb = |time: &str, entity: &str| CodingHeartbeat {
+        time: at(time),
+        editor_name: "VS Code".to_string(),
+        entity: entity.to_string(),
+        is_file: true,
+        language: Some("Rust".to_string()),
+        project: Some("timigs".to_string()),
+        branch: Some("main".to_string()),
+        is_ai_assisted: false,
+        repo: None,
+    };
+    for heartbeat in [
+        hb("2024-05-01T09:10:00+00:00", "/src/main.rs"),
+        hb("2024-05-01T09:12:00+00:00", "/src/main.rs"),
+        hb("2024-05-01T09:20:00+00:00", "/src/db.rs"),
+        // Past the timeout: a new session, the gap isn't counted
+        hb("2024-05-01T09:50:00+00:00", "/src/db.rs"),
+    ] {
+        record_coding_heartbeat_in(&conn, &heartbeat).unwrap();
+    }
+
+    let rows: Vec<(String, Option<String>, i64)> = conn
+        .prepare("SELECT source, file_path, duration_seconds FROM coding_sessions ORDER BY julianday(start_time), id")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .unwrap()
+        .collect::<Result<_>>()
+        .unwrap();
+    let heartbeat = |f: &str, secs| (CODING_SOURCE_HEARTBEAT.to_string(), Some(f.to_string()), secs);
+    let title = |secs| (CODING_SOURCE_TITLE.to_string(), None, secs);
+    assert_eq!(
+        rows,
+        vec![
+            title(10 * 60),
+            heartbeat("/src/main.rs", 10 * 60),
+            title(40 * 60),
+            heartbeat("/src/db.rs", 0),
+            heartbeat("/src/db.rs", 0),
+        ]
+    );
+
+    // Both parts of the split title session keep its repository
+    let repos: Vec<(Option<String>, Option<String>)> = conn
+        .prepare("SELECT repo_root, repo_name FROM coding_sessions WHERE source = ?1")
+        .unwrap()
+        .query_map([CODING_SOURCE_TITLE], |r| Ok((r.get(0)?, r.get(1)?)))
+        .unwrap()
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(repos, vec![(Some("/src".to_string()), Some("timigs".to_string())); 2]);
+}
+
+#[test]
+fn test_shell_command_timing() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    crate::migrations::run_migrations(&mut conn, None).unwrap();
+
+    let at = |s: &str| parse_session_time(s).unwrap();
+    let session = Some("4242");
+    start_shell_command_in(&conn, session, Some("zsh"), "cargo build", Some("/src/timigs"), at("2024-05-01T09:00:00+00:00")).unwrap();
+    start_shell_command_in(&conn, Some("777"), Some("bash"), "htop", None, at("2024-05-01T09:00:01+00:00")).unwrap();
+
+    // No duration from the shell: measured between the two events
+    let id = end_shell_command_in(&conn, session, at("2024-05-01T09:00:42.500+00:00"), Some(101), None).unwrap();
+    let (duration, exit_code): (i64, i64) = conn
+        .query_row("SELECT duration_ms, exit_code FROM shell_commands WHERE id = ?1", [id.unwrap()], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .unwrap();
+    assert_eq!((duration, exit_code), (42_500, 101));
+
+    // Nothing left open for that shell; the other one is untouched
+    assert_eq!(end_shell_command_in(&conn, session, at("2024-05-01T09:01:00+00:00"), Some(0), None).unwrap(), None);
+    let open: i64 = conn
+        .query_row("SELECT COUNT(*) FROM shell_commands WHERE ended_at IS NULL", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(open, 1);
+}
+
+#[test]
+fn test_normalize_url() {
+    assert_eq!(
+        normalize_url("https://me:pw@www.GitHub.com/BANSAFAn/timiGS-?tab=readme#top"),
+        Some((
+            "https://www.github.com/BANSAFAn/timiGS-".to_string(),
+            "github.com".to_string()
+        ))
+    );
+    assert_eq!(normalize_url("chrome://newtab/"), None);
+    assert_eq!(normalize_url("not a url"), None);
+}
+
+#[test]
+fn test_browser_tab_labels_sessions() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    crate::migrations::run_migrations(&mut conn, None).unwrap();
+
+    conn.execute_batch(
+        "INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time)
+             VALUES ('firefox', 'Issues · BANSAFAn/timiGS- — Mozilla Firefox', '/usr/bin/firefox', '2024-05-01T10:00:00+02:00');
+         INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time)
+             VALUES ('kitty', 'gh issue list: Issues · BANSAFAn/timiGS-', '/usr/bin/kitty', '2024-05-01T10:00:00+02:00');",
+    )
+    .unwrap();
+    let tab = |browser: &str, url: &str, domain: &str, title: &str| BrowserTab {
+        browser: browser.to_string(),
+        url: url.to_string(),
+        domain: domain.to_string(),
+        title: title.to_string(),
+    };
+    let labels = |conn: &Connection| -> Vec<Option<String>> {
+        let mut stmt = conn.prepare("SELECT domain FROM activity_sessions ORDER BY id").unwrap();
+        stmt.query_map([], |r| r.get(0)).unwrap().collect::<Result<_>>().unwrap()
+    };
+
+    // Only the browser's own session gets the domain, not another browser's or a
+    // terminal showing the same text
+    let issues = "Issues · BANSAFAn/timiGS-";
+    record_browser_tab_in(&conn, &tab("chromium", "https://example.com/", "example.com", issues)).unwrap();
+    assert_eq!(labels(&conn), vec![None, None]);
+    record_browser_tab_in(&conn, &tab("firefox", "https://github.com/BANSAFAn/timiGS-/issues", "github.com", issues)).unwrap();
+    assert_eq!(labels(&conn), vec![Some("github.com".to_string()), None]);
+
+    // A tab reported before its window's session exists is attached when it starts
+    record_browser_tab_in(&conn, &tab("firefox", "https://docs.rs/", "docs.rs", "Docs.rs")).unwrap();
+    conn.execute_batch(
+        "UPDATE activity_sessions SET end_time = '2024-05-01T10:05:00+02:00';
+         INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time)
+             VALUES ('firefox', 'Docs.rs — Mozilla Firefox', '/usr/bin/firefox', '2024-05-01T10:05:00+02:00');",
+    )
+    .unwrap();
+    let id = conn.last_insert_rowid();
+    let now = Local::now();
+    attach_browser_tab_in(&conn, id, "Google-chrome", "Docs.rs — Google Chrome", now).unwrap();
+    assert_eq!(labels(&conn)[2], None);
+    attach_browser_tab_in(&conn, id, "firefox", "Docs.rs — Mozilla Firefox", now + chrono::Duration::minutes(1))
+        .unwrap();
+    assert_eq!(labels(&conn)[2], None);
+    attach_browser_tab_in(&conn, id, "firefox", "Docs.rs — Mozilla Firefox", now).unwrap();
+    assert_eq!(
+        labels(&conn),
+        vec![Some("github.com".to_string()), None, Some("docs.rs".to_string())]
+    );
+}
+
+#[test]
+fn test_scrub_titles() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    crate::migrations::run_migrations(&mut conn, None).unwrap();
+
+    conn.execute_batch(
+        "INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, url, domain)
+             VALUES ('firefox', 'Bank statement — Mozilla Firefox', '/usr/bin/firefox', '2024-05-01T10:00:00+02:00',
+                     'https://bank.example/statements', 'bank.example');
+         INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time)
+             VALUES ('Code', 'main.rs - timigs', '/usr/bin/code', '2024-05-01T11:00:00+02:00');
+         INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time)
+             VALUES ('firefox', 'News — Mozilla Firefox', '/usr/bin/firefox', '2024-05-03T10:00:00+02:00');",
+    )
+    .unwrap();
+    let edit = SessionRow {
+        id: 2,
+        app_name: "firefox".to_string(),
+        window_title: "Bank statement — Mozilla Firefox".to_string(),
+        exe_path: "/usr/bin/firefox".to_string(),
+        start_time: "2024-05-01T10:00:00+02:00".to_string(),
+        end_time: None,
+        duration_seconds: 0,
+        is_manual: false,
+        url: Some("https://bank.example/statements".to_string()),
+        domain: Some("bank.example".to_string()),
+        process_name: None,
+        process_cwd: None,
+    };
+    record_session_edit(&conn, "update", std::slice::from_ref(&edit), std::slice::from_ref(&edit)).unwrap();
+
+    // Firefox titles on the 1st only: nothing left but the app name
+    let filter = TitleFilter {
+        app_name: Some("Firefox"),
+        from: Some("2024-05-01"),
+        to: Some("2024-05-01"),
+    };
+    let app_only = |_: &str, _: &str, _: &str| (String::new(), false);
+
+    let report = scrub_titles_in(&conn, &filter, true, &app_only).unwrap();
+    assert_eq!((report.activity_sessions, report.session_edits), (1, 1));
+    let title: String = conn
+        .query_row("SELECT window_title FROM activity_sessions WHERE id = 1", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(title, "Bank statement — Mozilla Firefox");
+
+    scrub_titles_in(&conn, &filter, false, &app_only).unwrap();
+    let rows: Vec<(String, Option<String>)> = conn
+        .prepare("SELECT window_title, domain FROM activity_sessions ORDER BY id")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+        .unwrap()
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(
+        rows,
+        vec![
+            (String::new(), None),
+            ("main.rs - timigs".to_string(), None),
+            ("News — Mozilla Firefox".to_string(), None),
+        ]
+    );
+    let before: String = conn.query_row("SELECT before_json FROM session_edits", [], |r| r.get(0)).unwrap();
+    assert!(!before.contains("Bank statement") && !before.contains("bank.example"));
+
+    // Already scrubbed: nothing to do
+    assert_eq!(scrub_titles_in(&conn, &filter, false, &app_only).unwrap().activity_sessions, 0);
+}
+
+#[test]
+fn test_rollup_sessions() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    crate::migrations::run_migrations(&mut conn, None).unwrap();
+
+    conn.execute_batch(
+        "INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, end_time, duration_seconds, domain) VALUES
+            ('firefox', 'Docs', '/usr/bin/firefox', '2024-05-01T09:00:00+00:00', '2024-05-01T09:10:00+00:00', 600, 'docs.rs'),
+            ('firefox', 'Docs', '/usr/bin/firefox', '2024-05-01T11:00:00+00:00', '2024-05-01T11:05:00+00:00', 300, NULL),
+            ('code', 'main.rs', '/usr/bin/code', '2024-05-01T12:00:00+00:00', '2024-05-01T13:00:00+00:00', 3600, NULL),
+            ('code', 'main.rs', '/usr/bin/code', '2024-05-03T12:00:00+00:00', '2024-05-03T13:00:00+00:00', 3600, NULL),
+            ('code', 'lib.rs', '/usr/bin/code', '2024-05-01T23:00:00+00:00', NULL, 0, NULL);",
+    )
+    .unwrap();
+    let remaining = |conn: &Connection| -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM activity_sessions", [], |r| r.get(0)).unwrap()
+    };
+
+    // A dry run only reports
+    let preview = rollup_sessions_in(&conn, "2024-05-02", true).unwrap();
+    assert_eq!(preview.sessions_removed, 3);
+    assert_eq!(preview.rollup_rows, 2);
+    assert_eq!(preview.seconds_rolled_up, 4500);
+    assert_eq!(preview.oldest_session.as_deref(), Some("2024-05-01T09:00:00+00:00"));
+    assert_eq!(remaining(&conn), 5);
+
+    // Closed sessions before the cutoff collapse into one row per title; open ones stay
+    let report = rollup_sessions_in(&conn, "2024-05-02", false).unwrap();
+    assert_eq!(report, RetentionReport { dry_run: false, ..preview });
+    assert_eq!(remaining(&conn), 2);
+
+    let mut stmt = conn
+        .prepare("SELECT date, app_name, exe_path, window_title, domain, total_seconds, session_count FROM daily_usage ORDER BY app_name")
+        .unwrap();
+    let rows = stmt.query_map([], read_daily_usage_row).unwrap().collect::<Result<Vec<_>>>().unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!((rows[0].app_name.as_str(), rows[0].total_seconds, rows[0].session_count), ("code", 3600, 1));
+    assert_eq!(rows[1].domain.as_deref(), Some("docs.rs"));
+    assert_eq!((rows[1].total_seconds, rows[1].session_count), (900, 2));
+
+    // Later passes add to the same day, and summaries still see it
+    conn.execute_batch(
+        "INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, end_time, duration_seconds)
+             VALUES ('code', 'main.rs', '/usr/bin/code', '2024-05-01T15:00:00+00:00', '2024-05-01T15:10:00+00:00', 600);",
+    )
+    .unwrap();
+    rollup_sessions_in(&conn, "2024-05-02", false).unwrap();
+    let total: i64 = conn
+        .query_row(
+            &format!("SELECT total FROM ({}) WHERE app_name = 'code'", DAY_SUMMARY_SQL),
+            ["2024-05-01"],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(total, 4200);
+}
+
+#[test]
+fn test_search_history() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    crate::migrations::run_migrations(&mut conn, None).unwrap();
+
+    conn.execute_batch(
+        "INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, end_time, duration_seconds) VALUES
+            ('soffice', 'Invoice-2024.ods - LibreOffice Calc', '/usr/bin/soffice', '2024-05-01T09:00:00+02:00', '2024-05-01T09:30:00+02:00', 1800),
+            ('soffice', 'Invoices archive.ods - LibreOffice Calc', '/usr/bin/soffice', '2024-05-03T09:00:00+02:00', '2024-05-03T09:10:00+02:00', 600),
+            ('firefox', 'Inbox — Mozilla Firefox', '/usr/bin/firefox', '2024-05-03T10:00:00+02:00', NULL, 0);
+         INSERT INTO coding_sessions (app_name, editor_name, file_path, window_title, exe_path, start_time, duration_seconds)
+            VALUES ('code', 'VS Code', '/home/me/billing/invoice.rs', 'invoice.rs - billing', '/usr/bin/code', '2024-05-03T11:00:00+02:00', 900);",
+    )
+    .unwrap();
+    let search = |conn: &Connection, text: &str, app: Option<&str>| {
+        let query = SearchQuery {
+            query: text.to_string(),
+            app_name: app.map(str::to_string),
+            ..SearchQuery::default()
+        };
+        search_history_in(conn, &query, ("[", "]")).unwrap()
+    };
+
+    // Prefix match on the last word, grouped by day, newest first
+    let days = search(&conn, "invoice", None);
+    assert_eq!(days.iter().map(|d| d.date.as_str()).collect::<Vec<_>>(), ["2024-05-03", "2024-05-01"]);
+    assert_eq!(days[0].hits.len(), 2);
+    assert_eq!(days[0].hits[0].kind, "coding");
+    assert_eq!(days[0].total_seconds, 1500);
+    assert!(days[1].hits[0].snippet.contains("[Invoice]"));
+
+    // App filter, and query syntax characters are plain text
+    assert_eq!(search(&conn, "invoice", Some("SOFFICE")).iter().map(|d| d.hits.len()).sum::<usize>(), 2);
+    assert_eq!(search(&conn, "invoice-2024 \"calc", None).len(), 1);
+    assert!(search(&conn, "   ", None).is_empty());
+
+    // Edits and deletions reach the index
+    conn.execute("UPDATE activity_sessions SET window_title = 'LibreOffice Calc' WHERE id = 1", []).unwrap();
+    conn.execute("DELETE FROM coding_sessions", []).unwrap();
+    let days = search(&conn, "invoice", None);
+    assert_eq!(days.len(), 1);
+    assert_eq!(days[0].hits[0].session_id, 2);
+}
+
+#[test]
+fn test_seal_cloud_tokens() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    crate::migrations::run_migrations(&mut conn, None).unwrap();
+
+    conn.execute_batch(
+        "INSERT INTO cloud_accounts (email, provider, access_token, refresh_token, created_at) VALUES
+            ('me@example.com', 'google', 'ya29.plain', '1//refresh', '2024-05-01T10:00:00+00:00'),
+            ('me@example.com', 'dropbox', 'enc:v1:already', '', '2024-05-01T10:00:00+00:00');",
+    )
+    .unwrap();
+    let fake_seal = |value: &str| -> std::result::Result<String, String> {
+        if value.is_empty() || crate::vault::is_sealed(value) {
+            Ok(value.to_string())
+        } else {
+            Ok(format!("enc:v1:{}", value.len()))
+        }
+    };
+
+    assert_eq!(seal_cloud_tokens_in(&conn, &fake_seal).unwrap(), 1);
+    let tokens: (String, String) = conn
+        .query_row("SELECT access_token, refresh_token FROM cloud_accounts WHERE provider = 'google'", [], |r| {
+            Ok((r.get(0)?, r.get(1)?))
+        })
+        .unwrap();
+    assert_eq!(tokens, ("enc:v1:10".to_string(), "enc:v1:10".to_string()));
+    // Nothing left to seal
+    assert_eq!(seal_cloud_tokens_in(&conn, &fake_seal).unwrap(), 0);
+}
@@ -1,6 +1,7 @@
 //! Фокус покус....
 
 
+use chrono::{DateTime, Local};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
@@ -31,6 +32,7 @@ struct FocusSession {
     password_hash: String,
     remaining: Arc<std::sync::atomic::AtomicU64>,
     total_secs: u64,
+    started: DateTime<Local>,
 }
 
 fn simple_hash(password: &str) -> String {
@@ -41,6 +43,13 @@ fn simple_hash(password: &str) -> String {
     format!("{:x}", hash)
 }
 
+/// Keep the finished session for the calendar export.
+fn record_session(app_name: &str, started: DateTime<Local>) {
+    if let Err(e) = crate::db::record_focus_block("focus", app_name, started, Local::now()) {
+        eprintln!("Failed to record focus session: {}", e);
+    }
+}
+
 pub fn start_focus(
     app_name: &str,
     exe_path: &str,
@@ -53,6 +62,8 @@ pub fn start_focus(
 // виправити проблему що бере іноді не той шлях до файлу
     let remaining = Arc::new(std::sync::atomic::AtomicU64::new(duration_secs));
     let password_hash = simple_hash(password);
+    let started = Local::now();
+    let label = app_name.to_string();
 
     *FOCUS_STATE.lock() = Some(FocusSession {
         app_name: app_name.to_string(),
@@ -60,6 +71,7 @@ pub fn start_focus(
         password_hash,
         remaining: remaining.clone(),
         total_secs: duration_secs,
+        started,
     });
 
     FOCUS_BYPASS_ATTEMPTS.store(0, Ordering::SeqCst);
@@ -73,6 +85,7 @@ pub fn start_focus(
             if left == 0 {
                 FOCUS_RUNNING.store(false, Ordering::SeqCst);
                 *FOCUS_STATE.lock() = None;
+                record_session(&label, started);
                 break;
             }
 
@@ -95,17 +108,19 @@ pub fn start_focus(
 
 pub fn stop_focus(password: &str) -> Result<(), String> {
     let state = FOCUS_STATE.lock();
-    if let Some(session) = state.as_ref() {
+    let (app_name, started) = if let Some(session) = state.as_ref() {
         if simple_hash(password) != session.password_hash {
             return Err("Wrong password".to_string());
         }
+        (session.app_name.clone(), session.started)
     } else {
         return Err("Focus mode is not active".to_string());
-    }
+    };
     drop(state);
 
     FOCUS_RUNNING.store(false, Ordering::SeqCst);
     *FOCUS_STATE.lock() = None;
+    record_session(&app_name, started);
     Ok(())
 }
 
@@ -0,0 +1,184 @@
+//! iCalendar (RFC 5545) output for the calendar export
+//!
+//! Only what a read-only feed needs: VEVENTs with a stable UID, UTC start and end,
+//! summary, description and categories. Text is escaped and lines are folded at 75
+//! octets, which Google Calendar and Outlook are strict about.
+
+use chrono::{DateTime, Local, Utc};
+
+/// Calendar apps re-read a subscribed feed, so the same session keeps the same UID.
+pub struct CalendarEvent {
+    pub uid: String,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub summary: String,
+    pub description: String,
+    pub categories: Vec<String>,
+}
+
+/// Activity sessions closer than this become one block.
+pub const BLOCK_GAP_SECS: i64 = 300;
+/// Blocks shorter than this are left out of the calendar.
+pub const MIN_BLOCK_SECS: i64 = 60;
+
+/// One stretch of activity: consecutive sessions of the same category.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActivityBlock {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub category: Option<String>,
+    /// `(app, seconds)`, longest first.
+    pub apps: Vec<(String, i64)>,
+}
+
+/// Merge `(start, end, app, category)` spans, sorted by start, into activity blocks.
+pub fn merge_blocks(
+    spans: impl IntoIterator<Item = (DateTime<Local>, DateTime<Local>, String, Option<String>)>,
+) -> Vec<ActivityBlock> {
+    let mut blocks: Vec<ActivityBlock> = Vec::new();
+    for (start, end, app, category) in spans {
+        let seconds = (end - start).num_seconds().max(0);
+        match blocks.last_mut() {
+            Some(block)
+                if block.category == category && (start - block.end).num_seconds() <= BLOCK_GAP_SECS =>
+            {
+                block.end = block.end.max(end);
+                match block.apps.iter_mut().find(|(name, _)| *name == app) {
+                    Some((_, total)) => *total += seconds,
+                    None => block.apps.push((app, seconds)),
+                }
+            }
+            _ => blocks.push(ActivityBlock {
+                start,
+                end,
+                category,
+                apps: vec![(app, seconds)],
+            }),
+        }
+    }
+    blocks.retain(|b| (b.end - b.start).num_seconds() >= MIN_BLOCK_SECS);
+    for block in &mut blocks {
+        block.apps.sort_by_key(|app| std::cmp::Reverse(app.1));
+    }
+    blocks
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\;"),
+            ',' => out.push_str("\\,"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Append `line` folded into 75-octet pieces, never splitting a UTF-8 character.
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// A complete VCALENDAR with `events`.
+pub fn calendar(name: &str, events: &[CalendarEvent]) -> String {
+    let stamp = timestamp(Utc::now());
+    let mut out = String::new();
+    for line in [
+        "BEGIN:VCALENDAR",
+        "VERSION:2.0",
+        "PRODID:-//TimiGS//Activity Calendar//EN",
+        "CALSCALE:GREGORIAN",
+        "METHOD:PUBLISH",
+    ] {
+        push_line(&mut out, line);
+    }
+    push_line(&mut out, &format!("X-WR-CALNAME:{}", escape(name)));
+    push_line(&mut out, "REFRESH-INTERVAL;VALUE=DURATION:PT1H");
+    push_line(&mut out, "X-PUBLISHED-TTL:PT1H");
+
+    for event in events {
+        push_line(&mut out, "BEGIN:VEVENT");
+        push_line(&mut out, &format!("UID:{}", escape(&event.uid)));
+        push_line(&mut out, &format!("DTSTAMP:{}", stamp));
+        push_line(&mut out, &format!("DTSTART:{}", timestamp(event.start.with_timezone(&Utc))));
+        push_line(&mut out, &format!("DTEND:{}", timestamp(event.end.with_timezone(&Utc))));
+        push_line(&mut out, &format!("SUMMARY:{}", escape(&event.summary)));
+        if !event.description.is_empty() {
+            push_line(&mut out, &format!("DESCRIPTION:{}", escape(&event.description)));
+        }
+        if !event.categories.is_empty() {
+            let categories: Vec<String> = event.categories.iter().map(|c| escape(c)).collect();
+            push_line(&mut out, &format!("CATEGORIES:{}", categories.join(",")));
+        }
+        // Tracked time shouldn't make anyone look busy to schedulers
+        push_line(&mut out, "TRANSP:TRANSPARENT");
+        push_line(&mut out, "END:VEVENT");
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, 1, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_merge_blocks() {
+        let dev = Some("Development".to_string());
+        let blocks = merge_blocks([
+            (at(9, 0), at(9, 30), "Code".to_string(), dev.clone()),
+            (at(9, 32), at(9, 40), "Terminal".to_string(), dev.clone()),
+            (at(9, 40), at(10, 0), "Code".to_string(), dev.clone()),
+            (at(10, 0), at(10, 0), "Slack".to_string(), None),
+            (at(11, 0), at(11, 20), "Code".to_string(), dev.clone()),
+        ]);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!((blocks[0].start, blocks[0].end), (at(9, 0), at(10, 0)));
+        assert_eq!(blocks[0].apps, [("Code".to_string(), 3000), ("Terminal".to_string(), 480)]);
+        assert_eq!(blocks[1].start, at(11, 0));
+    }
+
+    #[test]
+    fn test_calendar_escapes_and_folds() {
+        let event = CalendarEvent {
+            uid: "activity-1@timigs".to_string(),
+            start: at(9, 0),
+            end: at(10, 0),
+            summary: "Development; Code, Terminal".to_string(),
+            description: format!("Code 50m\n{}", "é".repeat(60)),
+            categories: vec!["Development".to_string(), "Activity".to_string()],
+        };
+        let ics = calendar("TimiGS", &[event]);
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert!(ics.contains("SUMMARY:Development\\; Code\\, Terminal\r\n"));
+        assert!(ics.contains("CATEGORIES:Development,Activity\r\n"));
+        assert!(ics.contains("DESCRIPTION:Code 50m\\n"));
+        for line in ics.split("\r\n") {
+            assert!(line.len() <= 75, "{:?} is not folded", line);
+        }
+    }
+}
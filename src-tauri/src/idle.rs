@@ -0,0 +1,297 @@
+//! User idle time on Linux.
+//!
+//! Backends, picked the same way the tracker picks a compositor:
+//! - Wayland + GNOME: Mutter `IdleMonitor` over the session bus
+//! - other Wayland compositors: `ext-idle-notify-v1` (wlroots, KDE, ...)
+//! - X11: the MIT-SCREEN-SAVER extension
+//! - anything else: logind's `IdleHint` / `IdleSinceHint` on the system bus
+//!
+//! logind only flips `IdleHint` after the desktop's own idle timeout, so it is
+//! the coarsest source and always the last resort.
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{wl_registry, wl_seat};
+use wayland_client::{Connection as WaylandConnection, Dispatch, QueueHandle};
+use wayland_protocols::ext::idle_notify::v1::client::{
+    ext_idle_notification_v1::{self, ExtIdleNotificationV1},
+    ext_idle_notifier_v1::ExtIdleNotifierV1,
+};
+use x11rb::connection::Connection as _;
+use x11rb::protocol::screensaver::ConnectionExt as _;
+
+/// How long to wait before probing again when no backend is available.
+const REPROBE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Inactivity after which the compositor reports `idled`. The real idle time is
+/// this plus the time since the event.
+const WAYLAND_NOTIFY_TIMEOUT: Duration = Duration::from_secs(1);
+
+enum IdleBackend {
+    X11 {
+        conn: Box<x11rb::rust_connection::RustConnection>,
+        root: u32,
+    },
+    Mutter(zbus::blocking::Connection),
+    WaylandNotify {
+        idle_since: Arc<Mutex<Option<Instant>>>,
+        alive: Arc<AtomicBool>,
+    },
+    Logind(zbus::blocking::Proxy<'static>),
+    Unavailable {
+        probed_at: Instant,
+    },
+}
+
+static IDLE_BACKEND: Lazy<Mutex<Option<IdleBackend>>> = Lazy::new(|| Mutex::new(None));
+
+/// Time since the last keyboard/mouse input, or zero when no backend works.
+pub fn get_idle_time() -> Duration {
+    let mut guard = IDLE_BACKEND.lock();
+
+    if let Some(IdleBackend::Unavailable { probed_at }) = guard.as_ref() {
+        if probed_at.elapsed() < REPROBE_INTERVAL {
+            return Duration::ZERO;
+        }
+        *guard = None;
+    }
+
+    if guard.is_none() {
+        *guard = Some(probe_backend());
+    }
+
+    match guard.as_ref().and_then(query) {
+        Some(idle) => idle,
+        None => {
+            // The backend went away (compositor restart, bus error): pick again next tick
+            *guard = None;
+            Duration::ZERO
+        }
+    }
+}
+
+/// Name of the active backend, for diagnostics.
+pub fn backend_name() -> &'static str {
+    match IDLE_BACKEND.lock().as_ref() {
+        Some(IdleBackend::X11 { .. }) => "x11-screensaver",
+        Some(IdleBackend::Mutter(_)) => "mutter-idle-monitor",
+        Some(IdleBackend::WaylandNotify { .. }) => "ext-idle-notify-v1",
+        Some(IdleBackend::Logind(_)) => "logind",
+        Some(IdleBackend::Unavailable { .. }) => "none",
+        None => "unprobed",
+    }
+}
+
+fn probe_backend() -> IdleBackend {
+    let is_wayland = std::env::var("XDG_SESSION_TYPE")
+        .map(|s| s.to_lowercase() == "wayland")
+        .unwrap_or(false);
+    let desktop = std::env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .to_lowercase();
+
+    let backend = if is_wayland {
+        // XWayland's screensaver extension only sees input to X clients, so skip it
+        if desktop.contains("gnome") {
+            connect_mutter().or_else(connect_wayland_notify)
+        } else {
+            connect_wayland_notify().or_else(connect_mutter)
+        }
+    } else {
+        connect_x11().or_else(connect_mutter)
+    };
+
+    backend
+        .or_else(connect_logind)
+        .unwrap_or(IdleBackend::Unavailable {
+            probed_at: Instant::now(),
+        })
+}
+
+fn query(backend: &IdleBackend) -> Option<Duration> {
+    match backend {
+        IdleBackend::X11 { conn, root } => {
+            let info = conn.screensaver_query_info(*root).ok()?.reply().ok()?;
+            Some(Duration::from_millis(info.ms_since_user_input as u64))
+        }
+        IdleBackend::Mutter(conn) => mutter_idle_time(conn),
+        IdleBackend::WaylandNotify { idle_since, alive } => {
+            if !alive.load(Ordering::SeqCst) {
+                return None;
+            }
+            Some(idle_since.lock().map(|t| t.elapsed()).unwrap_or(Duration::ZERO))
+        }
+        IdleBackend::Logind(proxy) => logind_idle_time(proxy),
+        IdleBackend::Unavailable { .. } => Some(Duration::ZERO),
+    }
+}
+
+// ---- X11 ----
+
+fn connect_x11() -> Option<IdleBackend> {
+    let (conn, screen_num) = x11rb::connect(None).ok()?;
+    let root = conn.setup().roots.get(screen_num)?.root;
+    // Fails when the server lacks MIT-SCREEN-SAVER
+    conn.screensaver_query_info(root).ok()?.reply().ok()?;
+    Some(IdleBackend::X11 {
+        conn: Box::new(conn),
+        root,
+    })
+}
+
+// ---- GNOME / Mutter ----
+
+fn connect_mutter() -> Option<IdleBackend> {
+    let conn = zbus::blocking::Connection::session().ok()?;
+    mutter_idle_time(&conn)?;
+    Some(IdleBackend::Mutter(conn))
+}
+
+fn mutter_idle_time(conn: &zbus::blocking::Connection) -> Option<Duration> {
+    let reply = conn
+        .call_method(
+            Some("org.gnome.Mutter.IdleMonitor"),
+            "/org/gnome/Mutter/IdleMonitor/Core",
+            Some("org.gnome.Mutter.IdleMonitor"),
+            "GetIdletime",
+            &(),
+        )
+        .ok()?;
+    let ms: u64 = reply.body().deserialize().ok()?;
+    Some(Duration::from_millis(ms))
+}
+
+// ---- ext-idle-notify-v1 ----
+
+struct WaylandIdleState {
+    idle_since: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for WaylandIdleState {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &WaylandConnection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for WaylandIdleState {
+    fn event(
+        _: &mut Self,
+        _: &wl_seat::WlSeat,
+        _: wl_seat::Event,
+        _: &(),
+        _: &WaylandConnection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtIdleNotifierV1, ()> for WaylandIdleState {
+    fn event(
+        _: &mut Self,
+        _: &ExtIdleNotifierV1,
+        _: <ExtIdleNotifierV1 as wayland_client::Proxy>::Event,
+        _: &(),
+        _: &WaylandConnection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtIdleNotificationV1, ()> for WaylandIdleState {
+    fn event(
+        state: &mut Self,
+        _: &ExtIdleNotificationV1,
+        event: ext_idle_notification_v1::Event,
+        _: &(),
+        _: &WaylandConnection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_idle_notification_v1::Event::Idled => {
+                let now = Instant::now();
+                *state.idle_since.lock() = Some(now.checked_sub(WAYLAND_NOTIFY_TIMEOUT).unwrap_or(now));
+            }
+            ext_idle_notification_v1::Event::Resumed => {
+                *state.idle_since.lock() = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Subscribe to idle notifications and keep the event queue running on its own
+/// thread; the compositor only tells us when idle starts and ends.
+fn connect_wayland_notify() -> Option<IdleBackend> {
+    let conn = WaylandConnection::connect_to_env().ok()?;
+    let (globals, mut queue) = registry_queue_init::<WaylandIdleState>(&conn).ok()?;
+    let qh = queue.handle();
+
+    let notifier: ExtIdleNotifierV1 = globals.bind(&qh, 1..=1, ()).ok()?;
+    let seat: wl_seat::WlSeat = globals.bind(&qh, 1..=1, ()).ok()?;
+    let notification = notifier.get_idle_notification(
+        WAYLAND_NOTIFY_TIMEOUT.as_millis() as u32,
+        &seat,
+        &qh,
+        (),
+    );
+
+    let idle_since = Arc::new(Mutex::new(None));
+    let alive = Arc::new(AtomicBool::new(true));
+    let mut state = WaylandIdleState {
+        idle_since: idle_since.clone(),
+    };
+    queue.roundtrip(&mut state).ok()?;
+
+    let thread_alive = alive.clone();
+    std::thread::spawn(move || {
+        let _objects = (conn, notifier, seat, notification);
+        while queue.blocking_dispatch(&mut state).is_ok() {}
+        thread_alive.store(false, Ordering::SeqCst);
+    });
+
+    Some(IdleBackend::WaylandNotify { idle_since, alive })
+}
+
+// ---- logind ----
+
+fn connect_logind() -> Option<IdleBackend> {
+    let conn = zbus::blocking::Connection::system().ok()?;
+    let proxy = zbus::blocking::proxy::Builder::new(&conn)
+        .destination("org.freedesktop.login1")
+        .ok()?
+        .path("/org/freedesktop/login1/session/auto")
+        .ok()?
+        .interface("org.freedesktop.login1.Session")
+        .ok()?
+        .cache_properties(zbus::proxy::CacheProperties::No)
+        .build()
+        .ok()?;
+    logind_idle_time(&proxy)?;
+    Some(IdleBackend::Logind(proxy))
+}
+
+fn logind_idle_time(proxy: &zbus::blocking::Proxy<'static>) -> Option<Duration> {
+    let idle: bool = proxy.get_property("IdleHint").ok()?;
+    if !idle {
+        return Some(Duration::ZERO);
+    }
+
+    // Microseconds since the epoch (CLOCK_REALTIME)
+    let since_us: u64 = proxy.get_property("IdleSinceHint").ok()?;
+    let now_us = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0);
+    Some(Duration::from_micros(now_us.saturating_sub(since_us)))
+}
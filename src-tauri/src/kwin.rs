@@ -0,0 +1,187 @@
+//! Active window on KDE Plasma (Wayland) via a KWin script.
+//!
+//! KWin has no D-Bus call that returns the active window, so we load a small
+//! script into KWin that reports every focus and caption change back to a D-Bus
+//! object we own on the session bus. Works with the Plasma 5 (`clientActivated`)
+//! and Plasma 6 (`windowActivated`) scripting APIs.
+
+use crate::tracker::ActiveWindow;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const BUS_NAME: &str = "com.timigs.app.KWinBridge";
+const OBJECT_PATH: &str = "/com/timigs/app/KWinBridge";
+const SCRIPT_NAME: &str = "timigs-active-window";
+
+/// How long to wait before trying again when KWin is not reachable.
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+const KWIN_SCRIPT: &str = r#"
+const plasma6 = workspace.activeWindow !== undefined;
+const hooked = new Set();
+
+function activeWindow() {
+    return plasma6 ? workspace.activeWindow : workspace.activeClient;
+}
+
+function report() {
+    const w = activeWindow();
+    callDBus("com.timigs.app.KWinBridge", "/com/timigs/app/KWinBridge",
+        "com.timigs.app.KWinBridge", "UpdateActiveWindow",
+        w ? String(w.resourceClass || "") : "",
+        w ? String(w.caption || "") : "",
+        w ? String(w.pid || 0) : "0");
+}
+
+function onActivated(w) {
+    if (w && !hooked.has(w)) {
+        hooked.add(w);
+        w.captionChanged.connect(function () {
+            if (activeWindow() === w) {
+                report();
+            }
+        });
+    }
+    report();
+}
+
+(plasma6 ? workspace.windowActivated : workspace.clientActivated).connect(onActivated);
+(plasma6 ? workspace.windowRemoved : workspace.clientRemoved).connect(function (w) {
+    hooked.delete(w);
+});
+onActivated(activeWindow());
+"#;
+
+type Latest = Arc<Mutex<Option<ActiveWindow>>>;
+
+struct KWinBridge {
+    latest: Latest,
+}
+
+#[zbus::interface(name = "com.timigs.app.KWinBridge")]
+impl KWinBridge {
+    fn update_active_window(&self, resource_class: String, caption: String, pid: String) {
+        let window = if resource_class.is_empty() && caption.is_empty() {
+            None
+        } else {
+            let pid = pid.parse::<u32>().ok().filter(|&pid| pid > 0);
+            let exe_path = pid
+                .and_then(|pid| std::fs::read_link(format!("/proc/{}/exe", pid)).ok())
+                .and_then(|p| p.into_os_string().into_string().ok())
+                .unwrap_or_else(|| "unknown".to_string());
+            Some(ActiveWindow {
+                app_name: resource_class,
+                window_title: caption,
+                exe_path,
+                pid,
+            })
+        };
+        *self.latest.lock() = window;
+    }
+}
+
+enum KWinState {
+    Connected {
+        // Keeps the bus name and the bridge object alive
+        _conn: zbus::blocking::Connection,
+        latest: Latest,
+    },
+    Unavailable {
+        since: Instant,
+    },
+}
+
+static KWIN: Lazy<Mutex<Option<KWinState>>> = Lazy::new(|| Mutex::new(None));
+
+/// The focused KWin window, or `None` when KWin scripting is not reachable.
+pub fn get_active_window() -> Option<ActiveWindow> {
+    let mut guard = KWIN.lock();
+
+    if let Some(KWinState::Unavailable { since }) = guard.as_ref() {
+        if since.elapsed() < RETRY_INTERVAL {
+            return None;
+        }
+        *guard = None;
+    }
+
+    if guard.is_none() {
+        *guard = Some(match connect() {
+            Ok(state) => state,
+            Err(e) => {
+                eprintln!("KWin active-window script unavailable: {}", e);
+                KWinState::Unavailable {
+                    since: Instant::now(),
+                }
+            }
+        });
+    }
+
+    match guard.as_ref() {
+        Some(KWinState::Connected { latest, .. }) => latest.lock().clone(),
+        _ => None,
+    }
+}
+
+/// Write the script where only this user can, since KWin runs whatever it finds
+/// there: the private runtime dir, else the app data dir. A file or symlink left
+/// at the path is removed rather than followed.
+fn write_script() -> std::io::Result<std::path::PathBuf> {
+    let dir = match dirs::runtime_dir() {
+        Some(dir) => dir,
+        None => crate::db::get_db_path()
+            .parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or_default(),
+    };
+    let path = dir.join(format!("{}.js", SCRIPT_NAME));
+    match std::fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&path)?;
+    std::io::Write::write_all(&mut file, KWIN_SCRIPT.as_bytes())?;
+    Ok(path)
+}
+
+fn connect() -> Result<KWinState, Box<dyn std::error::Error>> {
+    let latest: Latest = Arc::new(Mutex::new(None));
+    let conn = zbus::blocking::connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(
+            OBJECT_PATH,
+            KWinBridge {
+                latest: latest.clone(),
+            },
+        )?
+        .build()?;
+
+    let script_path = write_script()?;
+
+    let scripting = zbus::blocking::Proxy::new(
+        &conn,
+        "org.kde.KWin",
+        "/Scripting",
+        "org.kde.kwin.Scripting",
+    )?;
+    // A copy left over from a previous run would make loadScript fail
+    let _: bool = scripting.call("unloadScript", &(SCRIPT_NAME,)).unwrap_or(false);
+    let id: i32 = scripting.call(
+        "loadScript",
+        &(script_path.to_string_lossy().as_ref(), SCRIPT_NAME),
+    )?;
+    if id < 0 {
+        return Err(format!("KWin refused to load {:?}", script_path).into());
+    }
+    scripting.call::<_, _, ()>("start", &())?;
+
+    Ok(KWinState::Connected {
+        _conn: conn,
+        latest,
+    })
+}
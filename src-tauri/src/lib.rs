@@ -2,27 +2,55 @@
 // TimiGS - Activity Tracker
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 
+mod activitywatch;
+mod api;
+mod archive;
 mod auth;
+mod backup;
+mod billing;
+#[cfg(desktop)]
+mod browser;
+#[cfg(desktop)]
+mod cli;
 mod commands;
 mod db;
+mod migrations;
 mod music;
 
 mod drive;
 mod icons;
+#[cfg(target_os = "linux")]
+mod idle;
 
 #[cfg(desktop)]
 mod tray;
 
 mod focus;
+mod git;
+mod ical;
 mod notifications;
 mod p2p;
 mod picker;
+mod privacy;
+mod rules;
 mod tasks;
 mod timeout;
 mod timer;
+mod vault;
+mod wakatime;
 
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+mod replay;
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+mod terminal;
 #[cfg(any(target_os = "windows", target_os = "linux"))]
 mod tracker;
+#[cfg(target_os = "linux")]
+mod kwin;
+#[cfg(target_os = "linux")]
+mod wlr_toplevel;
+#[cfg(target_os = "linux")]
+mod x11_window;
 
 #[cfg(desktop)]
 use tauri::WindowEvent;
@@ -35,6 +63,17 @@ use windows::core::PCWSTR;
 #[cfg(target_os = "windows")]
 use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONERROR, MB_OK};
 
+/// Run a `timigs <command>` invocation. Returns `None` when the app should start normally.
+pub fn run_cli() -> Option<i32> {
+    #[cfg(desktop)]
+    {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        cli::run(&args)
+    }
+    #[cfg(not(desktop))]
+    None
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // Initialize database
@@ -171,11 +210,25 @@ pub fn run() {
         music::load_music_paths(app.handle());
         music::load_music_settings(app.handle());
 
-        // Start auto-export timer
+        // Local query API (opt-in)
+        let settings = db::get_settings();
+        if settings.api_enabled {
+            if let Err(e) = api::start(settings.api_port) {
+                eprintln!("Failed to start local API: {}", e);
+            }
+        }
+
+        // Start backup, auto-export and retention timer
         std::thread::spawn(move || {
             loop {
                 std::thread::sleep(std::time::Duration::from_secs(3600)); // Check every hour
+                if let Err(e) = backup::snapshot_if_needed() {
+                    eprintln!("{}", e);
+                }
                 let _ = db::auto_export_if_needed();
+                if let Err(e) = db::retention_if_needed() {
+                    eprintln!("Retention failed: {}", e);
+                }
             }
         });
 
@@ -207,6 +260,11 @@ pub fn run() {
             commands::get_today_activity,
             commands::get_today_summary,
             commands::get_summary_by_date_cmd,
+            commands::get_category_stats_cmd,
+            commands::get_productivity_score_cmd,
+            commands::get_categories_cmd,
+            commands::save_category_cmd,
+            commands::delete_category_cmd,
             commands::get_computer_name,
             commands::get_music_today_summary,
             commands::get_total_music_time_today,
@@ -271,6 +329,13 @@ pub fn run() {
             commands::delete_project_board,
             commands::get_board_items_cmd,
             commands::populate_board_cmd,
+            commands::set_board_billing_cmd,
+            commands::get_clients_cmd,
+            commands::create_client_cmd,
+            commands::update_client_cmd,
+            commands::delete_client_cmd,
+            commands::get_timesheet_cmd,
+            commands::export_timesheet_cmd,
             // Project Tasks
             commands::add_project_task_cmd,
             commands::get_project_tasks_cmd,
@@ -278,13 +343,35 @@ pub fn run() {
             commands::delete_project_task_cmd,
             // Data Management
             commands::reset_all_data_cmd,
+            commands::list_backups_cmd,
+            commands::create_backup_cmd,
+            commands::restore_backup_cmd,
+            commands::export_archive_cmd,
+            commands::import_archive_cmd,
             commands::export_data_csv_cmd,
             commands::export_data_html_cmd,
             commands::export_data_json_cmd,
+            commands::export_data_activitywatch_cmd,
+            commands::export_data_ics_cmd,
             commands::import_data_cmd,
             commands::export_data_markdown_cmd,
+            commands::create_manual_entry_cmd,
+            commands::update_session_cmd,
+            commands::split_session_cmd,
+            commands::merge_sessions_cmd,
+            commands::delete_session_cmd,
+            commands::get_session_edits_cmd,
+            commands::revert_session_edit_cmd,
+            commands::get_api_status_cmd,
+            commands::regenerate_api_token_cmd,
+            commands::get_classification_rules_cmd,
+            commands::save_classification_rule_cmd,
+            commands::delete_classification_rule_cmd,
+            commands::export_rules_cmd,
+            commands::import_rules_cmd,
             commands::save_auto_export_settings_cmd,
             commands::get_auto_export_settings_cmd,
+            commands::run_retention_cmd,
             // Music
             commands::get_music_files_cmd,
             commands::add_music_file_cmd,
@@ -307,6 +394,17 @@ pub fn run() {
             commands::get_coding_sessions_range_cmd,
             commands::get_coding_stats_today,
             commands::get_coding_project_stats_today,
+            commands::get_coding_repo_stats_cmd,
+            commands::get_coding_branch_stats_cmd,
+            commands::get_shell_commands_cmd,
+            commands::get_incognito_status_cmd,
+            commands::start_incognito_cmd,
+            commands::stop_incognito_cmd,
+            commands::scrub_titles_cmd,
+            commands::search_history_cmd,
+            commands::get_encryption_status_cmd,
+            commands::encrypt_database_cmd,
+            commands::decrypt_database_cmd,
             commands::get_total_coding_time_today,
             commands::get_total_ai_coding_time_today,
             commands::get_current_coding_session,
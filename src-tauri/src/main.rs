@@ -28,5 +28,9 @@ fn main() {
         }));
     }
 
+    if let Some(code) = timigs_lib::run_cli() {
+        std::process::exit(code);
+    }
+
     timigs_lib::run()
 }
@@ -0,0 +1,850 @@
+//! Versioned schema migrations for the activity database
+//!
+//! The schema version lives in `PRAGMA user_version`. Every migration runs in its
+//! own transaction and bumps the version only when it commits, so a failed upgrade
+//! leaves the database at the last good version instead of half-migrated.
+
+use rusqlite::{params, Connection, Transaction};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+/// Ordered list of migrations. Never edit or reorder a released entry — append a new one.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "baseline_schema",
+        up: m001_baseline_schema,
+    },
+    Migration {
+        version: 2,
+        name: "session_heartbeats",
+        up: m002_session_heartbeats,
+    },
+    Migration {
+        version: 3,
+        name: "classification_rules",
+        up: m003_classification_rules,
+    },
+    Migration {
+        version: 4,
+        name: "categories",
+        up: m004_categories,
+    },
+    Migration {
+        version: 5,
+        name: "session_edits",
+        up: m005_session_edits,
+    },
+    Migration {
+        version: 6,
+        name: "coding_heartbeats",
+        up: m006_coding_heartbeats,
+    },
+    Migration {
+        version: 7,
+        name: "coding_repositories",
+        up: m007_coding_repositories,
+    },
+    Migration {
+        version: 8,
+        name: "terminal_activity",
+        up: m008_terminal_activity,
+    },
+    Migration {
+        version: 9,
+        name: "browser_urls",
+        up: m009_browser_urls,
+    },
+    Migration {
+        version: 10,
+        name: "title_privacy",
+        up: m010_title_privacy,
+    },
+    Migration {
+        version: 11,
+        name: "daily_usage",
+        up: m011_daily_usage,
+    },
+    Migration {
+        version: 12,
+        name: "search_index",
+        up: m012_search_index,
+    },
+    Migration {
+        version: 13,
+        name: "focus_blocks",
+        up: m013_focus_blocks,
+    },
+    Migration {
+        version: 14,
+        name: "billing",
+        up: m014_billing,
+    },
+    Migration {
+        version: 15,
+        name: "dashboard_categories",
+        up: m015_dashboard_categories,
+    },
+];
+
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The database was written by a newer TimiGS release.
+    TooNew { found: i64, supported: i64 },
+    /// Copying the database before upgrading failed, nothing was migrated.
+    Backup { path: PathBuf, source: std::io::Error },
+    /// A migration failed and was rolled back.
+    Failed {
+        version: i64,
+        name: &'static str,
+        source: rusqlite::Error,
+        backup: Option<PathBuf>,
+    },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::TooNew { found, supported } => write!(
+                f,
+                "database schema version {} is newer than this build supports ({}), please update TimiGS",
+                found, supported
+            ),
+            MigrationError::Backup { path, source } => {
+                write!(f, "could not back up database to {:?} before migrating: {}", path, source)
+            }
+            MigrationError::Failed {
+                version,
+                name,
+                source,
+                backup,
+            } => {
+                write!(f, "migration {} ({}) failed: {}", version, name, source)?;
+                if let Some(path) = backup {
+                    write!(f, " (pre-migration backup: {:?})", path)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<MigrationError> for rusqlite::Error {
+    fn from(e: MigrationError) -> Self {
+        rusqlite::Error::ToSqlConversionFailure(Box::new(e))
+    }
+}
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+pub fn current_version(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Bring the database up to `latest_version()`.
+///
+/// When `db_path` is given and the database already holds data, a copy named
+/// `activity.db.v<old version>.bak` is written next to it before anything changes.
+pub fn run_migrations(conn: &mut Connection, db_path: Option<&Path>) -> rusqlite::Result<()> {
+    run_migrations_with(conn, db_path, MIGRATIONS)
+}
+
+fn run_migrations_with(
+    conn: &mut Connection,
+    db_path: Option<&Path>,
+    migrations: &[Migration],
+) -> rusqlite::Result<()> {
+    let current = current_version(conn)?;
+    let latest = migrations.last().map(|m| m.version).unwrap_or(0);
+
+    if current > latest {
+        return Err(MigrationError::TooNew {
+            found: current,
+            supported: latest,
+        }
+        .into());
+    }
+
+    let pending: Vec<&Migration> = migrations.iter().filter(|m| m.version > current).collect();
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let backup = match db_path {
+        Some(path) if has_user_tables(conn)? => Some(backup_database(path, current)?),
+        _ => None,
+    };
+
+    for migration in pending {
+        eprintln!(
+            "Applying database migration {} ({})",
+            migration.version, migration.name
+        );
+
+        let fail = |source: rusqlite::Error| MigrationError::Failed {
+            version: migration.version,
+            name: migration.name,
+            source,
+            backup: backup.clone(),
+        };
+
+        let tx = conn.transaction().map_err(fail)?;
+        (migration.up)(&tx).map_err(fail)?;
+        tx.pragma_update(None, "user_version", migration.version)
+            .map_err(fail)?;
+        tx.commit().map_err(fail)?;
+    }
+
+    Ok(())
+}
+
+fn has_user_tables(conn: &Connection) -> rusqlite::Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+fn backup_database(db_path: &Path, version: i64) -> Result<PathBuf, MigrationError> {
+    let file_name = db_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "activity.db".to_string());
+    let backup_path = db_path.with_file_name(format!("{}.v{}.bak", file_name, version));
+
+    std::fs::copy(db_path, &backup_path).map_err(|source| MigrationError::Backup {
+        path: backup_path.clone(),
+        source,
+    })?;
+
+    eprintln!("Database backed up to {:?}", backup_path);
+    Ok(backup_path)
+}
+
+fn has_column(tx: &Transaction, table: &str, column: &str) -> rusqlite::Result<bool> {
+    let mut stmt = tx.prepare(&format!("PRAGMA table_info({})", table))?;
+    let names = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(names.iter().any(|n| n.eq_ignore_ascii_case(column)))
+}
+
+/// `ALTER TABLE ... ADD COLUMN` that is a no-op when the column already exists,
+/// but still fails loudly on any other error.
+fn add_column_if_missing(
+    tx: &Transaction,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    if !has_column(tx, table, column)? {
+        tx.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+// ── Migrations ──
+
+/// Schema as shipped before versioning existed. Databases from any older release
+/// (user_version 0) are brought to this shape, including the columns that used to
+/// be added with ignored `ALTER TABLE` statements.
+fn m001_baseline_schema(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS activity_sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            app_name TEXT NOT NULL,
+            window_title TEXT NOT NULL,
+            exe_path TEXT NOT NULL,
+            start_time TEXT NOT NULL,
+            end_time TEXT,
+            duration_seconds INTEGER DEFAULT 0
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_start_time ON activity_sessions(start_time)",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS tasks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            app_name TEXT NOT NULL,
+            description TEXT,
+            goal_seconds INTEGER NOT NULL,
+            created_at TEXT NOT NULL,
+            status TEXT DEFAULT 'active',
+            title_filter TEXT
+        )",
+        [],
+    )?;
+    add_column_if_missing(tx, "tasks", "title_filter", "TEXT")?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS project_boards (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            board_type TEXT NOT NULL DEFAULT 'activity',
+            github_project_id TEXT,
+            github_project_url TEXT,
+            synced_at TEXT,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    add_column_if_missing(
+        tx,
+        "project_boards",
+        "board_type",
+        "TEXT NOT NULL DEFAULT 'activity'",
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS board_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            board_id INTEGER NOT NULL,
+            app_name TEXT NOT NULL,
+            window_title TEXT,
+            tracked_seconds INTEGER DEFAULT 0,
+            status TEXT DEFAULT 'recorded',
+            date TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (board_id) REFERENCES project_boards(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_board_items_board_id ON board_items(board_id)",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS project_tasks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            board_id INTEGER NOT NULL,
+            title TEXT NOT NULL,
+            description TEXT,
+            status TEXT NOT NULL DEFAULT 'todo',
+            priority TEXT NOT NULL DEFAULT 'medium',
+            due_date TEXT,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (board_id) REFERENCES project_boards(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS cloud_accounts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            email TEXT NOT NULL,
+            provider TEXT NOT NULL,
+            access_token TEXT NOT NULL,
+            refresh_token TEXT NOT NULL DEFAULT '',
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS music_sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            app_name TEXT NOT NULL,
+            window_title TEXT,
+            exe_path TEXT NOT NULL,
+            start_time TEXT NOT NULL,
+            end_time TEXT,
+            duration_seconds INTEGER DEFAULT 0
+        )",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_music_start_time ON music_sessions(start_time)",
+        [],
+    )?;
+
+    // Coding sessions - track developer activity
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS coding_sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            app_name TEXT NOT NULL,
+            editor_name TEXT NOT NULL,
+            file_path TEXT,
+            language TEXT,
+            project_dir TEXT,
+            is_ai_assisted INTEGER NOT NULL DEFAULT 0,
+            window_title TEXT NOT NULL,
+            exe_path TEXT NOT NULL,
+            start_time TEXT NOT NULL,
+            end_time TEXT,
+            duration_seconds INTEGER DEFAULT 0
+        )",
+        [],
+    )?;
+
+    // Early builds created coding_sessions with fewer columns
+    for (column, definition) in [
+        ("app_name", "TEXT NOT NULL DEFAULT ''"),
+        ("editor_name", "TEXT NOT NULL DEFAULT ''"),
+        ("file_path", "TEXT"),
+        ("language", "TEXT"),
+        ("project_dir", "TEXT"),
+        ("is_ai_assisted", "INTEGER NOT NULL DEFAULT 0"),
+        ("window_title", "TEXT NOT NULL DEFAULT ''"),
+        ("exe_path", "TEXT NOT NULL DEFAULT ''"),
+        ("start_time", "TEXT NOT NULL DEFAULT ''"),
+        ("end_time", "TEXT"),
+        ("duration_seconds", "INTEGER DEFAULT 0"),
+    ] {
+        add_column_if_missing(tx, "coding_sessions", column, definition)?;
+    }
+
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_coding_start_time ON coding_sessions(start_time)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Last time the tracker saw each open session alive, so rows left open by a crash
+/// can be closed there on the next start.
+fn m002_session_heartbeats(tx: &Transaction) -> rusqlite::Result<()> {
+    for table in ["activity_sessions", "music_sessions", "coding_sessions"] {
+        add_column_if_missing(tx, table, "last_heartbeat", "TEXT")?;
+    }
+    Ok(())
+}
+
+/// User-defined rules that classify windows by app name, exe path and/or title.
+fn m003_classification_rules(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS classification_rules (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            priority INTEGER NOT NULL DEFAULT 0,
+            enabled INTEGER NOT NULL DEFAULT 1,
+            app_pattern TEXT,
+            exe_pattern TEXT,
+            title_pattern TEXT,
+            category TEXT,
+            project TEXT,
+            tag TEXT,
+            ignore INTEGER NOT NULL DEFAULT 0,
+            music INTEGER NOT NULL DEFAULT 0,
+            coding INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Categories with a productivity weight from -1.0 (distracting) to 1.0 (productive).
+fn m004_categories(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS categories (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            productivity_weight REAL NOT NULL DEFAULT 0,
+            color TEXT
+        )",
+        [],
+    )?;
+
+    let defaults: [(&str, f64, &str); 7] = [
+        ("Development", 1.0, "#60a5fa"),
+        ("Work", 1.0, "#8b5cf6"),
+        ("Learning", 0.5, "#10b981"),
+        ("Communication", 0.25, "#f59e0b"),
+        ("Music", 0.0, "#f43f5e"),
+        ("Entertainment", -1.0, "#ef4444"),
+        ("Social Media", -1.0, "#ec4899"),
+    ];
+    for (name, weight, color) in defaults {
+        tx.execute(
+            "INSERT OR IGNORE INTO categories (name, productivity_weight, color) VALUES (?1, ?2, ?3)",
+            params![name, weight, color],
+        )?;
+    }
+    Ok(())
+}
+
+/// Manual entries and an audit trail of every edit made to activity sessions.
+/// `before_json`/`after_json` hold the affected rows so an edit can be reverted.
+fn m005_session_edits(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "activity_sessions", "is_manual", "INTEGER NOT NULL DEFAULT 0")?;
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS session_edits (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            action TEXT NOT NULL,
+            before_json TEXT NOT NULL,
+            after_json TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            reverted_at TEXT
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Coding sessions fed by editor plugins (WakaTime heartbeats) next to the
+/// title-parsed ones.
+fn m006_coding_heartbeats(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "coding_sessions", "source", "TEXT NOT NULL DEFAULT 'title'")?;
+    add_column_if_missing(tx, "coding_sessions", "branch", "TEXT")?;
+    Ok(())
+}
+
+fn m007_coding_repositories(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "coding_sessions", "repo_root", "TEXT")?;
+    add_column_if_missing(tx, "coding_sessions", "repo_name", "TEXT")?;
+    add_column_if_missing(tx, "coding_sessions", "repo_remote", "TEXT")?;
+    Ok(())
+}
+
+/// The program running in a terminal window, and shell commands reported by the
+/// shell hook (`timigs shell-hook`).
+fn m008_terminal_activity(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "activity_sessions", "process_name", "TEXT")?;
+    add_column_if_missing(tx, "activity_sessions", "process_cwd", "TEXT")?;
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS shell_commands (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            session TEXT,
+            shell TEXT,
+            command TEXT NOT NULL,
+            cwd TEXT,
+            started_at TEXT NOT NULL,
+            ended_at TEXT,
+            duration_ms INTEGER,
+            exit_code INTEGER
+        )",
+        [],
+    )?;
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_shell_commands_started_at ON shell_commands(started_at)",
+        [],
+    )?;
+    Ok(())
+}
+
+fn m009_browser_urls(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "activity_sessions", "url", "TEXT")?;
+    add_column_if_missing(tx, "activity_sessions", "domain", "TEXT")?;
+    // Latest active tab per browser, as reported by the native-messaging host
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS browser_tabs (
+            browser TEXT PRIMARY KEY,
+            url TEXT NOT NULL,
+            domain TEXT NOT NULL,
+            title TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn m010_title_privacy(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "classification_rules", "privacy", "TEXT")?;
+    add_column_if_missing(tx, "classification_rules", "redact_pattern", "TEXT")?;
+    Ok(())
+}
+
+fn m011_daily_usage(tx: &Transaction) -> rusqlite::Result<()> {
+    // Activity sessions older than the retention window, summed per day and title
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS daily_usage (
+            date TEXT NOT NULL,
+            app_name TEXT NOT NULL,
+            exe_path TEXT NOT NULL,
+            window_title TEXT NOT NULL,
+            domain TEXT,
+            total_seconds INTEGER NOT NULL DEFAULT 0,
+            session_count INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (date, app_name, exe_path, window_title)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// `(fts table, session table, indexed columns)` for `m012_search_index`.
+const SEARCH_TABLES: [(&str, &str, &[&str]); 3] = [
+    ("activity_fts", "activity_sessions", &["window_title", "domain"]),
+    ("coding_fts", "coding_sessions", &["file_path"]),
+    ("music_fts", "music_sessions", &["window_title"]),
+];
+
+fn m012_search_index(tx: &Transaction) -> rusqlite::Result<()> {
+    // External-content FTS5 tables: the text stays in the session tables, triggers
+    // keep the index in step with every insert, edit, scrub and deletion
+    for (fts, table, columns) in SEARCH_TABLES {
+        let list = columns.join(", ");
+        let values = |prefix: &str| {
+            columns
+                .iter()
+                .map(|c| format!("{}.{}", prefix, c))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let insert = format!(
+            "INSERT INTO {fts}(rowid, {list}) VALUES (new.id, {new});",
+            fts = fts,
+            list = list,
+            new = values("new")
+        );
+        let delete = format!(
+            "INSERT INTO {fts}({fts}, rowid, {list}) VALUES ('delete', old.id, {old});",
+            fts = fts,
+            list = list,
+            old = values("old")
+        );
+        tx.execute_batch(&format!(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS {fts} USING fts5(
+                {list}, content = '{table}', content_rowid = 'id',
+                tokenize = 'unicode61 remove_diacritics 2'
+            );
+            CREATE TRIGGER IF NOT EXISTS {fts}_insert AFTER INSERT ON {table} BEGIN {insert} END;
+            CREATE TRIGGER IF NOT EXISTS {fts}_delete AFTER DELETE ON {table} BEGIN {delete} END;
+            CREATE TRIGGER IF NOT EXISTS {fts}_update AFTER UPDATE OF {list} ON {table} BEGIN {delete} {insert} END;
+            INSERT INTO {fts}({fts}) VALUES ('rebuild');",
+            fts = fts,
+            table = table,
+            list = list,
+            insert = insert,
+            delete = delete
+        ))?;
+    }
+    Ok(())
+}
+
+fn m013_focus_blocks(tx: &Transaction) -> rusqlite::Result<()> {
+    // Finished focus sessions and Time OUT breaks, which were only kept in memory
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS focus_blocks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            kind TEXT NOT NULL,
+            label TEXT NOT NULL DEFAULT '',
+            start_time TEXT NOT NULL,
+            end_time TEXT NOT NULL,
+            duration_seconds INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS idx_focus_blocks_start ON focus_blocks(start_time);",
+    )?;
+    Ok(())
+}
+
+fn m014_billing(tx: &Transaction) -> rusqlite::Result<()> {
+    // Clients and rates for timesheets and invoices (see `billing`)
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS clients (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            email TEXT,
+            address TEXT,
+            currency TEXT NOT NULL DEFAULT 'USD',
+            hourly_rate REAL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    add_column_if_missing(tx, "project_boards", "client_id", "INTEGER REFERENCES clients(id)")?;
+    // Overrides the client's rate
+    add_column_if_missing(tx, "project_boards", "hourly_rate", "REAL")?;
+    add_column_if_missing(tx, "project_boards", "rounding_minutes", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(tx, "project_boards", "rounding_mode", "TEXT NOT NULL DEFAULT 'up'")?;
+    Ok(())
+}
+
+/// The categories the dashboard sorts apps into (Work is already there), so they
+/// carry a productivity weight too.
+fn m015_dashboard_categories(tx: &Transaction) -> rusqlite::Result<()> {
+    let defaults: [(&str, f64, &str); 3] = [
+        ("Games", -1.0, "#fbbf24"),
+        ("Rest", -0.5, "#f472b6"),
+        ("Programs", 0.0, "#94a3b8"),
+    ];
+    for (name, weight, color) in defaults {
+        tx.execute(
+            "INSERT OR IGNORE INTO categories (name, productivity_weight, color) VALUES (?1, ?2, ?3)",
+            params![name, weight, color],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Schema written by releases before the coding tracker and board types existed.
+    const LEGACY_FIXTURE: &str = "
+        CREATE TABLE activity_sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            app_name TEXT NOT NULL,
+            window_title TEXT NOT NULL,
+            exe_path TEXT NOT NULL,
+            start_time TEXT NOT NULL,
+            end_time TEXT,
+            duration_seconds INTEGER DEFAULT 0
+        );
+        CREATE TABLE settings (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+        CREATE TABLE tasks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            app_name TEXT NOT NULL,
+            description TEXT,
+            goal_seconds INTEGER NOT NULL,
+            created_at TEXT NOT NULL,
+            status TEXT DEFAULT 'active'
+        );
+        CREATE TABLE project_boards (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            github_project_id TEXT,
+            github_project_url TEXT,
+            synced_at TEXT,
+            created_at TEXT NOT NULL
+        );
+        CREATE TABLE coding_sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            editor_name TEXT NOT NULL,
+            start_time TEXT NOT NULL
+        );
+        INSERT INTO activity_sessions (app_name, window_title, exe_path, start_time, end_time, duration_seconds)
+            VALUES ('Code', 'main.rs - timigs', '/usr/bin/code', '2024-05-01T10:00:00+00:00', '2024-05-01T10:30:00+00:00', 1800);
+        INSERT INTO tasks (app_name, goal_seconds, created_at) VALUES ('Code', 3600, '2024-05-01T09:00:00+00:00');
+        INSERT INTO project_boards (name, created_at) VALUES ('Legacy board', '2024-05-01T09:00:00+00:00');
+        INSERT INTO coding_sessions (editor_name, start_time) VALUES ('VS Code', '2024-05-01T10:00:00+00:00');
+    ";
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table)).unwrap();
+        stmt.query_map([], |row| row.get::<_, String>(1))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn fresh_database_reaches_latest_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn, None).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+
+        // Running again is a no-op
+        run_migrations(&mut conn, None).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn legacy_database_keeps_data_and_gains_columns() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(LEGACY_FIXTURE).unwrap();
+
+        run_migrations(&mut conn, None).unwrap();
+
+        assert!(columns(&conn, "tasks").contains(&"title_filter".to_string()));
+        assert!(columns(&conn, "project_boards").contains(&"board_type".to_string()));
+        let coding_columns = columns(&conn, "coding_sessions");
+        for column in ["app_name", "file_path", "is_ai_assisted", "duration_seconds"] {
+            assert!(coding_columns.contains(&column.to_string()), "missing {}", column);
+        }
+
+        let duration: i64 = conn
+            .query_row("SELECT duration_seconds FROM activity_sessions", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(duration, 1800);
+        let board_type: String = conn
+            .query_row("SELECT board_type FROM project_boards", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(board_type, "activity");
+    }
+
+    #[test]
+    fn failed_migration_rolls_back_and_reports_version() {
+        fn create_widgets(tx: &Transaction) -> rusqlite::Result<()> {
+            tx.execute("CREATE TABLE widgets (id INTEGER PRIMARY KEY)", [])?;
+            Ok(())
+        }
+        fn broken(tx: &Transaction) -> rusqlite::Result<()> {
+            tx.execute("CREATE TABLE gadgets (id INTEGER PRIMARY KEY)", [])?;
+            tx.execute("INSERT INTO no_such_table VALUES (1)", [])?;
+            Ok(())
+        }
+        let migrations = [
+            Migration { version: 1, name: "create_widgets", up: create_widgets },
+            Migration { version: 2, name: "broken", up: broken },
+        ];
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        let err = run_migrations_with(&mut conn, None, &migrations).unwrap_err();
+
+        assert!(err.to_string().contains("migration 2 (broken) failed"), "{}", err);
+        assert_eq!(current_version(&conn).unwrap(), 1);
+        let gadgets: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM sqlite_master WHERE name = 'gadgets'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(gadgets, 0);
+    }
+
+    #[test]
+    fn newer_database_is_rejected() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+        let err = run_migrations(&mut conn, None).unwrap_err();
+        assert!(err.to_string().contains("newer than this build supports"), "{}", err);
+    }
+
+    #[test]
+    fn existing_database_is_backed_up_before_migrating() {
+        let dir = std::env::temp_dir().join(format!("timigs-migrations-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("activity.db");
+        let _ = std::fs::remove_file(&db_path);
+
+        let mut conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(LEGACY_FIXTURE).unwrap();
+        run_migrations(&mut conn, Some(&db_path)).unwrap();
+
+        let backup = dir.join("activity.db.v0.bak");
+        assert!(backup.exists());
+        let legacy = Connection::open(&backup).unwrap();
+        assert_eq!(current_version(&legacy).unwrap(), 0);
+        assert!(!columns(&legacy, "tasks").contains(&"title_filter".to_string()));
+
+        drop(legacy);
+        drop(conn);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,395 @@
+//! Window-title privacy
+//!
+//! Titles are reduced before they are stored, according to a privacy level: the
+//! default from Settings, overridden per app by classification rules (`privacy` and
+//! `redact_pattern`, see `rules`). Private-browsing windows keep only the app name,
+//! and incognito mode pauses recording altogether. `scrub` applies a level to rows
+//! that were recorded before the policy changed.
+
+use crate::db;
+use crate::rules::RuleSet;
+use chrono::{DateTime, Local, TimeZone};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TitlePrivacy {
+    /// Store the title as it is.
+    #[default]
+    Full,
+    /// Store no title, only the app.
+    AppOnly,
+    /// Store a salted hash: windows stay distinguishable, their titles unreadable.
+    Hash,
+    /// Replace the parts matching the redaction pattern with `[redacted]`.
+    Redact,
+}
+
+impl TitlePrivacy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TitlePrivacy::Full => "full",
+            TitlePrivacy::AppOnly => "app_only",
+            TitlePrivacy::Hash => "hash",
+            TitlePrivacy::Redact => "redact",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().replace('-', "_").as_str() {
+            "full" => Some(TitlePrivacy::Full),
+            "app_only" | "app" => Some(TitlePrivacy::AppOnly),
+            "hash" => Some(TitlePrivacy::Hash),
+            "redact" => Some(TitlePrivacy::Redact),
+            _ => None,
+        }
+    }
+
+    /// Whether a URL reported by the browser extension may be kept next to the title.
+    pub fn keeps_url(self) -> bool {
+        matches!(self, TitlePrivacy::Full | TitlePrivacy::Redact)
+    }
+}
+
+const REDACTED: &str = "[redacted]";
+const HASH_PREFIX: &str = "#";
+const SALT_SETTING: &str = "title_hash_salt";
+const INCOGNITO_SETTING: &str = "incognito_until";
+
+/// Used by `redact` when the rule has no pattern of its own: e-mail addresses, long
+/// numbers (phone, card, ticket) and `key=value` secrets.
+static DEFAULT_REDACTION: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)[\w.+-]+@[\w-]+(\.[\w-]+)+|\d[\d -]{4,}\d|\b(token|key|secret|password|passwd|pwd)=\S+",
+    )
+    .unwrap()
+});
+
+/// Title markers of private-browsing windows in the common browsers.
+const PRIVATE_WINDOW_MARKERS: &[&str] = &[
+    "private browsing",
+    "(incognito)",
+    "- incognito",
+    "inprivate",
+    "(private)",
+    "private window",
+];
+
+pub fn is_private_window(app_name: &str, window_title: &str) -> bool {
+    if !db::is_browser(app_name) {
+        return false;
+    }
+    let title = window_title.to_lowercase();
+    PRIVATE_WINDOW_MARKERS.iter().any(|m| title.contains(m))
+}
+
+/// Apply `level` to a title. Hashing and app-only are idempotent, so rows can be
+/// scrubbed more than once.
+pub fn apply(level: TitlePrivacy, title: &str, pattern: Option<&Regex>, salt: &str) -> String {
+    match level {
+        TitlePrivacy::Full => title.to_string(),
+        TitlePrivacy::AppOnly => String::new(),
+        TitlePrivacy::Hash if title.is_empty() || is_hashed(title) => title.to_string(),
+        TitlePrivacy::Hash => {
+            let digest = Sha256::new()
+                .chain_update(salt.as_bytes())
+                .chain_update(title.as_bytes())
+                .finalize();
+            let hex: String = digest.iter().take(8).map(|b| format!("{:02x}", b)).collect();
+            format!("{}{}", HASH_PREFIX, hex)
+        }
+        TitlePrivacy::Redact => pattern
+            .unwrap_or(&DEFAULT_REDACTION)
+            .replace_all(title, REDACTED)
+            .into_owned(),
+    }
+}
+
+fn is_hashed(title: &str) -> bool {
+    title
+        .strip_prefix(HASH_PREFIX)
+        .is_some_and(|h| h.len() == 16 && h.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// The settings that decide how titles are stored.
+pub struct Policy {
+    pub default_level: TitlePrivacy,
+    pub private_windows: bool,
+    salt: String,
+}
+
+impl Policy {
+    pub fn new(default_level: TitlePrivacy, private_windows: bool, salt: String) -> Self {
+        Self {
+            default_level,
+            private_windows,
+            salt,
+        }
+    }
+
+    /// The level for a window and, for `Redact`, the rule's own pattern.
+    pub fn level_for<'a>(
+        &self,
+        rules: &'a RuleSet,
+        app_name: &str,
+        exe_path: &str,
+        window_title: &str,
+    ) -> (TitlePrivacy, Option<&'a Regex>) {
+        if self.private_windows && is_private_window(app_name, window_title) {
+            return (TitlePrivacy::AppOnly, None);
+        }
+        rules
+            .title_privacy(app_name, exe_path, window_title)
+            .unwrap_or((self.default_level, None))
+    }
+
+    pub fn apply(&self, level: TitlePrivacy, title: &str, pattern: Option<&Regex>) -> String {
+        apply(level, title, pattern, &self.salt)
+    }
+}
+
+static POLICY: Lazy<RwLock<Option<Arc<Policy>>>> = Lazy::new(|| RwLock::new(None));
+
+fn salt() -> Result<String, String> {
+    if let Some(salt) = db::get_setting(SALT_SETTING).filter(|s| !s.is_empty()) {
+        return Ok(salt);
+    }
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(|e| e.to_string())?;
+    let salt: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    db::save_setting(SALT_SETTING, &salt).map_err(|e| e.to_string())?;
+    Ok(salt)
+}
+
+/// The current policy, loaded from the database on first use.
+/// Takes the DB lock when the cache is cold, so never call it while holding that lock.
+pub fn policy() -> Arc<Policy> {
+    if let Some(policy) = POLICY.read().as_ref() {
+        return policy.clone();
+    }
+    let settings = db::get_settings();
+    match salt() {
+        Ok(salt) => {
+            let policy = Arc::new(Policy::new(
+                settings.title_privacy,
+                settings.redact_private_windows,
+                salt,
+            ));
+            *POLICY.write() = Some(policy.clone());
+            policy
+        }
+        // Database not ready yet: store nothing readable rather than everything
+        Err(_) => Arc::new(Policy::new(TitlePrivacy::AppOnly, true, String::new())),
+    }
+}
+
+/// Drop the cached policy after the settings changed.
+pub fn invalidate() {
+    *POLICY.write() = None;
+}
+
+/// The privacy decision for one window, applied to its title and anything derived
+/// from it (music tracks, coding sessions).
+pub struct WindowPrivacy {
+    pub level: TitlePrivacy,
+    pattern: Option<Regex>,
+    policy: Arc<Policy>,
+}
+
+impl WindowPrivacy {
+    pub fn title(&self, title: &str) -> String {
+        self.policy.apply(self.level, title, self.pattern.as_ref())
+    }
+
+    pub fn keeps_url(&self) -> bool {
+        self.level.keeps_url()
+    }
+}
+
+pub fn for_window(app_name: &str, exe_path: &str, window_title: &str) -> WindowPrivacy {
+    window_privacy(&crate::rules::ruleset(), policy(), app_name, exe_path, window_title)
+}
+
+/// `for_window` with the given rules and policy instead of the cached ones.
+pub fn window_privacy(
+    rules: &RuleSet,
+    policy: Arc<Policy>,
+    app_name: &str,
+    exe_path: &str,
+    window_title: &str,
+) -> WindowPrivacy {
+    let (level, pattern) = policy.level_for(rules, app_name, exe_path, window_title);
+    let pattern = pattern.cloned();
+    WindowPrivacy {
+        level,
+        pattern,
+        policy,
+    }
+}
+
+// ── Incognito ──
+
+/// Unix time at which incognito mode ends: 0 when off, `i64::MAX` until turned off,
+/// -1 until read from the database.
+static INCOGNITO_UNTIL: AtomicI64 = AtomicI64::new(-1);
+const INCOGNITO_FOREVER: i64 = i64::MAX;
+
+fn load_incognito_until() -> i64 {
+    match db::get_setting(INCOGNITO_SETTING).as_deref() {
+        Some("forever") => INCOGNITO_FOREVER,
+        Some(s) => DateTime::parse_from_rfc3339(s).map(|t| t.timestamp()).unwrap_or(0),
+        None => 0,
+    }
+}
+
+fn incognito_until_raw() -> i64 {
+    let until = INCOGNITO_UNTIL.load(Ordering::SeqCst);
+    if until >= 0 {
+        return until;
+    }
+    let until = load_incognito_until();
+    INCOGNITO_UNTIL.store(until, Ordering::SeqCst);
+    until
+}
+
+/// Re-read the incognito state, for processes other than the app (the native host)
+/// that must notice it being switched.
+pub fn reload_incognito() {
+    INCOGNITO_UNTIL.store(load_incognito_until(), Ordering::SeqCst);
+}
+
+/// Pause all recording, until `until` or until turned off (`None`).
+pub fn start_incognito(until: Option<DateTime<Local>>) -> Result<(), String> {
+    let (raw, stored) = match until {
+        Some(t) => (t.timestamp(), t.to_rfc3339()),
+        None => (INCOGNITO_FOREVER, "forever".to_string()),
+    };
+    db::save_setting(INCOGNITO_SETTING, &stored).map_err(|e| e.to_string())?;
+    INCOGNITO_UNTIL.store(raw, Ordering::SeqCst);
+    Ok(())
+}
+
+pub fn stop_incognito() -> Result<(), String> {
+    db::save_setting(INCOGNITO_SETTING, "").map_err(|e| e.to_string())?;
+    INCOGNITO_UNTIL.store(0, Ordering::SeqCst);
+    Ok(())
+}
+
+pub fn is_incognito() -> bool {
+    incognito_until_raw() > Local::now().timestamp()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IncognitoStatus {
+    pub active: bool,
+    /// End of a timed incognito period.
+    pub until: Option<String>,
+}
+
+pub fn incognito_status() -> IncognitoStatus {
+    let until = incognito_until_raw();
+    IncognitoStatus {
+        active: until > Local::now().timestamp(),
+        until: (until > 0 && until != INCOGNITO_FOREVER)
+            .then(|| Local.timestamp_opt(until, 0).single())
+            .flatten()
+            .map(|t| t.to_rfc3339()),
+    }
+}
+
+// ── Retroactive scrub ──
+
+/// What to scrub. Without a level, the current policy (settings and rules) is
+/// applied; with one, it replaces the policy for the selected rows.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ScrubRequest {
+    #[serde(default)]
+    pub level: Option<TitlePrivacy>,
+    /// Redaction pattern for `level: redact`; the built-in one when missing.
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// Only rows of this app (case-insensitive).
+    #[serde(default)]
+    pub app_name: Option<String>,
+    /// `YYYY-MM-DD`, inclusive.
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub to: Option<String>,
+    /// Count what would change without writing anything.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Rewrite stored titles under the request's policy. Browser URLs are dropped with
+/// titles that may not keep them; the session edit history is rewritten too.
+pub fn scrub(request: &ScrubRequest) -> Result<db::ScrubReport, String> {
+    let pattern = match request.pattern.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(p) => Some(
+            regex::RegexBuilder::new(p)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| format!("invalid redaction pattern: {}", e))?,
+        ),
+    };
+    // Both load from the database, so before `scrub_titles` takes the lock
+    let rules = crate::rules::ruleset();
+    let policy = policy();
+
+    let decide = |app_name: &str, exe_path: &str, title: &str| {
+        let (level, rule_pattern) = match request.level {
+            Some(level) => (level, pattern.as_ref()),
+            None => policy.level_for(&rules, app_name, exe_path, title),
+        };
+        (policy.apply(level, title, rule_pattern), level.keeps_url())
+    };
+    let filter = db::TitleFilter {
+        app_name: request.app_name.as_deref(),
+        from: request.from.as_deref(),
+        to: request.to.as_deref(),
+    };
+    db::scrub_titles(&filter, request.dry_run, &decide).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_levels() {
+        let title = "Invoice 4411 2233 for jane@example.com - Mozilla Thunderbird";
+        assert_eq!(apply(TitlePrivacy::Full, title, None, "s"), title);
+        assert_eq!(apply(TitlePrivacy::AppOnly, title, None, "s"), "");
+        assert_eq!(
+            apply(TitlePrivacy::Redact, title, None, "s"),
+            "Invoice [redacted] for [redacted] - Mozilla Thunderbird"
+        );
+        let acme = Regex::new("(?i)acme corp").unwrap();
+        assert_eq!(
+            apply(TitlePrivacy::Redact, "ACME Corp roadmap", Some(&acme), "s"),
+            "[redacted] roadmap"
+        );
+
+        let hashed = apply(TitlePrivacy::Hash, title, None, "s");
+        assert!(is_hashed(&hashed));
+        assert_eq!(apply(TitlePrivacy::Hash, &hashed, None, "s"), hashed);
+        assert_eq!(apply(TitlePrivacy::Hash, title, None, "s"), hashed);
+        assert_ne!(apply(TitlePrivacy::Hash, title, None, "other salt"), hashed);
+    }
+
+    #[test]
+    fn test_private_windows() {
+        assert!(is_private_window("firefox", "GitHub — Mozilla Firefox Private Browsing"));
+        assert!(is_private_window("msedge", "[InPrivate] New tab - Microsoft Edge"));
+        assert!(is_private_window("Google-chrome", "New Tab - Google Chrome (Incognito)"));
+        assert!(!is_private_window("firefox", "Privacy policy — Mozilla Firefox"));
+        assert!(!is_private_window("code", "private window.rs - Visual Studio Code"));
+    }
+}
@@ -0,0 +1,269 @@
+//! Scripted window source for the tracker.
+//!
+//! Plays back a recorded timeline instead of asking the OS for the foreground
+//! window, so the tracking loop (session splitting, idle handling, music and
+//! coding detection) can be exercised without a desktop session.
+//!
+//! A timeline is either a JSON array:
+//!
+//! ```json
+//! [
+//!   { "app_name": "Code", "window_title": "main.rs - timigs - Visual Studio Code",
+//!     "exe_path": "/usr/bin/code", "duration_secs": 120 },
+//!   { "idle_secs": 600, "duration_secs": 30 },
+//!   { "duration_secs": 5 }
+//! ]
+//! ```
+//!
+//! or a CSV file with the header `duration_secs,idle_secs,app_name,window_title,exe_path`.
+//! An event without an app name means "no trackable window" (locked screen, desktop).
+//! Every tick moves the source's clock on by one second, so a replayed timeline
+//! records the same session times on every run.
+
+use crate::tracker::{ActiveWindow, WindowSource};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+fn default_duration() -> u64 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReplayEvent {
+    #[serde(default)]
+    pub app_name: Option<String>,
+    #[serde(default)]
+    pub window_title: Option<String>,
+    #[serde(default)]
+    pub exe_path: Option<String>,
+    #[serde(default)]
+    pub idle_secs: u64,
+    /// Number of tracker ticks (seconds) this event stays current.
+    #[serde(default = "default_duration")]
+    pub duration_secs: u64,
+}
+
+impl ReplayEvent {
+    fn window(&self) -> Option<ActiveWindow> {
+        let app_name = self.app_name.as_deref().filter(|s| !s.is_empty())?;
+        Some(ActiveWindow {
+            app_name: app_name.to_string(),
+            window_title: self.window_title.clone().unwrap_or_default(),
+            exe_path: self
+                .exe_path
+                .clone()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| app_name.to_string()),
+            pid: None,
+        })
+    }
+}
+
+pub struct ScriptedSource {
+    events: Vec<ReplayEvent>,
+    index: usize,
+    /// Ticks already spent on `events[index]`.
+    elapsed: u64,
+    started: bool,
+    /// Time of the first tick; every tick after it is one second later.
+    start_time: DateTime<Local>,
+    ticks: u64,
+}
+
+impl ScriptedSource {
+    pub fn new(events: Vec<ReplayEvent>) -> Self {
+        Self {
+            events,
+            index: 0,
+            elapsed: 0,
+            started: false,
+            start_time: Local::now(),
+            ticks: 0,
+        }
+    }
+
+    /// Play the timeline as if its first tick happened at `start_time`.
+    pub fn starting_at(mut self, start_time: DateTime<Local>) -> Self {
+        self.start_time = start_time;
+        self
+    }
+
+    /// Load a timeline, picking the format from the file extension (`.csv`, otherwise JSON).
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let is_csv = path
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("csv"))
+            .unwrap_or(false);
+
+        let events = if is_csv {
+            parse_csv(&content)?
+        } else {
+            parse_json(&content)?
+        };
+        Ok(Self::new(events))
+    }
+
+    fn current(&self) -> Option<&ReplayEvent> {
+        self.events.get(self.index)
+    }
+}
+
+impl WindowSource for ScriptedSource {
+    fn active_window(&mut self) -> Option<ActiveWindow> {
+        self.current().and_then(ReplayEvent::window)
+    }
+
+    fn idle_time(&mut self) -> Duration {
+        Duration::from_secs(self.current().map(|e| e.idle_secs).unwrap_or(0))
+    }
+
+    fn advance(&mut self) {
+        // The first tick plays the first event; every later tick moves the clock on.
+        if !self.started {
+            self.started = true;
+            return;
+        }
+        if self.index >= self.events.len() {
+            return;
+        }
+        self.ticks += 1;
+        self.elapsed += 1;
+        if self.elapsed >= self.events[self.index].duration_secs.max(1) {
+            self.index += 1;
+            self.elapsed = 0;
+        }
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.index >= self.events.len()
+    }
+
+    fn now(&self) -> DateTime<Local> {
+        self.start_time + chrono::Duration::seconds(self.ticks as i64)
+    }
+}
+
+pub fn parse_json(content: &str) -> Result<Vec<ReplayEvent>, String> {
+    serde_json::from_str(content).map_err(|e| e.to_string())
+}
+
+pub fn parse_csv(content: &str) -> Result<Vec<ReplayEvent>, String> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(content.as_bytes());
+
+    let headers = rdr.headers().map_err(|e| e.to_string())?.clone();
+    let col = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let duration_col = col("duration_secs");
+    let idle_col = col("idle_secs");
+    let app_col = col("app_name");
+    let title_col = col("window_title");
+    let exe_col = col("exe_path");
+
+    let mut events = Vec::new();
+    for (line, record) in rdr.records().enumerate() {
+        let record = record.map_err(|e| e.to_string())?;
+        let text = |idx: Option<usize>| {
+            idx.and_then(|i| record.get(i))
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+        };
+        let number = |idx: Option<usize>, default: u64| -> Result<u64, String> {
+            match text(idx) {
+                Some(s) => s
+                    .parse()
+                    .map_err(|_| format!("row {}: invalid number '{}'", line + 2, s)),
+                None => Ok(default),
+            }
+        };
+
+        events.push(ReplayEvent {
+            app_name: text(app_col),
+            window_title: text(title_col),
+            exe_path: text(exe_col),
+            idle_secs: number(idle_col, 0)?,
+            duration_secs: number(duration_col, default_duration())?,
+        });
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(source: &mut ScriptedSource) -> Vec<(Option<String>, u64)> {
+        let mut ticks = Vec::new();
+        loop {
+            source.advance();
+            if source.is_exhausted() {
+                break;
+            }
+            let app = source.active_window().map(|w| w.app_name);
+            ticks.push((app, source.idle_time().as_secs()));
+        }
+        ticks
+    }
+
+    #[test]
+    fn test_parse_json_defaults() {
+        let events = parse_json(
+            r#"[{"app_name":"Code","window_title":"main.rs"},{"idle_secs":400,"duration_secs":3}]"#,
+        )
+        .unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].duration_secs, 1);
+        assert_eq!(events[0].idle_secs, 0);
+        assert_eq!(events[1].app_name, None);
+        assert_eq!(events[1].idle_secs, 400);
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let events = parse_csv(
+            "duration_secs,idle_secs,app_name,window_title,exe_path\n\
+             2,0,Firefox,\"GitHub, Inc. - Mozilla Firefox\",/usr/bin/firefox\n\
+             1,,,,\n",
+        )
+        .unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].window_title.as_deref(), Some("GitHub, Inc. - Mozilla Firefox"));
+        assert_eq!(events[0].duration_secs, 2);
+        assert!(events[1].window().is_none());
+
+        assert!(parse_csv("duration_secs,app_name\nabc,Code\n").is_err());
+    }
+
+    #[test]
+    fn test_playback_follows_durations() {
+        let mut source = ScriptedSource::new(
+            parse_json(
+                r#"[{"app_name":"Code","duration_secs":2},{"idle_secs":600},{"app_name":"Slack","exe_path":"/usr/bin/slack"}]"#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            drain(&mut source),
+            vec![
+                (Some("Code".to_string()), 0),
+                (Some("Code".to_string()), 0),
+                (None, 600),
+                (Some("Slack".to_string()), 0),
+            ]
+        );
+        assert!(source.is_exhausted());
+    }
+
+    #[test]
+    fn test_empty_timeline_is_exhausted() {
+        let mut source = ScriptedSource::new(Vec::new());
+        source.advance();
+        assert!(source.is_exhausted());
+        assert!(source.active_window().is_none());
+    }
+}
@@ -0,0 +1,381 @@
+//! User-defined classification rules
+//!
+//! Each rule matches on app name, exe path and/or window title (case-insensitive,
+//! unanchored regexes; all given patterns must match) and assigns a category,
+//! project, tag, title privacy level, or the ignore / music / coding flags. Rules are
+//! evaluated from the highest priority down: the first matching rule that sets a
+//! category, project or privacy level wins, tags and flags accumulate over every
+//! matching rule.
+//!
+//! Rules live in the `classification_rules` table and are shared as JSON files
+//! (`{"format": "timigs-rules", "version": 1, "rules": [...]}`).
+
+use crate::db::{self, ClassificationRule};
+use crate::privacy::TitlePrivacy;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+const RULES_FORMAT: &str = "timigs-rules";
+const RULES_VERSION: u32 = 1;
+
+/// What the rules say about a window.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Classification {
+    pub category: Option<String>,
+    pub project: Option<String>,
+    pub tags: Vec<String>,
+    pub ignore: bool,
+    pub music: bool,
+    pub coding: bool,
+}
+
+struct CompiledRule {
+    app: Option<Regex>,
+    exe: Option<Regex>,
+    title: Option<Regex>,
+    redact: Option<Regex>,
+    rule: ClassificationRule,
+}
+
+fn compile_pattern(field: &str, pattern: &Option<String>) -> Result<Option<Regex>, String> {
+    match pattern.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(p) => RegexBuilder::new(p)
+            .case_insensitive(true)
+            .build()
+            .map(Some)
+            .map_err(|e| format!("invalid {} pattern: {}", field, e)),
+    }
+}
+
+impl CompiledRule {
+    fn compile(rule: ClassificationRule) -> Result<Self, String> {
+        let app = compile_pattern("app", &rule.app_pattern)?;
+        let exe = compile_pattern("exe", &rule.exe_pattern)?;
+        let title = compile_pattern("title", &rule.title_pattern)?;
+        let redact = compile_pattern("redact", &rule.redact_pattern)?;
+        if app.is_none() && exe.is_none() && title.is_none() {
+            return Err("a rule needs at least one of app, exe or title pattern".to_string());
+        }
+        Ok(Self {
+            app,
+            exe,
+            title,
+            redact,
+            rule,
+        })
+    }
+
+    fn matches(&self, app_name: &str, exe_path: &str, window_title: &str) -> bool {
+        self.app.as_ref().is_none_or(|r| r.is_match(app_name))
+            && self.exe.as_ref().is_none_or(|r| r.is_match(exe_path))
+            && self.title.as_ref().is_none_or(|r| r.is_match(window_title))
+    }
+}
+
+pub struct RuleSet {
+    rules: Vec<CompiledRule>,
+}
+
+impl RuleSet {
+    /// Compile the enabled rules, ordered by priority. Broken rules are skipped.
+    pub fn new(mut rules: Vec<ClassificationRule>) -> Self {
+        rules.sort_by(|a, b| b.priority.cmp(&a.priority).then(a.id.cmp(&b.id)));
+        let rules = rules
+            .into_iter()
+            .filter(|r| r.enabled)
+            .filter_map(|r| {
+                let name = r.name.clone();
+                CompiledRule::compile(r)
+                    .map_err(|e| eprintln!("Skipping classification rule '{}': {}", name, e))
+                    .ok()
+            })
+            .collect();
+        Self { rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn classify(&self, app_name: &str, exe_path: &str, window_title: &str) -> Classification {
+        let mut result = Classification::default();
+
+        for compiled in &self.rules {
+            if !compiled.matches(app_name, exe_path, window_title) {
+                continue;
+            }
+            let rule = &compiled.rule;
+            if result.category.is_none() {
+                result.category = rule.category.clone().filter(|c| !c.is_empty());
+            }
+            if result.project.is_none() {
+                result.project = rule.project.clone().filter(|p| !p.is_empty());
+            }
+            if let Some(tag) = rule.tag.as_ref().filter(|t| !t.is_empty()) {
+                if !result.tags.contains(tag) {
+                    result.tags.push(tag.clone());
+                }
+            }
+            result.ignore |= rule.ignore;
+            result.music |= rule.music;
+            result.coding |= rule.coding;
+        }
+
+        result
+    }
+
+    /// Privacy level of the first matching rule that sets one, with its redaction
+    /// pattern. Titles are matched before they are reduced.
+    pub fn title_privacy(
+        &self,
+        app_name: &str,
+        exe_path: &str,
+        window_title: &str,
+    ) -> Option<(TitlePrivacy, Option<&Regex>)> {
+        self.rules.iter().find_map(|compiled| {
+            let privacy = compiled.rule.privacy?;
+            compiled
+                .matches(app_name, exe_path, window_title)
+                .then_some((privacy, compiled.redact.as_ref()))
+        })
+    }
+}
+
+static RULESET: Lazy<RwLock<Option<Arc<RuleSet>>>> = Lazy::new(|| RwLock::new(None));
+
+/// The current rules, loaded from the database on first use.
+/// Takes the DB lock when the cache is cold, so never call it while holding that lock.
+pub fn ruleset() -> Arc<RuleSet> {
+    if let Some(set) = RULESET.read().as_ref() {
+        return set.clone();
+    }
+
+    match db::get_classification_rules() {
+        Ok(rules) => {
+            let set = Arc::new(RuleSet::new(rules));
+            *RULESET.write() = Some(set.clone());
+            set
+        }
+        // Database not ready yet: don't cache, try again next time
+        Err(_) => Arc::new(RuleSet::new(Vec::new())),
+    }
+}
+
+/// Drop the cached rules after they changed in the database.
+pub fn invalidate() {
+    *RULESET.write() = None;
+}
+
+pub fn classify(app_name: &str, exe_path: &str, window_title: &str) -> Classification {
+    ruleset().classify(app_name, exe_path, window_title)
+}
+
+/// Check that a rule compiles before it is stored.
+pub fn validate(rule: &ClassificationRule) -> Result<(), String> {
+    if rule.name.trim().is_empty() {
+        return Err("rule name is empty".to_string());
+    }
+    CompiledRule::compile(rule.clone()).map(|_| ())
+}
+
+#[derive(Serialize, Deserialize)]
+struct RulesFile {
+    format: String,
+    version: u32,
+    rules: Vec<ClassificationRule>,
+}
+
+fn parse_rules_file(content: &str) -> Result<Vec<ClassificationRule>, String> {
+    let value: serde_json::Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+
+    // A bare array of rules is accepted too
+    let rules: Vec<ClassificationRule> = if value.is_array() {
+        serde_json::from_value(value).map_err(|e| e.to_string())?
+    } else {
+        let file: RulesFile = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        if file.format != RULES_FORMAT {
+            return Err(format!("not a TimiGS rules file (format '{}')", file.format));
+        }
+        if file.version > RULES_VERSION {
+            return Err(format!(
+                "rules file version {} is newer than supported ({})",
+                file.version, RULES_VERSION
+            ));
+        }
+        file.rules
+    };
+
+    for rule in &rules {
+        validate(rule).map_err(|e| format!("rule '{}': {}", rule.name, e))?;
+    }
+    Ok(rules)
+}
+
+pub fn export_rules(path: &str) -> Result<usize, String> {
+    let rules = db::get_classification_rules().map_err(|e| e.to_string())?;
+    let count = rules.len();
+    let file = RulesFile {
+        format: RULES_FORMAT.to_string(),
+        version: RULES_VERSION,
+        rules: rules
+            .into_iter()
+            .map(|r| ClassificationRule { id: 0, ..r })
+            .collect(),
+    };
+    let json = serde_json::to_string_pretty(&file).map_err(|e| e.to_string())?;
+    std::fs::write(path, json).map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+/// Import a rules file. With `replace`, the existing rules are dropped first;
+/// otherwise the file's rules are added next to them.
+pub fn import_rules(path: &str, replace: bool) -> Result<usize, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let rules = parse_rules_file(&content)?;
+    let count = db::import_classification_rules(&rules, replace).map_err(|e| e.to_string())?;
+    invalidate();
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str, priority: i64) -> ClassificationRule {
+        ClassificationRule {
+            id: 0,
+            name: name.to_string(),
+            priority,
+            enabled: true,
+            app_pattern: None,
+            exe_pattern: None,
+            title_pattern: None,
+            category: None,
+            project: None,
+            tag: None,
+            ignore: false,
+            music: false,
+            coding: false,
+            privacy: None,
+            redact_pattern: None,
+        }
+    }
+
+    #[test]
+    fn test_priority_and_accumulation() {
+        let set = RuleSet::new(vec![
+            ClassificationRule {
+                app_pattern: Some("firefox".into()),
+                category: Some("Browsing".into()),
+                tag: Some("web".into()),
+                ..rule("browsers", 0)
+            },
+            ClassificationRule {
+                title_pattern: Some(r"\bacme\b".into()),
+                category: Some("Work".into()),
+                project: Some("Acme".into()),
+                tag: Some("client".into()),
+                ..rule("acme", 10)
+            },
+        ]);
+
+        let c = set.classify("Firefox", "/usr/bin/firefox", "ACME dashboard - Mozilla Firefox");
+        assert_eq!(c.category.as_deref(), Some("Work"));
+        assert_eq!(c.project.as_deref(), Some("Acme"));
+        assert_eq!(c.tags, vec!["client".to_string(), "web".to_string()]);
+
+        let c = set.classify("Firefox", "/usr/bin/firefox", "News");
+        assert_eq!(c.category.as_deref(), Some("Browsing"));
+        assert_eq!(c.project, None);
+    }
+
+    #[test]
+    fn test_title_privacy() {
+        let set = RuleSet::new(vec![
+            ClassificationRule {
+                app_pattern: Some("thunderbird".into()),
+                privacy: Some(TitlePrivacy::Redact),
+                redact_pattern: Some(r"\bfrom .*".into()),
+                ..rule("mail", 0)
+            },
+            ClassificationRule {
+                app_pattern: Some("thunderbird".into()),
+                title_pattern: Some("^Inbox".into()),
+                privacy: Some(TitlePrivacy::AppOnly),
+                ..rule("inbox", 10)
+            },
+            ClassificationRule {
+                app_pattern: Some("thunderbird".into()),
+                category: Some("Communication".into()),
+                ..rule("no privacy", 20)
+            },
+        ]);
+
+        let (level, pattern) = set.title_privacy("Thunderbird", "", "Inbox - Thunderbird").unwrap();
+        assert_eq!((level, pattern.is_none()), (TitlePrivacy::AppOnly, true));
+        let (level, pattern) = set.title_privacy("Thunderbird", "", "Re: lunch from Bob").unwrap();
+        assert_eq!(level, TitlePrivacy::Redact);
+        assert_eq!(pattern.unwrap().replace("Re: lunch from Bob", ""), "Re: lunch ");
+        assert!(set.title_privacy("Firefox", "", "Inbox").is_none());
+    }
+
+    #[test]
+    fn test_all_patterns_must_match() {
+        let set = RuleSet::new(vec![ClassificationRule {
+            app_pattern: Some("^code$".into()),
+            exe_pattern: Some("insiders".into()),
+            coding: true,
+            ..rule("insiders", 0)
+        }]);
+        assert!(set.classify("Code", "/opt/code-insiders/code", "").coding);
+        assert!(!set.classify("Code", "/usr/bin/code", "").coding);
+    }
+
+    #[test]
+    fn test_disabled_and_invalid_rules_are_skipped() {
+        let set = RuleSet::new(vec![
+            ClassificationRule {
+                app_pattern: Some("slack".into()),
+                ignore: true,
+                enabled: false,
+                ..rule("disabled", 0)
+            },
+            ClassificationRule {
+                app_pattern: Some("(unclosed".into()),
+                ignore: true,
+                ..rule("broken", 0)
+            },
+            rule("no patterns", 0),
+        ]);
+        assert!(set.is_empty());
+        assert!(!set.classify("Slack", "", "").ignore);
+    }
+
+    #[test]
+    fn test_rules_file_round_trip() {
+        let file = RulesFile {
+            format: RULES_FORMAT.to_string(),
+            version: RULES_VERSION,
+            rules: vec![ClassificationRule {
+                app_pattern: Some("spotify".into()),
+                music: true,
+                ..rule("spotify", 0)
+            }],
+        };
+        let json = serde_json::to_string(&file).unwrap();
+        let rules = parse_rules_file(&json).unwrap();
+        assert_eq!(rules.len(), 1);
+        assert!(rules[0].music);
+
+        // Minimal hand-written rule in a bare array
+        let rules = parse_rules_file(r#"[{"name":"vim","app_pattern":"n?vim","coding":true}]"#).unwrap();
+        assert!(rules[0].enabled && rules[0].coding);
+
+        assert!(parse_rules_file(r#"{"format":"other","version":1,"rules":[]}"#).is_err());
+        assert!(parse_rules_file(r#"[{"name":"bad","title_pattern":"("}]"#).is_err());
+    }
+}
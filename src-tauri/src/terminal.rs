@@ -0,0 +1,337 @@
+//! Terminal-aware tracking
+//!
+//! When the foreground window is a terminal emulator, its title says little about
+//! what is going on. On Linux the terminal's shells are found among its child
+//! processes and the foreground process group of their PTY (`tpgid` in
+//! `/proc/<pid>/stat`) tells which program is running, with its working directory.
+//! With several tabs, the PTY with the most recent input/output wins.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForegroundProcess {
+    pub pid: u32,
+    /// Program name (`/proc/<pid>/comm`), e.g. `cargo` or `nvim`.
+    pub name: String,
+    pub exe: String,
+    pub cwd: Option<String>,
+    /// Command line arguments, without the program itself.
+    pub args: Vec<String>,
+}
+
+/// Whole app ids and executable names; a prefix would also catch unrelated apps
+/// (`rio` in Element's `im.riot.Riot`).
+const TERMINALS: &[&str] = &[
+    "gnome-terminal",
+    "gnome-terminal-server",
+    "kgx",
+    "gnome-console",
+    "konsole",
+    "alacritty",
+    "kitty",
+    "wezterm",
+    "wezterm-gui",
+    "foot",
+    "footclient",
+    "xterm",
+    "uxterm",
+    "urxvt",
+    "rxvt",
+    "tilix",
+    "terminator",
+    "xfce4-terminal",
+    "mate-terminal",
+    "lxterminal",
+    "qterminal",
+    "terminology",
+    "ghostty",
+    "st-256color",
+    "sakura",
+    "guake",
+    "yakuake",
+    "tilda",
+    "rio",
+    "warp",
+    "warp-terminal",
+    "blackbox",
+    "blackbox-terminal",
+    "ptyxis",
+    "cool-retro-term",
+];
+
+const SHELLS: &[&str] = &[
+    "bash", "zsh", "fish", "sh", "dash", "ksh", "mksh", "tcsh", "csh", "nu", "xonsh", "elvish",
+    "pwsh", "ion", "osh", "login", "sudo", "su",
+];
+
+/// Editors that run inside a terminal, by program name.
+const TERMINAL_EDITORS: &[(&str, &str)] = &[
+    ("nvim", "Neovim"),
+    ("vim", "Vim"),
+    ("vi", "Vim"),
+    ("hx", "Helix"),
+    ("helix", "Helix"),
+    ("emacs", "Emacs"),
+    ("nano", "Nano"),
+    ("micro", "Micro"),
+    ("kak", "Kakoune"),
+    ("amp", "Amp"),
+];
+
+/// Build tools and compilers worth a coding session, with the language they imply.
+const BUILD_TOOLS: &[(&str, Option<&str>)] = &[
+    ("cargo", Some("Rust")),
+    ("rustc", Some("Rust")),
+    ("go", Some("Go")),
+    ("make", None),
+    ("cmake", None),
+    ("ninja", None),
+    ("meson", None),
+    ("bazel", None),
+    ("just", None),
+    ("gcc", Some("C")),
+    ("g++", Some("C++")),
+    ("clang", Some("C")),
+    ("clang++", Some("C++")),
+    ("zig", Some("Zig")),
+    ("npm", Some("JavaScript")),
+    ("npx", Some("JavaScript")),
+    ("pnpm", Some("JavaScript")),
+    ("yarn", Some("JavaScript")),
+    ("bun", Some("JavaScript")),
+    ("deno", Some("TypeScript")),
+    ("tsc", Some("TypeScript")),
+    ("gradle", Some("Java")),
+    ("gradlew", Some("Java")),
+    ("mvn", Some("Java")),
+    ("dotnet", Some("C#")),
+    ("mix", Some("Elixir")),
+    ("swift", Some("Swift")),
+    ("pytest", Some("Python")),
+    ("tox", Some("Python")),
+];
+
+pub fn is_terminal(app_name: &str, exe_path: &str) -> bool {
+    // App ids may be reverse-DNS ("org.wezfurlong.wezterm")
+    let app = app_name.to_lowercase();
+    let app = app.rsplit('.').next().unwrap_or_default();
+    let exe = std::path::Path::new(exe_path)
+        .file_name()
+        .map(|f| f.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    TERMINALS.iter().any(|t| app == *t || exe == *t)
+}
+
+impl ForegroundProcess {
+    pub fn is_shell(&self) -> bool {
+        let name = self.name.trim_start_matches('-');
+        SHELLS.contains(&name)
+    }
+
+    /// Editor name when this is a terminal editor.
+    pub fn editor(&self) -> Option<&'static str> {
+        TERMINAL_EDITORS
+            .iter()
+            .find(|(name, _)| *name == self.name)
+            .map(|(_, editor)| *editor)
+    }
+
+    /// `Some(language)` when this is a build tool; the language may be unknown.
+    pub fn build_tool_language(&self) -> Option<Option<&'static str>> {
+        BUILD_TOOLS
+            .iter()
+            .find(|(name, _)| *name == self.name)
+            .map(|(_, language)| *language)
+    }
+
+    /// The file a terminal editor was opened with: its first argument that is not an
+    /// option, made absolute against the working directory.
+    pub fn opened_file(&self) -> Option<String> {
+        self.editor()?;
+        let arg = self
+            .args
+            .iter()
+            .find(|a| !a.is_empty() && !a.starts_with('-') && !a.starts_with('+'))?;
+        let path = std::path::Path::new(arg);
+        if path.is_absolute() {
+            return Some(arg.clone());
+        }
+        let cwd = self.cwd.as_deref()?;
+        Some(std::path::Path::new(cwd).join(path).to_string_lossy().into_owned())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod procfs {
+    use super::ForegroundProcess;
+    use std::fs;
+
+    /// The fields of `/proc/<pid>/stat` this module needs.
+    pub(super) struct Stat {
+        pub ppid: u32,
+        pub pgrp: u32,
+        pub tty_nr: i32,
+        pub tpgid: i32,
+    }
+
+    pub(super) fn parse_stat(content: &str) -> Option<Stat> {
+        // "pid (comm) state ppid pgrp session tty_nr tpgid ...": comm may contain spaces
+        let rest = &content[content.rfind(')')? + 1..];
+        let fields: Vec<&str> = rest.split_whitespace().collect();
+        Some(Stat {
+            ppid: fields.get(1)?.parse().ok()?,
+            pgrp: fields.get(2)?.parse().ok()?,
+            tty_nr: fields.get(4)?.parse().ok()?,
+            tpgid: fields.get(5)?.parse().ok()?,
+        })
+    }
+
+    fn stat(pid: u32) -> Option<Stat> {
+        parse_stat(&fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?)
+    }
+
+    fn children(pid: u32) -> Vec<u32> {
+        let mut children = Vec::new();
+        let mut listed = false;
+        if let Ok(tasks) = fs::read_dir(format!("/proc/{}/task", pid)) {
+            for task in tasks.flatten() {
+                if let Ok(list) = fs::read_to_string(task.path().join("children")) {
+                    listed = true;
+                    children.extend(list.split_whitespace().filter_map(|c| c.parse::<u32>().ok()));
+                }
+            }
+        }
+        if listed {
+            return children;
+        }
+
+        // Kernels without CONFIG_PROC_CHILDREN: scan every process
+        let Ok(entries) = fs::read_dir("/proc") else {
+            return children;
+        };
+        for entry in entries.flatten() {
+            let Some(child) = entry.file_name().to_str().and_then(|n| n.parse::<u32>().ok()) else {
+                continue;
+            };
+            if stat(child).is_some_and(|s| s.ppid == pid) {
+                children.push(child);
+            }
+        }
+        children
+    }
+
+    /// Last input/output on the process's terminal, from the PTY device times.
+    fn tty_activity(pid: u32) -> Option<std::time::SystemTime> {
+        let tty = fs::read_link(format!("/proc/{}/fd/0", pid)).ok()?;
+        let meta = fs::metadata(tty).ok()?;
+        Some(meta.modified().ok()?.max(meta.accessed().ok()?))
+    }
+
+    fn describe(pid: u32) -> Option<ForegroundProcess> {
+        let name = fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+        let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+        let read_link = |what: &str| {
+            fs::read_link(format!("/proc/{}/{}", pid, what))
+                .ok()
+                .and_then(|p| p.into_os_string().into_string().ok())
+        };
+        Some(ForegroundProcess {
+            pid,
+            name: name.trim().to_string(),
+            exe: read_link("exe").unwrap_or_default(),
+            cwd: read_link("cwd"),
+            args: cmdline
+                .split(|&b| b == 0)
+                .skip(1)
+                .filter(|a| !a.is_empty())
+                .map(|a| String::from_utf8_lossy(a).into_owned())
+                .collect(),
+        })
+    }
+
+    pub(super) fn foreground_process(terminal_pid: u32) -> Option<ForegroundProcess> {
+        // Processes attached to a terminal, a couple of levels below the emulator
+        // (some spawn their shells from a helper process)
+        let mut candidates = Vec::new();
+        let mut level = vec![terminal_pid];
+        for _ in 0..3 {
+            let mut next = Vec::new();
+            for pid in level {
+                for child in children(pid) {
+                    match stat(child) {
+                        Some(s) if s.tty_nr != 0 && s.tpgid > 0 => candidates.push((child, s)),
+                        Some(_) => next.push(child),
+                        None => {}
+                    }
+                }
+            }
+            if !candidates.is_empty() || next.is_empty() {
+                break;
+            }
+            level = next;
+        }
+
+        let (shell, stat) = candidates
+            .into_iter()
+            .max_by_key(|(pid, _)| tty_activity(*pid))?;
+        let foreground = stat.tpgid as u32;
+        if foreground == stat.pgrp {
+            return describe(shell);
+        }
+        // The group leader runs the job; it may have exited and left its children
+        describe(foreground).or_else(|| describe(shell))
+    }
+}
+
+/// What runs in the foreground of the terminal window owned by `terminal_pid`.
+#[cfg(target_os = "linux")]
+pub fn foreground_process(terminal_pid: u32) -> Option<ForegroundProcess> {
+    procfs::foreground_process(terminal_pid)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn foreground_process(_terminal_pid: u32) -> Option<ForegroundProcess> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(name: &str, args: &[&str]) -> ForegroundProcess {
+        ForegroundProcess {
+            pid: 1,
+            name: name.to_string(),
+            exe: String::new(),
+            cwd: Some("/home/me/timigs".to_string()),
+            args: args.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_classify_foreground_process() {
+        assert!(is_terminal("kitty", "/usr/bin/kitty"));
+        assert!(is_terminal("Gnome-terminal", "/usr/libexec/gnome-terminal-server"));
+        assert!(!is_terminal("firefox", "/usr/lib/firefox/firefox"));
+        assert!(is_terminal("org.wezfurlong.wezterm", "/usr/bin/wezterm-gui"));
+        assert!(is_terminal("footclient", "/usr/bin/footclient"));
+        assert!(!is_terminal("im.riot.Riot", "/app/bin/element"));
+        assert!(!is_terminal("Warpinator", "/usr/bin/warpinator"));
+        assert!(!is_terminal("Footnotes", "/usr/bin/footnotes"));
+
+        assert!(process("-zsh", &[]).is_shell());
+        let nvim = process("nvim", &["-O", "+12", "src/main.rs"]);
+        assert_eq!(nvim.editor(), Some("Neovim"));
+        assert_eq!(nvim.opened_file().as_deref(), Some("/home/me/timigs/src/main.rs"));
+        assert_eq!(process("cargo", &["build"]).build_tool_language(), Some(Some("Rust")));
+        assert_eq!(process("cargo", &["build"]).opened_file(), None);
+        assert_eq!(process("htop", &[]).build_tool_language(), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_stat() {
+        let stat = procfs::parse_stat("4242 (tmux: client (1)) S 4200 4242 4200 34817 4242 4194304 ...").unwrap();
+        assert_eq!((stat.ppid, stat.pgrp, stat.tty_nr, stat.tpgid), (4200, 4242, 34817, 4242));
+    }
+}
@@ -1,7 +1,7 @@
 //! Time OUT module — enforced break reminders
 //! During breaks: sets TimiGS to fullscreen + always-on-top and blocks other apps.
 
-use chrono::{Datelike, Local, Timelike};
+use chrono::{DateTime, Datelike, Local, Timelike};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
@@ -166,6 +166,13 @@ fn simple_hash(password: &str) -> String {
     format!("{:x}", hash)
 }
 
+/// Keep the finished break for the calendar export.
+fn record_break(started: DateTime<Local>) {
+    if let Err(e) = crate::db::record_focus_block("break", "", started, Local::now()) {
+        eprintln!("Failed to record break: {}", e);
+    }
+}
+
 pub fn start_timeout(
     interval_secs: u64,
     break_duration_secs: u64,
@@ -244,6 +251,7 @@ pub fn start_timeout(
     // Main timeout loop
     thread::spawn(move || {
         let mut was_on_break = false;
+        let mut break_started = Local::now();
         let mut five_min_notified = false;
         let mut loop_counter: u64 = 0;
 
@@ -252,6 +260,7 @@ pub fn start_timeout(
                 // Just entered break — make window fullscreen + always on top
                 if !was_on_break {
                     was_on_break = true;
+                    break_started = Local::now();
                     set_break_window_state(&app_handle_clone, true);
                 }
 
@@ -261,6 +270,7 @@ pub fn start_timeout(
                     BREAK_ACTIVE.store(false, Ordering::SeqCst);
                     next_break_main.store(interval_secs, Ordering::SeqCst);
                     was_on_break = false;
+                    record_break(break_started);
                     set_break_window_state(&app_handle_clone, false);
                     let _ = app_handle_clone.emit("timeout-break-end", ());
                     five_min_notified = false; // Reset for next cycle
@@ -305,6 +315,7 @@ pub fn start_timeout(
 
         // If stopped while on break, restore window
         if was_on_break {
+            record_break(break_started);
             set_break_window_state(&app_handle_clone, false);
         }
     });
@@ -1,10 +1,12 @@
 //! Cross-platform activity tracker module
 
 use crate::db;
+use crate::rules::{self, Classification};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
@@ -20,10 +22,17 @@ use windows::Win32::{
 
 static RUNNING: AtomicBool = AtomicBool::new(false);
 static THREAD_GENERATION: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+static IDLE_THRESHOLD_SECS: std::sync::atomic::AtomicU64 =
+    std::sync::atomic::AtomicU64::new(db::DEFAULT_IDLE_THRESHOLD_SECS);
+static TERMINAL_TRACKING: AtomicBool = AtomicBool::new(true);
 static APP_HANDLE: once_cell::sync::OnceCell<tauri::AppHandle> = once_cell::sync::OnceCell::new();
 static CURRENT_SESSION: Lazy<Mutex<Option<CurrentSession>>> = Lazy::new(|| Mutex::new(None));
 static CURRENT_MUSIC_SESSION: Lazy<Mutex<Option<CurrentMusicSession>>> = Lazy::new(|| Mutex::new(None));
 static CURRENT_CODING_SESSION: Lazy<Mutex<Option<CurrentCodingSession>>> = Lazy::new(|| Mutex::new(None));
+/// Held by the tracking loop during each tick, so the database can be swapped between two.
+static TICK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+/// Sessions were closed behind the loop's back; its next tick starts from scratch.
+static STATE_STALE: AtomicBool = AtomicBool::new(false);
 
 // List of music streaming applications
 const MUSIC_APPS: &[&str] = &[
@@ -87,6 +96,8 @@ pub struct CurrentCodingSession {
     pub is_ai_assisted: bool,
     pub window_title: String,
     pub exe_path: String,
+    pub repo_name: Option<String>,
+    pub branch: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -94,8 +105,63 @@ pub struct ActiveWindow {
     pub app_name: String,
     pub window_title: String,
     pub exe_path: String,
+    /// Process owning the window, when the backend knows it.
+    #[serde(default)]
+    pub pid: Option<u32>,
 }
 
+/// Supplies the foreground window and user idle time to the tracking loop.
+///
+/// The live backends below query the OS; `replay::ScriptedSource` plays back a
+/// recorded timeline so the loop can run without a display server.
+pub trait WindowSource: Send {
+    /// The window the user is working in, or `None` when nothing trackable is
+    /// focused (locked screen, desktop, ignored app).
+    fn active_window(&mut self) -> Option<ActiveWindow>;
+
+    /// Time since the last keyboard/mouse input.
+    fn idle_time(&mut self) -> Duration {
+        get_system_idle_time()
+    }
+
+    /// Called once at the start of every tracking tick. Live sources have nothing
+    /// to do here; scripted sources step their timeline.
+    fn advance(&mut self) {}
+
+    /// A source that has run out of data stops the tracking loop.
+    fn is_exhausted(&self) -> bool {
+        false
+    }
+
+    /// The time of the current tick; scripted sources keep their own clock.
+    fn now(&self) -> chrono::DateTime<chrono::Local> {
+        chrono::Local::now()
+    }
+}
+
+impl<S: WindowSource + ?Sized> WindowSource for Box<S> {
+    fn active_window(&mut self) -> Option<ActiveWindow> {
+        (**self).active_window()
+    }
+
+    fn idle_time(&mut self) -> Duration {
+        (**self).idle_time()
+    }
+
+    fn advance(&mut self) {
+        (**self).advance()
+    }
+
+    fn is_exhausted(&self) -> bool {
+        (**self).is_exhausted()
+    }
+
+    fn now(&self) -> chrono::DateTime<chrono::Local> {
+        (**self).now()
+    }
+}
+
+
 #[cfg(windows)]
 fn get_system_idle_time() -> std::time::Duration {
     use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
@@ -117,13 +183,18 @@ fn get_system_idle_time() -> std::time::Duration {
     }
 }
 
-#[cfg(not(windows))]
+#[cfg(target_os = "linux")]
+fn get_system_idle_time() -> std::time::Duration {
+    crate::idle::get_idle_time()
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
 fn get_system_idle_time() -> std::time::Duration {
     std::time::Duration::from_secs(0)
 }
 
 #[cfg(windows)]
-fn get_foreground_window_info() -> Option<ActiveWindow> {
+fn get_win32_active_window() -> Option<ActiveWindow> {
     unsafe {
         let hwnd: HWND = GetForegroundWindow();
         if hwnd.0.is_null() {
@@ -214,10 +285,24 @@ fn get_foreground_window_info() -> Option<ActiveWindow> {
             app_name,
             window_title,
             exe_path,
+            pid: Some(process_id),
         })
     }
 }
 
+/// Foreground window via `GetForegroundWindow`, idle time via `GetLastInputInfo`.
+#[cfg(windows)]
+#[derive(Default)]
+pub struct Win32Source;
+
+#[cfg(windows)]
+impl WindowSource for Win32Source {
+    fn active_window(&mut self) -> Option<ActiveWindow> {
+        get_win32_active_window()
+    }
+}
+
+
 #[cfg(target_os = "linux")]
 fn get_hyprland_active_window() -> Option<ActiveWindow> {
     use std::process::Command;
@@ -246,6 +331,7 @@ fn get_hyprland_active_window() -> Option<ActiveWindow> {
                 app_name,
                 window_title,
                 exe_path,
+                pid: u32::try_from(pid).ok().filter(|&pid| pid > 0),
             });
         }
     }
@@ -273,6 +359,7 @@ fn find_focused_node(val: &serde_json::Value) -> Option<ActiveWindow> {
             app_name,
             window_title,
             exe_path,
+            pid: u32::try_from(pid).ok().filter(|&pid| pid > 0),
         });
     }
     if let Some(nodes) = val["nodes"].as_array() {
@@ -345,6 +432,7 @@ fn get_gnome_active_window() -> Option<ActiveWindow> {
                         app_name,
                         window_title,
                         exe_path,
+                        pid: u32::try_from(pid).ok().filter(|&pid| pid > 0),
                     });
                 }
             }
@@ -354,125 +442,165 @@ fn get_gnome_active_window() -> Option<ActiveWindow> {
 }
 
 #[cfg(target_os = "linux")]
-fn get_foreground_window_info() -> Option<ActiveWindow> {
-    use std::process::Command;
-    
-    let mut active_window = None;
-
-    // Check if we are running under Wayland
-    let is_wayland = std::env::var("XDG_SESSION_TYPE")
-        .map(|s| s.to_lowercase() == "wayland")
-        .unwrap_or(false);
-
-    if is_wayland {
-        let desktop = std::env::var("XDG_CURRENT_DESKTOP")
-            .unwrap_or_default()
-            .to_lowercase();
-
-        if desktop.contains("hyprland") {
-            active_window = get_hyprland_active_window();
-        } else if desktop.contains("sway") {
-            active_window = get_sway_active_window();
-        } else if desktop.contains("gnome") {
-            active_window = get_gnome_active_window();
-        }
+pub struct HyprlandSource;
+
+#[cfg(target_os = "linux")]
+impl WindowSource for HyprlandSource {
+    fn active_window(&mut self) -> Option<ActiveWindow> {
+        get_hyprland_active_window()
     }
+}
 
-    if active_window.is_none() {
-        // Fallback to X11 xdotool
-        if let Ok(output) = Command::new("xdotool")
-            .args(["getactivewindow", "getwindowname"])
-            .output()
-        {
-            if output.status.success() {
-                if let Ok(title) = String::from_utf8(output.stdout) {
-                    let title = title.trim().to_string();
-                    if !title.is_empty() {
-                        let app_name = Command::new("xdotool")
-                            .args(["getactivewindow", "getwindowclassname"])
-                            .output()
-                            .ok()
-                            .and_then(|o| String::from_utf8(o.stdout).ok())
-                            .map(|s| s.trim().to_string())
-                            .unwrap_or_else(|| "Unknown".to_string());
+#[cfg(target_os = "linux")]
+pub struct SwaySource;
 
-                        let exe_path = Command::new("xdotool")
-                            .args(["getactivewindow", "getwindowpid"])
-                            .output()
-                            .ok()
-                            .and_then(|o| String::from_utf8(o.stdout).ok())
-                            .and_then(|pid_str| {
-                                let pid = pid_str.trim();
-                                let proc_path = format!("/proc/{}/exe", pid);
-                                std::fs::read_link(&proc_path).ok()
-                            })
-                            .and_then(|p| p.into_os_string().into_string().ok())
-                            .unwrap_or_else(|| "unknown".to_string());
+#[cfg(target_os = "linux")]
+impl WindowSource for SwaySource {
+    fn active_window(&mut self) -> Option<ActiveWindow> {
+        get_sway_active_window()
+    }
+}
 
-                        active_window = Some(ActiveWindow {
-                            app_name,
-                            window_title: title,
-                            exe_path,
-                        });
-                    }
-                }
-            }
-        }
+#[cfg(target_os = "linux")]
+pub struct GnomeSource;
+
+#[cfg(target_os = "linux")]
+impl WindowSource for GnomeSource {
+    fn active_window(&mut self) -> Option<ActiveWindow> {
+        get_gnome_active_window()
     }
+}
 
-    if active_window.is_none() {
-        // Fallback: try wmctrl
-        if let Ok(output) = Command::new("wmctrl")
-            .arg("-lp")
-            .output()
-        {
-            if output.status.success() {
-                if let Ok(output_str) = String::from_utf8(output.stdout) {
-                    // Get the last line (active window)
-                    if let Some(line) = output_str.lines().last() {
-                        let parts: Vec<&str> = line.split_whitespace().collect();
-                        if parts.len() >= 3 {
-                            let app_name = parts[2].to_string();
-                            let title = parts[3..].join(" ");
-
-                            active_window = Some(ActiveWindow {
-                                app_name,
-                                window_title: title,
-                                exe_path: "unknown".to_string(),
-                            });
-                        }
-                    }
-                }
+/// KDE Plasma via a KWin script reporting back over D-Bus.
+#[cfg(target_os = "linux")]
+pub struct KWinSource;
+
+#[cfg(target_os = "linux")]
+impl WindowSource for KWinSource {
+    fn active_window(&mut self) -> Option<ActiveWindow> {
+        crate::kwin::get_active_window()
+    }
+}
+
+/// river, niri, labwc, Wayfire, ... via `wlr-foreign-toplevel-management`.
+#[cfg(target_os = "linux")]
+pub struct WlrToplevelSource;
+
+#[cfg(target_os = "linux")]
+impl WindowSource for WlrToplevelSource {
+    fn active_window(&mut self) -> Option<ActiveWindow> {
+        crate::wlr_toplevel::get_active_window()
+    }
+}
+
+/// Native X11 via `_NET_ACTIVE_WINDOW`, no external binaries.
+#[cfg(target_os = "linux")]
+pub struct X11Source;
+
+#[cfg(target_os = "linux")]
+impl WindowSource for X11Source {
+    fn active_window(&mut self) -> Option<ActiveWindow> {
+        crate::x11_window::get_active_window()
+    }
+}
+
+/// Picks the compositor backend from `XDG_SESSION_TYPE` / `XDG_CURRENT_DESKTOP`,
+/// falls back to X11 (or XWayland), and drops ignored apps.
+#[cfg(target_os = "linux")]
+#[derive(Default)]
+pub struct LinuxSource;
+
+#[cfg(target_os = "linux")]
+impl WindowSource for LinuxSource {
+    fn active_window(&mut self) -> Option<ActiveWindow> {
+        let mut active_window = None;
+
+        // Check if we are running under Wayland
+        let is_wayland = std::env::var("XDG_SESSION_TYPE")
+            .map(|s| s.to_lowercase() == "wayland")
+            .unwrap_or(false);
+
+        if is_wayland {
+            let desktop = std::env::var("XDG_CURRENT_DESKTOP")
+                .unwrap_or_default()
+                .to_lowercase();
+
+            if desktop.contains("hyprland") {
+                active_window = HyprlandSource.active_window();
+            } else if desktop.contains("sway") {
+                active_window = SwaySource.active_window();
+            } else if desktop.contains("gnome") {
+                active_window = GnomeSource.active_window();
+            } else if desktop.contains("kde") {
+                active_window = KWinSource.active_window();
+            } else {
+                active_window = WlrToplevelSource.active_window();
             }
         }
-    }
 
-    // Filter ignored apps consistently
-    if let Some(aw) = active_window {
-        let name_lower = aw.app_name.to_lowercase();
-        if name_lower == "explorer"
-            || name_lower == "lockapp"
-            || name_lower == "timigs"
-            || name_lower == "antigravity"
-            || name_lower.is_empty()
-        {
-            None
+        if active_window.is_none() {
+            // Fallback to X11
+            active_window = X11Source.active_window();
+        }
+
+        // Filter ignored apps consistently
+        if let Some(aw) = active_window {
+            let name_lower = aw.app_name.to_lowercase();
+            if name_lower == "explorer"
+                || name_lower == "lockapp"
+                || name_lower == "timigs"
+                || name_lower == "antigravity"
+                || name_lower.is_empty()
+            {
+                None
+            } else {
+                Some(aw)
+            }
         } else {
-            Some(aw)
+            None
         }
-    } else {
+    }
+}
+
+/// Stub for unsupported platforms (macOS, etc.)
+#[cfg(not(any(windows, target_os = "linux")))]
+#[derive(Default)]
+pub struct NullSource;
+
+#[cfg(not(any(windows, target_os = "linux")))]
+impl WindowSource for NullSource {
+    fn active_window(&mut self) -> Option<ActiveWindow> {
         None
     }
 }
 
+/// The live backend for the current platform.
+#[cfg(windows)]
+pub type SystemSource = Win32Source;
+#[cfg(target_os = "linux")]
+pub type SystemSource = LinuxSource;
 #[cfg(not(any(windows, target_os = "linux")))]
-fn get_foreground_window_info() -> Option<ActiveWindow> {
-    // Stub for unsupported platforms (macOS, etc.)
-    None
+pub type SystemSource = NullSource;
+
+/// Window source for the tracking loop. `TIMIGS_WINDOW_SOURCE=replay:<file>` swaps
+/// the OS backend for a recorded timeline (JSON or CSV, see `replay`).
+fn select_window_source() -> Box<dyn WindowSource> {
+    if let Ok(spec) = std::env::var("TIMIGS_WINDOW_SOURCE") {
+        if let Some(path) = spec.strip_prefix("replay:") {
+            match crate::replay::ScriptedSource::from_file(path) {
+                Ok(source) => {
+                    println!("Tracking from replay timeline {}", path);
+                    return Box::new(source);
+                }
+                Err(e) => eprintln!("Failed to load replay timeline {}: {}", path, e),
+            }
+        }
+    }
+    Box::new(SystemSource::default())
 }
 
 pub fn get_current_active() -> Option<ActiveWindow> {
-    get_foreground_window_info()
+    SystemSource::default().active_window()
 }
 
 pub fn get_current_session() -> Option<CurrentSession> {
@@ -484,455 +612,618 @@ pub fn get_current_coding_session() -> Option<CurrentCodingSession> {
 }
 
 pub fn start_tracking_with_app_handle(app_handle: tauri::AppHandle) {
+    let _ = APP_HANDLE.set(app_handle);
+    start_tracking();
+}
+
+/// Set by the logind watcher while the session is locked / the machine is suspending.
+static SCREEN_LOCKED: AtomicBool = AtomicBool::new(false);
+static SLEEPING: AtomicBool = AtomicBool::new(false);
+
+fn is_system_paused() -> bool {
+    SCREEN_LOCKED.load(Ordering::SeqCst) || SLEEPING.load(Ordering::SeqCst)
+}
+
+/// Listen for logind Lock/Unlock/PrepareForSleep so sessions end at the exact
+/// lock or suspend time instead of absorbing the whole away period.
+#[cfg(target_os = "linux")]
+fn spawn_logind_watcher() {
+    static STARTED: std::sync::Once = std::sync::Once::new();
+    STARTED.call_once(|| {
+        thread::spawn(|| {
+            if let Err(e) = watch_logind() {
+                eprintln!("logind watcher stopped: {}", e);
+            }
+        });
+    });
+}
+
+#[cfg(target_os = "linux")]
+fn watch_logind() -> zbus::Result<()> {
+    use std::collections::HashMap;
+    use zbus::blocking::{Connection, MessageIterator};
+    use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+
+    let conn = Connection::system()?;
+    let session_path: OwnedObjectPath = conn
+        .call_method(
+            Some("org.freedesktop.login1"),
+            "/org/freedesktop/login1",
+            Some("org.freedesktop.login1.Manager"),
+            "GetSessionByPID",
+            &(std::process::id()),
+        )
+        .or_else(|_| {
+            conn.call_method(
+                Some("org.freedesktop.login1"),
+                "/org/freedesktop/login1",
+                Some("org.freedesktop.login1.Manager"),
+                "GetSession",
+                &("auto"),
+            )
+        })?
+        .body()
+        .deserialize()?;
+
+    let rule = zbus::MatchRule::builder()
+        .msg_type(zbus::message::Type::Signal)
+        .sender("org.freedesktop.login1")?
+        .build();
+
+    let messages = MessageIterator::for_match_rule(rule, &conn, None)?;
+    let mut inhibitor = inhibit_sleep(&conn);
+
+    for msg in messages {
+        let msg = msg?;
+        let header = msg.header();
+        let member = header.member().map(|m| m.as_str()).unwrap_or_default();
+        let is_our_session = header.path().map(|p| p.as_str()) == Some(session_path.as_str());
+
+        match member {
+            "PrepareForSleep" => {
+                let starting: bool = msg.body().deserialize()?;
+                set_sleeping(starting);
+                if starting {
+                    // Sessions are closed, let the suspend go ahead
+                    drop(inhibitor.take());
+                } else if inhibitor.is_none() {
+                    inhibitor = inhibit_sleep(&conn);
+                }
+            }
+            "Lock" if is_our_session => set_screen_locked(true),
+            "Unlock" if is_our_session => set_screen_locked(false),
+            "PropertiesChanged" if is_our_session => {
+                // Desktops that lock on their own only publish LockedHint
+                let (_, changed, _): (String, HashMap<String, OwnedValue>, Vec<String>) =
+                    msg.body().deserialize()?;
+                if let Some(locked) = changed.get("LockedHint").and_then(|v| bool::try_from(v).ok()) {
+                    set_screen_locked(locked);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Take a logind "delay" inhibitor. Suspend waits until it is dropped (at most
+/// logind's InhibitDelayMaxSec), so `PrepareForSleep` is handled before the machine
+/// sleeps rather than after it wakes up.
+#[cfg(target_os = "linux")]
+fn inhibit_sleep(conn: &zbus::blocking::Connection) -> Option<zbus::zvariant::OwnedFd> {
+    conn.call_method(
+        Some("org.freedesktop.login1"),
+        "/org/freedesktop/login1",
+        Some("org.freedesktop.login1.Manager"),
+        "Inhibit",
+        &("sleep", "TimiGS", "Close activity sessions before suspend", "delay"),
+    )
+    .and_then(|reply| reply.body().deserialize())
+    .map_err(|e| eprintln!("Cannot delay suspend through logind: {}", e))
+    .ok()
+}
+
+/// End the open sessions between two ticks and make the next tick start afresh, so
+/// the window still in front after resume or unlock gets a new session.
+fn close_sessions_now() {
+    with_sessions_closed(|| ());
+}
+
+fn set_screen_locked(locked: bool) {
+    if SCREEN_LOCKED.swap(locked, Ordering::SeqCst) != locked && locked {
+        close_sessions_now();
+    }
+}
+
+fn set_sleeping(sleeping: bool) {
+    if SLEEPING.swap(sleeping, Ordering::SeqCst) != sleeping && sleeping {
+        close_sessions_now();
+    }
+}
+
+pub fn start_tracking() {
+    // A second tracker would record every window twice
+    if !db::holds_instance_lock() {
+        eprintln!("Another TimiGS instance is tracking, not starting the tracker");
+        return;
+    }
+    let settings = db::get_settings();
+    set_idle_threshold(settings.idle_threshold_secs);
+    set_terminal_tracking(settings.terminal_tracking);
+    #[cfg(target_os = "linux")]
+    spawn_logind_watcher();
+    start_tracking_with_source(select_window_source());
+}
+
+/// Seconds without input after which open sessions are closed as AFK.
+pub fn set_idle_threshold(secs: u64) {
+    IDLE_THRESHOLD_SECS.store(secs.max(db::MIN_IDLE_THRESHOLD_SECS), Ordering::SeqCst);
+}
+
+/// Whether the program running inside terminal windows is looked up and recorded.
+pub fn set_terminal_tracking(enabled: bool) {
+    TERMINAL_TRACKING.store(enabled, Ordering::SeqCst);
+}
+
+/// Start the tracking loop on its own thread, fed by `source`.
+pub fn start_tracking_with_source<S: WindowSource + 'static>(source: S) {
     if RUNNING.load(Ordering::SeqCst) {
         return;
     }
 
-    let _ = APP_HANDLE.set(app_handle);
     RUNNING.store(true, Ordering::SeqCst);
     let gen = THREAD_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
 
-    let _ = thread::spawn(move || {
-        let mut last_app = String::new();
-        let mut last_title = String::new();
-        let mut last_music_app = String::new();
-        let mut last_coding_key = String::new();
-        let mut ticker = 0;
-
-        while RUNNING.load(Ordering::SeqCst) && THREAD_GENERATION.load(Ordering::SeqCst) == gen {
-            // Use catch_unwind to prevent panics from crashing the thread
-            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-                let idle_time = get_system_idle_time();
-                if idle_time.as_secs() >= 300 {
-                    let end_time = chrono::Local::now() - chrono::Duration::seconds(300);
-
-                    if let Some(session) = CURRENT_SESSION.lock().take() {
-                        let _ = db::end_session_retroactive(session.id, end_time);
-                    }
-                    last_app = String::new();
-                    last_title = String::new();
+    let _ = thread::spawn(move || run_tracking_loop(source, gen));
+}
 
-                    if let Some(session) = CURRENT_CODING_SESSION.lock().take() {
-                        let _ = db::end_coding_session_retroactive(session.id, end_time);
-                    }
-                    last_coding_key.clear();
+/// How often (in 1 s ticks) open sessions are stamped with a heartbeat for crash recovery.
+const HEARTBEAT_INTERVAL_TICKS: u32 = 30;
 
-                    if let Some(session) = CURRENT_MUSIC_SESSION.lock().take() {
-                        let _ = db::end_music_session_retroactive(session.id, end_time);
-                    }
-                    last_music_app = String::new();
-                } else if let Some(active) = get_foreground_window_info() {
-                    // Check if this is YouTube Music in browser
-                    let is_yt_music = is_youtube_music_title(&active.window_title);
-
-                    // Check if this is a music app (including YouTube Music in browser)
-                    let is_music = is_music_app(&active.app_name, &active.exe_path) || is_yt_music;
-
-                    if is_music {
-                        // For YouTube Music in browser, use "YouTube Music" as app name
-                        let app_name = if is_yt_music {
-                            "YouTube Music".to_string()
-                        } else {
-                            active.app_name.clone()
-                        };
-
-                        // Enrich generic titles like "Spotify Free" with real track info
-                        let enriched_title = enrich_music_title(&app_name, &active.window_title);
-
-                        // Handle music session
-                        if active.exe_path != last_music_app || enriched_title != last_title {
-                            // End previous music session
-                            if let Some(session) = CURRENT_MUSIC_SESSION.lock().take() {
-                                let _ = db::end_music_session(session.id);
-                            }
-
-                            // Start new music session with enriched title
-                            if let Ok(id) = db::start_music_session(
-                                &app_name,
-                                Some(&enriched_title),
-                                &active.exe_path
-                            ) {
-                                *CURRENT_MUSIC_SESSION.lock() = Some(CurrentMusicSession {
-                                    id,
-                                    app_name: app_name.clone(),
-                                    window_title: Some(enriched_title.clone()),
-                                    exe_path: active.exe_path.clone(),
-                                });
-                            }
-
-                            last_music_app = active.exe_path.clone();
-                            last_title = enriched_title;
-                        }
+fn run_tracking_loop<S: WindowSource>(mut source: S, gen: u64) {
+    let mut state = TrackerState::default();
+    let mut ticker = 0;
 
-                        // End coding session if music is active
-                        if let Some(session) = CURRENT_CODING_SESSION.lock().take() {
-                            let _ = db::end_coding_session(session.id);
-                        }
-                        last_coding_key.clear();
-                    } else {
-                        last_music_app = String::new();
-
-                        // Handle regular session
-                        if active.exe_path != last_app || active.window_title != last_title {
-                            // End previous session
-                            if let Some(session) = CURRENT_SESSION.lock().take() {
-                                let _ = db::end_session(session.id);
-                            }
-
-                            // Start new session
-                            if let Ok(id) =
-                                db::start_session(&active.app_name, &active.window_title, &active.exe_path)
-                            {
-                                *CURRENT_SESSION.lock() = Some(CurrentSession {
-                                    id,
-                                    app_name: active.app_name.clone(),
-                                    window_title: active.window_title.clone(),
-                                    exe_path: active.exe_path.clone(),
-                                });
-                            }
-
-                            last_app = active.exe_path.clone();
-                            last_title = active.window_title.clone();
-                        }
+    while RUNNING.load(Ordering::SeqCst) && THREAD_GENERATION.load(Ordering::SeqCst) == gen {
+        source.advance();
+        if source.is_exhausted() {
+            RUNNING.store(false, Ordering::SeqCst);
+            break;
+        }
 
-                        // Ну нахуя ви тут дивитися ?
-                        let is_browser_ai = is_browser_ai_site(&active.app_name, &active.window_title);
-                        let editor_opt = detect_code_editor(&active.app_name, &active.exe_path);
-
-                        if editor_opt.is_some() || is_browser_ai {
-                            let editor_name = if is_browser_ai {
-                                "AI Web Assistant".to_string()
-                            } else {
-                                editor_opt.unwrap()
-                            };
-                            let coding_key = format!("{}|{}", active.exe_path, active.window_title);
-                            if coding_key != last_coding_key {
-                                if let Some(session) = CURRENT_CODING_SESSION.lock().take() {
-                                    let _ = db::end_coding_session(session.id);
-                                }
-
-                                let (file_path, language, project_dir) =
-                                    parse_coding_info(&active.window_title, &editor_name);
-                                let ai = is_ai_assisted(&active.app_name, &active.window_title, &editor_name);
-
-                                if let Ok(id) = db::start_coding_session(
-                                    &active.app_name,
-                                    &editor_name,
-                                    file_path.as_deref(),
-                                    language.as_deref(),
-                                    project_dir.as_deref(),
-                                    ai,
-                                    &active.window_title,
-                                    &active.exe_path,
-                                ) {
-                                    *CURRENT_CODING_SESSION.lock() = Some(CurrentCodingSession {
-                                        id,
-                                        app_name: active.app_name.clone(),
-                                        editor_name,
-                                        file_path,
-                                        language,
-                                        project_dir,
-                                        is_ai_assisted: ai,
-                                        window_title: active.window_title.clone(),
-                                        exe_path: active.exe_path.clone(),
-                                    });
-                                }
-
-                                last_coding_key = coding_key;
-                            }
-                        } else {
-                            // Not a code editor - end any active coding session
-                            if let Some(session) = CURRENT_CODING_SESSION.lock().take() {
-                                let _ = db::end_coding_session(session.id);
-                            }
-                            last_coding_key.clear();
-                        }
-                    }
-                } else {
-                    // No active window (locked screen, desktop focused, etc.)
-                    // End active sessions immediately at current time
-                    if let Some(session) = CURRENT_SESSION.lock().take() {
-                        let _ = db::end_session(session.id);
-                    }
-                    last_app = String::new();
-                    last_title = String::new();
+        // Use catch_unwind to prevent panics from crashing the thread
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _tick = TICK.lock();
+            if STATE_STALE.swap(false, Ordering::SeqCst) {
+                state.reset();
+            }
+            state.tick(&mut source);
+        }));
 
-                    if let Some(session) = CURRENT_CODING_SESSION.lock().take() {
-                        let _ = db::end_coding_session(session.id);
-                    }
-                    last_coding_key.clear();
+        if let Err(e) = result {
+            eprintln!("Tracking thread panicked: {:?}", e);
+        }
 
-                    if let Some(session) = CURRENT_MUSIC_SESSION.lock().take() {
-                        let _ = db::end_music_session(session.id);
-                    }
-                    last_music_app = String::new();
-                }
-            }));
+        thread::sleep(Duration::from_secs(1));
 
-            if let Err(e) = result {
-                eprintln!("Tracking thread panicked: {:?}", e);
+        ticker += 1;
+        if ticker % 3 == 0 {
+            if let Some(app_handle) = APP_HANDLE.get() {
+                use tauri::Emitter;
+                let _ = app_handle.emit("activity-tracker-tick", ());
             }
-
-            thread::sleep(Duration::from_secs(1));
-
-            ticker += 1;
-            if ticker % 3 == 0 {
+        }
+        if ticker % HEARTBEAT_INTERVAL_TICKS == 0 {
+            let _tick = TICK.lock();
+            let _ = db::heartbeat_sessions(
+                CURRENT_SESSION.lock().as_ref().map(|s| s.id),
+                CURRENT_MUSIC_SESSION.lock().as_ref().map(|s| s.id),
+                CURRENT_CODING_SESSION.lock().as_ref().map(|s| s.id),
+            );
+        }
+        if ticker >= 60 {
+            ticker = 0;
+            if let Some(session) = CURRENT_SESSION.lock().as_ref() {
                 if let Some(app_handle) = APP_HANDLE.get() {
-                    use tauri::Emitter;
-                    let _ = app_handle.emit("activity-tracker-tick", ());
-                }
-            }
-            if ticker >= 60 {
-                ticker = 0;
-                if let Some(session) = CURRENT_SESSION.lock().as_ref() {
-                    if let Some(app_handle) = APP_HANDLE.get() {
-                        let _ = crate::tasks::check_goals(app_handle, &session.app_name);
-                    }
+                    let _ = crate::tasks::check_goals(app_handle, &session.app_name);
                 }
             }
         }
+    }
 
-        // End final sessions when stopping, but ONLY if we are the current active generation
-        if THREAD_GENERATION.load(Ordering::SeqCst) == gen {
-            if let Some(session) = CURRENT_SESSION.lock().take() {
-                let _ = db::end_session(session.id);
-            }
-            if let Some(session) = CURRENT_MUSIC_SESSION.lock().take() {
-                let _ = db::end_music_session(session.id);
-            }
-            if let Some(session) = CURRENT_CODING_SESSION.lock().take() {
-                let _ = db::end_coding_session(session.id);
-            }
+    // End final sessions when stopping, but ONLY if we are the current active generation
+    if THREAD_GENERATION.load(Ordering::SeqCst) == gen {
+        state.recorder.end_all_at(source.now());
+    }
+}
+
+/// Close every open session with `end_time` as its end.
+pub fn end_all_sessions_at(end_time: chrono::DateTime<chrono::Local>) {
+    Recorder::default().end_all_at(end_time);
+}
+
+/// Run `replace` (a restore or import that rewrites sessions) between two ticks, with
+/// the open sessions ended first: their ids may point at other rows afterwards. The
+/// tracker opens new ones on its next tick.
+pub fn with_sessions_closed<T>(replace: impl FnOnce() -> T) -> T {
+    let _tick = TICK.lock();
+    end_all_sessions_at(chrono::Local::now());
+    let result = replace();
+    STATE_STALE.store(true, Ordering::SeqCst);
+    result
+}
+
+/// Where the tracker records sessions. The app writes to the shared database with the
+/// cached rules and privacy policy; replay tests bring their own.
+#[derive(Default)]
+struct Recorder {
+    conn: Option<rusqlite::Connection>,
+    rules: Option<Arc<rules::RuleSet>>,
+    policy: Option<Arc<crate::privacy::Policy>>,
+}
+
+impl Recorder {
+    /// Run `f` on the recorder's connection. Holds the DB lock for the app database,
+    /// so `f` must not call anything that takes it again.
+    fn db<T>(&self, f: impl FnOnce(&rusqlite::Connection) -> rusqlite::Result<T>) -> rusqlite::Result<T> {
+        match &self.conn {
+            Some(conn) => f(conn),
+            None => db::with_connection(f),
         }
-    });
+    }
+
+    fn rules(&self) -> Arc<rules::RuleSet> {
+        self.rules.clone().unwrap_or_else(rules::ruleset)
+    }
+
+    fn privacy(&self, active: &ActiveWindow) -> crate::privacy::WindowPrivacy {
+        let policy = self.policy.clone().unwrap_or_else(crate::privacy::policy);
+        crate::privacy::window_privacy(
+            &self.rules(),
+            policy,
+            &active.app_name,
+            &active.exe_path,
+            &active.window_title,
+        )
+    }
+
+    fn end_session(&self, end_time: chrono::DateTime<chrono::Local>) {
+        if let Some(session) = CURRENT_SESSION.lock().take() {
+            let _ = self.db(|conn| db::end_session_in(conn, session.id, end_time));
+        }
+    }
+
+    fn end_coding_session(&self, end_time: chrono::DateTime<chrono::Local>) {
+        if let Some(session) = CURRENT_CODING_SESSION.lock().take() {
+            let _ = self.db(|conn| db::end_coding_session_in(conn, session.id, end_time));
+        }
+    }
+
+    fn end_music_session(&self, end_time: chrono::DateTime<chrono::Local>) {
+        if let Some(session) = CURRENT_MUSIC_SESSION.lock().take() {
+            let _ = self.db(|conn| db::end_music_session_in(conn, session.id, end_time));
+        }
+    }
+
+    fn end_all_at(&self, end_time: chrono::DateTime<chrono::Local>) {
+        self.end_session(end_time);
+        self.end_coding_session(end_time);
+        self.end_music_session(end_time);
+    }
 }
 
-pub fn start_tracking() {
-    if RUNNING.load(Ordering::SeqCst) {
-        return;
+/// What the previous tick saw, so a session is only split when something changes.
+#[derive(Default)]
+struct TrackerState {
+    last_app: String,
+    last_title: String,
+    last_music_app: String,
+    last_coding_key: String,
+    /// Program and working directory in the focused terminal, `name|cwd`.
+    last_process: String,
+    recorder: Recorder,
+}
+
+impl TrackerState {
+    fn reset(&mut self) {
+        self.last_app.clear();
+        self.last_title.clear();
+        self.last_music_app.clear();
+        self.last_coding_key.clear();
+        self.last_process.clear();
     }
 
-    RUNNING.store(true, Ordering::SeqCst);
-    let gen = THREAD_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    fn tick<S: WindowSource + ?Sized>(&mut self, source: &mut S) {
+        let now = source.now();
+        if is_system_paused() {
+            // Screen locked or going to sleep: the watcher already closed the sessions at
+            // the exact timestamp, this only catches one opened by a tick racing with it
+            self.end_sessions_at(now);
+            return;
+        }
 
-    let _ = thread::spawn(move || {
-        let mut last_app = String::new();
-        let mut last_title = String::new();
-        let mut last_music_app = String::new();
-        let mut last_coding_key = String::new();
-        let mut ticker = 0;
-
-        while RUNNING.load(Ordering::SeqCst) && THREAD_GENERATION.load(Ordering::SeqCst) == gen {
-            // Use catch_unwind to prevent panics from crashing the thread
-            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-                let idle_time = get_system_idle_time();
-                if idle_time.as_secs() >= 300 {
-                    let end_time = chrono::Local::now() - chrono::Duration::seconds(300);
-
-                    if let Some(session) = CURRENT_SESSION.lock().take() {
-                        let _ = db::end_session_retroactive(session.id, end_time);
-                    }
-                    last_app = String::new();
-                    last_title = String::new();
+        if crate::privacy::is_incognito() {
+            // Incognito: nothing is recorded until it is turned off or runs out
+            self.end_sessions_at(now);
+            return;
+        }
 
-                    if let Some(session) = CURRENT_CODING_SESSION.lock().take() {
-                        let _ = db::end_coding_session_retroactive(session.id, end_time);
-                    }
-                    last_coding_key.clear();
+        let idle_time = source.idle_time();
+        let threshold = IDLE_THRESHOLD_SECS.load(Ordering::SeqCst);
+        if idle_time.as_secs() >= threshold {
+            self.end_sessions_at(now - chrono::Duration::seconds(threshold as i64));
+        } else if let Some(active) = source.active_window() {
+            let class = self
+                .recorder
+                .rules()
+                .classify(&active.app_name, &active.exe_path, &active.window_title);
+            if class.ignore {
+                // Ignored by a user rule: treat it like no window at all
+                self.end_sessions_at(now);
+            } else {
+                self.handle_active_window(active, &class, now);
+            }
+        } else {
+            // No active window (locked screen, desktop focused, etc.)
+            self.end_sessions_at(now);
+        }
+    }
 
-                    if let Some(session) = CURRENT_MUSIC_SESSION.lock().take() {
-                        let _ = db::end_music_session_retroactive(session.id, end_time);
-                    }
-                    last_music_app = String::new();
-                } else if let Some(active) = get_foreground_window_info() {
-                    // Check if this is YouTube Music in browser
-                    let is_yt_music = is_youtube_music_title(&active.window_title);
-
-                    // Check if this is a music app (including YouTube Music in browser)
-                    let is_music = is_music_app(&active.app_name, &active.exe_path) || is_yt_music;
-
-                    if is_music {
-                        let app_name = if is_yt_music {
-                            "YouTube Music".to_string()
-                        } else {
-                            active.app_name.clone()
-                        };
-
-                        // Enrich generic titles like "Spotify Free" with real track info
-                        let enriched_title = enrich_music_title(&app_name, &active.window_title);
-
-                        // Handle music session
-                        if active.exe_path != last_music_app || enriched_title != last_title {
-                            // End previous music session
-                            if let Some(session) = CURRENT_MUSIC_SESSION.lock().take() {
-                                let _ = db::end_music_session(session.id);
-                            }
-
-                            // Start new music session with enriched title
-                            if let Ok(id) = db::start_music_session(
-                                &app_name,
-                                Some(&enriched_title),
-                                &active.exe_path
-                            ) {
-                                *CURRENT_MUSIC_SESSION.lock() = Some(CurrentMusicSession {
-                                    id,
-                                    app_name: app_name.clone(),
-                                    window_title: Some(enriched_title.clone()),
-                                    exe_path: active.exe_path.clone(),
-                                });
-                            }
-
-                            last_music_app = active.exe_path.clone();
-                            last_title = enriched_title;
-                        }
+    /// End all open sessions at `end_time` and forget what the last tick saw.
+    fn end_sessions_at(&mut self, end_time: chrono::DateTime<chrono::Local>) {
+        self.recorder.end_all_at(end_time);
+        self.reset();
+    }
 
-                        // End coding session if music is active
-                        if let Some(session) = CURRENT_CODING_SESSION.lock().take() {
-                            let _ = db::end_coding_session(session.id);
-                        }
-                        last_coding_key.clear();
-                    } else {
-                        // End music session if we were in one
-                        if let Some(session) = CURRENT_MUSIC_SESSION.lock().take() {
-                            let _ = db::end_music_session(session.id);
-                        }
-                        last_music_app.clear();
-
-                        // Handle regular session
-                        if active.exe_path != last_app || active.window_title != last_title {
-                            // End previous session
-                            if let Some(session) = CURRENT_SESSION.lock().take() {
-                                let _ = db::end_session(session.id);
-                            }
-
-                            // Start new session
-                            if let Ok(id) =
-                                db::start_session(&active.app_name, &active.window_title, &active.exe_path)
-                            {
-                                *CURRENT_SESSION.lock() = Some(CurrentSession {
-                                    id,
-                                    app_name: active.app_name.clone(),
-                                    window_title: active.window_title.clone(),
-                                    exe_path: active.exe_path.clone(),
-                                });
-                            }
-
-                            last_app = active.exe_path.clone();
-                            last_title = active.window_title.clone();
-                        }
+    fn handle_active_window(
+        &mut self,
+        active: ActiveWindow,
+        class: &Classification,
+        now: chrono::DateTime<chrono::Local>,
+    ) {
+        // Detection below looks at the real title; only what gets stored is reduced
+        let privacy = self.recorder.privacy(&active);
 
-                        // Handle coding session (independent of regular session)
-                        let is_browser_ai = is_browser_ai_site(&active.app_name, &active.window_title);
-                        let editor_opt = detect_code_editor(&active.app_name, &active.exe_path);
-
-                        if editor_opt.is_some() || is_browser_ai {
-                            let editor_name = if is_browser_ai {
-                                "AI Web Assistant".to_string()
-                            } else {
-                                editor_opt.unwrap()
-                            };
-                            // Generic comment
-                            let coding_key = format!("{}|{}", active.exe_path, active.window_title);
-                            if coding_key != last_coding_key {
-                                // End previous coding session
-                                if let Some(session) = CURRENT_CODING_SESSION.lock().take() {
-                                    let _ = db::end_coding_session(session.id);
-                                }
-
-                                let (file_path, language, project_dir) =
-                                    parse_coding_info(&active.window_title, &editor_name);
-                                let ai = is_ai_assisted(&active.app_name, &active.window_title, &editor_name);
-
-                                if let Ok(id) = db::start_coding_session(
-                                    &active.app_name,
-                                    &editor_name,
-                                    file_path.as_deref(),
-                                    language.as_deref(),
-                                    project_dir.as_deref(),
-                                    ai,
-                                    &active.window_title,
-                                    &active.exe_path,
-                                ) {
-                                    *CURRENT_CODING_SESSION.lock() = Some(CurrentCodingSession {
-                                        id,
-                                        app_name: active.app_name.clone(),
-                                        editor_name,
-                                        file_path,
-                                        language,
-                                        project_dir,
-                                        is_ai_assisted: ai,
-                                        window_title: active.window_title.clone(),
-                                        exe_path: active.exe_path.clone(),
-                                    });
-                                }
-
-                                last_coding_key = coding_key;
-                            }
-                        } else {
-                            // Not a code editor - end any active coding session
-                            if let Some(session) = CURRENT_CODING_SESSION.lock().take() {
-                                let _ = db::end_coding_session(session.id);
-                            }
-                            last_coding_key.clear();
-                        }
-                    }
-                } else {
-                    // No active window (locked screen, desktop focused, etc.)
-                    // End active sessions immediately at current time
-                    if let Some(session) = CURRENT_SESSION.lock().take() {
-                        let _ = db::end_session(session.id);
-                    }
-                    last_app = String::new();
-                    last_title = String::new();
+        // Check if this is YouTube Music in browser
+        let is_yt_music = is_youtube_music_title(&active.window_title);
 
-                    if let Some(session) = CURRENT_CODING_SESSION.lock().take() {
-                        let _ = db::end_coding_session(session.id);
-                    }
-                    last_coding_key.clear();
+        // Check if this is a music app (including YouTube Music in browser)
+        let is_music =
+            class.music || is_music_app(&active.app_name, &active.exe_path) || is_yt_music;
 
-                    if let Some(session) = CURRENT_MUSIC_SESSION.lock().take() {
-                        let _ = db::end_music_session(session.id);
+        if is_music {
+            // For YouTube Music in browser, use "YouTube Music" as app name
+            let app_name = if is_yt_music {
+                "YouTube Music".to_string()
+            } else {
+                active.app_name.clone()
+            };
+
+            // Enrich generic titles like "Spotify Free" with real track info
+            let enriched_title = enrich_music_title(&app_name, &active.window_title);
+
+            // Handle music session
+            if active.exe_path != self.last_music_app || enriched_title != self.last_title {
+                // End previous music session
+                self.recorder.end_music_session(now);
+
+                // Start new music session with enriched title
+                let stored_title = privacy.title(&enriched_title);
+                if let Ok(id) = self.recorder.db(|conn| {
+                    db::start_music_session_in(conn, &app_name, Some(&stored_title), &active.exe_path, now)
+                }) {
+                    *CURRENT_MUSIC_SESSION.lock() = Some(CurrentMusicSession {
+                        id,
+                        app_name: app_name.clone(),
+                        window_title: Some(stored_title),
+                        exe_path: active.exe_path.clone(),
+                    });
+                }
+
+                self.last_music_app = active.exe_path.clone();
+                self.last_title = enriched_title;
+            }
+
+            // End coding session if music is active
+            self.recorder.end_coding_session(now);
+            self.last_coding_key.clear();
+        } else {
+            self.last_music_app = String::new();
+
+            let process = terminal_process(&active);
+            let process_key = process
+                .as_ref()
+                .map(|p| format!("{}|{}", p.name, p.cwd.as_deref().unwrap_or_default()))
+                .unwrap_or_default();
+
+            // Handle regular session
+            if active.exe_path != self.last_app
+                || active.window_title != self.last_title
+                || process_key != self.last_process
+            {
+                // End previous session
+                self.recorder.end_session(now);
+
+                // Start new session
+                let stored_title = privacy.title(&active.window_title);
+                if let Ok(id) = self.recorder.db(|conn| {
+                    db::start_session_in(conn, &active.app_name, &stored_title, &active.exe_path, now)
+                }) {
+                    if let Some(process) = &process {
+                        let _ = self.recorder.db(|conn| {
+                            db::set_session_process_in(conn, id, &process.name, process.cwd.as_deref())
+                        });
+                    }
+                    if privacy.keeps_url() && db::is_browser(&active.app_name) {
+                        let _ = self
+                            .recorder
+                            .db(|conn| {
+                                db::attach_browser_tab_in(conn, id, &active.app_name, &active.window_title, now)
+                            });
                     }
-                    last_music_app = String::new();
+                    *CURRENT_SESSION.lock() = Some(CurrentSession {
+                        id,
+                        app_name: active.app_name.clone(),
+                        window_title: stored_title,
+                        exe_path: active.exe_path.clone(),
+                    });
                 }
-            }));
 
-            if let Err(e) = result {
-                eprintln!("Tracking thread panicked: {:?}", e);
+                self.last_app = active.exe_path.clone();
+                self.last_title = active.window_title.clone();
+                self.last_process = process_key;
             }
 
-            thread::sleep(Duration::from_secs(1));
+            // Ну нахуя ви тут дивитися ?
+            let is_browser_ai = is_browser_ai_site(&active.app_name, &active.window_title);
+            let editor_opt = detect_code_editor(&active.app_name, &active.exe_path)
+                .or_else(|| class.coding.then(|| active.app_name.clone()));
+            let terminal_coding = if editor_opt.is_none() && !is_browser_ai {
+                process.as_ref().and_then(terminal_coding_info)
+            } else {
+                None
+            };
 
-            ticker += 1;
-            if ticker % 3 == 0 {
-                if let Some(app_handle) = APP_HANDLE.get() {
-                    use tauri::Emitter;
-                    let _ = app_handle.emit("activity-tracker-tick", ());
+            if let (Some(process), Some((editor_name, file_path, language))) = (&process, terminal_coding) {
+                let cwd = process.cwd.as_deref();
+                let coding_key = format!(
+                    "{}|{}|{}|{}",
+                    active.exe_path,
+                    process.name,
+                    cwd.unwrap_or_default(),
+                    file_path.as_deref().unwrap_or_default()
+                );
+                if coding_key != self.last_coding_key {
+                    self.recorder.end_coding_session(now);
+
+                    let project_dir = class.project.clone().or_else(|| {
+                        let name = std::path::Path::new(cwd?).file_name()?;
+                        Some(name.to_string_lossy().into_owned())
+                    });
+                    let repo = crate::git::resolve(file_path.as_deref(), cwd);
+                    let stored_title = privacy.title(&active.window_title);
+
+                    let session = db::NewCodingSession {
+                        app_name: &active.app_name,
+                        editor_name: &editor_name,
+                        file_path: file_path.as_deref(),
+                        language: language.as_deref(),
+                        project_dir: project_dir.as_deref(),
+                        is_ai_assisted: false,
+                        window_title: &stored_title,
+                        exe_path: &active.exe_path,
+                    };
+                    if let Ok(id) = self.recorder.db(|conn| db::start_coding_session_in(conn, &session, now)) {
+                        if let Some(repo) = &repo {
+                            let _ = self
+                                .recorder
+                                .db(|conn| db::set_coding_session_repo_in(conn, id, repo, true));
+                        }
+                        *CURRENT_CODING_SESSION.lock() = Some(CurrentCodingSession {
+                            id,
+                            app_name: active.app_name.clone(),
+                            editor_name,
+                            file_path,
+                            language,
+                            project_dir,
+                            is_ai_assisted: false,
+                            window_title: stored_title,
+                            exe_path: active.exe_path.clone(),
+                            repo_name: repo.as_ref().map(|r| r.name.clone()),
+                            branch: repo.and_then(|r| r.branch),
+                        });
+                    }
+
+                    self.last_coding_key = coding_key;
                 }
-            }
-            if ticker >= 60 {
-                ticker = 0;
-                if let Some(session) = CURRENT_SESSION.lock().as_ref() {
-                    if let Some(app_handle) = APP_HANDLE.get() {
-                        let _ = crate::tasks::check_goals(app_handle, &session.app_name);
+            } else if editor_opt.is_some() || is_browser_ai {
+                let editor_name = if is_browser_ai {
+                    "AI Web Assistant".to_string()
+                } else {
+                    editor_opt.unwrap()
+                };
+                let coding_key = format!("{}|{}", active.exe_path, active.window_title);
+                if coding_key != self.last_coding_key {
+                    // End previous coding session
+                    self.recorder.end_coding_session(now);
+
+                    // File and project come from the title, so only what privacy leaves of it
+                    let stored_title = privacy.title(&active.window_title);
+                    let (file_path, language, parsed_project) =
+                        parse_coding_info(&stored_title, &editor_name);
+                    let project_dir = class.project.clone().or(parsed_project);
+                    let ai = is_ai_assisted(&active.app_name, &active.window_title, &editor_name);
+                    let repo = if is_browser_ai {
+                        None
+                    } else {
+                        crate::git::resolve(file_path.as_deref(), project_dir.as_deref())
+                    };
+
+                    let session = db::NewCodingSession {
+                        app_name: &active.app_name,
+                        editor_name: &editor_name,
+                        file_path: file_path.as_deref(),
+                        language: language.as_deref(),
+                        project_dir: project_dir.as_deref(),
+                        is_ai_assisted: ai,
+                        window_title: &stored_title,
+                        exe_path: &active.exe_path,
+                    };
+                    if let Ok(id) = self.recorder.db(|conn| db::start_coding_session_in(conn, &session, now)) {
+                        if let Some(repo) = &repo {
+                            let _ = self
+                                .recorder
+                                .db(|conn| db::set_coding_session_repo_in(conn, id, repo, true));
+                        }
+                        *CURRENT_CODING_SESSION.lock() = Some(CurrentCodingSession {
+                            id,
+                            app_name: active.app_name.clone(),
+                            editor_name,
+                            file_path,
+                            language,
+                            project_dir,
+                            is_ai_assisted: ai,
+                            window_title: stored_title,
+                            exe_path: active.exe_path.clone(),
+                            repo_name: repo.as_ref().map(|r| r.name.clone()),
+                            branch: repo.and_then(|r| r.branch),
+                        });
                     }
+
+                    self.last_coding_key = coding_key;
                 }
+            } else {
+                // Not a code editor - end any active coding session
+                self.recorder.end_coding_session(now);
+                self.last_coding_key.clear();
             }
         }
+    }
+}
 
-        // End final sessions when stopping, but ONLY if we are the current active generation
-        if THREAD_GENERATION.load(Ordering::SeqCst) == gen {
-            if let Some(session) = CURRENT_SESSION.lock().take() {
-                let _ = db::end_session(session.id);
-            }
-            if let Some(session) = CURRENT_MUSIC_SESSION.lock().take() {
-                let _ = db::end_music_session(session.id);
-            }
-            if let Some(session) = CURRENT_CODING_SESSION.lock().take() {
-                let _ = db::end_coding_session(session.id);
-            }
-        }
-    });
+/// The program in the foreground of a terminal window, unless it is just the shell
+/// prompt or terminal tracking is off.
+fn terminal_process(active: &ActiveWindow) -> Option<crate::terminal::ForegroundProcess> {
+    if !TERMINAL_TRACKING.load(Ordering::SeqCst)
+        || !crate::terminal::is_terminal(&active.app_name, &active.exe_path)
+    {
+        return None;
+    }
+    crate::terminal::foreground_process(active.pid?).filter(|p| !p.is_shell())
+}
+
+/// Editor name, file and language for a terminal editor or build tool.
+fn terminal_coding_info(
+    process: &crate::terminal::ForegroundProcess,
+) -> Option<(String, Option<String>, Option<String>)> {
+    if let Some(editor) = process.editor() {
+        let file_path = process.opened_file();
+        let language = file_path.as_deref().and_then(detect_language);
+        return Some((editor.to_string(), file_path, language));
+    }
+    let language = process.build_tool_language()?;
+    Some((process.name.clone(), None, language.map(str::to_string)))
 }
 
 pub fn stop_tracking() {
@@ -1610,6 +1901,77 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_replay_splits_and_closes_idle_sessions() {
+        use crate::replay::{parse_json, ScriptedSource};
+        use chrono::TimeZone;
+
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::migrations::run_migrations(&mut conn, None).unwrap();
+        let rules: Vec<db::ClassificationRule> =
+            serde_json::from_str(r#"[{"name": "Games", "app_pattern": "^Steam$", "ignore": true}]"#).unwrap();
+        let mut state = TrackerState {
+            recorder: Recorder {
+                conn: Some(conn),
+                rules: Some(Arc::new(rules::RuleSet::new(rules))),
+                policy: Some(Arc::new(crate::privacy::Policy::new(
+                    crate::privacy::TitlePrivacy::Full,
+                    false,
+                    String::new(),
+                ))),
+            },
+            ..Default::default()
+        };
+
+        let start = chrono::Local.with_ymd_and_hms(2024, 5, 1, 9, 0, 0).unwrap();
+        let mut source = ScriptedSource::new(
+            parse_json(
+                r#"[
+                    {"app_name": "Slack", "window_title": "general", "duration_secs": 60},
+                    {"app_name": "Slack", "window_title": "random", "duration_secs": 340},
+                    {"app_name": "Slack", "window_title": "random", "idle_secs": 300, "duration_secs": 10},
+                    {"app_name": "Steam", "window_title": "Library", "duration_secs": 20},
+                    {"app_name": "Files", "window_title": "Downloads", "duration_secs": 15},
+                    {"duration_secs": 1}
+                ]"#,
+            )
+            .unwrap(),
+        )
+        .starting_at(start);
+
+        loop {
+            source.advance();
+            if source.is_exhausted() {
+                break;
+            }
+            state.tick(&mut source);
+        }
+
+        let at = |secs: i64| (start + chrono::Duration::seconds(secs)).to_rfc3339();
+        let conn = state.recorder.conn.as_ref().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT app_name, window_title, start_time, end_time, duration_seconds FROM activity_sessions ORDER BY id")
+            .unwrap();
+        let sessions: Vec<(String, String, String, String, i64)> = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+
+        assert_eq!(
+            sessions,
+            vec![
+                // Split on the title change
+                ("Slack".into(), "general".into(), at(0), at(60), 60),
+                // Idle: closed where the idle threshold started, not when it was noticed
+                ("Slack".into(), "random".into(), at(60), at(100), 40),
+                // Steam is ignored by the rule; no window closes Files
+                ("Files".into(), "Downloads".into(), at(430), at(445), 15),
+            ]
+        );
+        assert!(get_current_session().is_none());
+    }
+
     #[test]
     fn dump_db() {
         let conn = rusqlite::Connection::open("C:\\Users\\baneronetwo\\AppData\\Roaming\\TimiGS\\activity.db").unwrap();
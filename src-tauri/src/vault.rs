@@ -0,0 +1,533 @@
+//! Encryption at rest
+//!
+//! Two independent layers:
+//!
+//! * **Secret columns.** `cloud_accounts.access_token` and `refresh_token` are sealed
+//!   with AES-256-GCM under a random key kept in the OS secret store (Keychain,
+//!   Windows Credential Manager, Secret Service / libsecret). Where no secret store is
+//!   reachable the key falls back to `field-key.key` next to the database, readable
+//!   by the user only, unless tokens are already sealed: those may need the key in
+//!   the store, so sealing and opening fail until it is reachable again. Sealed
+//!   values look like `enc:v1:<base64 nonce + ciphertext>`; anything else is legacy
+//!   plaintext and gets sealed on the next start.
+//! * **The whole database.** Builds with the `sqlcipher` cargo feature can encrypt
+//!   `activity.db` with SQLCipher. The key is random and kept in the secret store, or
+//!   derived with Argon2id from a passphrase read from `TIMIGS_DB_PASSPHRASE`. The
+//!   choice is recorded in `activity.db.encryption`; without that file the database
+//!   is plaintext.
+
+use crate::db;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose, Engine as _};
+use once_cell::sync::OnceCell;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const SERVICE: &str = "TimiGS";
+const FIELD_KEY: &str = "field-key";
+const DATABASE_KEY: &str = "database-key";
+const SEALED_PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+pub const PASSPHRASE_ENV: &str = "TIMIGS_DB_PASSPHRASE";
+const NO_SQLCIPHER: &str =
+    "This build of TimiGS has no SQLCipher support (cargo feature `sqlcipher`)";
+
+type Key = [u8; 32];
+
+/// Where the column key is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStore {
+    Keyring,
+    File,
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N], String> {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [_, _] => u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+fn to_key(bytes: &[u8], what: &str) -> Result<Key, String> {
+    bytes
+        .try_into()
+        .map_err(|_| format!("The stored {} has the wrong length", what))
+}
+
+// ── Secret store ──
+
+/// `Ok(None)` when the store works but has no such key, `Err` when it is unreachable.
+fn keyring_get(name: &str) -> Result<Option<Key>, String> {
+    let entry = keyring::Entry::new(SERVICE, name).map_err(|e| e.to_string())?;
+    match entry.get_secret() {
+        Ok(secret) => to_key(&secret, name).map(Some),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn keyring_set(name: &str, key: &Key) -> Result<(), String> {
+    let entry = keyring::Entry::new(SERVICE, name).map_err(|e| e.to_string())?;
+    entry.set_secret(key).map_err(|e| e.to_string())
+}
+
+fn keyring_delete(name: &str) -> Result<(), String> {
+    let entry = keyring::Entry::new(SERVICE, name).map_err(|e| e.to_string())?;
+    match entry.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn key_file(db_path: &Path, name: &str) -> PathBuf {
+    db_path.with_file_name(format!("{}.key", name))
+}
+
+fn read_key_file(path: &Path) -> Result<Option<Key>, String> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => {
+            let bytes = unhex(text.trim()).ok_or_else(|| format!("{} is damaged", path.display()))?;
+            to_key(&bytes, "key file").map(Some)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+fn write_key_file(path: &Path, key: &Key) -> Result<(), String> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    std::io::Write::write_all(&mut file, hex(key).as_bytes()).map_err(|e| e.to_string())
+}
+
+/// Whether the database already holds values sealed under some column key. Uses its
+/// own connection: `seal` may run while the shared one is locked.
+fn has_sealed_values(db_path: &Path) -> Result<bool, String> {
+    if !db_path.exists() {
+        return Ok(false);
+    }
+    let conn = Connection::open_with_flags(db_path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| e.to_string())?;
+    conn.busy_timeout(std::time::Duration::from_secs(5)).map_err(|e| e.to_string())?;
+    unlock(&conn, db_path).map_err(|e| e.to_string())?;
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM cloud_accounts WHERE access_token LIKE ?1 OR refresh_token LIKE ?1)",
+        [format!("{}%", SEALED_PREFIX)],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// The column key: from the secret store, else from the fallback file, else a new
+/// one in whichever of the two works. An unreachable store may hold the key stored
+/// values were sealed with, so then this fails (and is retried on the next use)
+/// instead of replacing it.
+fn load_field_key(db_path: &Path) -> Result<(Key, KeyStore), String> {
+    let file = key_file(db_path, FIELD_KEY);
+    // A key file means the secret store was unavailable before; keep using it
+    if let Some(key) = read_key_file(&file)? {
+        return Ok((key, KeyStore::File));
+    }
+    match keyring_get(FIELD_KEY) {
+        Ok(Some(key)) => return Ok((key, KeyStore::Keyring)),
+        Ok(None) => {
+            let key = random_bytes()?;
+            if keyring_set(FIELD_KEY, &key).is_ok() {
+                return Ok((key, KeyStore::Keyring));
+            }
+        }
+        Err(e) => {
+            if has_sealed_values(db_path)? {
+                return Err(format!("The secret store holding the encryption key is unavailable: {}", e));
+            }
+        }
+    }
+    let key = random_bytes()?;
+    write_key_file(&file, &key)?;
+    Ok((key, KeyStore::File))
+}
+
+static FIELD: OnceCell<(Key, KeyStore)> = OnceCell::new();
+
+fn field_key() -> Result<&'static (Key, KeyStore), String> {
+    FIELD.get_or_try_init(|| load_field_key(&db::get_db_path()))
+}
+
+// ── Sealed columns ──
+
+pub fn is_sealed(value: &str) -> bool {
+    value.starts_with(SEALED_PREFIX)
+}
+
+fn seal_with(key: &Key, plain: &str) -> Result<String, String> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|e| e.to_string())?;
+    let nonce: [u8; NONCE_LEN] = random_bytes()?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(
+        cipher
+            .encrypt(Nonce::from_slice(&nonce), plain.as_bytes())
+            .map_err(|_| "Encryption failed".to_string())?,
+    );
+    Ok(format!("{}{}", SEALED_PREFIX, general_purpose::STANDARD.encode(sealed)))
+}
+
+fn open_with(key: &Key, stored: &str) -> Result<String, String> {
+    let Some(encoded) = stored.strip_prefix(SEALED_PREFIX) else {
+        return Ok(stored.to_string());
+    };
+    let bytes = general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|_| "Sealed value is damaged".to_string())?;
+    if bytes.len() < NONCE_LEN {
+        return Err("Sealed value is damaged".to_string());
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|e| e.to_string())?;
+    let plain = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Cannot decrypt: the key in the secret store has changed".to_string())?;
+    String::from_utf8(plain).map_err(|e| e.to_string())
+}
+
+/// Seal a secret for storage. Empty and already sealed values are returned unchanged.
+pub fn seal(plain: &str) -> Result<String, String> {
+    if plain.is_empty() || is_sealed(plain) {
+        return Ok(plain.to_string());
+    }
+    seal_with(&field_key()?.0, plain)
+}
+
+/// Reverse of `seal`; plaintext from before sealing existed passes through.
+pub fn open(stored: &str) -> Result<String, String> {
+    if !is_sealed(stored) {
+        return Ok(stored.to_string());
+    }
+    open_with(&field_key()?.0, stored)
+}
+
+// ── Database encryption ──
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    /// Random key in the OS secret store.
+    Keyring,
+    /// Argon2id over `TIMIGS_DB_PASSPHRASE`.
+    Passphrase,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EncryptionConfig {
+    source: KeySource,
+    /// Hex Argon2 salt, for `Passphrase`.
+    #[serde(default)]
+    salt: Option<String>,
+}
+
+fn config_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path.as_os_str().to_owned();
+    name.push(".encryption");
+    PathBuf::from(name)
+}
+
+fn read_config(db_path: &Path) -> Result<Option<EncryptionConfig>, String> {
+    let path = config_path(db_path);
+    match std::fs::read_to_string(&path) {
+        Ok(text) => serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|e| format!("{}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, String> {
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| e.to_string())?;
+    Ok(key)
+}
+
+fn passphrase() -> Result<String, String> {
+    std::env::var(PASSPHRASE_ENV)
+        .ok()
+        .filter(|p| !p.is_empty())
+        .ok_or_else(|| format!("The database is encrypted with a passphrase: set {}", PASSPHRASE_ENV))
+}
+
+fn database_key(config: &EncryptionConfig) -> Result<Key, String> {
+    match config.source {
+        KeySource::Keyring => keyring_get(DATABASE_KEY)?
+            .ok_or_else(|| "The database key is missing from the secret store".to_string()),
+        KeySource::Passphrase => {
+            let salt = config
+                .salt
+                .as_deref()
+                .and_then(unhex)
+                .ok_or_else(|| "The encryption settings have no salt".to_string())?;
+            derive_key(&passphrase()?, &salt)
+        }
+    }
+}
+
+/// `PRAGMA key` / `ATTACH … KEY` argument for a raw SQLCipher key.
+fn raw_key_sql(key: &Key) -> String {
+    format!("\"x'{}'\"", hex(key))
+}
+
+fn sql_error(msg: String) -> rusqlite::Error {
+    rusqlite::Error::ToSqlConversionFailure(msg.into())
+}
+
+/// Give a freshly opened connection its key. Plaintext databases need none.
+pub fn unlock(conn: &Connection, db_path: &Path) -> rusqlite::Result<()> {
+    let Some(config) = read_config(db_path).map_err(sql_error)? else {
+        return Ok(());
+    };
+    if !cfg!(feature = "sqlcipher") {
+        return Err(sql_error(NO_SQLCIPHER.to_string()));
+    }
+    let key = database_key(&config).map_err(sql_error)?;
+    conn.execute_batch(&format!("PRAGMA key = {};", raw_key_sql(&key)))?;
+    // A wrong key only shows once a page is read
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+        .map_err(|_| sql_error("Cannot unlock the database: wrong key or passphrase".to_string()))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EncryptionStatus {
+    pub sqlcipher_available: bool,
+    pub database_encrypted: bool,
+    pub key_source: Option<KeySource>,
+    /// Copies made before schema upgrades, and snapshots taken before encryption.
+    pub plaintext_backups: Vec<String>,
+}
+
+fn is_plaintext(path: &Path) -> bool {
+    let mut header = [0u8; 16];
+    std::fs::File::open(path)
+        .and_then(|mut file| std::io::Read::read_exact(&mut file, &mut header))
+        .is_ok_and(|_| &header == b"SQLite format 3\0")
+}
+
+fn plaintext_backups(db_path: &Path) -> Vec<String> {
+    let Some(name) = db_path.file_name().and_then(|n| n.to_str()) else {
+        return Vec::new();
+    };
+    let prefix = format!("{}.v", name);
+    let dir = db_path.parent().unwrap_or(Path::new("."));
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut backups: Vec<String> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(&prefix) && n.ends_with(".bak"))
+        })
+        .map(|p| p.display().to_string())
+        .collect();
+    if let Ok(snapshots) = crate::backup::list_snapshots() {
+        backups.extend(
+            snapshots
+                .into_iter()
+                .filter(|s| is_plaintext(Path::new(&s.path)))
+                .map(|s| s.path),
+        );
+    }
+    backups.sort();
+    backups
+}
+
+pub fn status() -> Result<EncryptionStatus, String> {
+    let db_path = db::get_db_path();
+    let config = read_config(&db_path)?;
+    Ok(EncryptionStatus {
+        sqlcipher_available: cfg!(feature = "sqlcipher"),
+        database_encrypted: config.is_some(),
+        key_source: config.map(|c| c.source),
+        plaintext_backups: plaintext_backups(&db_path),
+    })
+}
+
+/// Copy the open database to `target` with `target_key` (an empty string for
+/// plaintext), keeping the schema version.
+#[cfg(feature = "sqlcipher")]
+fn export_copy(target: &Path, target_key: &str) -> Result<(), String> {
+    let _ = std::fs::remove_file(target);
+    db::with_connection(|conn| {
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        conn.execute_batch(&format!(
+            "ATTACH DATABASE '{}' AS target KEY {};
+             SELECT sqlcipher_export('target');
+             PRAGMA target.user_version = {};
+             DETACH DATABASE target;",
+            target.display().to_string().replace('\'', "''"),
+            target_key,
+            version
+        ))
+    })
+    .map_err(|e| e.to_string())
+}
+
+/// Put `copy` in place of the database and reopen it, undoing `rollback` on failure.
+#[cfg(feature = "sqlcipher")]
+fn swap_in(db_path: &Path, copy: &Path, rollback: impl FnOnce()) -> Result<(), String> {
+    db::close_database();
+    if let Err(e) = std::fs::rename(copy, db_path) {
+        rollback();
+        let _ = db::init_database_client();
+        return Err(format!("Cannot replace {}: {}", db_path.display(), e));
+    }
+    db::init_database_client().map_err(|e| e.to_string())
+}
+
+/// Encrypt the plaintext database in place. Other TimiGS processes must be closed.
+#[cfg(feature = "sqlcipher")]
+pub fn encrypt_database(source: KeySource) -> Result<EncryptionStatus, String> {
+    let db_path = db::get_db_path();
+    if read_config(&db_path)?.is_some() {
+        return Err("The database is already encrypted".to_string());
+    }
+    let config = match source {
+        KeySource::Keyring => {
+            let key = random_bytes()?;
+            keyring_set(DATABASE_KEY, &key)
+                .map_err(|e| format!("The OS secret store is unavailable: {}", e))?;
+            EncryptionConfig { source, salt: None }
+        }
+        KeySource::Passphrase => {
+            passphrase()?;
+            EncryptionConfig {
+                source,
+                salt: Some(hex(&random_bytes::<16>()?)),
+            }
+        }
+    };
+    let key = database_key(&config)?;
+
+    let copy = db_path.with_extension("db.encrypting");
+    let config_file = config_path(&db_path);
+    // Whatever the tracker wrote between the copy and the swap would be lost
+    crate::backup::with_tracker_paused(|| {
+        export_copy(&copy, &raw_key_sql(&key))?;
+        std::fs::write(&config_file, serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?)
+            .map_err(|e| format!("{}: {}", config_file.display(), e))?;
+        swap_in(&db_path, &copy, || {
+            let _ = std::fs::remove_file(&config_file);
+        })
+    })?;
+    status()
+}
+
+/// Decrypt the database in place and forget its key.
+#[cfg(feature = "sqlcipher")]
+pub fn decrypt_database() -> Result<EncryptionStatus, String> {
+    let db_path = db::get_db_path();
+    let Some(config) = read_config(&db_path)? else {
+        return Err("The database is not encrypted".to_string());
+    };
+
+    let copy = db_path.with_extension("db.decrypting");
+    let config_file = config_path(&db_path);
+    crate::backup::with_tracker_paused(|| {
+        export_copy(&copy, "''")?;
+        let saved = std::fs::read(&config_file).map_err(|e| e.to_string())?;
+        std::fs::remove_file(&config_file).map_err(|e| e.to_string())?;
+        swap_in(&db_path, &copy, || {
+            let _ = std::fs::write(&config_file, &saved);
+        })
+    })?;
+    if config.source == KeySource::Keyring {
+        keyring_delete(DATABASE_KEY)?;
+    }
+    status()
+}
+
+#[cfg(not(feature = "sqlcipher"))]
+pub fn encrypt_database(_source: KeySource) -> Result<EncryptionStatus, String> {
+    Err(NO_SQLCIPHER.to_string())
+}
+
+#[cfg(not(feature = "sqlcipher"))]
+pub fn decrypt_database() -> Result<EncryptionStatus, String> {
+    Err(NO_SQLCIPHER.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let key: Key = [7; 32];
+        let sealed = seal_with(&key, "ya29.token").unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.contains("ya29"));
+        // A fresh nonce every time
+        assert_ne!(seal_with(&key, "ya29.token").unwrap(), sealed);
+        assert_eq!(open_with(&key, &sealed).unwrap(), "ya29.token");
+
+        // Legacy plaintext passes through, tampering and wrong keys are caught
+        assert_eq!(open_with(&key, "plain").unwrap(), "plain");
+        assert!(open_with(&[8; 32], &sealed).is_err());
+        let mut tampered = sealed.clone();
+        tampered.pop();
+        tampered.push(if sealed.ends_with('A') { 'B' } else { 'A' });
+        assert!(open_with(&key, &tampered).is_err());
+    }
+
+    #[test]
+    fn test_has_sealed_values() {
+        let dir = std::env::temp_dir().join(format!("timigs-vault-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("activity.db");
+        assert!(!has_sealed_values(&db_path).unwrap());
+
+        let mut conn = Connection::open(&db_path).unwrap();
+        crate::migrations::run_migrations(&mut conn, None).unwrap();
+        conn.execute_batch(
+            "INSERT INTO cloud_accounts (email, provider, access_token, refresh_token, created_at)
+                 VALUES ('me@example.com', 'google', 'ya29.legacy', '', '2024-05-01T10:00:00+00:00');",
+        )
+        .unwrap();
+        assert!(!has_sealed_values(&db_path).unwrap());
+        conn.execute("UPDATE cloud_accounts SET refresh_token = 'enc:v1:AAAA'", []).unwrap();
+        assert!(has_sealed_values(&db_path).unwrap());
+
+        drop(conn);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_passphrase_key() {
+        let salt = [1u8; 16];
+        let key = derive_key("correct horse", &salt).unwrap();
+        assert_eq!(key, derive_key("correct horse", &salt).unwrap());
+        assert_ne!(key, derive_key("correct horse", &[2u8; 16]).unwrap());
+        assert_eq!(unhex(&hex(&key)).unwrap(), key.to_vec());
+        assert_eq!(raw_key_sql(&[0xab; 32]), format!("\"x'{}'\"", "ab".repeat(32)));
+    }
+}
@@ -0,0 +1,225 @@
+//! WakaTime-compatible heartbeat endpoint
+//!
+//! Editor plugins built on wakatime-cli can report to TimiGS instead of wakatime.com
+//! by pointing `~/.wakatime.cfg` at the local API:
+//!
+//! ```ini
+//! [settings]
+//! api_url = http://127.0.0.1:4646/api/v1
+//! api_key = <local API token>
+//! ```
+//!
+//! Heartbeats become `coding_sessions` rows with `source = 'heartbeat'` (see
+//! `db::record_coding_heartbeats`), which take precedence over sessions guessed
+//! from window titles.
+
+use crate::db::{self, CodingHeartbeat};
+use chrono::{Local, TimeZone};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+pub const PATH_PREFIX: &str = "/api/v1/users/current/";
+
+/// A heartbeat as sent by wakatime-cli. Fields TimiGS has no use for
+/// (`is_write`, `lineno`, `cursorpos`, ...) are ignored.
+#[derive(Debug, Deserialize)]
+struct WireHeartbeat {
+    entity: String,
+    #[serde(rename = "type", default)]
+    entity_type: Option<String>,
+    #[serde(default)]
+    category: Option<String>,
+    time: f64,
+    #[serde(default)]
+    project: Option<String>,
+    #[serde(default)]
+    branch: Option<String>,
+    #[serde(default)]
+    language: Option<String>,
+    #[serde(default)]
+    user_agent: Option<String>,
+}
+
+/// Editor names as the title-based tracker reports them, keyed by plugin name.
+const EDITOR_NAMES: &[(&str, &str)] = &[
+    ("vscode", "VS Code"),
+    ("vim", "Vim"),
+    ("neovim", "Neovim"),
+    ("emacs", "Emacs"),
+    ("sublime", "Sublime Text"),
+    ("jetbrains", "JetBrains IDE"),
+    ("intellij", "JetBrains IDE"),
+    ("zed", "Zed"),
+    ("helix", "Helix"),
+    ("kate", "Kate"),
+    ("xcode", "Xcode"),
+    ("visualstudio", "Visual Studio"),
+    ("cursor", "Cursor"),
+    ("windsurf", "Windsurf"),
+];
+
+/// Editor from a user agent like
+/// `wakatime/v1.90.0 (linux-6.5) go1.21.5 vscode/1.85.1 vscode-wakatime/24.4.0`.
+fn editor_from_user_agent(user_agent: &str) -> String {
+    let plugin = user_agent
+        .split_whitespace()
+        .rev()
+        .find_map(|part| part.split('/').next()?.strip_suffix("-wakatime"))
+        .unwrap_or("")
+        .to_lowercase();
+
+    if plugin.is_empty() {
+        return "WakaTime".to_string();
+    }
+    EDITOR_NAMES
+        .iter()
+        .find(|(key, _)| *key == plugin)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| {
+            let mut chars = plugin.chars();
+            chars
+                .next()
+                .map(|c| c.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        })
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+fn convert(wire: WireHeartbeat, header_agent: Option<&str>) -> Result<CodingHeartbeat, String> {
+    if wire.entity.trim().is_empty() {
+        return Err("entity is empty".to_string());
+    }
+    if !wire.time.is_finite() || wire.time < 0.0 {
+        return Err("time must be a Unix timestamp".to_string());
+    }
+    let secs = wire.time.trunc() as i64;
+    let nanos = (wire.time.fract() * 1e9) as u32;
+    let time = Local
+        .timestamp_opt(secs, nanos)
+        .single()
+        .ok_or("time is out of range")?;
+    if time > Local::now() + chrono::Duration::minutes(5) {
+        return Err("time is in the future".to_string());
+    }
+
+    let user_agent = wire.user_agent.as_deref().or(header_agent).unwrap_or("");
+    let category = wire.category.unwrap_or_default().to_lowercase();
+    let is_file = wire.entity_type.as_deref().is_none_or(|t| t == "file");
+    let repo = is_file
+        .then(|| crate::git::resolve(Some(&wire.entity), None))
+        .flatten();
+    Ok(CodingHeartbeat {
+        time,
+        editor_name: editor_from_user_agent(user_agent),
+        is_file,
+        language: non_empty(wire.language),
+        project: non_empty(wire.project),
+        branch: non_empty(wire.branch).or_else(|| repo.as_ref()?.branch.clone()),
+        is_ai_assisted: category == "ai coding",
+        entity: wire.entity,
+        repo,
+    })
+}
+
+fn accepted(wire: &Value) -> Value {
+    json!({ "data": wire })
+}
+
+/// Handle a request under `PATH_PREFIX`. Returns the HTTP status and body.
+pub fn handle(
+    path: &str,
+    is_post: bool,
+    body: &str,
+    user_agent: Option<&str>,
+) -> Result<(u16, Value), (u16, String)> {
+    let db_error = |e: rusqlite::Error| (500, e.to_string());
+
+    match (path, is_post) {
+        ("heartbeats", true) => {
+            let raw: Value = serde_json::from_str(body).map_err(|e| (400, e.to_string()))?;
+            let wire: WireHeartbeat =
+                serde_json::from_value(raw.clone()).map_err(|e| (400, e.to_string()))?;
+            let heartbeat = convert(wire, user_agent).map_err(|e| (400, e))?;
+            // Accepted but dropped while incognito, so plugins don't queue them up
+            if !crate::privacy::is_incognito() {
+                db::record_coding_heartbeats(&[heartbeat]).map_err(db_error)?;
+            }
+            Ok((201, accepted(&raw)))
+        }
+        ("heartbeats.bulk", true) => {
+            let raw: Vec<Value> = serde_json::from_str(body).map_err(|e| (400, e.to_string()))?;
+            let mut heartbeats = Vec::new();
+            let mut responses = Vec::new();
+            for item in raw {
+                let parsed = serde_json::from_value::<WireHeartbeat>(item.clone())
+                    .map_err(|e| e.to_string())
+                    .and_then(|wire| convert(wire, user_agent));
+                match parsed {
+                    Ok(heartbeat) => {
+                        heartbeats.push(heartbeat);
+                        responses.push(json!([accepted(&item), 201]));
+                    }
+                    Err(e) => responses.push(json!([{ "error": e }, 400])),
+                }
+            }
+            if !crate::privacy::is_incognito() {
+                db::record_coding_heartbeats(&heartbeats).map_err(db_error)?;
+            }
+            Ok((202, json!({ "responses": responses })))
+        }
+        // Used by `wakatime-cli --today` for editor status bars
+        ("statusbar/today", false) => {
+            let total = db::get_total_coding_time_today().map_err(db_error)?;
+            let (hrs, mins) = (total / 3600, (total % 3600) / 60);
+            let text = if hrs > 0 {
+                format!("{} hrs {} mins", hrs, mins)
+            } else {
+                format!("{} mins", mins)
+            };
+            Ok((
+                200,
+                json!({ "data": { "grand_total": { "text": text, "total_seconds": total } } }),
+            ))
+        }
+        _ => Err((404, format!("Unknown endpoint {}{}", PATH_PREFIX, path))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor_from_user_agent() {
+        assert_eq!(
+            editor_from_user_agent(
+                "wakatime/v1.90.0 (linux-6.5.0-x86_64) go1.21.5 vscode/1.85.1 vscode-wakatime/24.4.0"
+            ),
+            "VS Code"
+        );
+        assert_eq!(editor_from_user_agent("wakatime/v1.90.0 neovim/0.9.4 neovim-wakatime/1.0.0"), "Neovim");
+        assert_eq!(editor_from_user_agent("wakatime/v1.90.0 micro/2.0 micro-wakatime/0.1"), "Micro");
+        assert_eq!(editor_from_user_agent(""), "WakaTime");
+    }
+
+    #[test]
+    fn test_convert_heartbeat() {
+        let wire: WireHeartbeat = serde_json::from_str(
+            r#"{"entity":"/src/main.rs","type":"file","category":"ai coding","time":1700000000.5,
+                "project":"timigs","branch":" ","language":"Rust","is_write":true}"#,
+        )
+        .unwrap();
+        let hb = convert(wire, Some("wakatime/v1 vim-wakatime/9")).unwrap();
+        assert_eq!(hb.editor_name, "Vim");
+        assert!(hb.is_file && hb.is_ai_assisted);
+        assert_eq!(hb.branch, None);
+        assert_eq!(hb.time.timestamp_millis(), 1_700_000_000_500);
+
+        let wire: WireHeartbeat =
+            serde_json::from_str(r#"{"entity":"github.com","type":"domain","time":-1}"#).unwrap();
+        assert!(convert(wire, None).is_err());
+    }
+}
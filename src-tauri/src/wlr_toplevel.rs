@@ -0,0 +1,247 @@
+//! Active window on wlroots-style compositors via `wlr-foreign-toplevel-management`.
+//!
+//! Works on river, niri, labwc, Wayfire and anything else that exposes
+//! `zwlr_foreign_toplevel_manager_v1`. The compositor pushes title/app id/state
+//! changes, so a background thread keeps the event queue running and publishes the
+//! activated toplevel. The protocol carries no PID; the executable is looked up in
+//! `/proc` from the app id on a best-effort basis.
+
+use crate::tracker::ActiveWindow;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use wayland_client::backend::ObjectId;
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::wl_registry;
+use wayland_client::{event_created_child, Connection, Dispatch, Proxy, QueueHandle};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+/// How long to wait before reconnecting when the protocol is unavailable.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(30);
+
+struct Shared {
+    active: Mutex<Option<ActiveWindow>>,
+    alive: AtomicBool,
+}
+
+enum WlrState {
+    Connected(Arc<Shared>),
+    Unavailable { since: Instant },
+}
+
+static WLR: Lazy<Mutex<Option<WlrState>>> = Lazy::new(|| Mutex::new(None));
+
+/// The activated toplevel, or `None` when the compositor lacks the protocol.
+pub fn get_active_window() -> Option<ActiveWindow> {
+    let mut guard = WLR.lock();
+
+    match guard.as_ref() {
+        Some(WlrState::Connected(shared)) if !shared.alive.load(Ordering::SeqCst) => *guard = None,
+        Some(WlrState::Unavailable { since }) if since.elapsed() >= RECONNECT_INTERVAL => {
+            *guard = None
+        }
+        _ => {}
+    }
+
+    if guard.is_none() {
+        *guard = Some(match connect() {
+            Some(shared) => WlrState::Connected(shared),
+            None => WlrState::Unavailable {
+                since: Instant::now(),
+            },
+        });
+    }
+
+    match guard.as_ref() {
+        Some(WlrState::Connected(shared)) => shared.active.lock().clone(),
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct Toplevel {
+    title: String,
+    app_id: String,
+    activated: bool,
+}
+
+/// Changes received since the last `done`; only the fields that were sent are set.
+#[derive(Default)]
+struct PendingToplevel {
+    title: Option<String>,
+    app_id: Option<String>,
+    activated: Option<bool>,
+}
+
+struct ToplevelState {
+    pending: HashMap<ObjectId, PendingToplevel>,
+    current: HashMap<ObjectId, Toplevel>,
+    exe_cache: HashMap<String, String>,
+    shared: Arc<Shared>,
+}
+
+impl ToplevelState {
+    fn publish(&mut self) {
+        let Some(active) = self.current.values().find(|t| t.activated) else {
+            *self.shared.active.lock() = None;
+            return;
+        };
+
+        let exe_path = self
+            .exe_cache
+            .entry(active.app_id.clone())
+            .or_insert_with(|| exe_for_app_id(&active.app_id).unwrap_or_else(|| "unknown".to_string()))
+            .clone();
+        *self.shared.active.lock() = Some(ActiveWindow {
+            app_name: active.app_id.clone(),
+            window_title: active.title.clone(),
+            exe_path,
+            // The protocol doesn't expose the client's pid
+            pid: None,
+        });
+    }
+}
+
+fn connect() -> Option<Arc<Shared>> {
+    let conn = Connection::connect_to_env().ok()?;
+    let (globals, mut queue) = registry_queue_init::<ToplevelState>(&conn).ok()?;
+    let qh = queue.handle();
+    let manager: ZwlrForeignToplevelManagerV1 = globals.bind(&qh, 1..=3, ()).ok()?;
+
+    let shared = Arc::new(Shared {
+        active: Mutex::new(None),
+        alive: AtomicBool::new(true),
+    });
+    let mut state = ToplevelState {
+        pending: HashMap::new(),
+        current: HashMap::new(),
+        exe_cache: HashMap::new(),
+        shared: shared.clone(),
+    };
+    // The initial burst of toplevel events arrives during the first roundtrips
+    queue.roundtrip(&mut state).ok()?;
+    queue.roundtrip(&mut state).ok()?;
+
+    std::thread::spawn(move || {
+        let _objects = (conn, manager);
+        while queue.blocking_dispatch(&mut state).is_ok() {}
+        state.shared.alive.store(false, Ordering::SeqCst);
+    });
+
+    Some(shared)
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for ToplevelState {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for ToplevelState {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event {
+            state.pending.insert(toplevel.id(), PendingToplevel::default());
+        }
+    }
+
+    event_created_child!(ToplevelState, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for ToplevelState {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let id = handle.id();
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::Title { title } => {
+                state.pending.entry(id).or_default().title = Some(title);
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                state.pending.entry(id).or_default().app_id = Some(app_id);
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::State { state: states } => {
+                let activated = states
+                    .chunks_exact(4)
+                    .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+                    .any(|s| s == zwlr_foreign_toplevel_handle_v1::State::Activated as u32);
+                state.pending.entry(id).or_default().activated = Some(activated);
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Done => {
+                // Events only carry what changed; merge onto the last applied state
+                let pending = state.pending.remove(&id).unwrap_or_default();
+                let current = state.current.entry(id).or_default();
+                if let Some(title) = pending.title {
+                    current.title = title;
+                }
+                if let Some(app_id) = pending.app_id {
+                    current.app_id = app_id;
+                }
+                if let Some(activated) = pending.activated {
+                    current.activated = activated;
+                }
+                state.publish();
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+                state.pending.remove(&id);
+                state.current.remove(&id);
+                handle.destroy();
+                state.publish();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Best-effort executable for an app id such as `firefox` or `org.gnome.Nautilus`:
+/// the first process whose name matches the id (or its last dotted segment).
+fn exe_for_app_id(app_id: &str) -> Option<String> {
+    let wanted = app_id.rsplit('.').next().unwrap_or(app_id).to_lowercase();
+    if wanted.is_empty() {
+        return None;
+    }
+
+    for entry in std::fs::read_dir("/proc").ok()?.flatten() {
+        let name = entry.file_name();
+        if !name.to_string_lossy().chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        let Ok(exe) = std::fs::read_link(entry.path().join("exe")) else {
+            continue;
+        };
+        let matches = exe
+            .file_name()
+            .map(|f| f.to_string_lossy().to_lowercase() == wanted)
+            .unwrap_or(false);
+        if matches {
+            return exe.into_os_string().into_string().ok();
+        }
+    }
+    None
+}
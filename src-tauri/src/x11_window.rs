@@ -0,0 +1,212 @@
+//! In-process X11 active-window lookup.
+//!
+//! Reads `_NET_ACTIVE_WINDOW`, `_NET_WM_NAME`/`WM_NAME`, `WM_CLASS` and `_NET_WM_PID`
+//! straight from the X server instead of spawning `xdotool`. The root window and the
+//! current active window are watched for `PropertyNotify`, so the properties are only
+//! re-read after focus or the title actually changed.
+
+use crate::tracker::ActiveWindow;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, EventMask, GetPropertyReply, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_ACTIVE_WINDOW,
+        _NET_WM_NAME,
+        _NET_WM_PID,
+        UTF8_STRING,
+    }
+}
+
+/// How long to wait before reconnecting after the X server was unreachable.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(30);
+
+struct X11Watcher {
+    conn: RustConnection,
+    root: Window,
+    atoms: Atoms,
+    /// Window currently subscribed to for title changes.
+    watched: Option<Window>,
+    cached: Option<ActiveWindow>,
+    dirty: bool,
+}
+
+enum X11State {
+    Connected(Box<X11Watcher>),
+    Unavailable { since: Instant },
+}
+
+static X11: Lazy<Mutex<Option<X11State>>> = Lazy::new(|| Mutex::new(None));
+
+/// The focused X11 window, or `None` without an X server / EWMH window manager.
+pub fn get_active_window() -> Option<ActiveWindow> {
+    let mut guard = X11.lock();
+
+    if let Some(X11State::Unavailable { since }) = guard.as_ref() {
+        if since.elapsed() < RECONNECT_INTERVAL {
+            return None;
+        }
+        *guard = None;
+    }
+
+    if guard.is_none() {
+        *guard = Some(match X11Watcher::connect() {
+            Some(watcher) => X11State::Connected(Box::new(watcher)),
+            None => X11State::Unavailable {
+                since: Instant::now(),
+            },
+        });
+    }
+
+    let result = match guard.as_mut() {
+        Some(X11State::Connected(watcher)) => watcher.active_window(),
+        _ => return None,
+    };
+
+    match result {
+        Ok(window) => window,
+        Err(e) => {
+            eprintln!("X11 connection lost: {}", e);
+            *guard = Some(X11State::Unavailable {
+                since: Instant::now(),
+            });
+            None
+        }
+    }
+}
+
+type X11Result<T> = Result<T, Box<dyn std::error::Error>>;
+
+impl X11Watcher {
+    fn connect() -> Option<Self> {
+        let (conn, screen_num) = x11rb::connect(None).ok()?;
+        let root = conn.setup().roots.get(screen_num)?.root;
+        let atoms = Atoms::new(&conn).ok()?.reply().ok()?;
+
+        // Focus changes show up as _NET_ACTIVE_WINDOW changes on the root window
+        conn.change_window_attributes(
+            root,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )
+        .ok()?;
+        conn.flush().ok()?;
+
+        Some(Self {
+            conn,
+            root,
+            atoms,
+            watched: None,
+            cached: None,
+            dirty: true,
+        })
+    }
+
+    fn active_window(&mut self) -> X11Result<Option<ActiveWindow>> {
+        while let Some(event) = self.conn.poll_for_event()? {
+            if let Event::PropertyNotify(e) = event {
+                let focus_changed = e.window == self.root && e.atom == self.atoms._NET_ACTIVE_WINDOW;
+                let title_changed = Some(e.window) == self.watched
+                    && (e.atom == self.atoms._NET_WM_NAME || e.atom == u32::from(AtomEnum::WM_NAME));
+                if focus_changed || title_changed {
+                    self.dirty = true;
+                }
+            }
+            // Errors from windows that closed before we unsubscribed are expected, ignore them
+        }
+
+        if self.dirty {
+            self.cached = self.read_active_window()?;
+            self.dirty = false;
+        }
+        Ok(self.cached.clone())
+    }
+
+    fn read_active_window(&mut self) -> X11Result<Option<ActiveWindow>> {
+        let window = self
+            .get_property(self.root, self.atoms._NET_ACTIVE_WINDOW, AtomEnum::WINDOW.into())?
+            .and_then(|reply| reply.value32().and_then(|mut values| values.next()))
+            .filter(|&w| w != 0);
+
+        if window != self.watched {
+            if let Some(old) = self.watched {
+                let _ = self.conn.change_window_attributes(
+                    old,
+                    &ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT),
+                );
+            }
+            if let Some(new) = window {
+                let _ = self.conn.change_window_attributes(
+                    new,
+                    &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+                );
+            }
+            self.conn.flush()?;
+            self.watched = window;
+        }
+
+        let Some(window) = window else {
+            return Ok(None);
+        };
+
+        let title = match self.get_property(window, self.atoms._NET_WM_NAME, self.atoms.UTF8_STRING)? {
+            Some(name) if !name.value.is_empty() => String::from_utf8_lossy(&name.value).into_owned(),
+            _ => self
+                .get_property(window, AtomEnum::WM_NAME.into(), AtomEnum::ANY.into())?
+                .map(|name| String::from_utf8_lossy(&name.value).into_owned())
+                .unwrap_or_default(),
+        };
+        let title = title.trim().to_string();
+        if title.is_empty() {
+            return Ok(None);
+        }
+
+        // WM_CLASS is "instance\0class\0"; the class is what xdotool's getwindowclassname reports
+        let app_name = self
+            .get_property(window, AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into())?
+            .and_then(|class| {
+                class
+                    .value
+                    .split(|&b| b == 0)
+                    .rfind(|part| !part.is_empty())
+                    .map(|part| String::from_utf8_lossy(part).into_owned())
+            })
+            .unwrap_or_else(|| "Unknown".to_string());
+
+        let pid = self
+            .get_property(window, self.atoms._NET_WM_PID, AtomEnum::CARDINAL.into())?
+            .and_then(|reply| reply.value32().and_then(|mut values| values.next()))
+            .filter(|&pid| pid > 0);
+        let exe_path = pid
+            .and_then(|pid| std::fs::read_link(format!("/proc/{}/exe", pid)).ok())
+            .and_then(|p| p.into_os_string().into_string().ok())
+            .unwrap_or_else(|| "unknown".to_string());
+
+        Ok(Some(ActiveWindow {
+            app_name,
+            window_title: title,
+            exe_path,
+            pid,
+        }))
+    }
+
+    /// The property, or `None` when the window does not have it (or is gone).
+    fn get_property(&self, window: Window, property: u32, ty: u32) -> X11Result<Option<GetPropertyReply>> {
+        let cookie = self
+            .conn
+            .get_property(false, window, property, ty, 0, u32::MAX / 4)?;
+        match cookie.reply() {
+            Ok(reply) if reply.type_ != u32::from(AtomEnum::NONE) => Ok(Some(reply)),
+            Ok(_) => Ok(None),
+            // BadWindow: the window closed between the focus change and this read
+            Err(x11rb::errors::ReplyError::X11Error(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
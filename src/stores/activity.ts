@@ -22,6 +22,8 @@ export interface ActivitySession {
   start_time: string;
   end_time: string | null;
   duration_seconds: number;
+  url?: string | null;
+  domain?: string | null;
 }
 
 export interface AppUsageSummary {
@@ -29,6 +31,7 @@ export interface AppUsageSummary {
   exe_path: string;
   total_seconds: number;
   session_count: number;
+  category?: string | null;
 }
 
 export interface DailyStats {
@@ -67,6 +70,11 @@ export interface CodingSession {
   start_time: string;
   end_time: string | null;
   duration_seconds: number;
+  source: "title" | "heartbeat";
+  branch: string | null;
+  repo_root: string | null;
+  repo_name: string | null;
+  repo_remote: string | null;
 }
 
 export interface CodingStats {
@@ -94,6 +102,8 @@ export interface CurrentCodingSession {
   is_ai_assisted: boolean;
   window_title: string;
   exe_path: string;
+  repo_name: string | null;
+  branch: string | null;
 }
 
 export interface Settings {
@@ -101,6 +111,17 @@ export interface Settings {
   theme: string;
   autostart: boolean;
   minimize_to_tray: boolean;
+  idle_threshold_secs: number;
+  api_enabled?: boolean;
+  api_port?: number;
+  terminal_tracking?: boolean;
+  title_privacy?: 'full' | 'app_only' | 'hash' | 'redact';
+  redact_private_windows?: boolean;
+  retention_days?: number;
+  backup_enabled?: boolean;
+  backup_interval_hours?: number;
+  invoice_issuer?: string;
+  billing_currency?: string;
 }
 
 export function detectCategory(appName: string, exePath: string = ''): string {
@@ -529,7 +550,8 @@ export const useActivityStore = defineStore('activity', {
       language: 'en',
       theme: 'dark',
       autostart: true,
-      minimize_to_tray: true
+      minimize_to_tray: true,
+      idle_threshold_secs: 300
     } as Settings,
     appCategories: {} as Record<string, string>,
     isTracking: false,
@@ -567,6 +589,11 @@ export const useActivityStore = defineStore('activity', {
 
           if (session.window_title.toLowerCase().includes('youtube music')) return;
 
+          if (session.domain) {
+            SITE_USAGE[session.domain] = (SITE_USAGE[session.domain] || 0) + session.duration_seconds;
+            return;
+          }
+
           let site = 'Unknown';
           const title = session.window_title;
 